@@ -0,0 +1,84 @@
+// Boundary layer problem
+//
+//      eps y'' + y' = 0,   y(0) = 0,   y(1) = 1
+//
+// whose solution y = (1 - exp(-x/eps)) / (1 - exp(-1/eps)) varies rapidly in a
+// layer of width eps at x = 0. Shooting from x = 0 is ill-conditioned for small
+// eps, while collocation refines the mesh in the layer.
+
+extern crate ode_solvers;
+use ode_solvers::collocation::*;
+use ode_solvers::*;
+
+type State = Vector2<f64>;
+
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+const EPS: f64 = 1.0e-3;
+
+fn main() {
+    let mesh: Vec<f64> = (0..11).map(|i| i as f64 / 10.0).collect();
+    let guess = mesh.iter().map(|&x| State::new(x, 1.0)).collect();
+
+    let mut collocation = Collocation::new(system, boundary_conditions, mesh, guess, 1.0e-6, 1.0e-9);
+    collocation.set_jacobian(jacobian);
+    let res = collocation.solve();
+
+    // Handle result
+    match res {
+        Ok(stats) => {
+            println!("{}", stats);
+            let solution = collocation.solution().unwrap();
+            let mut err: f64 = 0.0;
+            for i in 0..1001 {
+                let x = i as f64 / 1000.0;
+                let exact = (1.0 - (-x / EPS).exp()) / (1.0 - (-1.0 / EPS).exp());
+                err = err.max((solution.y(x)[0] - exact).abs());
+            }
+            println!("Maximum error: {:e}", err);
+            let path = Path::new("./outputs/boundary_layer_collocation.dat");
+            save(collocation.x_out(), collocation.y_out(), path);
+            println!("Results saved in: {:?}", path);
+        }
+        Err(e) => println!("An error occured: {}", e),
+    }
+}
+
+fn system(_x: f64, y: &State, _p: &DVector<f64>, dy: &mut State) {
+    dy[0] = y[1];
+    dy[1] = -y[1] / EPS;
+}
+
+fn jacobian(_x: f64, _y: &State, _p: &DVector<f64>, jac: &mut DMatrix<f64>) {
+    jac[(0, 0)] = 0.0;
+    jac[(0, 1)] = 1.0;
+    jac[(1, 0)] = 0.0;
+    jac[(1, 1)] = -1.0 / EPS;
+}
+
+fn boundary_conditions(ya: &State, yb: &State, _p: &DVector<f64>, g: &mut DVector<f64>) {
+    g[0] = ya[0];
+    g[1] = yb[0] - 1.0;
+}
+
+pub fn save(mesh: &[f64], states: &[State], filename: &Path) {
+    // Create or open file
+    let mut buf = match File::create(filename) {
+        Err(e) => {
+            println!("Could not open file. Error: {:?}", e);
+            return;
+        }
+        Ok(buf) => buf,
+    };
+
+    // Write mesh points and state vector in a csv format
+    for (i, state) in states.iter().enumerate() {
+        buf.write_fmt(format_args!("{}", mesh[i])).unwrap();
+        for val in state.iter() {
+            buf.write_fmt(format_args!(", {}", val)).unwrap();
+        }
+        buf.write_fmt(format_args!("\n")).unwrap();
+    }
+}
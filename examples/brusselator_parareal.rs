@@ -0,0 +1,64 @@
+// Parallel-in-time integration of the Brusselator
+//
+//      y1' = A + y1^2 y2 - (B + 1) y1
+//      y2' = B y1 - y1^2 y2
+//
+// with A = 1 and B = 3 over 200 time units. The interval is split into 100
+// slices, predicted with 20 steps of RK4 per slice and corrected with Dop853.
+// The result is compared with a sequential integration with Dop853. The fine
+// integrations are split among the cores of the machine, so that a speedup is
+// only obtained on a machine with several cores.
+
+extern crate ode_solvers;
+use ode_solvers::dop853::*;
+use ode_solvers::parareal::*;
+use ode_solvers::*;
+
+use std::time::Instant;
+
+type State = Vector2<f64>;
+type Time = f64;
+
+const T_END: f64 = 200.0;
+
+fn main() {
+    let y0 = State::new(1.5, 3.0);
+
+    let start = Instant::now();
+    let mut parareal = Parareal::new(system, 0.0, T_END, y0, 100, 1.0e-12, 1.0e-12);
+    parareal.set_coarse_steps(20);
+    parareal.set_tolerance(1.0e-9);
+    let res = parareal.integrate();
+    let t_parareal = start.elapsed();
+
+    // Handle result
+    match res {
+        Ok(stats) => {
+            println!("{}", stats);
+            println!(
+                "Iterations: {}, residual: {:e}",
+                parareal.iterations(),
+                parareal.residual()
+            );
+
+            let start = Instant::now();
+            let mut stepper = Dop853::new(system, 0.0, T_END, T_END, y0, 1.0e-12, 1.0e-12);
+            stepper.integrate().unwrap();
+            let t_sequential = start.elapsed();
+            let y_end = stepper.y_out().last().unwrap();
+
+            println!("Parareal: {:?}", t_parareal);
+            println!("Sequential Dop853: {:?}", t_sequential);
+            println!(
+                "Difference of the final states: {:e}",
+                (y_end - parareal.y_out().last().unwrap()).norm()
+            );
+        }
+        Err(e) => println!("An error occured: {}", e),
+    }
+}
+
+fn system(_t: Time, y: &State, dy: &mut State) {
+    dy[0] = 1.0 + y[0] * y[0] * y[1] - 4.0 * y[0];
+    dy[1] = 3.0 * y[0] - y[0] * y[0] * y[1];
+}
@@ -0,0 +1,81 @@
+// Consecutive first order reactions A -> B -> C
+//
+//      a' = -k1 a
+//      b' =  k1 a - k2 b
+//      c' =  k2 b
+//
+// The rate constants k1 and k2 are estimated from noisy measurements of the
+// three concentrations, starting from a poor initial guess.
+
+extern crate ode_solvers;
+use ode_solvers::fitting::*;
+use ode_solvers::*;
+
+type State = Vector3<f64>;
+type Time = f64;
+
+const K1: f64 = 0.8;
+const K2: f64 = 0.3;
+
+fn main() {
+    let y0 = State::new(1.0, 0.0, 0.0);
+    let (x_obs, y_obs) = measurements();
+
+    let p0 = DVector::from_vec(vec![0.3, 1.0]);
+    let mut fit = Fit::new(system, parameter_derivatives, 0.0, y0, x_obs, y_obs, p0);
+    fit.set_jacobian(jacobian);
+    let res = fit.fit();
+
+    // Handle result
+    match res {
+        Ok(stats) => {
+            println!("{}", stats);
+            let p = fit.parameters();
+            let intervals = fit.confidence_intervals(0.95).unwrap();
+            for (i, (lo, hi)) in intervals.iter().enumerate() {
+                println!("k{} = {:.5}, 95% confidence interval: [{:.5}, {:.5}]", i + 1, p[i], lo, hi);
+            }
+            println!("Covariance matrix: {}", fit.covariance().unwrap());
+        }
+        Err(e) => println!("An error occured: {}", e),
+    }
+}
+
+fn system(_t: Time, y: &State, p: &DVector<f64>, dy: &mut State) {
+    dy[0] = -p[0] * y[0];
+    dy[1] = p[0] * y[0] - p[1] * y[1];
+    dy[2] = p[1] * y[1];
+}
+
+// Jacobian matrix of the system with respect to y
+fn jacobian(_t: Time, _y: &State, p: &DVector<f64>, jac: &mut DMatrix<f64>) {
+    jac.fill(0.0);
+    jac[(0, 0)] = -p[0];
+    jac[(1, 0)] = p[0];
+    jac[(1, 1)] = -p[1];
+    jac[(2, 1)] = p[1];
+}
+
+// Derivatives of the system with respect to (k1, k2)
+fn parameter_derivatives(_t: Time, y: &State, _p: &DVector<f64>, dfdp: &mut DMatrix<f64>) {
+    dfdp.fill(0.0);
+    dfdp[(0, 0)] = -y[0];
+    dfdp[(1, 0)] = y[0];
+    dfdp[(1, 1)] = -y[1];
+    dfdp[(2, 1)] = y[1];
+}
+
+// Analytical solution for (K1, K2) with a deterministic perturbation of about 1%
+fn measurements() -> (Vec<Time>, Vec<State>) {
+    let mut x_obs = Vec::new();
+    let mut y_obs = Vec::new();
+    for i in 1..21 {
+        let t = 0.5 * i as f64;
+        let a = (-K1 * t).exp();
+        let b = K1 / (K2 - K1) * ((-K1 * t).exp() - (-K2 * t).exp());
+        let noise = |j: f64| 0.01 * (7.3 * i as f64 + 2.1 * j).sin();
+        x_obs.push(t);
+        y_obs.push(State::new(a + noise(0.0), b + noise(1.0), 1.0 - a - b + noise(2.0)));
+    }
+    (x_obs, y_obs)
+}
@@ -0,0 +1,68 @@
+// Chemical reaction of Robertson generated from its reactions
+//
+//           A -> B          (k1 = 0.04)
+//       B + C -> A + C      (k2 = 1e4)
+//         2 B -> B + C      (k3 = 3e7)
+//
+// The rate equations and their Jacobian matrix, which are hand-coded in
+// chemical_reaction_seulex.rs, are generated by the network and integrated
+// with Seulex, through closures sharing the network. The conservation law of
+// the network, the total A + B + C, is checked at the end.
+
+extern crate ode_solvers;
+use ode_solvers::reaction::*;
+use ode_solvers::seulex::*;
+use ode_solvers::*;
+
+use std::rc::Rc;
+
+type State = Vector3<f64>;
+type Time = f64;
+
+fn main() {
+    let network = Rc::new(robertson());
+    let laws = network.conservation_laws();
+    for w in &laws {
+        println!("Conserved quantity: {}", w.transpose());
+    }
+
+    let y0 = State::new(1.0, 0.0, 0.0);
+    let rates = network.clone();
+    let mut stepper = Seulex::with_closure(
+        move |_: Time, y: &State, dy: &mut State| rates.rhs(y, dy),
+        0.0,
+        40.0,
+        0.5,
+        y0,
+        1.0e-8,
+        1.0e-12,
+    );
+    stepper
+        .set_jacobian(move |_: Time, y: &State, jac: &mut DMatrix<f64>| network.jacobian(y, jac));
+    let res = stepper.integrate();
+
+    // Handle result
+    match res {
+        Ok(stats) => {
+            println!("{}", stats);
+            let y_last = stepper.y_out().last().unwrap();
+            println!("y(40) = {}", y_last.transpose());
+            for w in &laws {
+                println!(
+                    "Drift of the conserved quantity: {:e}",
+                    w.dot(&DVector::from_column_slice(y_last.as_slice()))
+                        - w.dot(&DVector::from_column_slice(y0.as_slice()))
+                );
+            }
+        }
+        Err(e) => println!("An error occured: {}", e),
+    }
+}
+
+fn robertson() -> ReactionNetwork {
+    let mut network = ReactionNetwork::with_species(&["A", "B", "C"]);
+    network.add_reaction(&[(0, 1)], &[(1, 1)], 0.04);
+    network.add_reaction(&[(1, 1), (2, 1)], &[(0, 1), (2, 1)], 1.0e4);
+    network.add_reaction(&[(1, 2)], &[(1, 1), (2, 1)], 3.0e7);
+    network
+}
@@ -0,0 +1,76 @@
+// Chemical reaction of Robertson integrated with the stiff solver Seulex.
+// The conservation of mass y1 + y2 + y3 = 1 replaces the third differential
+// equation, which makes the system a differential-algebraic equation with the
+// singular mass matrix M = diag(1, 1, 0).
+
+extern crate ode_solvers;
+use ode_solvers::seulex::*;
+use ode_solvers::*;
+
+type State = Vector3<f64>;
+type Time = f64;
+
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+fn main() {
+    let y0 = State::new(1.0, 0.0, 0.0);
+    let mut stepper = Seulex::new(system, 0.0, 40.0, 0.5, y0, 1.0e-8, 1.0e-12);
+    stepper.set_jacobian(jacobian);
+    stepper.set_mass_matrix(DMatrix::from_diagonal_element(3, 3, 1.0).map_with_location(
+        |i, _, m| if i == 2 { 0.0 } else { m },
+    ));
+    let res = stepper.integrate();
+
+    // Handle result
+    match res {
+        Ok(stats) => {
+            println!("{}", stats);
+            let x_last = stepper.x_out().last().unwrap();
+            let y_last = stepper.y_out().last().unwrap();
+            println!("y({}) = {}", x_last, y_last.transpose());
+            let path = Path::new("./outputs/chemical_reaction_seulex.dat");
+            save(stepper.x_out(), stepper.y_out(), path);
+            println!("Results saved in: {:?}", path);
+        }
+        Err(e) => println!("An error occured: {}", e),
+    }
+}
+
+fn system(_: Time, y: &State, dy: &mut State) {
+    dy[0] = -0.04 * y[0] + 1.0e4 * y[1] * y[2];
+    dy[1] = 0.04 * y[0] - 1.0e4 * y[1] * y[2] - 3.0e7 * y[1] * y[1];
+    dy[2] = y[0] + y[1] + y[2] - 1.0;
+}
+
+fn jacobian(_: Time, y: &State, jac: &mut DMatrix<f64>) {
+    jac[(0, 0)] = -0.04;
+    jac[(0, 1)] = 1.0e4 * y[2];
+    jac[(0, 2)] = 1.0e4 * y[1];
+    jac[(1, 0)] = 0.04;
+    jac[(1, 1)] = -1.0e4 * y[2] - 6.0e7 * y[1];
+    jac[(1, 2)] = -1.0e4 * y[1];
+    jac[(2, 0)] = 1.0;
+    jac[(2, 1)] = 1.0;
+    jac[(2, 2)] = 1.0;
+}
+
+pub fn save(times: &[Time], states: &[State], filename: &Path) {
+    // Create or open file
+    let mut buf = match File::create(filename) {
+        Err(e) => {
+            println!("Could not open file. Error: {:?}", e);
+            return;
+        }
+        Ok(buf) => buf,
+    };
+    // Write time and state in a csv format
+    for (i, state) in states.iter().enumerate() {
+        buf.write_fmt(format_args!("{}", times[i])).unwrap();
+        for val in state.iter() {
+            buf.write_fmt(format_args!(", {}", val)).unwrap();
+        }
+        buf.write_fmt(format_args!("\n")).unwrap();
+    }
+}
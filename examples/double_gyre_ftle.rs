@@ -0,0 +1,91 @@
+// FTLE field of the double gyre
+//
+// The double gyre is a periodically forced pair of counter-rotating vortices
+// on [0, 2] x [0, 1]. The ridges of the forward FTLE field show the repelling
+// Lagrangian coherent structure separating the two gyres.
+
+extern crate ode_solvers;
+use ode_solvers::ftle::*;
+use ode_solvers::*;
+
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+type State = Vector2<f64>;
+type Time = f64;
+
+// Define problem specific constants
+const A: f64 = 0.1;
+const EPS: f64 = 0.25;
+const OMEGA: f64 = 2.0 * PI / 10.0;
+
+fn main() {
+    // Grid of 200 x 100 initial conditions at the centers of the cells, away
+    // from the invariant boundaries of the domain
+    let mut grid = Vec::new();
+    for j in 0..100 {
+        for i in 0..200 {
+            grid.push(State::new((i as f64 + 0.5) / 100.0, (j as f64 + 0.5) / 100.0));
+        }
+    }
+
+    let mut ftle = Ftle::new(system, 0.0, 15.0, grid, 1e-8, 1e-8);
+    ftle.set_jacobian(jacobian);
+    let res = ftle.integrate();
+
+    // Handle result
+    match res {
+        Ok(stats) => {
+            println!("{}", stats);
+            let max = ftle.ftle().iter().cloned().fold(0.0, f64::max);
+            println!("Largest FTLE: {}", max);
+            let path = Path::new("./outputs/double_gyre_ftle.dat");
+            save(ftle.grid(), ftle.ftle(), path);
+            println!("Results saved in: {:?}", path);
+        }
+        Err(e) => println!("An error occured: {}", e),
+    }
+}
+
+// Forcing of the double gyre and its derivative with respect to x
+fn forcing(t: Time, x: f64) -> (f64, f64) {
+    let a = EPS * (OMEGA * t).sin();
+    let b = 1.0 - 2.0 * a;
+    (a * x * x + b * x, 2.0 * a * x + b)
+}
+
+fn system(t: Time, y: &State, dy: &mut State) {
+    let (f, df) = forcing(t, y[0]);
+    dy[0] = -PI * A * (PI * f).sin() * (PI * y[1]).cos();
+    dy[1] = PI * A * (PI * f).cos() * (PI * y[1]).sin() * df;
+}
+
+fn jacobian(t: Time, y: &State, jac: &mut DMatrix<f64>) {
+    let (f, df) = forcing(t, y[0]);
+    let d2f = 2.0 * EPS * (OMEGA * t).sin();
+    let (sf, cf) = (PI * f).sin_cos();
+    let (sy, cy) = (PI * y[1]).sin_cos();
+    jac[(0, 0)] = -PI * PI * A * cf * cy * df;
+    jac[(0, 1)] = PI * PI * A * sf * sy;
+    jac[(1, 0)] = PI * A * sy * (-PI * sf * df * df + cf * d2f);
+    jac[(1, 1)] = PI * PI * A * cf * cy * df;
+}
+
+pub fn save(grid: &[State], ftle: &[f64], filename: &Path) {
+    // Create or open file
+    let mut buf = match File::create(filename) {
+        Err(e) => {
+            println!("Could not open file. Error: {:?}", e);
+            return;
+        }
+        Ok(buf) => buf,
+    };
+
+    // Write initial condition and FTLE in a csv format
+    for (i, y) in grid.iter().enumerate() {
+        buf.write_fmt(format_args!("{}, {}, {}\n", y[0], y[1], ftle[i]))
+            .unwrap();
+    }
+}
@@ -0,0 +1,71 @@
+// Stochastic simulation of the Michaelis-Menten enzyme kinetics
+//
+//      E + S -> ES     (c1 = 1.66e-3)
+//         ES -> E + S  (c2 = 1e-4)
+//         ES -> E + P  (c3 = 0.1)
+//
+// with 301 substrate and 120 enzyme molecules. Trajectories over 50 time units
+// are simulated with the direct method, the next reaction method and tau
+// leaping, and the mean and standard deviation of the number of products at
+// the end are compared.
+
+extern crate ode_solvers;
+use ode_solvers::reaction::*;
+use ode_solvers::ssa::*;
+use ode_solvers::*;
+
+use std::time::Instant;
+
+type State = Vector4<f64>;
+
+const RUNS: usize = 1000;
+const T_END: f64 = 50.0;
+
+fn main() {
+    // Species: E, S, ES, P
+    let mut network = ReactionNetwork::new(4);
+    network.add_reaction(&[(0, 1), (1, 1)], &[(2, 1)], 1.66e-3);
+    network.add_reaction(&[(2, 1)], &[(0, 1), (1, 1)], 1.0e-4);
+    network.add_reaction(&[(2, 1)], &[(0, 1), (3, 1)], 0.1);
+    let y0 = State::new(120.0, 301.0, 0.0, 0.0);
+
+    let start = Instant::now();
+    let mut direct = Direct::new(network.clone(), 0.0, T_END, T_END, y0, 0);
+    let products = simulate(|| {
+        direct.integrate().unwrap();
+        direct.y_out().last().unwrap()[3]
+    });
+    print("Direct", &products, start);
+
+    let start = Instant::now();
+    let mut next = NextReaction::new(network.clone(), 0.0, T_END, T_END, y0, 1);
+    let products = simulate(|| {
+        next.integrate().unwrap();
+        next.y_out().last().unwrap()[3]
+    });
+    print("Next reaction", &products, start);
+
+    let start = Instant::now();
+    let mut tau = TauLeaping::new(network, 0.0, T_END, T_END, y0, 2);
+    let products = simulate(|| {
+        tau.integrate().unwrap();
+        tau.y_out().last().unwrap()[3]
+    });
+    print("Tau leaping", &products, start);
+}
+
+fn simulate<F: FnMut() -> f64>(mut run: F) -> Vec<f64> {
+    (0..RUNS).map(|_| run()).collect()
+}
+
+fn print(method: &str, products: &[f64], start: Instant) {
+    let mean = products.iter().sum::<f64>() / RUNS as f64;
+    let var = products.iter().map(|p| (p - mean).powi(2)).sum::<f64>() / (RUNS - 1) as f64;
+    println!(
+        "{:>13}: P = {:.2} +/- {:.2} in {:?}",
+        method,
+        mean,
+        var.sqrt(),
+        start.elapsed()
+    );
+}
@@ -0,0 +1,87 @@
+// Geometric Brownian motion dy = mu y dx + sigma y dW, integrated in the sense of Itô
+// with the Euler-Maruyama, Milstein and adaptive stochastic Runge-Kutta methods on
+// the same path of the Wiener process. The exact solution on that path is
+//
+//      y(x) = y0 exp((mu - sigma^2 / 2) x + sigma W(x))
+
+extern crate ode_solvers;
+use ode_solvers::sde::*;
+use ode_solvers::*;
+
+type State = Vector1<f64>;
+type Time = f64;
+
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+const MU: f64 = 1.5;
+const SIGMA: f64 = 1.0;
+
+fn main() {
+    let y0 = State::new(1.0);
+    let x_end = 1.0;
+
+    let g = Noise::Diagonal(diffusion);
+    let mut srk = Srk::new(drift, g, 0.0, x_end, 0.01, y0, 1.0e-4, 1.0e-4, 42);
+    let res = srk.integrate();
+
+    // Handle result
+    match res {
+        Ok(stats) => {
+            println!("{}", stats);
+            let path = Path::new("./outputs/geometric_brownian_motion_sde.dat");
+            save(srk.x_out(), srk.y_out(), path);
+            println!("Results saved in: {:?}", path);
+        }
+        Err(e) => {
+            println!("An error occured: {}", e);
+            return;
+        }
+    }
+
+    // The fixed step methods are run on the path sampled by the adaptive method
+    let mut em = EulerMaruyama::new(drift, Noise::Diagonal(diffusion), 0.0, x_end, 1.0e-3, y0, 0);
+    em.set_path(srk.path().clone());
+    let mut milstein = Milstein::new(drift, Noise::Diagonal(diffusion), 0.0, x_end, 1.0e-3, y0, 0);
+    milstein.set_path(srk.path().clone());
+    if em.integrate().is_err() || milstein.integrate().is_err() {
+        println!("An error occured with the fixed step methods");
+        return;
+    }
+
+    let mut path = srk.path().clone();
+    let exact = (MU - 0.5 * SIGMA * SIGMA) * x_end + SIGMA * path.w(x_end).unwrap()[0];
+    println!("Exact:          {}", y0[0] * exact.exp());
+    println!("Euler-Maruyama: {}", em.y_out().last().unwrap()[0]);
+    println!("Milstein:       {}", milstein.y_out().last().unwrap()[0]);
+    println!("Srk:            {}", srk.y_out().last().unwrap()[0]);
+}
+
+fn drift(_t: Time, y: &State, dy: &mut State) {
+    dy[0] = MU * y[0];
+}
+
+fn diffusion(_t: Time, y: &State, g: &mut State) {
+    g[0] = SIGMA * y[0];
+}
+
+pub fn save(times: &[Time], states: &[State], filename: &Path) {
+    // Create or open file
+    let mut buf = match File::create(filename) {
+        Err(e) => {
+            println!("Could not open file. Error: {:?}", e);
+            return;
+        }
+        Ok(buf) => buf,
+    };
+
+    // Write time and state vector in a csv format
+    for (i, state) in states.iter().enumerate() {
+        buf.write_fmt(format_args!("{}", times[i])).unwrap();
+        for val in state.iter() {
+            buf.write_fmt(format_args!(", {}", val)).unwrap();
+        }
+        buf.write_fmt(format_args!("\n")).unwrap();
+    }
+}
@@ -0,0 +1,73 @@
+// Model of the spread of an infectious disease with an incubation period of one
+// day and an immunity period of ten days, integrated with the delay
+// differential equation solver Retard:
+//
+//      y1'(x) = -y1(x) y2(x - 1) + y2(x - 10)
+//      y2'(x) =  y1(x) y2(x - 1) - y2(x)
+//      y3'(x) =  y2(x) - y2(x - 10)
+//
+// where y1, y2 and y3 are the susceptible, infected and immune populations.
+
+extern crate ode_solvers;
+use ode_solvers::retard::*;
+use ode_solvers::*;
+
+type State = Vector3<f64>;
+type Time = f64;
+
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+fn main() {
+    let y0 = State::new(5.0, 0.1, 1.0);
+    let mut stepper = Retard::new(system, initial_function, 0.0, 40.0, 0.1, y0, 1.0e-8, 1.0e-8);
+    // The delays are given to locate the discontinuities of the derivatives
+    stepper.set_delays(vec![|_, _| 1.0, |_, _| 10.0]);
+    let res = stepper.integrate();
+
+    // Handle result
+    match res {
+        Ok(stats) => {
+            println!("{}", stats);
+            println!("Discontinuities: {:?}", stepper.breakpoints());
+            let path = Path::new("./outputs/infectious_disease_retard.dat");
+            save(stepper.x_out(), stepper.y_out(), path);
+            println!("Results saved in: {:?}", path);
+        }
+        Err(e) => println!("An error occured: {}", e),
+    }
+}
+
+// Solution before the initial time
+fn initial_function(_t: Time) -> State {
+    State::new(5.0, 0.1, 1.0)
+}
+
+fn system(t: Time, y: &State, history: &History<State>, dy: &mut State) {
+    let y2_1 = history.y(t - 1.0)[1];
+    let y2_10 = history.y(t - 10.0)[1];
+    dy[0] = -y[0] * y2_1 + y2_10;
+    dy[1] = y[0] * y2_1 - y[1];
+    dy[2] = y[1] - y2_10;
+}
+
+pub fn save(times: &[Time], states: &[State], filename: &Path) {
+    // Create or open file
+    let mut buf = match File::create(filename) {
+        Err(e) => {
+            println!("Could not open file. Error: {:?}", e);
+            return;
+        }
+        Ok(buf) => buf,
+    };
+
+    // Write time and state vector in a csv format
+    for (i, state) in states.iter().enumerate() {
+        buf.write_fmt(format_args!("{}", times[i])).unwrap();
+        for val in state.iter() {
+            buf.write_fmt(format_args!(", {}", val)).unwrap();
+        }
+        buf.write_fmt(format_args!("\n")).unwrap();
+    }
+}
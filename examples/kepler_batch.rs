@@ -0,0 +1,109 @@
+// Batched integration of Kepler orbits
+//
+// The orbits of 1000 spacecraft, whose initial velocities differ from the one of
+// the kepler_orbit example by up to 10 m/s, are integrated over five periods in
+// lockstep with BatchDopri5, and one at a time with Dopri5 for comparison.
+
+extern crate ode_solvers;
+use ode_solvers::batch::*;
+use ode_solvers::dopri5::*;
+use ode_solvers::*;
+
+use std::f64::consts::PI;
+use std::time::Instant;
+
+type State = Vector6<f64>;
+
+const MU: f64 = 398600.435436;
+const LANES: usize = 1000;
+
+fn main() {
+    let a: f64 = 20000.0;
+    let period = 2.0 * PI * (a.powi(3) / MU).sqrt();
+    let y0: Vec<State> = (0..LANES)
+        .map(|l| {
+            let dv = 1.0e-2 * (l as f64 / LANES as f64 - 0.5);
+            State::new(
+                -5007.248417988539,
+                -1444.918140151374,
+                3628.534606178356,
+                0.717716656891 + dv,
+                -10.224093784269 - dv,
+                0.748229399696 + dv,
+            )
+        })
+        .collect();
+
+    let start = Instant::now();
+    let mut batch = BatchDopri5::new(
+        system,
+        0.0,
+        5.0 * period,
+        period,
+        y0.clone(),
+        1.0e-10,
+        1.0e-10,
+    );
+    let res = batch.integrate();
+    let t_batch = start.elapsed();
+
+    // Handle result
+    match res {
+        Ok(stats) => {
+            println!("{}", stats);
+
+            // Integration of each orbit with Dopri5
+            let start = Instant::now();
+            let mut diff: f64 = 0.0;
+            for (l, y) in y0.iter().enumerate() {
+                let mut stepper = Dopri5::new(
+                    scalar_system,
+                    0.0,
+                    5.0 * period,
+                    period,
+                    *y,
+                    1.0e-10,
+                    1.0e-10,
+                );
+                stepper.integrate().unwrap();
+                let y_end = stepper.y_out().last().unwrap();
+                diff = diff.max((y_end - batch.y_out()[l].last().unwrap()).norm());
+            }
+            let t_single = start.elapsed();
+
+            println!("Batched integration: {:?}", t_batch);
+            println!("Integration of each orbit: {:?}", t_single);
+            println!("Largest difference of the final states: {:e}", diff);
+        }
+        Err(e) => println!("An error occured: {}", e),
+    }
+}
+
+// Equations of motion of all the lanes. Entry (l, i) of y is the i-th component
+// of the state of lane l.
+fn system(_t: &[f64], y: &DMatrix<f64>, dy: &mut DMatrix<f64>) {
+    let n = y.nrows();
+    let (y, dy) = (y.as_slice(), dy.as_mut_slice());
+    for l in 0..n {
+        let (rx, ry, rz) = (y[l], y[n + l], y[2 * n + l]);
+        let r2 = rx * rx + ry * ry + rz * rz;
+        let c = -MU / (r2 * r2.sqrt());
+        dy[l] = y[3 * n + l];
+        dy[n + l] = y[4 * n + l];
+        dy[2 * n + l] = y[5 * n + l];
+        dy[3 * n + l] = c * rx;
+        dy[4 * n + l] = c * ry;
+        dy[5 * n + l] = c * rz;
+    }
+}
+
+fn scalar_system(_t: f64, y: &State, dy: &mut State) {
+    let r = (y[0] * y[0] + y[1] * y[1] + y[2] * y[2]).sqrt();
+
+    dy[0] = y[3];
+    dy[1] = y[4];
+    dy[2] = y[5];
+    dy[3] = -MU * y[0] / r.powi(3);
+    dy[4] = -MU * y[1] / r.powi(3);
+    dy[5] = -MU * y[2] / r.powi(3);
+}
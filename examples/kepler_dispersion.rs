@@ -0,0 +1,113 @@
+// Dispersion of a Kepler orbit
+//
+// The initial velocity of the spacecraft of the kepler_orbit example is
+// perturbed by a Gaussian error of 1 m/s per axis. The mean, the standard
+// deviation and the 5% and 95% quantiles of the position over two periods are
+// computed online from 5000 trajectories, without storing them.
+
+extern crate ode_solvers;
+extern crate rand;
+use ode_solvers::ensemble::*;
+use ode_solvers::*;
+
+use rand::distributions::StandardNormal;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+type State = Vector6<f64>;
+type Time = f64;
+
+const MU: f64 = 398600.435436;
+const SIGMA_V: f64 = 1.0e-3;
+
+fn main() {
+    let a: f64 = 20000.0;
+    let period = 2.0 * PI * (a.powi(3) / MU).sqrt();
+
+    // Each trajectory is reduced to its final radius
+    let mut ensemble = Ensemble::new(system, generator, final_radius, 5000, 0.0, 2.0 * period);
+    ensemble.set_dx(period / 200.0);
+    let mut statistics = Statistics::new(vec![0.05, 0.95]);
+    let stats = ensemble.accumulate(&mut statistics);
+    println!("{}", stats);
+
+    let std = statistics.variance_out().last().unwrap().map(|v| v.sqrt());
+    println!("Trajectories: {}", statistics.count());
+    println!(
+        "Standard deviation of the final position (km): {}, {}, {}",
+        std[0], std[1], std[2]
+    );
+    let radii: Vec<f64> = ensemble
+        .results()
+        .iter()
+        .filter_map(|r| r.as_ref().ok())
+        .cloned()
+        .collect();
+    let r_min = radii.iter().cloned().fold(f64::INFINITY, f64::min);
+    let r_max = radii.iter().cloned().fold(0.0, f64::max);
+    println!("Final radius between {} and {} km", r_min, r_max);
+
+    let path = Path::new("./outputs/kepler_dispersion.dat");
+    save(&statistics, path);
+    println!("Results saved in: {:?}", path);
+}
+
+// Equations of motion of the system
+fn system(_t: Time, y: &State, p: &DVector<f64>, dy: &mut State) {
+    let r = (y[0] * y[0] + y[1] * y[1] + y[2] * y[2]).sqrt();
+
+    dy[0] = y[3];
+    dy[1] = y[4];
+    dy[2] = y[5];
+    dy[3] = -p[0] * y[0] / r.powi(3);
+    dy[4] = -p[0] * y[1] / r.powi(3);
+    dy[5] = -p[0] * y[2] / r.powi(3);
+}
+
+// Nominal state of the kepler_orbit example with a random velocity error
+fn generator(i: usize) -> (State, DVector<f64>) {
+    let mut rng = StdRng::seed_from_u64(i as u64);
+    let y0 = State::new(
+        -5007.248417988539,
+        -1444.918140151374,
+        3628.534606178356,
+        0.717716656891 + SIGMA_V * rng.sample(StandardNormal),
+        -10.224093784269 + SIGMA_V * rng.sample(StandardNormal),
+        0.748229399696 + SIGMA_V * rng.sample(StandardNormal),
+    );
+    (y0, DVector::from_element(1, MU))
+}
+
+fn final_radius(_i: usize, _x: &[f64], y: &[State]) -> f64 {
+    let y = y.last().unwrap();
+    (y[0] * y[0] + y[1] * y[1] + y[2] * y[2]).sqrt()
+}
+
+pub fn save(statistics: &Statistics<State>, filename: &Path) {
+    // Create or open file
+    let mut buf = match File::create(filename) {
+        Err(e) => {
+            println!("Could not open file. Error: {:?}", e);
+            return;
+        }
+        Ok(buf) => buf,
+    };
+
+    // Write time, mean, 5% and 95% quantiles of the position in a csv format
+    let q05 = statistics.quantile_out(0);
+    let q95 = statistics.quantile_out(1);
+    for (i, x) in statistics.x_out().iter().enumerate() {
+        buf.write_fmt(format_args!("{}", x)).unwrap();
+        for y in &[statistics.mean_out()[i], q05[i], q95[i]] {
+            for j in 0..3 {
+                buf.write_fmt(format_args!(", {}", y[j])).unwrap();
+            }
+        }
+        buf.write_fmt(format_args!("\n")).unwrap();
+    }
+}
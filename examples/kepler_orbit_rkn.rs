@@ -0,0 +1,65 @@
+// The equations of motion describing the motion of a spacecraft on a Kepler
+// orbit are integrated as a second order system using Rkn64.
+
+extern crate ode_solvers;
+use ode_solvers::rkn64::*;
+use ode_solvers::*;
+
+type State = Vector3<f64>;
+type Time = f64;
+
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+const MU: f64 = 398600.435436;
+
+fn main() {
+    let a: f64 = 20000.0;
+    let period = 2.0 * PI * (a.powi(3) / MU).sqrt();
+
+    // Orbit with: a = 20000km, e = 0.7, i = 35 deg, raan = 100 deg, arg_per = 65 deg, true_an = 30 deg
+    let r0 = State::new(-5007.248417988539, -1444.918140151374, 3628.534606178356);
+    let v0 = State::new(0.717716656891, -10.224093784269, 0.748229399696);
+
+    let mut stepper = Rkn64::new(system, 0.0, 5.0 * period, 60.0, r0, v0, 1.0e-10, 1.0e-10);
+    let res = stepper.integrate();
+
+    // Handle result
+    match res {
+        Ok(stats) => {
+            println!("{}", stats);
+            let path = Path::new("./outputs/kepler_orbit_rkn64.dat");
+            save(stepper.x_out(), stepper.y_out(), stepper.dy_out(), path);
+            println!("Results saved in: {:?}", path);
+        }
+        Err(e) => println!("An error occured: {}", e),
+    }
+}
+
+// Equations of motion of the system
+fn system(_t: Time, r: &State, d2r: &mut State) {
+    let norm = r.norm();
+    *d2r = -MU * r / norm.powi(3);
+}
+
+pub fn save(times: &[Time], positions: &[State], velocities: &[State], filename: &Path) {
+    // Create or open file
+    let mut buf = match File::create(filename) {
+        Err(e) => {
+            println!("Could not open file. Error: {:?}", e);
+            return;
+        }
+        Ok(buf) => buf,
+    };
+
+    // Write time, position and velocity in a csv format
+    for (i, time) in times.iter().enumerate() {
+        buf.write_fmt(format_args!("{}", time)).unwrap();
+        for val in positions[i].iter().chain(velocities[i].iter()) {
+            buf.write_fmt(format_args!(", {}", val)).unwrap();
+        }
+        buf.write_fmt(format_args!("\n")).unwrap();
+    }
+}
@@ -0,0 +1,71 @@
+// The state transition matrix of a spacecraft on a Kepler orbit is integrated
+// together with the equations of motion over one period. Since the flow of the
+// equations of motion preserves volume, the determinant of the state transition
+// matrix remains equal to one.
+
+extern crate ode_solvers;
+use ode_solvers::stm::*;
+use ode_solvers::*;
+
+type State = Vector6<f64>;
+type Time = f64;
+
+use std::f64::consts::PI;
+
+const MU: f64 = 398600.435436;
+
+fn main() {
+    let a: f64 = 20000.0;
+    let period = 2.0 * PI * (a.powi(3) / MU).sqrt();
+
+    // Orbit with: a = 20000km, e = 0.7, i = 35 deg, raan = 100 deg, arg_per = 65 deg, true_an = 30 deg
+    let y0 = State::new(
+        -5007.248417988539,
+        -1444.918140151374,
+        3628.534606178356,
+        0.717716656891,
+        -10.224093784269,
+        0.748229399696,
+    );
+
+    let mut stm = StateTransition::new(system, 0.0, period, period / 10.0, y0, 1.0e-12, 1.0e-12);
+    stm.set_jacobian(jacobian);
+    let res = stm.integrate();
+
+    // Handle result
+    match res {
+        Ok(stats) => {
+            println!("{}", stats);
+            let phi = stm.phi_out().last().unwrap();
+            println!("State transition matrix after one period: {}", phi);
+            println!("Determinant: {}", phi.determinant());
+        }
+        Err(e) => println!("An error occured: {}", e),
+    }
+}
+
+// Equations of motion of the system
+fn system(_t: Time, y: &State, dy: &mut State) {
+    let r = (y[0] * y[0] + y[1] * y[1] + y[2] * y[2]).sqrt();
+
+    dy[0] = y[3];
+    dy[1] = y[4];
+    dy[2] = y[5];
+    dy[3] = -MU * y[0] / r.powi(3);
+    dy[4] = -MU * y[1] / r.powi(3);
+    dy[5] = -MU * y[2] / r.powi(3);
+}
+
+// Jacobian matrix of the equations of motion
+fn jacobian(_t: Time, y: &State, jac: &mut DMatrix<f64>) {
+    let r = (y[0] * y[0] + y[1] * y[1] + y[2] * y[2]).sqrt();
+
+    jac.fill(0.0);
+    for i in 0..3 {
+        jac[(i, i + 3)] = 1.0;
+        for j in 0..3 {
+            let delta = if i == j { 1.0 } else { 0.0 };
+            jac[(i + 3, j)] = -MU * (delta / r.powi(3) - 3.0 * y[i] * y[j] / r.powi(5));
+        }
+    }
+}
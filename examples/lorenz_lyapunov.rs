@@ -0,0 +1,85 @@
+// Lyapunov spectrum of the Lorenz attractor
+//
+// The spectrum is approximately (0.906, 0, -14.572). The positive exponent
+// shows that the motion is chaotic, and the sum of the exponents is the trace
+// of the Jacobian matrix, -(SIG + 1 + BETA).
+
+extern crate ode_solvers;
+use ode_solvers::lyapunov::*;
+use ode_solvers::*;
+
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+type State = Vector3<f64>;
+type Time = f64;
+
+// Define problem specific constants
+const SIG: f64 = 10.0;
+const BETA: f64 = 8.0 / 3.0;
+const RHO: f64 = 28.0;
+
+fn main() {
+    // Initial state
+    let y0 = State::new(1.0, 1.0, 1.0);
+
+    // Skip a transient of 50 time units, then average over 2000 time units
+    let mut lyapunov = LyapunovSpectrum::new(system, 0.0, 2050.0, y0, 1e-10, 1e-10);
+    lyapunov.set_jacobian(jacobian);
+    lyapunov.set_transient(50.0);
+    lyapunov.set_interval(0.5);
+    let res = lyapunov.integrate();
+
+    // Handle result
+    match res {
+        Ok(stats) => {
+            println!("{}", stats);
+            println!("Lyapunov exponents: {:?}", lyapunov.exponents());
+            let sum: f64 = lyapunov.exponents().iter().sum();
+            println!("Sum of the exponents: {} (expected {})", sum, -(SIG + 1.0 + BETA));
+            let path = Path::new("./outputs/lorenz_lyapunov.dat");
+            save(lyapunov.x_out(), lyapunov.exponents_out(), path);
+            println!("Convergence history saved in: {:?}", path);
+        }
+        Err(e) => println!("An error occured: {}", e),
+    }
+}
+
+fn system(_t: Time, y: &State, dy: &mut State) {
+    dy[0] = SIG * (y[1] - y[0]);
+    dy[1] = y[0] * (RHO - y[2]) - y[1];
+    dy[2] = y[0] * y[1] - BETA * y[2];
+}
+
+fn jacobian(_t: Time, y: &State, jac: &mut DMatrix<f64>) {
+    jac[(0, 0)] = -SIG;
+    jac[(0, 1)] = SIG;
+    jac[(0, 2)] = 0.0;
+    jac[(1, 0)] = RHO - y[2];
+    jac[(1, 1)] = -1.0;
+    jac[(1, 2)] = -y[0];
+    jac[(2, 0)] = y[1];
+    jac[(2, 1)] = y[0];
+    jac[(2, 2)] = -BETA;
+}
+
+pub fn save(times: &[Time], exponents: &[Vec<f64>], filename: &Path) {
+    // Create or open file
+    let mut buf = match File::create(filename) {
+        Err(e) => {
+            println!("Could not open file. Error: {:?}", e);
+            return;
+        }
+        Ok(buf) => buf,
+    };
+
+    // Write time and estimates of the exponents in a csv format
+    for (i, estimate) in exponents.iter().enumerate() {
+        buf.write_fmt(format_args!("{}", times[i])).unwrap();
+        for val in estimate.iter() {
+            buf.write_fmt(format_args!(", {}", val)).unwrap();
+        }
+        buf.write_fmt(format_args!("\n")).unwrap();
+    }
+}
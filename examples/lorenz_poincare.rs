@@ -0,0 +1,76 @@
+// Poincaré section of the Lorenz attractor
+//
+// The crossings of the plane z = rho - 1, which contains the two non-trivial
+// equilibria, are recorded with decreasing z over a long integration. The
+// integration is split into chunks so that the continuous extension of the
+// whole trajectory is not kept in memory.
+
+extern crate ode_solvers;
+use ode_solvers::dopri5::*;
+use ode_solvers::poincare::*;
+use ode_solvers::*;
+
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+type State = Vector3<f64>;
+type Time = f64;
+
+// Define problem specific constants
+const SIG: f64 = 10.0;
+const BETA: f64 = 8.0 / 3.0;
+const RHO: f64 = 28.0;
+
+fn main() {
+    let mut poincare = PoincareSection::new(section, Direction::Decreasing);
+    poincare.set_projection(projection);
+
+    // Integrate over 10 chunks of 100 time units
+    let mut y = State::new(1.0, 1.0, 1.0);
+    for k in 0..10 {
+        let x = 100.0 * k as f64;
+        let mut stepper = Dopri5::new(system, x, x + 100.0, 100.0, y, 1e-10, 1e-10);
+        if let Err(e) = poincare.integrate(&mut stepper) {
+            println!("An error occured: {}", e);
+            return;
+        }
+        y = *stepper.y_out().last().unwrap();
+    }
+
+    println!("Number of crossings: {}", poincare.x_out().len());
+    let path = Path::new("./outputs/lorenz_poincare.dat");
+    save(poincare.coordinates_out(), path);
+    println!("Results saved in: {:?}", path);
+}
+
+fn system(_t: Time, y: &State, dy: &mut State) {
+    dy[0] = SIG * (y[1] - y[0]);
+    dy[1] = y[0] * (RHO - y[2]) - y[1];
+    dy[2] = y[0] * y[1] - BETA * y[2];
+}
+
+fn section(y: &State) -> f64 {
+    y[2] - (RHO - 1.0)
+}
+
+// Coordinates (x, y) on the plane
+fn projection(y: &State) -> DVector<f64> {
+    DVector::from_column_slice(&[y[0], y[1]])
+}
+
+pub fn save(points: &[DVector<f64>], filename: &Path) {
+    // Create or open file
+    let mut buf = match File::create(filename) {
+        Err(e) => {
+            println!("Could not open file. Error: {:?}", e);
+            return;
+        }
+        Ok(buf) => buf,
+    };
+
+    // Write the coordinates of the crossings in a csv format
+    for point in points {
+        buf.write_fmt(format_args!("{}, {}\n", point[0], point[1])).unwrap();
+    }
+}
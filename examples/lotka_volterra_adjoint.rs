@@ -0,0 +1,72 @@
+// Gradient of the objective
+//
+//      Ψ = ∫ y2 dx + y1(x_end)
+//
+// for the predator-prey model of Lotka and Volterra
+//
+//      y1' =  a y1 - b y1 y2
+//      y2' = -c y2 + d y1 y2
+//
+// with respect to the parameters (a, b, c, d) and the initial populations,
+// computed with the adjoint method.
+
+extern crate ode_solvers;
+use ode_solvers::adjoint::*;
+use ode_solvers::*;
+
+type State = Vector2<f64>;
+type Time = f64;
+
+const A: f64 = 1.5;
+const B: f64 = 1.0;
+const C: f64 = 3.0;
+const D: f64 = 1.0;
+
+fn main() {
+    let y0 = State::new(1.0, 1.0);
+    let dfdp = parameter_derivatives;
+    let mut adjoint = Adjoint::new(system, dfdp, 4, 0.0, 10.0, y0, 1.0e-10, 1.0e-10);
+    adjoint.set_jacobian(jacobian);
+
+    // Running cost y2 and terminal cost y1, with their gradients
+    adjoint.set_running_cost(
+        |_, y| y[1],
+        |_, _, dg_dy| *dg_dy = State::new(0.0, 1.0),
+        |_, _, dg_dp| dg_dp.fill(0.0),
+    );
+    adjoint.set_terminal_cost(|y| y[0], |_, dg_dy| *dg_dy = State::new(1.0, 0.0));
+    let res = adjoint.integrate();
+
+    // Handle result
+    match res {
+        Ok(stats) => {
+            println!("{}", stats);
+            println!("Objective: {}", adjoint.objective());
+            println!("Gradient with respect to (a, b, c, d): {}", adjoint.gradient_p().transpose());
+            println!("Gradient with respect to y0: {}", adjoint.gradient_y0().transpose());
+        }
+        Err(e) => println!("An error occured: {}", e),
+    }
+}
+
+fn system(_t: Time, y: &State, dy: &mut State) {
+    dy[0] = A * y[0] - B * y[0] * y[1];
+    dy[1] = -C * y[1] + D * y[0] * y[1];
+}
+
+// Jacobian matrix of the system with respect to y
+fn jacobian(_t: Time, y: &State, jac: &mut DMatrix<f64>) {
+    jac[(0, 0)] = A - B * y[1];
+    jac[(0, 1)] = -B * y[0];
+    jac[(1, 0)] = D * y[1];
+    jac[(1, 1)] = -C + D * y[0];
+}
+
+// Derivatives of the system with respect to (a, b, c, d)
+fn parameter_derivatives(_t: Time, y: &State, dfdp: &mut DMatrix<f64>) {
+    dfdp.fill(0.0);
+    dfdp[(0, 0)] = y[0];
+    dfdp[(0, 1)] = -y[0] * y[1];
+    dfdp[(1, 2)] = -y[1];
+    dfdp[(1, 3)] = y[0] * y[1];
+}
@@ -0,0 +1,44 @@
+// Predator-prey model of Lotka and Volterra
+//
+//      y1' =  a y1 - b y1 y2
+//      y2' = -c y2 + d y1 y2
+//
+// integrated with Dopri5 on dual numbers. The parameter a is appended to the
+// state with a' = 0 and chosen as the variable, so that the derivative parts of
+// the solution are the sensitivities of the populations with respect to a.
+
+extern crate ode_solvers;
+use ode_solvers::dopri5::*;
+use ode_solvers::dual::Dual;
+use ode_solvers::*;
+
+type State = Vector3<Dual>;
+type Time = f64;
+
+const B: f64 = 1.0;
+const C: f64 = 3.0;
+const D: f64 = 1.0;
+
+fn main() {
+    let y0 = State::new(Dual::constant(1.0), Dual::constant(1.0), Dual::variable(1.5));
+    let mut stepper = Dopri5::new(system, 0.0, 10.0, 0.1, y0, 1.0e-10, 1.0e-10);
+    let res = stepper.integrate();
+
+    // Handle result
+    match res {
+        Ok(stats) => {
+            println!("{}", stats);
+            let y_end = stepper.y_out().last().unwrap();
+            println!("y at x = 10: ({}, {})", y_end[0].re(), y_end[1].re());
+            println!("dy/da at x = 10: ({}, {})", y_end[0].eps(), y_end[1].eps());
+        }
+        Err(e) => println!("An error occured: {}", e),
+    }
+}
+
+fn system(_t: Time, y: &State, dy: &mut State) {
+    let (b, c, d) = (Dual::constant(B), Dual::constant(C), Dual::constant(D));
+    dy[0] = y[2] * y[0] - b * y[0] * y[1];
+    dy[1] = -c * y[1] + d * y[0] * y[1];
+    dy[2] = Dual::constant(0.0);
+}
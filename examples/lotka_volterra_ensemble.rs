@@ -0,0 +1,66 @@
+// Monte Carlo study of the predator-prey model of Lotka and Volterra
+//
+//      y1' =  a y1 - b y1 y2
+//      y2' = -c y2 + d y1 y2
+//
+// with initial populations and parameters drawn uniformly around their nominal
+// values. Each trajectory is reduced to the peak of the predator population.
+// Run with `--features rayon` to integrate the trajectories in parallel.
+
+extern crate ode_solvers;
+extern crate rand;
+use ode_solvers::ensemble::*;
+use ode_solvers::*;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+type State = Vector2<f64>;
+type Time = f64;
+
+const N: usize = 10000;
+
+fn main() {
+    let mut ensemble = Ensemble::new(system, generator, peak_predators, N, 0.0, 10.0);
+    ensemble.set_dx(0.01);
+    let stats = ensemble.integrate();
+    println!("{}", stats);
+
+    let peaks: Vec<f64> = ensemble
+        .results()
+        .iter()
+        .filter_map(|r| r.as_ref().ok())
+        .cloned()
+        .collect();
+    let mean = peaks.iter().sum::<f64>() / peaks.len() as f64;
+    let var = peaks.iter().map(|p| (p - mean).powi(2)).sum::<f64>() / (peaks.len() - 1) as f64;
+    println!("Failed integrations: {}", ensemble.num_failed());
+    println!(
+        "Peak of the predator population: {} +/- {}",
+        mean,
+        var.sqrt()
+    );
+}
+
+fn system(_t: Time, y: &State, p: &DVector<f64>, dy: &mut State) {
+    dy[0] = p[0] * y[0] - p[1] * y[0] * y[1];
+    dy[1] = -p[2] * y[1] + p[3] * y[0] * y[1];
+}
+
+// The random numbers of the i-th trajectory are drawn from a generator seeded
+// with i, so that the results do not depend on the order of execution
+fn generator(i: usize) -> (State, DVector<f64>) {
+    let mut rng = StdRng::seed_from_u64(i as u64);
+    let y0 = State::new(rng.gen_range(0.9, 1.1), rng.gen_range(0.9, 1.1));
+    let p = DVector::from_column_slice(&[
+        rng.gen_range(1.4, 1.6),
+        rng.gen_range(0.9, 1.1),
+        rng.gen_range(2.8, 3.2),
+        rng.gen_range(0.9, 1.1),
+    ]);
+    (y0, p)
+}
+
+fn peak_predators(_i: usize, _x: &[f64], y: &[State]) -> f64 {
+    y.iter().map(|y| y[1]).fold(0.0, f64::max)
+}
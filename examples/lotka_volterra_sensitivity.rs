@@ -0,0 +1,96 @@
+// Predator-prey model of Lotka and Volterra
+//
+//      y1' =  a y1 - b y1 y2
+//      y2' = -c y2 + d y1 y2
+//
+// integrated with Dop853 together with the sensitivities of the populations with
+// respect to the four parameters (a, b, c, d).
+
+extern crate ode_solvers;
+use ode_solvers::dop853::*;
+use ode_solvers::sensitivity::*;
+use ode_solvers::*;
+
+type State = Vector2<f64>;
+type Time = f64;
+
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+const A: f64 = 1.5;
+const B: f64 = 1.0;
+const C: f64 = 3.0;
+const D: f64 = 1.0;
+
+fn main() {
+    let y0 = State::new(1.0, 1.0);
+    let mut stepper = Dop853::new(system, 0.0, 10.0, 0.1, y0, 1.0e-10, 1.0e-10);
+
+    let mut sensitivity = Sensitivity::new(4, parameter_derivatives);
+    sensitivity.set_jacobian(jacobian);
+    stepper.set_sensitivity(sensitivity);
+    let res = stepper.integrate();
+
+    // Handle result
+    match res {
+        Ok(stats) => {
+            println!("{}", stats);
+            let s_end = stepper.s_out().last().unwrap();
+            for (name, s) in ["a", "b", "c", "d"].iter().zip(s_end) {
+                println!("dy/d{} at x = 10: ({}, {})", name, s[0], s[1]);
+            }
+            let path = Path::new("./outputs/lotka_volterra_sensitivity.dat");
+            save(stepper.x_out(), stepper.y_out(), stepper.s_out(), path);
+            println!("Results saved in: {:?}", path);
+        }
+        Err(e) => println!("An error occured: {}", e),
+    }
+}
+
+fn system(_t: Time, y: &State, dy: &mut State) {
+    dy[0] = A * y[0] - B * y[0] * y[1];
+    dy[1] = -C * y[1] + D * y[0] * y[1];
+}
+
+// Jacobian matrix of the system with respect to y
+fn jacobian(_t: Time, y: &State, jac: &mut DMatrix<f64>) {
+    jac[(0, 0)] = A - B * y[1];
+    jac[(0, 1)] = -B * y[0];
+    jac[(1, 0)] = D * y[1];
+    jac[(1, 1)] = -C + D * y[0];
+}
+
+// Derivatives of the system with respect to (a, b, c, d)
+fn parameter_derivatives(_t: Time, y: &State, dfdp: &mut DMatrix<f64>) {
+    dfdp.fill(0.0);
+    dfdp[(0, 0)] = y[0];
+    dfdp[(0, 1)] = -y[0] * y[1];
+    dfdp[(1, 2)] = -y[1];
+    dfdp[(1, 3)] = y[0] * y[1];
+}
+
+pub fn save(times: &[Time], states: &[State], sensitivities: &[Vec<State>], filename: &Path) {
+    // Create or open file
+    let mut buf = match File::create(filename) {
+        Err(e) => {
+            println!("Could not open file. Error: {:?}", e);
+            return;
+        }
+        Ok(buf) => buf,
+    };
+
+    // Write time, state vector and sensitivities in a csv format
+    for (i, state) in states.iter().enumerate() {
+        buf.write_fmt(format_args!("{}", times[i])).unwrap();
+        for val in state.iter() {
+            buf.write_fmt(format_args!(", {}", val)).unwrap();
+        }
+        for s in sensitivities[i].iter() {
+            for val in s.iter() {
+                buf.write_fmt(format_args!(", {}", val)).unwrap();
+            }
+        }
+        buf.write_fmt(format_args!("\n")).unwrap();
+    }
+}
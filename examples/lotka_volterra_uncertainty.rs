@@ -0,0 +1,111 @@
+// Propagation of uncertainties in the predator-prey model of Lotka and Volterra
+//
+//      y1' =  a y1 - b y1 y2
+//      y2' = -c y2 + d y1 y2
+//
+// The uncertainty of the initial populations, Gaussian with a standard deviation
+// of 0.02, is propagated with the unscented transform, and the one of the
+// parameters a ~ U(1.4, 1.6) and c ~ N(3, 0.05) with a polynomial chaos
+// expansion. Both are compared with Monte Carlo ensembles of 10000 trajectories.
+
+extern crate ode_solvers;
+extern crate rand;
+use ode_solvers::ensemble::*;
+use ode_solvers::uncertainty::*;
+use ode_solvers::*;
+
+use rand::distributions::StandardNormal;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+type State = Vector2<f64>;
+type Time = f64;
+
+const N: usize = 10000;
+const SIGMA_Y: f64 = 0.02;
+
+fn main() {
+    // Unscented transform: 5 integrations
+    let covariance = DMatrix::from_diagonal_element(2, 2, SIGMA_Y * SIGMA_Y);
+    let mut ut = UnscentedTransform::new(
+        system,
+        0.0,
+        5.0,
+        0.05,
+        State::new(1.0, 1.0),
+        covariance,
+        1.0e-10,
+        1.0e-10,
+    );
+    let stats = ut.integrate().unwrap();
+    println!("Unscented transform\n{}", stats);
+    let p = ut.covariance_out().last().unwrap();
+    compare(
+        ut.mean_out().last().unwrap(),
+        &State::new(p[(0, 0)].sqrt(), p[(1, 1)].sqrt()),
+        initial_state_generator,
+    );
+
+    // Polynomial chaos expansion of order 4: 25 integrations
+    let mut pce = PolynomialChaos::new(
+        parametric_system,
+        State::new(1.0, 1.0),
+        vec![
+            Distribution::Uniform(1.4, 1.6),
+            Distribution::Normal(3.0, 0.05),
+        ],
+        4,
+        0.0,
+        5.0,
+    );
+    pce.set_dx(0.05);
+    pce.set_tolerances(1.0e-10, 1.0e-10);
+    let stats = pce.integrate().unwrap();
+    println!("\nPolynomial chaos expansion\n{}", stats);
+    let std = pce.variance_out().last().unwrap().map(|v| v.sqrt());
+    compare(pce.mean_out().last().unwrap(), &std, parameter_generator);
+}
+
+// Prints the mean and standard deviation of the final state along with the ones of a
+// Monte Carlo ensemble
+fn compare(mean: &State, std: &State, generator: fn(usize) -> (State, DVector<f64>)) {
+    let mut ensemble = Ensemble::new(parametric_system, generator, |_, _, _| (), N, 0.0, 5.0);
+    ensemble.set_dx(0.05);
+    let mut statistics = Statistics::new(Vec::new());
+    ensemble.accumulate(&mut statistics);
+    let mc_mean = statistics.mean_out().last().unwrap();
+    let mc_std = statistics.variance_out().last().unwrap().map(|v| v.sqrt());
+    println!("Final mean: {:.5}, {:.5}", mean[0], mean[1]);
+    println!("  Monte Carlo: {:.5}, {:.5}", mc_mean[0], mc_mean[1]);
+    println!("Final standard deviation: {:.5}, {:.5}", std[0], std[1]);
+    println!("  Monte Carlo: {:.5}, {:.5}", mc_std[0], mc_std[1]);
+}
+
+fn system(_t: Time, y: &State, dy: &mut State) {
+    dy[0] = 1.5 * y[0] - y[0] * y[1];
+    dy[1] = -3.0 * y[1] + y[0] * y[1];
+}
+
+// Parameters: a, c
+fn parametric_system(_t: Time, y: &State, p: &DVector<f64>, dy: &mut State) {
+    dy[0] = p[0] * y[0] - y[0] * y[1];
+    dy[1] = -p[1] * y[1] + y[0] * y[1];
+}
+
+fn initial_state_generator(i: usize) -> (State, DVector<f64>) {
+    let mut rng = StdRng::seed_from_u64(i as u64);
+    let y0 = State::new(
+        1.0 + SIGMA_Y * rng.sample(StandardNormal),
+        1.0 + SIGMA_Y * rng.sample(StandardNormal),
+    );
+    (y0, DVector::from_column_slice(&[1.5, 3.0]))
+}
+
+fn parameter_generator(i: usize) -> (State, DVector<f64>) {
+    let mut rng = StdRng::seed_from_u64(i as u64);
+    let p = DVector::from_column_slice(&[
+        rng.gen_range(1.4, 1.6),
+        3.0 + 0.05 * rng.sample(StandardNormal),
+    ]);
+    (State::new(1.0, 1.0), p)
+}
@@ -0,0 +1,61 @@
+// Global sensitivity analysis of the SIRS epidemic model
+//
+//      s' = -beta s i + xi r
+//      i' =  beta s i - gamma i
+//      r' =  gamma i - xi r
+//
+// where s, i and r are the fractions of susceptible, infected and recovered
+// people. The transmission rate beta, the recovery rate gamma and the rate of
+// loss of immunity xi are uncertain, and the first-order and total Sobol
+// indices of the peak of infections over a year are estimated.
+
+extern crate ode_solvers;
+use ode_solvers::sobol::*;
+use ode_solvers::uncertainty::Distribution;
+use ode_solvers::*;
+
+type State = Vector3<f64>;
+type Time = f64;
+
+fn main() {
+    let parameters = vec![
+        Distribution::Uniform(0.2, 0.4),
+        Distribution::Uniform(0.05, 0.15),
+        Distribution::Uniform(0.005, 0.02),
+    ];
+    let y0 = State::new(0.999, 0.001, 0.0);
+    let mut sobol = SobolIndices::new(system, y0, parameters, peak_infections, 4096, 0.0, 365.0);
+    sobol.set_dx(0.25);
+    sobol.set_seed(0);
+    match sobol.integrate() {
+        Ok(stats) => {
+            println!("{}", stats);
+
+            println!("Failed samples: {}", sobol.num_failed());
+            println!(
+                "Standard deviation of the peak: {}",
+                sobol.variance().sqrt()
+            );
+            for (k, name) in ["beta", "gamma", "xi"].iter().enumerate() {
+                println!(
+                    "{:>5}: first order {:.3}, total {:.3}",
+                    name,
+                    sobol.first_order()[k],
+                    sobol.total()[k]
+                );
+            }
+        }
+        Err(e) => println!("An error occured: {}", e),
+    }
+}
+
+// Parameters: beta, gamma, xi
+fn system(_t: Time, y: &State, p: &DVector<f64>, dy: &mut State) {
+    dy[0] = -p[0] * y[0] * y[1] + p[2] * y[2];
+    dy[1] = p[0] * y[0] * y[1] - p[1] * y[1];
+    dy[2] = p[1] * y[1] - p[2] * y[2];
+}
+
+fn peak_infections(_i: usize, _t: &[f64], y: &[State]) -> f64 {
+    y.iter().map(|y| y[1]).fold(0.0, f64::max)
+}
@@ -0,0 +1,56 @@
+// Planar Lyapunov orbit around the L1 point of the Earth-Moon system, in the
+// circular restricted three-body problem. The initial state on the section
+// y = 0 and the period are corrected from an approximate guess, keeping the x
+// coordinate fixed to select one orbit of the family. The Floquet multipliers
+// show the instability of the orbit.
+
+extern crate ode_solvers;
+use ode_solvers::periodic::*;
+use ode_solvers::poincare::Direction;
+use ode_solvers::*;
+
+type State = Vector6<f64>;
+type Time = f64;
+
+const MU: f64 = 0.012150585;
+
+fn main() {
+    let y0 = State::new(0.8234, 0.0, 0.0, 0.0, 0.1263, 0.0);
+    let mut orbit = PeriodicOrbit::new(system, section, Direction::Increasing, y0, 2.743, 1.0e-12, 1.0e-12);
+    orbit.set_fixed_components(vec![0]);
+    let res = orbit.solve();
+
+    // Handle result
+    match res {
+        Ok(stats) => {
+            println!("{}", stats);
+            println!("Newton iterations: {}", orbit.iterations());
+            println!("Initial state: {}", orbit.y0());
+            println!("Period: {}", orbit.period());
+            println!("Floquet multipliers:");
+            for (re, im) in orbit.floquet_multipliers() {
+                println!("  {:e} + {:e}i, modulus {:e}", re, im, (re * re + im * im).sqrt());
+            }
+        }
+        Err(e) => println!("An error occured: {}", e),
+    }
+}
+
+fn system(_t: Time, y: &State, dy: &mut State) {
+    let d = ((y[0] + MU).powi(2) + y[1].powi(2) + y[2].powi(2)).sqrt();
+    let r = ((y[0] - 1.0 + MU).powi(2) + y[1].powi(2) + y[2].powi(2)).sqrt();
+
+    dy[0] = y[3];
+    dy[1] = y[4];
+    dy[2] = y[5];
+    dy[3] = y[0] + 2.0 * y[4]
+        - (1.0 - MU) * (y[0] + MU) / d.powi(3)
+        - MU * (y[0] - 1.0 + MU) / r.powi(3);
+    dy[4] = -2.0 * y[3] + y[1] - (1.0 - MU) * y[1] / d.powi(3) - MU * y[1] / r.powi(3);
+    dy[5] = -(1.0 - MU) * y[2] / d.powi(3) - MU * y[2] / r.powi(3);
+}
+
+// Section y = 0, crossed with increasing y
+fn section(y: &State) -> f64 {
+    y[1]
+}
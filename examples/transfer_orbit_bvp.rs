@@ -0,0 +1,105 @@
+// Transfer between two positions in a given time (Lambert's problem) around a
+// central body with a unit gravitational parameter. The boundary conditions fix
+// the position at departure and at arrival, and the velocities are found by
+// single shooting and by multiple shooting from a straight line guess.
+
+extern crate ode_solvers;
+use ode_solvers::bvp::*;
+use ode_solvers::*;
+
+type State = Vector4<f64>;
+type Time = f64;
+
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+const T: f64 = 2.5;
+
+fn main() {
+    let (r0, r1) = ([1.0, 0.0], [0.0, 1.5]);
+    let v = [(r1[0] - r0[0]) / T, (r1[1] - r0[1]) / T];
+
+    // Single shooting
+    let guess = State::new(r0[0], r0[1], v[0], v[1]);
+    let mut single = Shooting::new(system, boundary_conditions, 0.0, T, guess, 1.0e-12, 1.0e-12);
+    single.set_jacobian(jacobian);
+    match single.solve() {
+        Ok(stats) => {
+            println!("Single shooting:\n{}", stats);
+            let y0 = single.node_values()[0];
+            println!("Departure velocity: ({}, {})", y0[2], y0[3]);
+        }
+        Err(e) => println!("An error occured: {}", e),
+    }
+
+    // Multiple shooting with 4 segments along the straight line
+    let nodes: Vec<f64> = (0..5).map(|i| T * i as f64 / 4.0).collect();
+    let guesses = nodes[..4]
+        .iter()
+        .map(|&t| State::new(r0[0] + v[0] * t, r0[1] + v[1] * t, v[0], v[1]))
+        .collect();
+    let mut multiple =
+        Shooting::multiple(system, boundary_conditions, nodes, guesses, 1.0e-12, 1.0e-12);
+    multiple.set_jacobian(jacobian);
+    match multiple.solve() {
+        Ok(stats) => {
+            println!("Multiple shooting:\n{}", stats);
+            let y0 = multiple.node_values()[0];
+            println!("Departure velocity: ({}, {})", y0[2], y0[3]);
+            let path = Path::new("./outputs/transfer_orbit_bvp.dat");
+            save(multiple.x_out(), multiple.y_out(), path);
+            println!("Results saved in: {:?}", path);
+        }
+        Err(e) => println!("An error occured: {}", e),
+    }
+}
+
+fn system(_t: Time, y: &State, dy: &mut State) {
+    let r3 = (y[0] * y[0] + y[1] * y[1]).powf(1.5);
+    dy[0] = y[2];
+    dy[1] = y[3];
+    dy[2] = -y[0] / r3;
+    dy[3] = -y[1] / r3;
+}
+
+fn jacobian(_t: Time, y: &State, jac: &mut DMatrix<f64>) {
+    let r2 = y[0] * y[0] + y[1] * y[1];
+    let r3 = r2.powf(1.5);
+    let r5 = r2 * r3;
+    jac.fill(0.0);
+    jac[(0, 2)] = 1.0;
+    jac[(1, 3)] = 1.0;
+    jac[(2, 0)] = 3.0 * y[0] * y[0] / r5 - 1.0 / r3;
+    jac[(2, 1)] = 3.0 * y[0] * y[1] / r5;
+    jac[(3, 0)] = 3.0 * y[0] * y[1] / r5;
+    jac[(3, 1)] = 3.0 * y[1] * y[1] / r5 - 1.0 / r3;
+}
+
+// Positions at departure and at arrival
+fn boundary_conditions(ya: &State, yb: &State, g: &mut State) {
+    g[0] = ya[0] - 1.0;
+    g[1] = ya[1];
+    g[2] = yb[0];
+    g[3] = yb[1] - 1.5;
+}
+
+pub fn save(times: &[Time], states: &[State], filename: &Path) {
+    // Create or open file
+    let mut buf = match File::create(filename) {
+        Err(e) => {
+            println!("Could not open file. Error: {:?}", e);
+            return;
+        }
+        Ok(buf) => buf,
+    };
+
+    // Write time and state vector in a csv format
+    for (i, state) in states.iter().enumerate() {
+        buf.write_fmt(format_args!("{}", times[i])).unwrap();
+        for val in state.iter() {
+            buf.write_fmt(format_args!(", {}", val)).unwrap();
+        }
+        buf.write_fmt(format_args!("\n")).unwrap();
+    }
+}
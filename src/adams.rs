@@ -0,0 +1,656 @@
+// This code is a Rust adaptation of the codes DE, STEP and INTRP written
+// originally in Fortran by:
+//
+//      L. F. Shampine & M. K. Gordon
+//      Sandia Laboratories, Albuquerque, New Mexico
+//
+// and described in "Computer Solution of Ordinary Differential Equations:
+// the Initial Value Problem", W. H. Freeman, San Francisco, 1975.
+//
+
+#![allow(clippy::needless_range_loop)]
+
+//! Variable step size, variable order (1 to 12) Adams-Bashforth-Moulton method in PECE mode with local extrapolation and dense output.
+
+use alga::linear::{FiniteDimInnerSpace, InnerSpace};
+use dop_shared::*;
+use na;
+use std::f64;
+
+// Maximum order of the method
+const K_MAX: usize = 12;
+
+// Coefficients used in the estimation of the local errors at orders k - 2, k - 1, k and k + 1
+const GSTR: [f64; 13] = [
+    0.500, 0.0833, 0.0417, 0.0264, 0.0188, 0.0143, 0.0114, 0.00936, 0.00789, 0.00679, 0.00592,
+    0.00524, 0.00468,
+];
+
+/// Structure containing the parameters for the numerical integration.
+pub struct Adams<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+{
+    f: fn(f64, &V, &mut V),
+    x: f64,
+    x0: f64,
+    x_old: f64,
+    x_end: f64,
+    xd: f64,
+    dx: f64,
+    y: V,
+    yp: V,
+    rtol: f64,
+    atol: f64,
+    x_out: Vec<f64>,
+    y_out: Vec<V>,
+    uround: f64,
+    h: f64,
+    h_old: f64,
+    n_max: u32,
+    k_max: usize,
+    k: usize,
+    k_old: usize,
+    ns: usize,
+    phase1: bool,
+    nornd: bool,
+    phi: Vec<V>,
+    psi: [f64; 13],
+    alpha: [f64; 13],
+    beta: [f64; 13],
+    sig: [f64; 14],
+    v: [f64; 13],
+    w: [f64; 13],
+    g: [f64; 14],
+    wt: Vec<f64>,
+    out_type: OutputType,
+    stats: Stats,
+}
+
+impl<V> Adams<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: Value,
+{
+    /// Default initializer for the structure.
+    ///
+    /// # Arguments
+    ///
+    /// * `f`       - Pointer to the function to integrate
+    /// * `x`       - Initial value of the independent variable (usually time)
+    /// * `x_end`   - Final value of the independent variable
+    /// * `dx`      - Increment in the dense output. This argument has no effect if the output type is Sparse
+    /// * `y`       - Initial value of the dependent variable(s)
+    /// * `rtol`    - Relative tolerance used in the computation of the adaptive step size
+    /// * `atol`    - Absolute tolerance used in the computation of the adaptive step size
+    ///
+    pub fn new(
+        f: fn(f64, &V, &mut V),
+        x: f64,
+        x_end: f64,
+        dx: f64,
+        y: V,
+        rtol: f64,
+        atol: f64,
+    ) -> Adams<V> {
+        Adams::from_param(
+            f,
+            x,
+            x_end,
+            dx,
+            y,
+            rtol,
+            atol,
+            0.0,
+            100000,
+            K_MAX,
+            OutputType::Dense,
+        )
+    }
+
+    /// Advanced initializer for the structure.
+    ///
+    /// # Arguments
+    ///
+    /// * `f`       - Pointer to the function to integrate
+    /// * `x`       - Initial value of the independent variable (usually time)
+    /// * `x_end`   - Final value of the independent variable
+    /// * `dx`      - Increment in the dense output. This argument has no effect if the output type is Sparse
+    /// * `y`       - Initial value of the dependent variable(s)
+    /// * `rtol`    - Relative tolerance used in the computation of the adaptive step size
+    /// * `atol`    - Absolute tolerance used in the computation of the adaptive step size
+    /// * `h`       - Initial value of the step size. If h = 0.0, the intial value of h is computed automatically
+    /// * `n_max`   - Maximum number of iterations. Default is 100000
+    /// * `k_max`   - Maximum order of the method, between 1 and 12. Default is 12
+    /// * `out_type`    - Type of the output. Must be a variant of the OutputType enum. Default is Dense
+    ///
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_param(
+        f: fn(f64, &V, &mut V),
+        x: f64,
+        x_end: f64,
+        dx: f64,
+        y: V,
+        rtol: f64,
+        atol: f64,
+        h: f64,
+        n_max: u32,
+        k_max: usize,
+        out_type: OutputType,
+    ) -> Adams<V> {
+        Adams {
+            f,
+            x,
+            x0: x,
+            x_old: x,
+            x_end,
+            xd: x,
+            dx,
+            y,
+            yp: V::zero(),
+            rtol,
+            atol,
+            x_out: Vec::<f64>::new(),
+            y_out: Vec::<V>::new(),
+            uround: f64::EPSILON,
+            h,
+            h_old: 0.0,
+            n_max,
+            k_max: K_MAX.min(k_max.max(1)),
+            k: 1,
+            k_old: 0,
+            ns: 0,
+            phase1: true,
+            nornd: true,
+            phi: vec![V::zero(); 17],
+            psi: [0.0; 13],
+            alpha: [0.0; 13],
+            beta: [0.0; 13],
+            sig: [0.0; 14],
+            v: [0.0; 13],
+            w: [0.0; 13],
+            g: [0.0; 14],
+            wt: Vec::new(),
+            out_type,
+            stats: Stats::new(),
+        }
+    }
+
+    /// Core integration method.
+    pub fn integrate(&mut self) -> Result<Stats, IntegrationError> {
+        // Initialization
+        let dim = na::dimension::<V>();
+        let posneg = sign(1.0, self.x_end - self.x);
+        let fouru = 4.0 * self.uround;
+        let mut n_step = 0;
+        let mut start = true;
+        self.wt = vec![0.0; dim];
+        self.x_old = self.x;
+        if self.h == 0.0 {
+            self.h = self.x_end - self.x;
+        }
+        self.h = sign(self.h.abs().max(fouru * self.x.abs()), posneg);
+
+        // Save initial values
+        let y_tmp = self.y;
+        self.solution_output(y_tmp);
+
+        loop {
+            // The integration cannot go past x_end
+            self.h = sign(self.h.abs().min((self.x_end - self.x).abs()), self.h);
+
+            // Check if step number is within allowed range
+            if n_step >= self.n_max {
+                return Err(IntegrationError::MaxNumStepReached { x: self.x, n_step });
+            }
+            n_step += 1;
+
+            for i in 0..dim {
+                let y_i = self.y[i].value();
+                self.wt[i] = self.rtol * y_i.abs() + self.atol;
+            }
+
+            self.step(start)?;
+            start = false;
+
+            // Normal exit
+            let last = (self.x_end - self.x).abs() <= fouru * self.x.abs();
+            if last {
+                self.x = self.x_end;
+            }
+            let y_tmp = self.y;
+            self.solution_output(y_tmp);
+            if last {
+                return Ok(self.stats);
+            }
+        }
+    }
+
+    /// Integrates the system over one step with the local error tolerance 1 in the norm weighted by
+    /// wt. Failed attempts are repeated with a smaller step size and possibly a lower order.
+    fn step(&mut self, start: bool) -> Result<(), IntegrationError> {
+        let dim = na::dimension::<V>();
+        let twou = 2.0 * self.uround;
+        let fouru = 4.0 * self.uround;
+        let eps = 1.0;
+        let p5eps = 0.5 * eps;
+
+        if self.h.abs() < fouru * self.x.abs() {
+            return Err(IntegrationError::StepSizeUnderflow { x: self.x });
+        }
+
+        // If the error tolerance is too small, roundoff prevents any progress
+        let mut round = 0.0;
+        for i in 0..dim {
+            let y_i = self.y[i].value();
+            round += (y_i / self.wt[i]).powi(2);
+        }
+        round = twou * round.sqrt();
+        if p5eps < round {
+            return Err(IntegrationError::StepSizeUnderflow { x: self.x });
+        }
+
+        self.g[1] = 1.0;
+        self.g[2] = 0.5;
+        self.sig[1] = 1.0;
+
+        if start {
+            // Initialize and compute the starting step size
+            (self.f)(self.x, &self.y, &mut self.yp);
+            self.stats.num_eval += 1;
+            self.phi[1] = self.yp;
+            self.phi[2] = V::zero();
+            let sum = self.norm(&self.yp);
+            let mut abs_h = self.h.abs();
+            if eps < 16.0 * sum * self.h * self.h {
+                abs_h = 0.25 * (eps / sum).sqrt();
+            }
+            self.h = sign(abs_h.max(fouru * self.x.abs()), self.h);
+            self.h_old = 0.0;
+            self.k = 1;
+            self.k_old = 0;
+            self.phase1 = true;
+            self.nornd = true;
+            if p5eps <= 100.0 * round {
+                self.nornd = false;
+                self.phi[15] = V::zero();
+            }
+        }
+
+        let mut ifail = 0;
+        loop {
+            let k = self.k;
+            let (km1, km2, kp1, kp2) = (k as isize - 1, k as isize - 2, k + 1, k + 2);
+
+            // Compute the coefficients of the formulas for this step. The coefficients that
+            // depend only on the step sizes are not recomputed while the step size is constant.
+            if self.h != self.h_old {
+                self.ns = 0;
+            }
+            if self.ns <= self.k_old {
+                self.ns += 1;
+            }
+            let ns = self.ns;
+            let nsp1 = ns + 1;
+
+            if k >= ns {
+                self.beta[ns] = 1.0;
+                self.alpha[ns] = 1.0 / ns as f64;
+                let mut temp1 = self.h * ns as f64;
+                self.sig[nsp1] = 1.0;
+                for i in nsp1..=k {
+                    let temp2 = self.psi[i - 1];
+                    self.psi[i - 1] = temp1;
+                    self.beta[i] = self.beta[i - 1] * self.psi[i - 1] / temp2;
+                    temp1 = temp2 + self.h;
+                    self.alpha[i] = self.h / temp1;
+                    self.sig[i + 1] = i as f64 * self.alpha[i] * self.sig[i];
+                }
+                self.psi[k] = temp1;
+
+                // Compute the integration coefficients g
+                if ns <= 1 {
+                    for iq in 1..=k {
+                        self.v[iq] = 1.0 / (iq * (iq + 1)) as f64;
+                        self.w[iq] = self.v[iq];
+                    }
+                } else {
+                    if k > self.k_old {
+                        self.v[k] = 1.0 / (k * kp1) as f64;
+                        for j in 1..ns.saturating_sub(1) {
+                            let i = k - j;
+                            self.v[i] -= self.alpha[j + 1] * self.v[i + 1];
+                        }
+                    }
+                    let temp5 = self.alpha[ns];
+                    for iq in 1..=kp1 - ns {
+                        self.v[iq] -= temp5 * self.v[iq + 1];
+                        self.w[iq] = self.v[iq];
+                    }
+                    self.g[nsp1] = self.w[1];
+                }
+                for i in ns + 2..=kp1 {
+                    let temp6 = self.alpha[i - 1];
+                    for iq in 1..=kp2 - i {
+                        self.w[iq] -= temp6 * self.w[iq + 1];
+                    }
+                    self.g[i] = self.w[1];
+                }
+            }
+
+            // Change phi to phi star
+            for i in nsp1..=k {
+                self.phi[i] *= na::convert(self.beta[i]);
+            }
+
+            // Predict the solution and the differences
+            self.phi[kp2] = self.phi[kp1];
+            self.phi[kp1] = V::zero();
+            let mut p = V::zero();
+            for i in (1..=k).rev() {
+                p += self.phi[i] * na::convert(self.g[i]);
+                let phi_ip1 = self.phi[i + 1];
+                self.phi[i] += phi_ip1;
+            }
+            if self.nornd {
+                p = self.y + p * na::convert(self.h);
+            } else {
+                let tau = p * na::convert(self.h) - self.phi[15];
+                p = self.y + tau;
+                self.phi[16] = (p - self.y) - tau;
+            }
+            let x_old = self.x;
+            self.x += self.h;
+            let abs_h = self.h.abs();
+            (self.f)(self.x, &p, &mut self.yp);
+            self.stats.num_eval += 1;
+
+            // Estimate the errors at orders k, k-1 and k-2
+            let mut erkm2 = 0.0;
+            let mut erkm1 = 0.0;
+            let mut erk = 0.0;
+            for i in 0..dim {
+                let temp3 = 1.0 / self.wt[i];
+                let temp4 = (self.yp[i] - self.phi[1][i]).value();
+                if km2 > 0 {
+                    let phi_i = self.phi[k - 1][i].value();
+                    erkm2 += ((phi_i + temp4) * temp3).powi(2);
+                }
+                if km2 >= 0 {
+                    let phi_i = self.phi[k][i].value();
+                    erkm1 += ((phi_i + temp4) * temp3).powi(2);
+                }
+                erk += (temp4 * temp3).powi(2);
+            }
+            if km2 > 0 {
+                erkm2 = abs_h * self.sig[k - 1] * GSTR[k - 3] * erkm2.sqrt();
+            }
+            if km2 >= 0 {
+                erkm1 = abs_h * self.sig[k] * GSTR[k - 2] * erkm1.sqrt();
+            }
+            let temp5 = abs_h * erk.sqrt();
+            let err = temp5 * (self.g[k] - self.g[kp1]);
+            erk = temp5 * self.sig[kp1] * GSTR[k - 1];
+            let mut k_new = k;
+
+            // Test if the order should be lowered
+            if km2 > 0 {
+                if erkm1.max(erkm2) <= erk {
+                    k_new = k - 1;
+                }
+            } else if km2 == 0 && erkm1 <= 0.5 * erk {
+                k_new = k - 1;
+            }
+
+            if err > eps {
+                // The step is rejected: restore x, phi and psi
+                self.phase1 = false;
+                self.x = x_old;
+                for i in 1..=k {
+                    let phi_ip1 = self.phi[i + 1];
+                    self.phi[i] = (self.phi[i] - phi_ip1) * na::convert(1.0 / self.beta[i]);
+                }
+                for i in 2..=k {
+                    self.psi[i - 1] = self.psi[i] - self.h;
+                }
+                if self.stats.accepted_steps >= 1 {
+                    self.stats.rejected_steps += 1;
+                }
+
+                // On the third failure, set the order to one. Thereafter, use the optimal step size.
+                ifail += 1;
+                let mut temp2 = 0.5;
+                if ifail > 3 && p5eps < 0.25 * erk {
+                    temp2 = (p5eps / erk).sqrt();
+                }
+                if ifail >= 3 {
+                    k_new = 1;
+                }
+                self.h *= temp2;
+                self.k = k_new;
+                if self.h.abs() < fouru * self.x.abs() {
+                    return Err(IntegrationError::StepSizeUnderflow { x: self.x });
+                }
+                continue;
+            }
+
+            // The step is accepted: correct and evaluate
+            self.stats.accepted_steps += 1;
+            self.x_old = x_old;
+            self.h_old = self.h;
+            self.k_old = k;
+            let temp1 = self.h * self.g[kp1];
+            if self.nornd {
+                self.y = p + (self.yp - self.phi[1]) * na::convert(temp1);
+            } else {
+                let rho = (self.yp - self.phi[1]) * na::convert(temp1) - self.phi[16];
+                self.y = p + rho;
+                self.phi[15] = (self.y - p) - rho;
+            }
+            (self.f)(self.x, &self.y, &mut self.yp);
+            self.stats.num_eval += 1;
+
+            // Update the differences for the next step
+            self.phi[kp1] = self.yp - self.phi[1];
+            self.phi[kp2] = self.phi[kp1] - self.phi[kp2];
+            for i in 1..=k {
+                let phi_kp1 = self.phi[kp1];
+                self.phi[i] += phi_kp1;
+            }
+
+            // Estimate the error at order k+1 unless the order is being lowered, the order is
+            // already maximal or the step size has not been constant long enough. In the first
+            // phase, the order is raised at every step.
+            let mut erkp1 = 0.0;
+            if k_new as isize == km1 || k == self.k_max {
+                self.phase1 = false;
+            }
+            if self.phase1 {
+                self.k = kp1;
+                erk = erkp1;
+            } else if k_new as isize == km1 {
+                self.k = k - 1;
+                erk = erkm1;
+            } else if kp1 <= ns {
+                for i in 0..dim {
+                    let phi_i = self.phi[kp2][i].value();
+                    erkp1 += (phi_i / self.wt[i]).powi(2);
+                }
+                erkp1 = abs_h * GSTR[kp1 - 1] * erkp1.sqrt();
+
+                // Using the estimated error at order k+1, determine the appropriate order
+                if k > 1 {
+                    if erkm1 <= erk.min(erkp1) {
+                        self.k = k - 1;
+                        erk = erkm1;
+                    } else if erkp1 < erk && k != self.k_max {
+                        self.k = kp1;
+                        erk = erkp1;
+                    }
+                } else if erkp1 < 0.5 * erk && k != self.k_max {
+                    self.k = kp1;
+                    erk = erkp1;
+                }
+            }
+
+            // Double the step size if possible, otherwise compute the new step size
+            let mut h_new = self.h + self.h;
+            if !self.phase1 && p5eps < erk * 2.0_f64.powi(self.k as i32 + 1) {
+                h_new = self.h;
+                if p5eps < erk {
+                    let r = (p5eps / erk).powf(1.0 / (self.k + 1) as f64);
+                    h_new = abs_h * 0.5_f64.max(r.min(0.9));
+                    h_new = sign(h_new.max(fouru * self.x.abs()), self.h);
+                }
+            }
+            self.h = h_new;
+            return Ok(());
+        }
+    }
+
+    /// Computes the solution at xd by interpolation of the Adams polynomial of the last step.
+    fn interpolate(&self, xd: f64) -> V {
+        let hi = xd - self.x;
+        let ki = self.k_old + 1;
+        let mut w = [0.0; 14];
+        let mut g = [0.0; 14];
+        for i in 1..=ki {
+            w[i] = 1.0 / i as f64;
+        }
+        g[1] = 1.0;
+        let mut term = 0.0;
+        for j in 2..=ki {
+            let psi_jm1 = self.psi[j - 1];
+            let gamma = (hi + term) / psi_jm1;
+            let eta = hi / psi_jm1;
+            for i in 1..=ki + 1 - j {
+                w[i] = gamma * w[i] - eta * w[i + 1];
+            }
+            g[j] = w[1];
+            term = psi_jm1;
+        }
+
+        let mut y = V::zero();
+        for i in (1..=ki).rev() {
+            y += self.phi[i] * na::convert(g[i]);
+        }
+        self.y + y * na::convert(hi)
+    }
+
+    /// Computes the weighted norm of a vector.
+    fn norm(&self, v: &V) -> f64 {
+        let mut sum = 0.0;
+        for i in 0..na::dimension::<V>() {
+            let v_i = v[i].value();
+            sum += (v_i / self.wt[i]).powi(2);
+        }
+        sum.sqrt()
+    }
+
+    /// If a dense output is required, computes the solution and pushes it into the output vector. Else, pushes the solution into the output vector.
+    fn solution_output(&mut self, y_next: V) {
+        if self.out_type == OutputType::Dense {
+            let posneg = sign(1.0, self.x_end - self.x0);
+            if self.x == self.x0 {
+                self.x_out.push(self.x0);
+                self.y_out.push(y_next);
+                self.xd += self.dx;
+            } else {
+                while (self.xd - self.x) * posneg <= 0.0 {
+                    let y = self.interpolate(self.xd);
+                    self.x_out.push(self.xd);
+                    self.y_out.push(y);
+                    self.xd += self.dx;
+                }
+            }
+        } else {
+            self.x_out.push(self.x);
+            self.y_out.push(y_next);
+        }
+    }
+
+    /// Getter for the independent variable's output.
+    pub fn x_out(&self) -> &Vec<f64> {
+        &self.x_out
+    }
+
+    /// Getter for the dependent variables' output.
+    pub fn y_out(&self) -> &Vec<V> {
+        &self.y_out
+    }
+}
+
+fn sign(a: f64, b: f64) -> f64 {
+    if b > 0.0 {
+        a.abs()
+    } else {
+        -a.abs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use na::Vector2;
+
+    fn oscillator(_x: f64, y: &Vector2<f64>, dy: &mut Vector2<f64>) {
+        dy[0] = y[1];
+        dy[1] = -y[0];
+    }
+
+    fn integrate(x_end: f64, tol: f64, k_max: usize) -> (Stats, f64, Vector2<f64>) {
+        let y0 = Vector2::new(1.0, 0.0);
+        let mut stepper = Adams::from_param(
+            oscillator,
+            0.0,
+            x_end,
+            x_end,
+            y0,
+            tol,
+            tol,
+            0.0,
+            1000000,
+            k_max,
+            OutputType::Sparse,
+        );
+        let stats = stepper.integrate().unwrap();
+        let x = *stepper.x_out().last().unwrap();
+        let y = *stepper.y_out().last().unwrap();
+        (stats, x, y)
+    }
+
+    #[test]
+    fn oscillator_forward_and_backward() {
+        for &x_end in &[10.0, -10.0] {
+            let (_, x, y) = integrate(x_end, 1e-10, 12);
+            assert_eq!(x, x_end);
+            let exact = Vector2::new(x_end.cos(), -x_end.sin());
+            assert!((y - exact).norm() < 1e-7);
+        }
+    }
+
+    #[test]
+    fn oscillator_dense_output() {
+        let y0 = Vector2::new(1.0, 0.0);
+        let mut stepper = Adams::new(oscillator, 0.0, 10.0, 0.5, y0, 1e-10, 1e-10);
+        stepper.integrate().unwrap();
+        assert_eq!(stepper.x_out().len(), 21);
+        for (x, y) in stepper.x_out().iter().zip(stepper.y_out()) {
+            assert!((y - Vector2::new(x.cos(), -x.sin())).norm() < 1e-7);
+        }
+    }
+
+    #[test]
+    fn maximal_order_one() {
+        // At order one, the number of steps grows as the inverse of the square root of the
+        // tolerance. It grows much more slowly at higher orders.
+        let (coarse, x, y) = integrate(10.0, 1e-6, 1);
+        assert_eq!(x, 10.0);
+        assert!((y - Vector2::new(10.0_f64.cos(), -10.0_f64.sin())).norm() < 1e-3);
+        let (fine, _, _) = integrate(10.0, 1e-8, 1);
+        let ratio = fine.accepted_steps as f64 / coarse.accepted_steps as f64;
+        assert!(ratio > 7.0 && ratio < 14.0);
+        let (order_two, _, _) = integrate(10.0, 1e-8, 2);
+        assert!(order_two.accepted_steps * 5 < fine.accepted_steps);
+    }
+}
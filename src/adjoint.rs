@@ -0,0 +1,578 @@
+#![allow(clippy::needless_range_loop)]
+
+//! Continuous adjoint sensitivity analysis.
+//!
+//! The gradient of the objective _Ψ = ∫ g(x, y, p) dx + G(y(x<sub>end</sub>))_ with respect to the
+//! parameters p and the initial value y0 is obtained with two integrations. The solution is first
+//! integrated forward with Dopri5 or Dop853, which store its continuous extension. The adjoint
+//! equations
+//!
+//! _λ' = -J(x, y)<sup>T</sup> λ - (∂g/∂y)<sup>T</sup>,  λ(x<sub>end</sub>) = (∂G/∂y)<sup>T</sup>_
+//!
+//! _μ' = -(∂f/∂p)<sup>T</sup> λ - (∂g/∂p)<sup>T</sup>,  μ(x<sub>end</sub>) = 0_
+//!
+//! are then solved backward, giving dΨ/dy0 = λ(x0) and dΨ/dp = μ(x0). The equation of λ is
+//! integrated with the same method, which evaluates the forward solution through its continuous
+//! extension. The integrals giving μ and the running cost are computed with a Gauss quadrature on
+//! every step of this integration. The cost of the backward integration does not depend on the
+//! number of parameters.
+
+use alga::general::SubsetOf;
+use alga::linear::{FiniteDimInnerSpace, InnerSpace};
+use dop853::Dop853;
+use dop_shared::*;
+use dopri5::Dopri5;
+use jacobian;
+use na;
+use na::{DMatrix, DVector};
+use sensitivity::Derivative;
+use std::rc::Rc;
+
+// Nodes and weights of the Gauss quadrature with five points on [0, 1], whose order 10 is above
+// the order of both methods
+const GAUSS_NODES: [f64; 5] = [
+    0.046910077030668,
+    0.23076534494715845,
+    0.5,
+    0.7692346550528415,
+    0.953089922969332,
+];
+const GAUSS_WEIGHTS: [f64; 5] = [
+    0.11846344252809454,
+    0.23931433524968324,
+    64.0 / 225.0,
+    0.23931433524968324,
+    0.11846344252809454,
+];
+
+/// Enumeration of the methods integrating the solution and the adjoint equations.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Method {
+    Dopri5,
+    Dop853,
+}
+
+/// Running cost g and its gradients with respect to y and p, for a system with or without
+/// parameters.
+#[derive(Clone, Copy)]
+enum RunningCost<V> {
+    Plain(
+        fn(f64, &V) -> f64,
+        fn(f64, &V, &mut V),
+        fn(f64, &V, &mut DVector<f64>),
+    ),
+    Parametric(
+        fn(f64, &V, &DVector<f64>) -> f64,
+        fn(f64, &V, &DVector<f64>, &mut V),
+        fn(f64, &V, &DVector<f64>, &mut DVector<f64>),
+    ),
+}
+
+impl<V> RunningCost<V> {
+    fn value(&self, x: f64, y: &V, p: &DVector<f64>) -> f64 {
+        match self {
+            RunningCost::Plain(g, _, _) => g(x, y),
+            RunningCost::Parametric(g, _, _) => g(x, y, p),
+        }
+    }
+
+    fn gradient_y(&self, x: f64, y: &V, p: &DVector<f64>, dg_dy: &mut V) {
+        match self {
+            RunningCost::Plain(_, g, _) => g(x, y, dg_dy),
+            RunningCost::Parametric(_, g, _) => g(x, y, p, dg_dy),
+        }
+    }
+
+    fn gradient_p(&self, x: f64, y: &V, p: &DVector<f64>, dg_dp: &mut DVector<f64>) {
+        match self {
+            RunningCost::Plain(_, _, g) => g(x, y, dg_dp),
+            RunningCost::Parametric(_, _, g) => g(x, y, p, dg_dp),
+        }
+    }
+}
+
+/// Structure containing the parameters of the objective and of the numerical integration.
+pub struct Adjoint<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+{
+    f: Rc<System<V>>,
+    p: DVector<f64>,
+    dfdp: Derivative<V>,
+    n_p: usize,
+    jac: Option<Derivative<V>>,
+    cost: Option<RunningCost<V>>,
+    terminal_cost: Option<fn(&V) -> f64>,
+    dterminal_cost_dy: Option<fn(&V, &mut V)>,
+    x0: f64,
+    x_end: f64,
+    y0: V,
+    rtol: f64,
+    atol: f64,
+    n_max: u32,
+    method: Method,
+    dfdp_mat: DMatrix<f64>,
+    objective: f64,
+    y_end: V,
+    gradient_y0: V,
+    gradient_p: DVector<f64>,
+    stats: Stats,
+}
+
+impl<V> Adjoint<V>
+where
+    V: FiniteDimInnerSpace + Copy + 'static,
+    <V as InnerSpace>::Real: SubsetOf<f64>,
+{
+    /// Default initializer for the structure. The objective is zero until a running or a terminal
+    /// cost is set.
+    ///
+    /// # Arguments
+    ///
+    /// * `f`       - Pointer to the function to integrate
+    /// * `dfdp`    - Function computing the n×n<sub>p</sub> matrix ∂f/∂p at (x, y)
+    /// * `n_p`     - Number of parameters
+    /// * `x`       - Initial value of the independent variable (usually time)
+    /// * `x_end`   - Final value of the independent variable
+    /// * `y`       - Initial value of the dependent variable(s)
+    /// * `rtol`    - Relative tolerance used in the computation of the adaptive step size
+    /// * `atol`    - Absolute tolerance used in the computation of the adaptive step size
+    ///
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        f: fn(f64, &V, &mut V),
+        dfdp: fn(f64, &V, &mut DMatrix<f64>),
+        n_p: usize,
+        x: f64,
+        x_end: f64,
+        y: V,
+        rtol: f64,
+        atol: f64,
+    ) -> Adjoint<V> {
+        let (f, dfdp) = (System::Plain(f), Derivative::Plain(dfdp));
+        Adjoint::from_system(f, dfdp, n_p, x, x_end, y, rtol, atol)
+    }
+
+    /// Initializer for a system depending on parameters p, which are passed to f and to `dfdp`
+    /// at every evaluation. The gradient is computed with respect to p. The other arguments are
+    /// the same as in `new`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn with_parameters(
+        f: fn(f64, &V, &DVector<f64>, &mut V),
+        p: DVector<f64>,
+        dfdp: fn(f64, &V, &DVector<f64>, &mut DMatrix<f64>),
+        x: f64,
+        x_end: f64,
+        y: V,
+        rtol: f64,
+        atol: f64,
+    ) -> Adjoint<V> {
+        let n_p = p.len();
+        let (f, dfdp) = (System::Parametric(f, p), Derivative::Parametric(dfdp));
+        Adjoint::from_system(f, dfdp, n_p, x, x_end, y, rtol, atol)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn from_system(
+        f: System<V>,
+        dfdp: Derivative<V>,
+        n_p: usize,
+        x: f64,
+        x_end: f64,
+        y: V,
+        rtol: f64,
+        atol: f64,
+    ) -> Adjoint<V> {
+        let dim = na::dimension::<V>();
+        Adjoint {
+            p: f.parameters().cloned().unwrap_or_else(|| DVector::zeros(0)),
+            f: Rc::new(f),
+            dfdp,
+            n_p,
+            jac: None,
+            cost: None,
+            terminal_cost: None,
+            dterminal_cost_dy: None,
+            x0: x,
+            x_end,
+            y0: y,
+            rtol,
+            atol,
+            n_max: 100000,
+            method: Method::Dopri5,
+            dfdp_mat: DMatrix::zeros(dim, n_p),
+            objective: 0.0,
+            y_end: y,
+            gradient_y0: V::zero(),
+            gradient_p: DVector::zeros(n_p),
+            stats: Stats::new(),
+        }
+    }
+
+    /// Sets the function computing the Jacobian matrix of f with respect to y. If no Jacobian is
+    /// given, it is approximated by finite differences, which limits the accuracy of the gradient
+    /// to about the square root of the machine precision.
+    pub fn set_jacobian(&mut self, jac: fn(f64, &V, &mut DMatrix<f64>)) {
+        self.jac = Some(Derivative::Plain(jac));
+    }
+
+    /// Sets the function computing the Jacobian matrix of f with respect to y for p.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the system is not created with `with_parameters`.
+    pub fn set_parametric_jacobian(&mut self, jac: fn(f64, &V, &DVector<f64>, &mut DMatrix<f64>)) {
+        self.assert_parametric();
+        self.jac = Some(Derivative::Parametric(jac));
+    }
+
+    /// Sets the running cost g(x, y) integrated over the interval, together with its gradients
+    /// with respect to y and p.
+    pub fn set_running_cost(
+        &mut self,
+        cost: fn(f64, &V) -> f64,
+        dcost_dy: fn(f64, &V, &mut V),
+        dcost_dp: fn(f64, &V, &mut DVector<f64>),
+    ) {
+        self.cost = Some(RunningCost::Plain(cost, dcost_dy, dcost_dp));
+    }
+
+    /// Sets the running cost g(x, y, p) integrated over the interval, together with its gradients
+    /// with respect to y and p.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the system is not created with `with_parameters`.
+    pub fn set_parametric_running_cost(
+        &mut self,
+        cost: fn(f64, &V, &DVector<f64>) -> f64,
+        dcost_dy: fn(f64, &V, &DVector<f64>, &mut V),
+        dcost_dp: fn(f64, &V, &DVector<f64>, &mut DVector<f64>),
+    ) {
+        self.assert_parametric();
+        self.cost = Some(RunningCost::Parametric(cost, dcost_dy, dcost_dp));
+    }
+
+    fn assert_parametric(&self) {
+        assert!(
+            self.f.parameters().is_some(),
+            "parametric functions need a system created with with_parameters"
+        );
+    }
+
+    /// Sets the terminal cost G(y(x_end)) together with its gradient with respect to y.
+    pub fn set_terminal_cost(&mut self, cost: fn(&V) -> f64, dcost_dy: fn(&V, &mut V)) {
+        self.terminal_cost = Some(cost);
+        self.dterminal_cost_dy = Some(dcost_dy);
+    }
+
+    /// Sets the maximum number of steps of each of the two integrations. Default is 100000.
+    pub fn set_n_max(&mut self, n_max: u32) {
+        self.n_max = n_max;
+    }
+
+    /// Sets the method of the two integrations. Default is `Method::Dopri5`.
+    pub fn set_method(&mut self, method: Method) {
+        self.method = method;
+    }
+
+    /// Integrates the solution forward and the adjoint equations backward. Without a Jacobian,
+    /// every evaluation of the adjoint equations counts as dim + 1 evaluations of f.
+    pub fn integrate(&mut self) -> Result<Stats, IntegrationError> {
+        let f = Rc::clone(&self.f);
+        let system = System::Closure(Box::new(move |x: f64, y: &V, dy: &mut V| f.eval(x, y, dy)));
+        let (y_end, solution, stats) = self.solve(system, self.x0, self.x_end, self.y0)?;
+        self.stats += stats;
+        self.y_end = y_end;
+        let solution = Rc::new(solution);
+
+        // Integrate λ backward, from its value at x_end given by the terminal cost
+        let mut lambda = V::zero();
+        if let Some(dcost_dy) = self.dterminal_cost_dy {
+            dcost_dy(&self.y_end, &mut lambda);
+        }
+        let (f, p, jac, cost) = (Rc::clone(&self.f), self.p.clone(), self.jac, self.cost);
+        let forward_solution = Rc::clone(&solution);
+        let rhs = move |x: f64, lambda: &V, dlambda: &mut V| {
+            let y = forward_solution.y(x);
+            adjoint_derivatives(&f, &p, jac, cost, x, &y, lambda, dlambda);
+        };
+        let system = System::Closure(Box::new(rhs));
+        let (gradient_y0, adjoint, mut stats) = self.solve(system, self.x_end, self.x0, lambda)?;
+        if self.jac.is_none() {
+            // Every evaluation approximates the Jacobian matrix by finite differences
+            stats.num_eval *= na::dimension::<V>() as u32 + 1;
+        }
+        self.stats += stats;
+        self.gradient_y0 = gradient_y0;
+
+        self.quadrature(&solution, &adjoint);
+        Ok(self.stats)
+    }
+
+    /// Integrates f from (x, y) to x_end with the chosen method. Returns the solution at x_end
+    /// and the continuous extension of the solution.
+    fn solve(
+        &self,
+        f: System<V>,
+        x: f64,
+        x_end: f64,
+        y: V,
+    ) -> Result<(V, ContinuousOutput<V>, Stats), IntegrationError> {
+        let (rtol, atol, n_max) = (self.rtol, self.atol, self.n_max);
+        match self.method {
+            Method::Dopri5 => {
+                let mut stepper = Dopri5::sparse(f, x, x_end, y, rtol, atol, n_max);
+                stepper.set_continuous_output(true);
+                let stats = stepper.integrate()?;
+                let y_end = *stepper.y_out().last().unwrap();
+                Ok((y_end, stepper.take_continuous_output().unwrap(), stats))
+            }
+            Method::Dop853 => {
+                let mut stepper = Dop853::sparse(f, x, x_end, y, rtol, atol, n_max);
+                stepper.set_continuous_output(true);
+                let stats = stepper.integrate()?;
+                let y_end = *stepper.y_out().last().unwrap();
+                Ok((y_end, stepper.take_continuous_output().unwrap(), stats))
+            }
+        }
+    }
+
+    /// Computes μ(x0) and the integral of the running cost over the steps of the backward
+    /// integration, given the continuous extensions of the solution and of λ.
+    fn quadrature(&mut self, solution: &ContinuousOutput<V>, adjoint: &ContinuousOutput<V>) {
+        let mut mu = DVector::zeros(self.n_p);
+        let mut q = 0.0;
+        let mut dmu = DVector::zeros(self.n_p);
+        for i in 0..adjoint.num_steps() {
+            let (a, b) = adjoint.step(i);
+            let h = b - a;
+            for (node, weight) in GAUSS_NODES.iter().zip(GAUSS_WEIGHTS.iter()) {
+                let x = a + node * h;
+                let dq = self.quadrature_rhs(x, &solution.y(x), &adjoint.y(x), &mut dmu);
+                mu += &dmu * (weight * h);
+                q += dq * weight * h;
+            }
+        }
+        self.gradient_p = mu;
+        self.objective = q + self.terminal_cost.map_or(0.0, |cost| cost(&self.y_end));
+    }
+
+    /// Computes the derivative of μ at (x, y). Returns the derivative of the integral of the
+    /// running cost.
+    fn quadrature_rhs(&mut self, x: f64, y: &V, lambda: &V, dmu: &mut DVector<f64>) -> f64 {
+        self.dfdp.eval(x, y, &self.p, &mut self.dfdp_mat);
+        *dmu = -self.dfdp_mat.tr_mul(&jacobian::to_dvector(lambda));
+        let mut dq = 0.0;
+        if let Some(cost) = self.cost {
+            let mut gp = DVector::zeros(self.n_p);
+            cost.gradient_p(x, y, &self.p, &mut gp);
+            *dmu -= gp;
+            dq = -cost.value(x, y, &self.p);
+        }
+        dq
+    }
+
+    /// Value of the objective.
+    pub fn objective(&self) -> f64 {
+        self.objective
+    }
+
+    /// Gradient of the objective with respect to the initial value.
+    pub fn gradient_y0(&self) -> &V {
+        &self.gradient_y0
+    }
+
+    /// Gradient of the objective with respect to the parameters.
+    pub fn gradient_p(&self) -> &DVector<f64> {
+        &self.gradient_p
+    }
+
+    /// Value of the solution at x_end.
+    pub fn y_end(&self) -> &V {
+        &self.y_end
+    }
+}
+
+/// Computes the derivatives of λ at (x, y).
+#[allow(clippy::too_many_arguments)]
+fn adjoint_derivatives<V>(
+    f: &System<V>,
+    p: &DVector<f64>,
+    jac: Option<Derivative<V>>,
+    cost: Option<RunningCost<V>>,
+    x: f64,
+    y: &V,
+    lambda: &V,
+    dlambda: &mut V,
+) where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: SubsetOf<f64>,
+{
+    let dim = na::dimension::<V>();
+    let mut jac_mat = DMatrix::zeros(dim, dim);
+    match jac {
+        Some(jac) => jac.eval(x, y, p, &mut jac_mat),
+        None => {
+            let mut f0 = V::zero();
+            f.eval(x, y, &mut f0);
+            let f = |x: f64, y: &V, dy: &mut V| f.eval(x, y, dy);
+            jacobian::forward_differences(f, x, y, &f0, &mut jac_mat);
+        }
+    }
+    let mut dl = -jac_mat.tr_mul(&jacobian::to_dvector(lambda));
+    if let Some(cost) = cost {
+        let mut gy = V::zero();
+        cost.gradient_y(x, y, p, &mut gy);
+        for i in 0..dim {
+            let gy_i: f64 = na::convert(gy[i]);
+            dl[i] -= gy_i;
+        }
+    }
+    *dlambda = jacobian::from_dvector(&dl);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dopri5::Dopri5;
+    use na::{Vector1, Vector2};
+
+    const A: f64 = 0.5;
+    const T: f64 = 3.0;
+
+    // Decay y' = -a y with the objective Ψ = ∫ y^2 dx + y(T)
+    fn decay(_x: f64, y: &Vector1<f64>, dy: &mut Vector1<f64>) {
+        dy[0] = -A * y[0];
+    }
+
+    fn decay_dfdp(_x: f64, y: &Vector1<f64>, dfdp: &mut DMatrix<f64>) {
+        dfdp[(0, 0)] = -y[0];
+    }
+
+    #[test]
+    fn gradient_of_the_decay() {
+        let y0 = 2.0;
+        let decay_rate = (-A * T).exp();
+        let integral = (1.0 - decay_rate * decay_rate) / (2.0 * A);
+        let dintegral_da = T * decay_rate * decay_rate / A - integral / A;
+
+        let mut adjoint =
+            Adjoint::new(decay, decay_dfdp, 1, 0.0, T, Vector1::new(y0), 1e-10, 1e-10);
+        adjoint.set_running_cost(
+            |_, y| y[0] * y[0],
+            |_, y, dg_dy| dg_dy[0] = 2.0 * y[0],
+            |_, _, dg_dp| dg_dp.fill(0.0),
+        );
+        adjoint.set_terminal_cost(|y| y[0], |_, dg_dy| dg_dy[0] = 1.0);
+        adjoint.integrate().unwrap();
+
+        assert!((adjoint.objective() - (y0 * y0 * integral + y0 * decay_rate)).abs() < 1e-8);
+        let gradient_y0 = 2.0 * y0 * integral + decay_rate;
+        assert!((adjoint.gradient_y0()[0] - gradient_y0).abs() < 1e-8);
+        let gradient_a = y0 * y0 * dintegral_da - T * y0 * decay_rate;
+        assert!((adjoint.gradient_p()[0] - gradient_a).abs() < 1e-8);
+    }
+
+    // Decay y' = -p y with the objective Ψ = ∫ p y^2 dx + y(T)
+    fn parametric_decay(_x: f64, y: &Vector1<f64>, p: &DVector<f64>, dy: &mut Vector1<f64>) {
+        dy[0] = -p[0] * y[0];
+    }
+
+    fn parametric_decay_dfdp(
+        _x: f64,
+        y: &Vector1<f64>,
+        _p: &DVector<f64>,
+        dfdp: &mut DMatrix<f64>,
+    ) {
+        dfdp[(0, 0)] = -y[0];
+    }
+
+    #[test]
+    fn gradient_of_the_parametric_decay() {
+        let y0 = 2.0;
+        let decay_rate = (-A * T).exp();
+        let integral = (1.0 - decay_rate * decay_rate) / (2.0 * A);
+        let dintegral_da = T * decay_rate * decay_rate / A - integral / A;
+
+        for method in &[Method::Dopri5, Method::Dop853] {
+            let mut adjoint = Adjoint::with_parameters(
+                parametric_decay,
+                DVector::from_element(1, A),
+                parametric_decay_dfdp,
+                0.0,
+                T,
+                Vector1::new(y0),
+                1e-10,
+                1e-10,
+            );
+            adjoint.set_method(*method);
+            adjoint.set_parametric_jacobian(|_, _, p, jac| jac[(0, 0)] = -p[0]);
+            adjoint.set_parametric_running_cost(
+                |_, y, p| p[0] * y[0] * y[0],
+                |_, y, p, dg_dy| dg_dy[0] = 2.0 * p[0] * y[0],
+                |_, y, _, dg_dp| dg_dp[0] = y[0] * y[0],
+            );
+            adjoint.set_terminal_cost(|y| y[0], |_, dg_dy| dg_dy[0] = 1.0);
+            let stats = adjoint.integrate().unwrap();
+            assert!(stats.num_eval > 0);
+
+            let objective = A * y0 * y0 * integral + y0 * decay_rate;
+            assert!((adjoint.objective() - objective).abs() < 1e-8);
+            let gradient_y0 = 2.0 * A * y0 * integral + decay_rate;
+            assert!((adjoint.gradient_y0()[0] - gradient_y0).abs() < 1e-8);
+            let gradient_a = y0 * y0 * (integral + A * dintegral_da) - T * y0 * decay_rate;
+            assert!((adjoint.gradient_p()[0] - gradient_a).abs() < 1e-8);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn parametric_cost_of_a_plain_system() {
+        let mut adjoint = Adjoint::new(decay, decay_dfdp, 1, 0.0, T, Vector1::new(1.0), 1e-8, 1e-8);
+        adjoint.set_parametric_running_cost(
+            |_, y, _| y[0],
+            |_, _, _, dg_dy| dg_dy[0] = 1.0,
+            |_, _, _, _| {},
+        );
+    }
+
+    // Van der Pol oscillator with the objective Ψ = y1(T)^2 + y2(T)
+    fn van_der_pol(_x: f64, y: &Vector2<f64>, dy: &mut Vector2<f64>) {
+        dy[0] = y[1];
+        dy[1] = (1.0 - y[0] * y[0]) * y[1] - y[0];
+    }
+
+    fn van_der_pol_dfdp(_x: f64, y: &Vector2<f64>, dfdp: &mut DMatrix<f64>) {
+        dfdp[(0, 0)] = 0.0;
+        dfdp[(1, 0)] = (1.0 - y[0] * y[0]) * y[1];
+    }
+
+    fn terminal_cost(y: &Vector2<f64>) -> f64 {
+        y[0] * y[0] + y[1]
+    }
+
+    #[test]
+    fn gradient_against_finite_differences() {
+        let y0 = Vector2::new(2.0, 0.0);
+        let mut adjoint = Adjoint::new(van_der_pol, van_der_pol_dfdp, 1, 0.0, T, y0, 1e-10, 1e-10);
+        adjoint.set_terminal_cost(terminal_cost, |y, dg_dy| {
+            *dg_dy = Vector2::new(2.0 * y[0], 1.0)
+        });
+        adjoint.integrate().unwrap();
+
+        let objective = |y0: Vector2<f64>| {
+            let mut stepper = Dopri5::new(van_der_pol, 0.0, T, T, y0, 1e-12, 1e-12);
+            stepper.integrate().unwrap();
+            terminal_cost(stepper.y_out().last().unwrap())
+        };
+        assert!((adjoint.objective() - objective(y0)).abs() < 1e-8);
+        let delta = 1e-6;
+        for i in 0..2 {
+            let mut e = Vector2::zeros();
+            e[i] = delta;
+            let fd = (objective(y0 + e) - objective(y0 - e)) / (2.0 * delta);
+            assert!((adjoint.gradient_y0()[i] - fd).abs() < 1e-6);
+        }
+    }
+}
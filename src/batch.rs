@@ -0,0 +1,538 @@
+#![allow(clippy::needless_range_loop)]
+
+//! Batched integration.
+//!
+//! For small systems, the cost of an integration with Dopri5 is dominated by the overhead of each
+//! step rather than by the arithmetic. [`BatchDopri5`](struct.BatchDopri5.html) advances many
+//! independent initial conditions, the lanes, together with the Dormand-Prince method. The states
+//! are stored as a structure of arrays, a matrix whose i-th column contains the i-th component of
+//! all the lanes, so that the stages are computed by loops over contiguous memory which the
+//! compiler can vectorize. The function to integrate also receives all the lanes at once:
+//!
+//! ```ignore
+//! fn system(x: &[f64], y: &DMatrix<f64>, dy: &mut DMatrix<f64>)
+//! ```
+//!
+//! where `x[l]` and the l-th row of `y` are the independent variable and the state of lane l.
+//!
+//! Each lane has its own step size by default, and the lanes which reached the end of the
+//! integration interval are frozen until all the others are done. With
+//! `StepControl::Common`, all the lanes take the same steps, chosen from the lane with the
+//! largest error.
+//!
+//! The statistics are summed over the lanes, each lane counting its evaluations of the function
+//! and its steps as in an integration of its own with Dopri5.
+
+use alga::general::SubsetOf;
+use alga::linear::{FiniteDimInnerSpace, InnerSpace};
+use butcher_tableau::Dopri54;
+use controller::Controller;
+use dop_shared::*;
+use na;
+use na::DMatrix;
+use std::f64;
+
+/// Enumeration of the step size strategies of the batched integration.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StepControl {
+    /// Every lane has its own step size
+    PerLane,
+    /// All the lanes take a common step, chosen from the lane with the largest error
+    Common,
+}
+
+/// Structure containing the parameters for the batched numerical integration.
+pub struct BatchDopri5<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+{
+    f: fn(&[f64], &DMatrix<f64>, &mut DMatrix<f64>),
+    x: f64,
+    x_end: f64,
+    dx: f64,
+    y0: Vec<V>,
+    rtol: f64,
+    atol: f64,
+    n_max: u32,
+    step_control: StepControl,
+    out_type: OutputType,
+    coeffs: Dopri54,
+    x_out: Vec<Vec<f64>>,
+    y_out: Vec<Vec<V>>,
+    stats: Stats,
+}
+
+impl<V> BatchDopri5<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: SubsetOf<f64>,
+{
+    /// Default initializer for the structure.
+    ///
+    /// # Arguments
+    ///
+    /// * `f`       - Pointer to the function to integrate, evaluated for all the lanes at once
+    /// * `x`       - Initial value of the independent variable (usually time)
+    /// * `x_end`   - Final value of the independent variable
+    /// * `dx`      - Increment in the dense output. This argument has no effect if the output type is Sparse
+    /// * `y`       - Initial values of the dependent variable(s), one per lane
+    /// * `rtol`    - Relative tolerance used in the computation of the adaptive step size
+    /// * `atol`    - Absolute tolerance used in the computation of the adaptive step size
+    ///
+    pub fn new(
+        f: fn(&[f64], &DMatrix<f64>, &mut DMatrix<f64>),
+        x: f64,
+        x_end: f64,
+        dx: f64,
+        y: Vec<V>,
+        rtol: f64,
+        atol: f64,
+    ) -> BatchDopri5<V> {
+        BatchDopri5 {
+            f,
+            x,
+            x_end,
+            dx,
+            y0: y,
+            rtol,
+            atol,
+            n_max: 100000,
+            step_control: StepControl::PerLane,
+            out_type: OutputType::Dense,
+            coeffs: Dopri54::new(),
+            x_out: Vec::new(),
+            y_out: Vec::new(),
+            stats: Stats::new(),
+        }
+    }
+
+    /// Sets the step size strategy. Default is `StepControl::PerLane`.
+    pub fn set_step_control(&mut self, step_control: StepControl) {
+        self.step_control = step_control;
+    }
+
+    /// Sets the type of the output. With `OutputType::Sparse`, the state of every lane is stored
+    /// after each of its accepted steps. Default is `OutputType::Dense`.
+    pub fn set_output_type(&mut self, out_type: OutputType) {
+        self.out_type = out_type;
+    }
+
+    /// Sets the maximum number of steps of the batch. Default is 100000.
+    pub fn set_n_max(&mut self, n_max: u32) {
+        self.n_max = n_max;
+    }
+
+    /// Core integration method. The statistics are summed over the lanes: a batched evaluation
+    /// of the function counts once for each lane still integrating, and the rejected steps of a
+    /// lane are counted from its first accepted step, as with Dopri5.
+    pub fn integrate(&mut self) -> Result<Stats, IntegrationError> {
+        let dim = na::dimension::<V>();
+        let lanes = self.y0.len();
+        let posneg = sign(1.0, self.x_end - self.x);
+        let uround = f64::EPSILON;
+        self.stats = Stats::new();
+        self.x_out = vec![Vec::new(); lanes];
+        self.y_out = vec![Vec::new(); lanes];
+        if lanes == 0 {
+            return Ok(self.stats);
+        }
+
+        // Structure of arrays: entry (l, i) is the i-th component of lane l
+        let mut y = DMatrix::zeros(lanes, dim);
+        for (l, y0) in self.y0.iter().enumerate() {
+            for i in 0..dim {
+                y[(l, i)] = na::convert(y0[i]);
+            }
+        }
+        let mut x = vec![self.x; lanes];
+        let mut x_old = x.clone();
+        let mut xd = x.clone();
+        let mut last = vec![false; lanes];
+        let mut done = vec![false; lanes];
+        let mut accepted = vec![false; lanes];
+        let mut started = vec![false; lanes];
+        let mut h_new = vec![0.0; lanes];
+        let mut err = vec![0.0; lanes];
+        let mut x_stage = vec![0.0; lanes];
+        let mut h_stage = vec![0.0; lanes];
+        let mut controllers: Vec<Controller> = (0..lanes)
+            .map(|_| {
+                Controller::new(
+                    0.2 - 0.04 * 0.75,
+                    0.04,
+                    10.0,
+                    0.2,
+                    self.x_end - self.x,
+                    0.9,
+                    posneg,
+                )
+            })
+            .collect();
+
+        let mut k: Vec<DMatrix<f64>> = vec![DMatrix::zeros(lanes, dim); 7];
+        let mut y_next = DMatrix::zeros(lanes, dim);
+        (self.f)(&x, &y, &mut k[0]);
+        self.stats.num_eval += lanes as u32;
+        let mut h = self.hinit(&x, &y, &k[0], posneg);
+        if self.step_control == StepControl::Common {
+            let h_min = h.iter().fold(f64::INFINITY, |m, h| m.min(h.abs()));
+            h = vec![posneg * h_min; lanes];
+        }
+        if self.out_type == OutputType::Sparse {
+            for l in 0..lanes {
+                self.push_output(l, x[l], &y);
+            }
+        }
+
+        let mut n_step = 0;
+        while done.iter().any(|d| !d) {
+            n_step += 1;
+            for l in 0..lanes {
+                if done[l] {
+                    continue;
+                }
+                if n_step > self.n_max {
+                    return Err(IntegrationError::MaxNumStepReached { x: x[l], n_step });
+                }
+                if 0.1 * h[l].abs() <= uround * x[l].abs() {
+                    return Err(IntegrationError::StepSizeUnderflow { x: x[l] });
+                }
+                if (x[l] + 1.01 * h[l] - self.x_end) * posneg > 0.0 {
+                    h[l] = self.x_end - x[l];
+                    last[l] = true;
+                }
+            }
+            // The lanes which are done take steps of size zero
+            for l in 0..lanes {
+                h_stage[l] = if done[l] { 0.0 } else { h[l] };
+            }
+
+            // 6 Stages
+            for s in 1..7 {
+                y_next.copy_from(&y);
+                for j in 0..s {
+                    let a = self.coeffs.a(s + 1, j + 1);
+                    if a != 0.0 {
+                        axpy(&mut y_next, a, &h_stage, &k[j]);
+                    }
+                }
+                let c = self.coeffs.c(s + 1);
+                for l in 0..lanes {
+                    x_stage[l] = x[l] + c * h_stage[l];
+                }
+                (self.f)(&x_stage, &y_next, &mut k[s]);
+            }
+            self.stats.num_eval += 6 * done.iter().filter(|d| !**d).count() as u32;
+
+            // Error estimate, k[6] being the derivative at the end of the step
+            let mut e = DMatrix::zeros(lanes, dim);
+            for (j, i) in [(0, 1), (2, 3), (3, 4), (4, 5), (5, 6), (6, 7)].iter() {
+                axpy(&mut e, self.coeffs.e(*i), &h_stage, &k[*j]);
+            }
+            for l in 0..lanes {
+                err[l] = 0.0;
+            }
+            let columns = y
+                .as_slice()
+                .chunks(lanes)
+                .zip(y_next.as_slice().chunks(lanes))
+                .zip(e.as_slice().chunks(lanes));
+            for ((y_i, y_next_i), e_i) in columns {
+                for l in 0..lanes {
+                    let sc = self.atol + y_i[l].abs().max(y_next_i[l].abs()) * self.rtol;
+                    err[l] += (e_i[l] / sc) * (e_i[l] / sc);
+                }
+            }
+            for l in 0..lanes {
+                err[l] = (err[l] / dim as f64).sqrt();
+            }
+
+            // Step size control
+            match self.step_control {
+                StepControl::PerLane => {
+                    for l in 0..lanes {
+                        if !done[l] {
+                            accepted[l] = controllers[l].accept(err[l], h[l], &mut h_new[l]);
+                        }
+                    }
+                }
+                StepControl::Common => {
+                    let err_max = (0..lanes)
+                        .filter(|&l| !done[l])
+                        .fold(0.0, |m: f64, l| m.max(err[l]));
+                    let l0 = (0..lanes).find(|&l| !done[l]).unwrap();
+                    let mut h_common = 0.0;
+                    let accept = controllers[0].accept(err_max, h[l0], &mut h_common);
+                    for l in 0..lanes {
+                        accepted[l] = accept;
+                        h_new[l] = h_common;
+                    }
+                }
+            }
+
+            for l in 0..lanes {
+                if done[l] {
+                    continue;
+                }
+                if accepted[l] {
+                    self.stats.accepted_steps += 1;
+                    started[l] = true;
+                    x_old[l] = x[l];
+                    x[l] += h[l];
+                    if self.out_type == OutputType::Dense {
+                        self.dense_output(l, &mut xd[l], x_old[l], x[l], h[l], &y, &y_next, &k);
+                    }
+                    if self.out_type == OutputType::Sparse {
+                        self.push_output(l, x[l], &y_next);
+                    }
+                    if last[l] {
+                        done[l] = true;
+                    }
+                } else {
+                    if started[l] {
+                        self.stats.rejected_steps += 1;
+                    }
+                    last[l] = false;
+                }
+                h[l] = h_new[l];
+            }
+
+            // New states of the lanes which accepted their step
+            for l in 0..lanes {
+                h_stage[l] = if accepted[l] && h_stage[l] != 0.0 {
+                    1.0
+                } else {
+                    0.0
+                };
+            }
+            let (k_0, k_6) = k.split_at_mut(6);
+            select(&mut y, &h_stage, &y_next);
+            select(&mut k_0[0], &h_stage, &k_6[0]);
+        }
+        Ok(self.stats)
+    }
+
+    /// Initial step size of every lane.
+    fn hinit(&mut self, x: &[f64], y: &DMatrix<f64>, f0: &DMatrix<f64>, posneg: f64) -> Vec<f64> {
+        let (lanes, dim) = y.shape();
+        let h_max = (self.x_end - self.x).abs();
+        let mut d0 = vec![0.0; lanes];
+        let mut d1 = vec![0.0; lanes];
+        for i in 0..dim {
+            for l in 0..lanes {
+                let sc = self.atol + y[(l, i)].abs() * self.rtol;
+                d0[l] += (y[(l, i)] / sc) * (y[(l, i)] / sc);
+                d1[l] += (f0[(l, i)] / sc) * (f0[(l, i)] / sc);
+            }
+        }
+        let h0: Vec<f64> = (0..lanes)
+            .map(|l| {
+                let h0 = if d0[l] < 1.0E-10 || d1[l] < 1.0E-10 {
+                    1.0E-6
+                } else {
+                    0.01 * (d0[l] / d1[l]).sqrt()
+                };
+                sign(h0.min(h_max), posneg)
+            })
+            .collect();
+
+        let mut y1 = y.clone();
+        axpy(&mut y1, 1.0, &h0, f0);
+        let x1: Vec<f64> = (0..lanes).map(|l| x[l] + h0[l]).collect();
+        let mut f1 = DMatrix::zeros(lanes, dim);
+        (self.f)(&x1, &y1, &mut f1);
+        self.stats.num_eval += lanes as u32;
+
+        let mut d2 = vec![0.0; lanes];
+        for i in 0..dim {
+            for l in 0..lanes {
+                let sc = self.atol + y[(l, i)].abs() * self.rtol;
+                let df = f1[(l, i)] - f0[(l, i)];
+                d2[l] += (df / sc) * (df / sc);
+            }
+        }
+        (0..lanes)
+            .map(|l| {
+                let d2 = d2[l].sqrt() / h0[l];
+                let h1 = if d1[l].sqrt().max(d2.abs()) <= 1.0E-15 {
+                    1.0E-6_f64.max(h0[l].abs() * 1.0E-3)
+                } else {
+                    (0.01 / (d1[l].sqrt().max(d2))).powf(1.0 / 5.0)
+                };
+                sign((100.0 * h0[l].abs()).min(h1.min(h_max)), posneg)
+            })
+            .collect()
+    }
+
+    /// Stores the dense output of lane l in the step from x_old to x.
+    #[allow(clippy::too_many_arguments)]
+    fn dense_output(
+        &mut self,
+        l: usize,
+        xd: &mut f64,
+        x_old: f64,
+        x: f64,
+        h: f64,
+        y: &DMatrix<f64>,
+        y_next: &DMatrix<f64>,
+        k: &[DMatrix<f64>],
+    ) {
+        let dim = y.ncols();
+        let posneg = sign(1.0, h);
+        while (*xd - x) * posneg <= 0.0 {
+            if (*xd - x_old) * posneg >= 0.0 {
+                let theta = (*xd - x_old) / h;
+                let theta1 = 1.0 - theta;
+                let mut y_d = V::zero();
+                for i in 0..dim {
+                    let ydiff = y_next[(l, i)] - y[(l, i)];
+                    let bspl = h * k[0][(l, i)] - ydiff;
+                    let r3 = ydiff - h * k[6][(l, i)] - bspl;
+                    let r4 = h
+                        * (self.coeffs.d(1) * k[0][(l, i)]
+                            + self.coeffs.d(3) * k[2][(l, i)]
+                            + self.coeffs.d(4) * k[3][(l, i)]
+                            + self.coeffs.d(5) * k[4][(l, i)]
+                            + self.coeffs.d(6) * k[5][(l, i)]
+                            + self.coeffs.d(7) * k[6][(l, i)]);
+                    y_d[i] = na::convert(
+                        y[(l, i)] + (ydiff + (bspl + (r3 + r4 * theta1) * theta) * theta1) * theta,
+                    );
+                }
+                self.x_out[l].push(*xd);
+                self.y_out[l].push(y_d);
+            }
+            *xd += self.dx;
+        }
+    }
+
+    fn push_output(&mut self, l: usize, x: f64, y: &DMatrix<f64>) {
+        let mut y_l = V::zero();
+        for i in 0..y.ncols() {
+            y_l[i] = na::convert(y[(l, i)]);
+        }
+        self.x_out[l].push(x);
+        self.y_out[l].push(y_l);
+    }
+
+    /// Getter for the independent variable's output of each lane.
+    pub fn x_out(&self) -> &Vec<Vec<f64>> {
+        &self.x_out
+    }
+
+    /// Getter for the dependent variables' output of each lane.
+    pub fn y_out(&self) -> &Vec<Vec<V>> {
+        &self.y_out
+    }
+}
+
+/// Computes y += a h k, where h is the step size of each lane, one component at a time.
+fn axpy(y: &mut DMatrix<f64>, a: f64, h: &[f64], k: &DMatrix<f64>) {
+    let lanes = y.nrows();
+    let y = y.as_mut_slice();
+    let k = k.as_slice();
+    for (y_i, k_i) in y.chunks_mut(lanes).zip(k.chunks(lanes)) {
+        for l in 0..lanes {
+            y_i[l] += a * h[l] * k_i[l];
+        }
+    }
+}
+
+/// Replaces the states of the lanes where mask is 1 by the ones of z.
+fn select(y: &mut DMatrix<f64>, mask: &[f64], z: &DMatrix<f64>) {
+    let lanes = y.nrows();
+    let y = y.as_mut_slice();
+    let z = z.as_slice();
+    for (y_i, z_i) in y.chunks_mut(lanes).zip(z.chunks(lanes)) {
+        for l in 0..lanes {
+            if mask[l] != 0.0 {
+                y_i[l] = z_i[l];
+            }
+        }
+    }
+}
+
+fn sign(a: f64, b: f64) -> f64 {
+    if b > 0.0 {
+        a.abs()
+    } else {
+        -a.abs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dopri5::Dopri5;
+    use na::{DVector, Vector1};
+
+    fn decay(x: &[f64], y: &DMatrix<f64>, dy: &mut DMatrix<f64>) {
+        for l in 0..y.nrows() {
+            dy[(l, 0)] = -(l as f64 + 1.0) * y[(l, 0)] + x[l].cos();
+        }
+    }
+
+    // Solution of the lane with the rate a and y(x0) = 1
+    fn exact(a: f64, x0: f64, x: f64) -> f64 {
+        let particular = |x: f64| (a * x.cos() + x.sin()) / (a * a + 1.0);
+        (1.0 - particular(x0)) * (-a * (x - x0)).exp() + particular(x)
+    }
+
+    #[test]
+    fn lanes_match_exact_solution() {
+        let y0 = vec![Vector1::new(1.0); 4];
+        for &control in &[StepControl::PerLane, StepControl::Common] {
+            let mut batch = BatchDopri5::new(decay, 0.0, 2.0, 0.5, y0.clone(), 1.0e-10, 1.0e-10);
+            batch.set_step_control(control);
+            batch.integrate().unwrap();
+            for l in 0..4 {
+                let a = l as f64 + 1.0;
+                assert_eq!(batch.x_out()[l].len(), 5);
+                for (x, y) in batch.x_out()[l].iter().zip(batch.y_out()[l].iter()) {
+                    assert!((y[0] - exact(a, 0.0, *x)).abs() < 1.0e-8);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn dense_output_across_zero_and_backward() {
+        let y0 = vec![Vector1::new(1.0); 4];
+        for &(x0, x_end, dx) in &[(-1.0, 1.0, 0.5), (0.0, -1.0, -0.25), (1.0, -1.0, -0.5)] {
+            let mut batch = BatchDopri5::new(decay, x0, x_end, dx, y0.clone(), 1.0e-10, 1.0e-10);
+            batch.integrate().unwrap();
+            for l in 0..4 {
+                let a = l as f64 + 1.0;
+                let x_out: Vec<f64> = (0..5).map(|i| x0 + i as f64 * dx).collect();
+                assert_eq!(batch.x_out()[l], x_out);
+                for (x, y) in batch.x_out()[l].iter().zip(batch.y_out()[l].iter()) {
+                    assert!((y[0] - exact(a, x0, *x)).abs() < 1.0e-8 * exact(a, x0, *x).abs());
+                }
+            }
+        }
+    }
+
+    fn lane_decay(x: f64, y: &Vector1<f64>, p: &DVector<f64>, dy: &mut Vector1<f64>) {
+        dy[0] = -p[0] * y[0] + x.cos();
+    }
+
+    #[test]
+    fn statistics_summed_over_the_lanes() {
+        // Each lane counts as its own integration with Dopri5
+        let y0 = vec![Vector1::new(1.0); 4];
+        let mut batch = BatchDopri5::new(decay, 0.0, 20.0, 1.0, y0.clone(), 1.0e-8, 1.0e-8);
+        let stats = batch.integrate().unwrap();
+        let mut expected = Stats::new();
+        for l in 0..4 {
+            let p = DVector::from_element(1, l as f64 + 1.0);
+            let mut stepper =
+                Dopri5::with_parameters(lane_decay, p, 0.0, 20.0, 1.0, y0[l], 1.0e-8, 1.0e-8);
+            expected += stepper.integrate().unwrap();
+        }
+        assert!(expected.rejected_steps > 0);
+        // Dopri5 evaluates the function at the initial point again for its initial step size
+        assert_eq!(stats.num_eval + 4, expected.num_eval);
+        assert_eq!(stats.accepted_steps, expected.accepted_steps);
+        assert_eq!(stats.rejected_steps, expected.rejected_steps);
+    }
+}
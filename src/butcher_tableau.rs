@@ -283,7 +283,7 @@ impl Dopri853 {
                 0.651282051282051282051282051282E+00,
                 0.6E+00,
                 0.857142857142857142857142857142E+00,
-                0.0,
+                1.0,
                 0.0,
                 0.1E+00,
                 0.2E+00,
@@ -425,6 +425,135 @@ impl Dopri853 {
     }
 }
 
+/// Structure containing the coefficients for the embedded Runge-Kutta-Nyström method of order 6(4).
+///
+/// The method integrates special second order systems _y'' = f(x, y)_. The last stage is evaluated at the
+/// new position so that it can be reused as the first stage of the next step (FSAL).
+pub struct Nystrom64 {
+    num_stages: usize,
+    pub order: i32,
+    a: Box<[Box<[f64]>]>,
+    b: Box<[f64]>,
+    bbar: Box<[f64]>,
+    c: Box<[f64]>,
+    e: Box<[f64]>,
+    ebar: Box<[f64]>,
+}
+
+impl Default for Nystrom64 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Nystrom64 {
+    /// Initialize the structure with the coefficients of the method.
+    pub fn new() -> Nystrom64 {
+        Nystrom64 {
+            num_stages: 6,
+            order: 6,
+            a: Box::new([
+                Box::new([9.0 / 800.0]),
+                Box::new([
+                    -5.97049440528935349426338499703E-3,
+                    1.04735926504054785593028817096E-1,
+                ]),
+                Box::new([
+                    4.82873172379232361615245009074E-1,
+                    -5.08652545897735249803102420984E-1,
+                    4.65232498518502888187857411910E-1,
+                ]),
+                Box::new([
+                    1.33444612003898359989259060277E-1,
+                    1.96815426185224267246383851792E-3,
+                    1.88030839318295403425652523953E-1,
+                    1.89408384543048975704130554451E-3,
+                ]),
+                Box::new([
+                    4.53005827079138446519006650802E-2,
+                    1.96859032199439214571420506885E-1,
+                    1.94318261038049478481392241642E-1,
+                    -3.55837514202918732071858808583E-3,
+                    6.70804991966266496160051744779E-2,
+                ]),
+            ]),
+            b: Box::new([
+                4.53005827079138446519006650802E-2,
+                2.31598861411104958319318243395E-1,
+                3.49772869868489061266506034956E-1,
+                -5.69340022724669971314974093733E-2,
+                3.46928354951625799560439132609E-1,
+                1.0 / 12.0,
+            ]),
+            bbar: Box::new([
+                4.53005827079138446519006650802E-2,
+                1.96859032199439214571420506885E-1,
+                1.94318261038049478481392241642E-1,
+                -3.55837514202918732071858808583E-3,
+                6.70804991966266496160051744779E-2,
+                0.0,
+            ]),
+            c: Box::new([0.0, 3.0 / 20.0, 4.0 / 9.0, 15.0 / 16.0, 1214.0 / 1505.0, 1.0]),
+            e: Box::new([
+                -5.37921776801513210079931661480E-2,
+                1.18742399662710166475318966779E-1,
+                -1.16042237162562635154510971559E-1,
+                -5.69340022724669971314974093733E-2,
+                1.08026017452470786818682580301E-1,
+                0.0,
+            ]),
+            ebar: Box::new([
+                -5.37921776801513210079931661480E-2,
+                1.00931039713303641504021121762E-1,
+                -6.44679095347570195302838730883E-2,
+                -3.55837514202918732071858808583E-3,
+                2.08874226436338863549745055599E-2,
+                0.0,
+            ]),
+        }
+    }
+
+    /// Returns the _a<sub>ij</sub>_ coefficient.
+    pub fn a(&self, i: usize, j: usize) -> f64 {
+        self.a[i - 2][j - 1]
+    }
+
+    /// Returns the _b<sub>i</sub>_ coefficient used to update the derivative.
+    pub fn b(&self, i: usize) -> f64 {
+        self.b[i - 1]
+    }
+
+    /// Returns the _b&#772;<sub>i</sub>_ coefficient used to update the solution.
+    pub fn bbar(&self, i: usize) -> f64 {
+        self.bbar[i - 1]
+    }
+
+    /// Returns the _c<sub>i</sub>_ coefficient.
+    pub fn c(&self, i: usize) -> f64 {
+        self.c[i - 1]
+    }
+
+    /// Returns the _e<sub>i</sub>_ coefficient of the error estimate of the derivative.
+    pub fn e(&self, i: usize) -> f64 {
+        self.e[i - 1]
+    }
+
+    /// Returns the _e&#772;<sub>i</sub>_ coefficient of the error estimate of the solution.
+    pub fn ebar(&self, i: usize) -> f64 {
+        self.ebar[i - 1]
+    }
+
+    /// Returns the number of stages of the Butcher tableau.
+    pub fn num_stages(&self) -> usize {
+        self.num_stages
+    }
+
+    /// Returns the order of the Butcher tableau.
+    pub fn order(&self) -> i32 {
+        self.order
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use butcher_tableau;
@@ -451,4 +580,25 @@ mod tests {
         assert_eq!(tab.a(15, 3), 0.0);
         assert_eq!(tab.a(4, 3), 8.87627564304205475450678981324E-2);
     }
+
+    #[test]
+    fn dopri853_c() {
+        let tab = butcher_tableau::Dopri853::new();
+        for i in 2..13 {
+            let sum: f64 = (1..i).map(|j| tab.a(i, j)).sum();
+            assert!((sum - tab.c(i)).abs() < 1.0E-14);
+        }
+    }
+
+    #[test]
+    fn nystrom64_weights() {
+        let tab = butcher_tableau::Nystrom64::new();
+        let b: f64 = (1..7).map(|i| tab.b(i)).sum();
+        let bbar: f64 = (1..7).map(|i| tab.bbar(i)).sum();
+        assert!((b - 1.0).abs() < 1.0E-15);
+        assert!((bbar - 0.5).abs() < 1.0E-15);
+        for j in 1..6 {
+            assert_eq!(tab.a(6, j), tab.bbar(j));
+        }
+    }
 }
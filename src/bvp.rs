@@ -0,0 +1,487 @@
+#![allow(clippy::needless_range_loop)]
+
+//! Boundary value problems solved by shooting.
+//!
+//! The two-point boundary value problem _y' = f(x, y)_, _g(y(a), y(b)) = 0_ is reduced to a
+//! system of nonlinear equations. With single shooting, the unknown is the initial value y(a),
+//! and the equations are the boundary conditions applied to the solution of the initial value
+//! problem. With multiple shooting, the interval is split at the nodes a = x<sub>0</sub> < ... <
+//! x<sub>M</sub> = b, the unknowns are the values s<sub>i</sub> at the start of each segment, and
+//! the continuity conditions _y(x<sub>i+1</sub>; x<sub>i</sub>, s<sub>i</sub>) = s<sub>i+1</sub>_
+//! are added to the boundary conditions, which keeps the growth of errors along each segment
+//! small.
+//!
+//! The equations are solved by Newton's method with a backtracking line search. The derivatives
+//! of the segment end points with respect to s<sub>i</sub> are the state transition matrices,
+//! integrated with Dop853 from the variational equations.
+
+use alga::general::SubsetOf;
+use alga::linear::{FiniteDimInnerSpace, InnerSpace};
+use dop853::Dop853;
+use dop_shared::*;
+use jacobian;
+use na;
+use na::{DMatrix, DVector};
+use std::error::Error;
+use std::fmt;
+use stm::StateTransition;
+
+/// Enumeration of the errors that may arise while solving a boundary value problem.
+#[derive(Debug)]
+pub enum BvpError {
+    Integration(IntegrationError),
+    SingularJacobian { iterations: u32 },
+    LineSearchFailed { iterations: u32 },
+    MaxIterationsReached { iterations: u32 },
+    MaxMeshSizeReached { points: usize },
+    SingularTerm,
+}
+
+impl Error for BvpError {}
+
+impl fmt::Display for BvpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BvpError::Integration(e) => write!(f, "Integration failed at the initial guess. {}", e),
+            BvpError::SingularJacobian { iterations } => {
+                write!(f, "Singular Jacobian matrix at iteration {}", iterations)
+            }
+            BvpError::LineSearchFailed { iterations } => {
+                write!(f, "No decrease of the residuals at iteration {}", iterations)
+            }
+            BvpError::MaxIterationsReached { iterations } => {
+                write!(f, "No convergence after {} iterations", iterations)
+            }
+            BvpError::MaxMeshSizeReached { points } => {
+                write!(f, "The mesh needs more than {} points", points)
+            }
+            BvpError::SingularTerm => write!(f, "I - S is not invertible for the singular term"),
+        }
+    }
+}
+
+/// Contains some statistics of the solution of a boundary value problem.
+#[derive(Clone, Copy, Debug)]
+pub struct BvpStats {
+    pub iterations: u32,
+    pub num_integrations: u32,
+    pub failed_integrations: u32,
+    pub num_eval: u32,
+}
+
+impl BvpStats {
+    fn new() -> BvpStats {
+        BvpStats {
+            iterations: 0,
+            num_integrations: 0,
+            failed_integrations: 0,
+            num_eval: 0,
+        }
+    }
+}
+
+impl fmt::Display for BvpStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Number of Newton iterations: {}", self.iterations)?;
+        writeln!(f, "Number of integrations: {}", self.num_integrations)?;
+        writeln!(f, "Number of failed integrations: {}", self.failed_integrations)?;
+        write!(f, "Number of function evaluations: {}", self.num_eval)
+    }
+}
+
+/// Structure containing the parameters of the shooting method.
+pub struct Shooting<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+{
+    f: fn(f64, &V, &mut V),
+    bc: fn(&V, &V, &mut V),
+    jac: Option<fn(f64, &V, &mut DMatrix<f64>)>,
+    #[allow(clippy::type_complexity)]
+    bc_jac: Option<fn(&V, &V, &mut DMatrix<f64>, &mut DMatrix<f64>)>,
+    nodes: Vec<f64>,
+    s: Vec<V>,
+    dx: f64,
+    rtol: f64,
+    atol: f64,
+    tol: f64,
+    max_iterations: u32,
+    x_out: Vec<f64>,
+    y_out: Vec<V>,
+    stats: BvpStats,
+}
+
+impl<V> Shooting<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: SubsetOf<f64>,
+{
+    /// Initializer for single shooting.
+    ///
+    /// # Arguments
+    ///
+    /// * `f`       - Pointer to the function to integrate
+    /// * `bc`      - Function computing the residuals g(y(a), y(b)) of the n boundary conditions
+    /// * `a`       - Start of the interval
+    /// * `b`       - End of the interval
+    /// * `y_guess` - Guess of the initial value y(a)
+    /// * `rtol`    - Relative tolerance used in the computation of the adaptive step size
+    /// * `atol`    - Absolute tolerance used in the computation of the adaptive step size
+    ///
+    pub fn new(
+        f: fn(f64, &V, &mut V),
+        bc: fn(&V, &V, &mut V),
+        a: f64,
+        b: f64,
+        y_guess: V,
+        rtol: f64,
+        atol: f64,
+    ) -> Shooting<V> {
+        Shooting::multiple(f, bc, vec![a, b], vec![y_guess], rtol, atol)
+    }
+
+    /// Initializer for multiple shooting.
+    ///
+    /// # Arguments
+    ///
+    /// * `f`       - Pointer to the function to integrate
+    /// * `bc`      - Function computing the residuals g(y(a), y(b)) of the n boundary conditions
+    /// * `nodes`   - Nodes a = x<sub>0</sub>, ..., x<sub>M</sub> = b, sorted in the direction of integration
+    /// * `guesses` - Guesses of the solution at the M first nodes
+    /// * `rtol`    - Relative tolerance used in the computation of the adaptive step size
+    /// * `atol`    - Absolute tolerance used in the computation of the adaptive step size
+    ///
+    pub fn multiple(
+        f: fn(f64, &V, &mut V),
+        bc: fn(&V, &V, &mut V),
+        nodes: Vec<f64>,
+        guesses: Vec<V>,
+        rtol: f64,
+        atol: f64,
+    ) -> Shooting<V> {
+        assert!(nodes.len() >= 2, "at least two nodes are needed");
+        assert_eq!(guesses.len(), nodes.len() - 1, "one guess is needed per segment");
+        let dx = (nodes[nodes.len() - 1] - nodes[0]) / 100.0;
+        Shooting {
+            f,
+            bc,
+            jac: None,
+            bc_jac: None,
+            nodes,
+            s: guesses,
+            dx,
+            rtol,
+            atol,
+            tol: 1.0e-10,
+            max_iterations: 50,
+            x_out: Vec::new(),
+            y_out: Vec::new(),
+            stats: BvpStats::new(),
+        }
+    }
+
+    /// Sets the function computing the Jacobian matrix of f with respect to y. If no Jacobian is
+    /// given, it is approximated by finite differences.
+    pub fn set_jacobian(&mut self, jac: fn(f64, &V, &mut DMatrix<f64>)) {
+        self.jac = Some(jac);
+    }
+
+    /// Sets the function computing the Jacobian matrices of the boundary conditions with respect
+    /// to y(a) and y(b). If none is given, they are approximated by finite differences.
+    pub fn set_bc_jacobian(&mut self, bc_jac: fn(&V, &V, &mut DMatrix<f64>, &mut DMatrix<f64>)) {
+        self.bc_jac = Some(bc_jac);
+    }
+
+    /// Sets the tolerance on the largest residual of the boundary and continuity conditions.
+    /// Default is 1e-10.
+    pub fn set_tolerance(&mut self, tol: f64) {
+        self.tol = tol;
+    }
+
+    /// Sets the maximum number of Newton iterations. Default is 50.
+    pub fn set_max_iterations(&mut self, max_iterations: u32) {
+        self.max_iterations = max_iterations;
+    }
+
+    /// Sets the increment of the output trajectory. Default is a hundredth of the interval.
+    pub fn set_dx(&mut self, dx: f64) {
+        self.dx = dx;
+    }
+
+    /// Solves the boundary value problem and computes the trajectory.
+    pub fn solve(&mut self) -> Result<BvpStats, BvpError> {
+        let dim = na::dimension::<V>();
+        let n_seg = self.s.len();
+        self.stats = BvpStats::new();
+
+        let mut z = DVector::zeros(n_seg * dim);
+        for i in 0..n_seg {
+            for j in 0..dim {
+                z[i * dim + j] = na::convert(self.s[i][j]);
+            }
+        }
+        let (mut res, mut jm) = self.residuals(&z).map_err(BvpError::Integration)?;
+
+        loop {
+            if res.amax() <= self.tol {
+                break;
+            }
+            if self.stats.iterations >= self.max_iterations {
+                return Err(BvpError::MaxIterationsReached {
+                    iterations: self.stats.iterations,
+                });
+            }
+            self.stats.iterations += 1;
+            let iterations = self.stats.iterations;
+
+            let delta = match jm.clone().lu().solve(&-&res) {
+                Some(delta) => delta,
+                None => return Err(BvpError::SingularJacobian { iterations }),
+            };
+
+            // Backtracking line search on the squared norm of the residuals
+            let phi = 0.5 * res.norm_squared();
+            let mut t = 1.0;
+            loop {
+                if t < 1.0e-6 {
+                    return Err(BvpError::LineSearchFailed { iterations });
+                }
+                let z_new = &z + &delta * t;
+                match self.residuals(&z_new) {
+                    Ok((res_new, jm_new)) => {
+                        if 0.5 * res_new.norm_squared() <= (1.0 - 2.0e-4 * t) * phi {
+                            z = z_new;
+                            res = res_new;
+                            jm = jm_new;
+                            break;
+                        }
+                    }
+                    Err(_) => self.stats.failed_integrations += 1,
+                }
+                t *= 0.5;
+            }
+            if t == 1.0 && delta.amax() <= self.tol * (1.0 + z.amax()) {
+                break;
+            }
+        }
+
+        for i in 0..n_seg {
+            self.s[i] = segment(&z, i);
+        }
+        self.trajectory().map_err(BvpError::Integration)?;
+        Ok(self.stats)
+    }
+
+    /// Computes the residuals of the continuity and boundary conditions and their Jacobian
+    /// matrix with respect to the values at the nodes.
+    fn residuals(&mut self, z: &DVector<f64>) -> Result<(DVector<f64>, DMatrix<f64>), IntegrationError> {
+        let dim = na::dimension::<V>();
+        let n_seg = self.s.len();
+        let mut res = DVector::zeros(n_seg * dim);
+        let mut jm = DMatrix::zeros(n_seg * dim, n_seg * dim);
+
+        let mut y_end = V::zero();
+        let mut phi_end = DMatrix::zeros(dim, dim);
+        for i in 0..n_seg {
+            let stepper = Dop853::sparse(
+                System::Plain(self.f),
+                self.nodes[i],
+                self.nodes[i + 1],
+                segment(z, i),
+                self.rtol,
+                self.atol,
+                100000,
+            );
+            let mut stm = StateTransition::from_stepper(stepper);
+            // The state transition matrices only steer Newton's method and do not need the
+            // accuracy of the solution, which a finite difference Jacobian could not reach
+            stm.set_error_control(false);
+            if let Some(jac) = self.jac {
+                stm.set_jacobian(jac);
+            }
+            self.stats.num_integrations += 1;
+            let stats = stm.integrate()?;
+            self.stats.num_eval += stats.num_eval;
+            let y_i = *stm.y_out().last().unwrap();
+            let phi_i = stm.phi_out().last().unwrap().clone();
+
+            if i + 1 < n_seg {
+                let y_next: V = segment(z, i + 1);
+                for j in 0..dim {
+                    let y_ij: f64 = na::convert(y_i[j]);
+                    let y_next_j: f64 = na::convert(y_next[j]);
+                    res[i * dim + j] = y_ij - y_next_j;
+                    jm[(i * dim + j, (i + 1) * dim + j)] = -1.0;
+                }
+                jm.slice_mut((i * dim, i * dim), (dim, dim)).copy_from(&phi_i);
+            } else {
+                y_end = y_i;
+                phi_end = phi_i;
+            }
+        }
+
+        // Boundary conditions in the last rows
+        let y_a: V = segment(z, 0);
+        let mut g = V::zero();
+        (self.bc)(&y_a, &y_end, &mut g);
+        let mut ga = DMatrix::zeros(dim, dim);
+        let mut gb = DMatrix::zeros(dim, dim);
+        match self.bc_jac {
+            Some(bc_jac) => bc_jac(&y_a, &y_end, &mut ga, &mut gb),
+            None => {
+                let bc = self.bc;
+                jacobian::forward_differences(|_, y, g| bc(y, &y_end, g), 0.0, &y_a, &g, &mut ga);
+                jacobian::forward_differences(|_, y, g| bc(&y_a, y, g), 0.0, &y_end, &g, &mut gb);
+            }
+        }
+        let row = (n_seg - 1) * dim;
+        for j in 0..dim {
+            res[row + j] = na::convert(g[j]);
+        }
+        let mut block = jm.slice_mut((row, 0), (dim, dim));
+        block += &ga;
+        let mut block = jm.slice_mut((row, row), (dim, dim));
+        block += gb * phi_end;
+        Ok((res, jm))
+    }
+
+    /// Integrates each segment from its converged initial value to build the trajectory.
+    fn trajectory(&mut self) -> Result<(), IntegrationError> {
+        self.x_out.clear();
+        self.y_out.clear();
+        for i in 0..self.s.len() {
+            let mut stepper = Dop853::new(
+                self.f,
+                self.nodes[i],
+                self.nodes[i + 1],
+                self.dx,
+                self.s[i],
+                self.rtol,
+                self.atol,
+            );
+            self.stats.num_integrations += 1;
+            let stats = stepper.integrate()?;
+            self.stats.num_eval += stats.num_eval;
+            for (x, y) in stepper.x_out().iter().zip(stepper.y_out()) {
+                // The first point of a segment repeats the last point of the previous one
+                if let Some(&x_last) = self.x_out.last() {
+                    if (x - x_last).abs() <= 1.0e-9 * self.dx.abs() {
+                        continue;
+                    }
+                }
+                self.x_out.push(*x);
+                self.y_out.push(*y);
+            }
+        }
+        Ok(())
+    }
+
+    /// Getter for the independent variable's output.
+    pub fn x_out(&self) -> &Vec<f64> {
+        &self.x_out
+    }
+
+    /// Getter for the dependent variables' output.
+    pub fn y_out(&self) -> &Vec<V> {
+        &self.y_out
+    }
+
+    /// Getter for the values of the solution at the M first nodes, i.e. y(a) for single shooting.
+    pub fn node_values(&self) -> &Vec<V> {
+        &self.s
+    }
+}
+
+/// Extracts the values at the start of segment i from the vector of unknowns.
+fn segment<V>(z: &DVector<f64>, i: usize) -> V
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: SubsetOf<f64>,
+{
+    let dim = na::dimension::<V>();
+    jacobian::from_dvector(&z.rows(i * dim, dim).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use na::Vector2;
+
+    // y'' = 3/2 y², y(0) = 4, y(1) = 1, solved by y = 4 / (1 + x)²
+    fn system(_x: f64, y: &Vector2<f64>, dy: &mut Vector2<f64>) {
+        dy[0] = y[1];
+        dy[1] = 1.5 * y[0] * y[0];
+    }
+
+    fn jacobian(_x: f64, y: &Vector2<f64>, jac: &mut DMatrix<f64>) {
+        jac[(0, 0)] = 0.0;
+        jac[(0, 1)] = 1.0;
+        jac[(1, 0)] = 3.0 * y[0];
+        jac[(1, 1)] = 0.0;
+    }
+
+    fn bc(ya: &Vector2<f64>, yb: &Vector2<f64>, g: &mut Vector2<f64>) {
+        g[0] = ya[0] - 4.0;
+        g[1] = yb[0] - 1.0;
+    }
+
+    fn bc_jacobian(
+        _ya: &Vector2<f64>,
+        _yb: &Vector2<f64>,
+        ga: &mut DMatrix<f64>,
+        gb: &mut DMatrix<f64>,
+    ) {
+        ga.fill(0.0);
+        gb.fill(0.0);
+        ga[(0, 0)] = 1.0;
+        gb[(1, 0)] = 1.0;
+    }
+
+    fn exact(x: f64) -> Vector2<f64> {
+        Vector2::new(4.0 / (1.0 + x).powi(2), -8.0 / (1.0 + x).powi(3))
+    }
+
+    fn check(shooting: &Shooting<Vector2<f64>>) {
+        assert!(shooting.x_out().len() >= 100);
+        for (i, x) in shooting.x_out().iter().enumerate() {
+            assert!((x - 0.01 * i as f64).abs() < 1.0e-12);
+        }
+        for (x, y) in shooting.x_out().iter().zip(shooting.y_out()) {
+            assert!((y - exact(*x)).norm() < 1.0e-7);
+        }
+    }
+
+    #[test]
+    fn single_shooting() {
+        let mut shooting = Shooting::new(
+            system,
+            bc,
+            0.0,
+            1.0,
+            Vector2::new(4.0, -6.0),
+            1.0e-12,
+            1.0e-12,
+        );
+        shooting.solve().unwrap();
+        assert!((shooting.node_values()[0] - exact(0.0)).norm() < 1.0e-8);
+        check(&shooting);
+    }
+
+    #[test]
+    fn multiple_shooting() {
+        // Guesses on the straight line between the boundary values
+        let nodes = vec![0.0, 0.25, 0.5, 0.75, 1.0];
+        let guesses = nodes[..4]
+            .iter()
+            .map(|x| Vector2::new(4.0 - 3.0 * x, -3.0))
+            .collect();
+        let mut shooting = Shooting::multiple(system, bc, nodes.clone(), guesses, 1.0e-12, 1.0e-12);
+        shooting.set_jacobian(jacobian);
+        shooting.set_bc_jacobian(bc_jacobian);
+        shooting.solve().unwrap();
+        for (x, s) in nodes.iter().zip(shooting.node_values()) {
+            assert!((s - exact(*x)).norm() < 1.0e-8);
+        }
+        check(&shooting);
+    }
+}
@@ -0,0 +1,784 @@
+#![allow(clippy::needless_range_loop)]
+
+//! Boundary value problems solved by collocation.
+//!
+//! The boundary value problem _y' = S y / (x - a) + f(x, y, p)_, _g(y(a), y(b), p) = 0_, where
+//! the singular term S y / (x - a) is optional and p are unknown parameters, is discretized on a
+//! mesh a = x<sub>0</sub> < ... < x<sub>N</sub> = b with the three-stage Lobatto IIIa formula,
+//! as in the bvp4c code of Kierzenka and Shampine. On each interval, the solution is approximated
+//! by the cubic polynomial interpolating the values and the derivatives at the end points, and
+//! collocation at the midpoint gives
+//!
+//! _y<sub>i+1</sub> - y<sub>i</sub> - h/6 (f<sub>i</sub> + 4 f(x<sub>i+1/2</sub>, y<sub>i+1/2</sub>) + f<sub>i+1</sub>) = 0_,
+//! _y<sub>i+1/2</sub> = (y<sub>i</sub> + y<sub>i+1</sub>)/2 - h/8 (f<sub>i+1</sub> - f<sub>i</sub>)_.
+//!
+//! The equations are solved by Newton's method with a backtracking line search. The linear systems
+//! are banded: the boundary values and the parameters are carried along the mesh as additional
+//! unknowns, which keeps general two-point boundary conditions local to the last mesh point.
+//! Once converged, the residual of the continuous solution in the differential equations is
+//! estimated on each interval, and the intervals where it exceeds the relative tolerance are
+//! split, until the residual is below the tolerance everywhere. Unlike shooting, the method does
+//! not integrate unstable initial value problems, which makes it suitable for boundary layers.
+
+use alga::general::SubsetOf;
+use alga::linear::{FiniteDimInnerSpace, InnerSpace};
+use bvp::BvpError;
+use jacobian;
+use na;
+use na::{DMatrix, DVector};
+use std::f64;
+use std::fmt;
+
+/// Contains some statistics of the collocation.
+#[derive(Clone, Copy, Debug)]
+pub struct CollocationStats {
+    pub iterations: u32,
+    pub mesh_refinements: u32,
+    pub mesh_points: usize,
+    pub num_eval: u32,
+}
+
+impl CollocationStats {
+    fn new() -> CollocationStats {
+        CollocationStats {
+            iterations: 0,
+            mesh_refinements: 0,
+            mesh_points: 0,
+            num_eval: 0,
+        }
+    }
+}
+
+impl fmt::Display for CollocationStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Number of Newton iterations: {}", self.iterations)?;
+        writeln!(f, "Number of mesh refinements: {}", self.mesh_refinements)?;
+        writeln!(f, "Number of mesh points: {}", self.mesh_points)?;
+        write!(f, "Number of function evaluations: {}", self.num_eval)
+    }
+}
+
+/// Continuous solution of a boundary value problem, piecewise cubic and continuously
+/// differentiable.
+pub struct ContinuousSolution<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+{
+    x: Vec<f64>,
+    y: Vec<V>,
+    dy: Vec<V>,
+    p: DVector<f64>,
+}
+
+impl<V> ContinuousSolution<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: SubsetOf<f64>,
+{
+    /// Value of the solution at x. Outside of the interval, the first or last cubic is
+    /// extrapolated.
+    pub fn y(&self, x: f64) -> V {
+        let i = self.interval(x);
+        let h = self.x[i + 1] - self.x[i];
+        hermite(&self.y[i], &self.dy[i], &self.y[i + 1], &self.dy[i + 1], h, (x - self.x[i]) / h).0
+    }
+
+    /// Derivative of the solution at x.
+    pub fn dy(&self, x: f64) -> V {
+        let i = self.interval(x);
+        let h = self.x[i + 1] - self.x[i];
+        hermite(&self.y[i], &self.dy[i], &self.y[i + 1], &self.dy[i + 1], h, (x - self.x[i]) / h).1
+    }
+
+    /// Getter for the final mesh.
+    pub fn mesh(&self) -> &Vec<f64> {
+        &self.x
+    }
+
+    /// Getter for the unknown parameters.
+    pub fn parameters(&self) -> &DVector<f64> {
+        &self.p
+    }
+
+    fn interval(&self, x: f64) -> usize {
+        let i = self.x.partition_point(|&x_i| x_i <= x);
+        i.max(1).min(self.x.len() - 1) - 1
+    }
+}
+
+/// Structure containing the parameters of the collocation method.
+pub struct Collocation<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+{
+    f: fn(f64, &V, &DVector<f64>, &mut V),
+    bc: fn(&V, &V, &DVector<f64>, &mut DVector<f64>),
+    #[allow(clippy::type_complexity)]
+    jac: Option<fn(f64, &V, &DVector<f64>, &mut DMatrix<f64>)>,
+    #[allow(clippy::type_complexity)]
+    dfdp: Option<fn(f64, &V, &DVector<f64>, &mut DMatrix<f64>)>,
+    singular: Option<(DMatrix<f64>, DMatrix<f64>)>,
+    mesh: Vec<f64>,
+    y: Vec<V>,
+    p: DVector<f64>,
+    rtol: f64,
+    atol: f64,
+    max_mesh_size: usize,
+    max_iterations: u32,
+    solution: Option<ContinuousSolution<V>>,
+    stats: CollocationStats,
+}
+
+impl<V> Collocation<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: SubsetOf<f64>,
+{
+    /// Default initializer for the structure, for a problem without unknown parameters.
+    ///
+    /// # Arguments
+    ///
+    /// * `f`       - Pointer to the function to integrate
+    /// * `bc`      - Function computing the residuals g(y(a), y(b), p) of the n + n<sub>p</sub> boundary conditions
+    /// * `mesh`    - Initial mesh a = x<sub>0</sub> < ... < x<sub>N</sub> = b
+    /// * `y_guess` - Guess of the solution at the mesh points
+    /// * `rtol`    - Relative tolerance on the residual
+    /// * `atol`    - Absolute tolerance on the residual
+    ///
+    pub fn new(
+        f: fn(f64, &V, &DVector<f64>, &mut V),
+        bc: fn(&V, &V, &DVector<f64>, &mut DVector<f64>),
+        mesh: Vec<f64>,
+        y_guess: Vec<V>,
+        rtol: f64,
+        atol: f64,
+    ) -> Collocation<V> {
+        assert!(mesh.len() >= 2, "at least two mesh points are needed");
+        assert_eq!(mesh.len(), y_guess.len(), "one guess is needed per mesh point");
+        assert!(
+            mesh.windows(2).all(|w| w[0] < w[1]),
+            "the mesh must be strictly increasing"
+        );
+        Collocation {
+            f,
+            bc,
+            jac: None,
+            dfdp: None,
+            singular: None,
+            mesh,
+            y: y_guess,
+            p: DVector::zeros(0),
+            rtol,
+            atol,
+            max_mesh_size: 10000 / na::dimension::<V>(),
+            max_iterations: 20,
+            solution: None,
+            stats: CollocationStats::new(),
+        }
+    }
+
+    /// Sets the guess of the unknown parameters. The boundary conditions must then give
+    /// n + n<sub>p</sub> residuals.
+    pub fn set_parameters(&mut self, p_guess: DVector<f64>) {
+        self.p = p_guess;
+    }
+
+    /// Sets the function computing the Jacobian matrix of f with respect to y. If no Jacobian is
+    /// given, it is approximated by finite differences.
+    pub fn set_jacobian(&mut self, jac: fn(f64, &V, &DVector<f64>, &mut DMatrix<f64>)) {
+        self.jac = Some(jac);
+    }
+
+    /// Sets the function computing the n×n<sub>p</sub> matrix ∂f/∂p. If none is given, it is
+    /// approximated by finite differences.
+    pub fn set_parameter_jacobian(&mut self, dfdp: fn(f64, &V, &DVector<f64>, &mut DMatrix<f64>)) {
+        self.dfdp = Some(dfdp);
+    }
+
+    /// Adds the singular term S y / (x - a) to the differential equations. The boundary conditions
+    /// must imply S y(a) = 0, and y'(a) is then given by (I - S)<sup>-1</sup> f(a, y(a), p). The
+    /// solution fails if I - S is not invertible.
+    pub fn set_singular_term(&mut self, s: DMatrix<f64>) {
+        // (I - S)^-1 is computed by solve
+        let dim = na::dimension::<V>();
+        self.singular = Some((s, DMatrix::zeros(dim, dim)));
+    }
+
+    /// Sets the maximum number of mesh points. Default is 10000 / n.
+    pub fn set_max_mesh_size(&mut self, max_mesh_size: usize) {
+        self.max_mesh_size = max_mesh_size;
+    }
+
+    /// Sets the maximum number of Newton iterations on each mesh. Default is 20.
+    pub fn set_max_iterations(&mut self, max_iterations: u32) {
+        self.max_iterations = max_iterations;
+    }
+
+    /// Solves the boundary value problem, refining the mesh until the residual is below the
+    /// tolerance.
+    pub fn solve(&mut self) -> Result<CollocationStats, BvpError> {
+        let dim = na::dimension::<V>();
+        let n_p = self.p.len();
+        let m = 2 * dim + n_p;
+        self.stats = CollocationStats::new();
+        self.solution = None;
+        if let Some((ref s, ref mut d)) = self.singular {
+            *d = (DMatrix::identity(dim, dim) - s)
+                .try_inverse()
+                .ok_or(BvpError::SingularTerm)?;
+        }
+
+        loop {
+            // Unknowns at each mesh point: y, y(a) and p
+            let n_mesh = self.mesh.len();
+            let mut w = DVector::zeros(n_mesh * m);
+            for i in 0..n_mesh {
+                for j in 0..dim {
+                    w[i * m + j] = na::convert(self.y[i][j]);
+                    w[i * m + dim + j] = na::convert(self.y[0][j]);
+                }
+                for j in 0..n_p {
+                    w[i * m + 2 * dim + j] = self.p[j];
+                }
+            }
+            self.stats.mesh_points = n_mesh;
+            self.newton(&mut w)?;
+            for i in 0..n_mesh {
+                self.y[i] = jacobian::from_dvector(&w.rows(i * m, dim).into_owned());
+            }
+            self.p = w.rows(2 * dim, n_p).into_owned();
+
+            // Residual of the continuous solution on each interval
+            let p = self.p.clone();
+            let mut dy = Vec::with_capacity(n_mesh);
+            for i in 0..n_mesh {
+                let (x_i, y_i) = (self.mesh[i], self.y[i]);
+                dy.push(self.rhs(x_i, &y_i, &p));
+            }
+            let mut mesh = vec![self.mesh[0]];
+            let mut y = vec![self.y[0]];
+            let mut refined = false;
+            for i in 0..n_mesh - 1 {
+                let h = self.mesh[i + 1] - self.mesh[i];
+                let res = self.interval_residual(i, &dy, &p);
+                let n_new = if res > 100.0 * self.rtol {
+                    2
+                } else if res > self.rtol {
+                    1
+                } else {
+                    0
+                };
+                for k in 1..=n_new {
+                    let t = k as f64 / (n_new + 1) as f64;
+                    mesh.push(self.mesh[i] + t * h);
+                    y.push(hermite(&self.y[i], &dy[i], &self.y[i + 1], &dy[i + 1], h, t).0);
+                    refined = true;
+                }
+                mesh.push(self.mesh[i + 1]);
+                y.push(self.y[i + 1]);
+            }
+
+            if !refined {
+                self.solution = Some(ContinuousSolution {
+                    x: self.mesh.clone(),
+                    y: self.y.clone(),
+                    dy,
+                    p,
+                });
+                return Ok(self.stats);
+            }
+            if mesh.len() > self.max_mesh_size {
+                return Err(BvpError::MaxMeshSizeReached {
+                    points: self.max_mesh_size,
+                });
+            }
+            self.stats.mesh_refinements += 1;
+            self.mesh = mesh;
+            self.y = y;
+        }
+    }
+
+    /// Solves the collocation equations on the current mesh by Newton's method.
+    fn newton(&mut self, w: &mut DVector<f64>) -> Result<(), BvpError> {
+        let mut n_iter = 0;
+        loop {
+            if n_iter >= self.max_iterations {
+                return Err(BvpError::MaxIterationsReached {
+                    iterations: self.stats.iterations,
+                });
+            }
+            n_iter += 1;
+            self.stats.iterations += 1;
+            let iterations = self.stats.iterations;
+
+            let (res, jm) = self.residuals(w, true);
+            let mut delta = -&res;
+            if !jm.unwrap().solve(&mut delta) {
+                return Err(BvpError::SingularJacobian { iterations });
+            }
+            let step = delta
+                .iter()
+                .zip(w.iter())
+                .fold(0.0_f64, |acc, (d, w)| acc.max(d.abs() / (self.atol + self.rtol * w.abs())));
+            if step <= 1.0e-2 {
+                *w += delta;
+                return Ok(());
+            }
+
+            // Backtracking line search on the squared norm of the residuals
+            let phi = 0.5 * res.norm_squared();
+            let mut t = 1.0;
+            loop {
+                if t < 1.0e-6 {
+                    return Err(BvpError::LineSearchFailed { iterations });
+                }
+                let w_new = &*w + &delta * t;
+                let (res_new, _) = self.residuals(&w_new, false);
+                if 0.5 * res_new.norm_squared() <= (1.0 - 2.0e-4 * t) * phi {
+                    *w = w_new;
+                    break;
+                }
+                t *= 0.5;
+            }
+        }
+    }
+
+    /// Computes the residuals of the collocation equations and, if requested, their banded
+    /// Jacobian matrix.
+    fn residuals(&mut self, w: &DVector<f64>, with_jacobian: bool) -> (DVector<f64>, Option<Banded>) {
+        let dim = na::dimension::<V>();
+        let n_p = self.p.len();
+        let m = 2 * dim + n_p;
+        let n_mesh = self.mesh.len();
+        let mut res = DVector::zeros(n_mesh * m);
+        let mut jm = if with_jacobian {
+            Some(Banded::new(n_mesh * m, m - 1, m - 1))
+        } else {
+            None
+        };
+        let y_at = |i: usize| -> V { jacobian::from_dvector(&w.rows(i * m, dim).into_owned()) };
+        let p_at = |i: usize| w.rows(i * m + 2 * dim, n_p).into_owned();
+
+        // Copy of y(a) at the first mesh point
+        for j in 0..dim {
+            res[j] = w[dim + j] - w[j];
+            if let Some(ref mut jm) = jm {
+                jm.add(j, j, -1.0);
+                jm.add(j, dim + j, 1.0);
+            }
+        }
+
+        for i in 0..n_mesh - 1 {
+            let row = dim + i * m;
+            let (x0, x1) = (self.mesh[i], self.mesh[i + 1]);
+            let h = x1 - x0;
+            let x_mid = x0 + 0.5 * h;
+            let (y0, y1, p) = (y_at(i), y_at(i + 1), p_at(i));
+            let f0 = self.rhs(x0, &y0, &p);
+            let f1 = self.rhs(x1, &y1, &p);
+            let y_mid = (y0 + y1) * na::convert(0.5) - (f1 - f0) * na::convert(h / 8.0);
+            let f_mid = self.rhs(x_mid, &y_mid, &p);
+            let phi = y1 - y0 - (f0 + f_mid * na::convert(4.0) + f1) * na::convert(h / 6.0);
+            for j in 0..dim {
+                res[row + j] = na::convert(phi[j]);
+                res[row + dim + j] = w[(i + 1) * m + dim + j] - w[i * m + dim + j];
+            }
+            for j in 0..n_p {
+                res[row + 2 * dim + j] = w[(i + 1) * m + 2 * dim + j] - w[i * m + 2 * dim + j];
+            }
+
+            if let Some(ref mut jm) = jm {
+                let (j0, fp0) = self.rhs_jacobians(x0, &y0, &p);
+                let (j1, fp1) = self.rhs_jacobians(x1, &y1, &p);
+                let (j_mid, fp_mid) = self.rhs_jacobians(x_mid, &y_mid, &p);
+                let id = DMatrix::<f64>::identity(dim, dim);
+                let dmid_dy0 = &id * 0.5 + &j0 * (h / 8.0);
+                let dmid_dy1 = &id * 0.5 - &j1 * (h / 8.0);
+                let dmid_dp = (&fp1 - &fp0) * (-h / 8.0);
+                let dphi_dy0 = -&id - (&j0 + &j_mid * dmid_dy0 * 4.0) * (h / 6.0);
+                let dphi_dy1 = &id - (&j1 + &j_mid * dmid_dy1 * 4.0) * (h / 6.0);
+                let dphi_dp = -(fp0 + (fp_mid + &j_mid * dmid_dp) * 4.0 + fp1) * (h / 6.0);
+                for r in 0..dim {
+                    for c in 0..dim {
+                        jm.add(row + r, i * m + c, dphi_dy0[(r, c)]);
+                        jm.add(row + r, (i + 1) * m + c, dphi_dy1[(r, c)]);
+                    }
+                    for c in 0..n_p {
+                        jm.add(row + r, i * m + 2 * dim + c, dphi_dp[(r, c)]);
+                    }
+                }
+                for k in dim..m {
+                    jm.add(row + k, i * m + k, -1.0);
+                    jm.add(row + k, (i + 1) * m + k, 1.0);
+                }
+            }
+        }
+
+        // Boundary conditions at the last mesh point, with the copy of y(a)
+        let last = (n_mesh - 1) * m;
+        let row = dim + (n_mesh - 1) * m;
+        let g = self.boundary_residuals(&w.rows(last, m).into_owned());
+        res.rows_mut(row, dim + n_p).copy_from(&g);
+        if let Some(ref mut jm) = jm {
+            let mut w_pert = w.rows(last, m).into_owned();
+            for c in 0..m {
+                let w_c = w_pert[c];
+                let delta = (f64::EPSILON * 1.0e-5_f64.max(w_c.abs())).sqrt();
+                w_pert[c] = w_c + delta;
+                let g_pert = self.boundary_residuals(&w_pert);
+                w_pert[c] = w_c;
+                for r in 0..dim + n_p {
+                    jm.add(row + r, last + c, (g_pert[r] - g[r]) / delta);
+                }
+            }
+        }
+        (res, jm)
+    }
+
+    /// Residuals of the boundary conditions for the unknowns (y(b), y(a), p) at the last mesh point.
+    fn boundary_residuals(&self, w_last: &DVector<f64>) -> DVector<f64> {
+        let dim = na::dimension::<V>();
+        let n_p = self.p.len();
+        let y_b: V = jacobian::from_dvector(&w_last.rows(0, dim).into_owned());
+        let y_a: V = jacobian::from_dvector(&w_last.rows(dim, dim).into_owned());
+        let p = w_last.rows(2 * dim, n_p).into_owned();
+        let mut g = DVector::zeros(dim + n_p);
+        (self.bc)(&y_a, &y_b, &p, &mut g);
+        g
+    }
+
+    /// Right-hand side of the differential equations, including the singular term.
+    fn rhs(&mut self, x: f64, y: &V, p: &DVector<f64>) -> V {
+        let mut fy = V::zero();
+        (self.f)(x, y, p, &mut fy);
+        self.stats.num_eval += 1;
+        if let Some((ref s, ref d)) = self.singular {
+            let a = self.mesh[0];
+            if x == a {
+                fy = jacobian::from_dvector(&(d * jacobian::to_dvector(&fy)));
+            } else {
+                fy += jacobian::from_dvector(&(s * jacobian::to_dvector(y) / (x - a)));
+            }
+        }
+        fy
+    }
+
+    /// Jacobian matrices of the right-hand side with respect to y and p, including the singular
+    /// term.
+    fn rhs_jacobians(&mut self, x: f64, y: &V, p: &DVector<f64>) -> (DMatrix<f64>, DMatrix<f64>) {
+        let dim = na::dimension::<V>();
+        let n_p = p.len();
+        let f = self.f;
+        let mut jac = DMatrix::zeros(dim, dim);
+        let mut dfdp = DMatrix::zeros(dim, n_p);
+        let mut f0 = V::zero();
+        if self.jac.is_none() || (self.dfdp.is_none() && n_p > 0) {
+            f(x, y, p, &mut f0);
+            self.stats.num_eval += 1;
+        }
+        match self.jac {
+            Some(jac_fn) => jac_fn(x, y, p, &mut jac),
+            None => {
+                self.stats.num_eval +=
+                    jacobian::forward_differences(|x, y, dy| f(x, y, p, dy), x, y, &f0, &mut jac)
+            }
+        }
+        match self.dfdp {
+            Some(dfdp_fn) => dfdp_fn(x, y, p, &mut dfdp),
+            None => {
+                let mut p_pert = p.clone();
+                let mut f_pert = V::zero();
+                for c in 0..n_p {
+                    let delta = (f64::EPSILON * 1.0e-5_f64.max(p[c].abs())).sqrt();
+                    p_pert[c] = p[c] + delta;
+                    f(x, y, &p_pert, &mut f_pert);
+                    self.stats.num_eval += 1;
+                    p_pert[c] = p[c];
+                    for r in 0..dim {
+                        let f_r: f64 = na::convert(f_pert[r]);
+                        let f0_r: f64 = na::convert(f0[r]);
+                        dfdp[(r, c)] = (f_r - f0_r) / delta;
+                    }
+                }
+            }
+        }
+        if let Some((ref s, ref d)) = self.singular {
+            let a = self.mesh[0];
+            if x == a {
+                jac = d * jac;
+                dfdp = d * dfdp;
+            } else {
+                jac += s / (x - a);
+            }
+        }
+        (jac, dfdp)
+    }
+
+    /// Estimates the residual of the continuous solution on interval i, relative to the size of
+    /// the derivatives. The residual vanishes at the end points and at the midpoint, and is
+    /// sampled at the two remaining points of the five-point Lobatto rule.
+    fn interval_residual(&mut self, i: usize, dy: &[V], p: &DVector<f64>) -> f64 {
+        let dim = na::dimension::<V>();
+        let h = self.mesh[i + 1] - self.mesh[i];
+        let mut sum = 0.0;
+        for &t in &[0.5 - 21.0_f64.sqrt() / 14.0, 0.5 + 21.0_f64.sqrt() / 14.0] {
+            let (y, s) = hermite(&self.y[i], &dy[i], &self.y[i + 1], &dy[i + 1], h, t);
+            let fy = self.rhs(self.mesh[i] + t * h, &y, p);
+            let mut rho = 0.0_f64;
+            for j in 0..dim {
+                let s_j: f64 = na::convert(s[j]);
+                let f_j: f64 = na::convert(fy[j]);
+                rho = rho.max((s_j - f_j).abs() / f_j.abs().max(self.atol / self.rtol));
+            }
+            sum += rho * rho;
+        }
+        (0.5 * sum).sqrt()
+    }
+
+    /// Getter for the mesh points.
+    pub fn x_out(&self) -> &Vec<f64> {
+        &self.mesh
+    }
+
+    /// Getter for the solution at the mesh points.
+    pub fn y_out(&self) -> &Vec<V> {
+        &self.y
+    }
+
+    /// Getter for the unknown parameters.
+    pub fn parameters(&self) -> &DVector<f64> {
+        &self.p
+    }
+
+    /// Getter for the continuous solution, available once the problem is solved.
+    pub fn solution(&self) -> Option<&ContinuousSolution<V>> {
+        self.solution.as_ref()
+    }
+}
+
+/// Value and derivative at x<sub>0</sub> + t h of the cubic interpolating the values and the
+/// derivatives at the ends of an interval of length h.
+fn hermite<V>(y0: &V, f0: &V, y1: &V, f1: &V, h: f64, t: f64) -> (V, V)
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: SubsetOf<f64>,
+{
+    let t2 = t * t;
+    let t3 = t2 * t;
+    let y = *y0 * na::convert(2.0 * t3 - 3.0 * t2 + 1.0)
+        + *f0 * na::convert(h * (t3 - 2.0 * t2 + t))
+        + *y1 * na::convert(3.0 * t2 - 2.0 * t3)
+        + *f1 * na::convert(h * (t3 - t2));
+    let dy = (*y1 - *y0) * na::convert(6.0 * (t - t2) / h)
+        + *f0 * na::convert(3.0 * t2 - 4.0 * t + 1.0)
+        + *f1 * na::convert(3.0 * t2 - 2.0 * t);
+    (y, dy)
+}
+
+/// Band matrix with kl subdiagonals and ku superdiagonals, with room for the fill-in of the LU
+/// factorization with partial pivoting.
+struct Banded {
+    n: usize,
+    kl: usize,
+    ku: usize,
+    data: Vec<f64>,
+}
+
+impl Banded {
+    fn new(n: usize, kl: usize, ku: usize) -> Banded {
+        Banded {
+            n,
+            kl,
+            ku,
+            data: vec![0.0; n * (2 * kl + ku + 1)],
+        }
+    }
+
+    fn index(&self, i: usize, j: usize) -> usize {
+        i * (2 * self.kl + self.ku + 1) + j + self.kl - i
+    }
+
+    fn add(&mut self, i: usize, j: usize, value: f64) {
+        let k = self.index(i, j);
+        self.data[k] += value;
+    }
+
+    fn get(&self, i: usize, j: usize) -> f64 {
+        self.data[self.index(i, j)]
+    }
+
+    /// Solves the system by Gaussian elimination with partial pivoting, overwriting `b` with the
+    /// solution. Returns false if the matrix is singular.
+    fn solve(mut self, b: &mut DVector<f64>) -> bool {
+        let n = self.n;
+        for k in 0..n {
+            let last = (k + self.kl).min(n - 1);
+            let end = (k + self.kl + self.ku).min(n - 1);
+            let mut pivot = k;
+            for r in k + 1..=last {
+                if self.get(r, k).abs() > self.get(pivot, k).abs() {
+                    pivot = r;
+                }
+            }
+            if self.get(pivot, k) == 0.0 {
+                return false;
+            }
+            if pivot != k {
+                for c in k..=end {
+                    let (i_k, i_p) = (self.index(k, c), self.index(pivot, c));
+                    self.data.swap(i_k, i_p);
+                }
+                b.swap_rows(k, pivot);
+            }
+            let a_kk = self.get(k, k);
+            for r in k + 1..=last {
+                let l = self.get(r, k) / a_kk;
+                if l != 0.0 {
+                    for c in k..=end {
+                        let a_kc = self.get(k, c);
+                        self.add(r, c, -l * a_kc);
+                    }
+                    b[r] -= l * b[k];
+                }
+            }
+        }
+        for k in (0..n).rev() {
+            let end = (k + self.kl + self.ku).min(n - 1);
+            let mut sum = b[k];
+            for c in k + 1..=end {
+                sum -= self.get(k, c) * b[c];
+            }
+            b[k] = sum / self.get(k, k);
+        }
+        b.iter().all(|v| v.is_finite())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use na::Vector2;
+    use std::f64::consts::PI;
+
+    const EPS: f64 = 0.01;
+
+    // Boundary layer ε y'' + y' = 0, y(0) = 0, y(1) = 1
+    fn boundary_layer(_x: f64, y: &Vector2<f64>, _p: &DVector<f64>, dy: &mut Vector2<f64>) {
+        dy[0] = y[1];
+        dy[1] = -y[1] / EPS;
+    }
+
+    fn boundary_layer_bc(
+        ya: &Vector2<f64>,
+        yb: &Vector2<f64>,
+        _p: &DVector<f64>,
+        g: &mut DVector<f64>,
+    ) {
+        g[0] = ya[0];
+        g[1] = yb[0] - 1.0;
+    }
+
+    #[test]
+    fn boundary_layer_solution() {
+        let mesh: Vec<f64> = (0..11).map(|i| 0.1 * i as f64).collect();
+        let guess = mesh.iter().map(|x| Vector2::new(*x, 1.0)).collect();
+        let mut collocation = Collocation::new(
+            boundary_layer,
+            boundary_layer_bc,
+            mesh,
+            guess,
+            1.0e-8,
+            1.0e-8,
+        );
+        let stats = collocation.solve().unwrap();
+        assert!(stats.mesh_refinements > 0);
+
+        let solution = collocation.solution().unwrap();
+        let scale = 1.0 - (-1.0 / EPS).exp();
+        for i in 0..=1000 {
+            let x = 0.001 * i as f64;
+            let y = (1.0 - (-x / EPS).exp()) / scale;
+            assert!((solution.y(x)[0] - y).abs() < 1.0e-6);
+        }
+    }
+
+    // y'' + p y = 0, y(0) = 0, y'(0) = 1, y(π) = 0, whose first eigenvalue is p = 1 with y = sin x
+    fn oscillator(_x: f64, y: &Vector2<f64>, p: &DVector<f64>, dy: &mut Vector2<f64>) {
+        dy[0] = y[1];
+        dy[1] = -p[0] * y[0];
+    }
+
+    fn eigenvalue_bc(
+        ya: &Vector2<f64>,
+        yb: &Vector2<f64>,
+        _p: &DVector<f64>,
+        g: &mut DVector<f64>,
+    ) {
+        g[0] = ya[0];
+        g[1] = ya[1] - 1.0;
+        g[2] = yb[0];
+    }
+
+    #[test]
+    fn eigenvalue() {
+        let mesh: Vec<f64> = (0..11).map(|i| 0.1 * PI * i as f64).collect();
+        let guess = mesh
+            .iter()
+            .map(|x| Vector2::new(x * (PI - x) / PI, 1.0 - 2.0 * x / PI))
+            .collect();
+        let mut collocation =
+            Collocation::new(oscillator, eigenvalue_bc, mesh, guess, 1.0e-8, 1.0e-8);
+        collocation.set_parameters(DVector::from_column_slice(&[1.3]));
+        collocation.solve().unwrap();
+        assert!((collocation.parameters()[0] - 1.0).abs() < 1.0e-8);
+        for (x, y) in collocation.x_out().iter().zip(collocation.y_out()) {
+            assert!((y - Vector2::new(x.sin(), x.cos())).norm() < 1.0e-7);
+        }
+    }
+
+    // Lane-Emden equation of index 1, y'' + 2/x y' + y = 0, y'(0) = 0, solved by sin(x) / x
+    fn lane_emden(_x: f64, y: &Vector2<f64>, _p: &DVector<f64>, dy: &mut Vector2<f64>) {
+        dy[0] = y[1];
+        dy[1] = -y[0];
+    }
+
+    fn lane_emden_bc(
+        ya: &Vector2<f64>,
+        yb: &Vector2<f64>,
+        _p: &DVector<f64>,
+        g: &mut DVector<f64>,
+    ) {
+        g[0] = ya[1];
+        g[1] = yb[0] - 1.0_f64.sin();
+    }
+
+    #[test]
+    fn singular_term() {
+        let mesh: Vec<f64> = (0..11).map(|i| 0.1 * i as f64).collect();
+        let guess = vec![Vector2::new(1.0, 0.0); mesh.len()];
+        let mut collocation =
+            Collocation::new(lane_emden, lane_emden_bc, mesh, guess, 1.0e-8, 1.0e-8);
+        collocation.set_singular_term(DMatrix::from_row_slice(2, 2, &[0.0, 0.0, 0.0, -2.0]));
+        collocation.solve().unwrap();
+        let solution = collocation.solution().unwrap();
+        assert!((solution.y(0.0)[0] - 1.0).abs() < 1.0e-7);
+        for i in 1..=100 {
+            let x = 0.01 * i as f64;
+            let y = Vector2::new(x.sin() / x, (x * x.cos() - x.sin()) / (x * x));
+            assert!((solution.y(x) - y).norm() < 1.0e-7);
+        }
+    }
+
+    #[test]
+    fn singular_term_without_derivative_at_a() {
+        let mesh: Vec<f64> = (0..11).map(|i| 0.1 * i as f64).collect();
+        let guess = vec![Vector2::new(1.0, 0.0); mesh.len()];
+        let mut collocation =
+            Collocation::new(lane_emden, lane_emden_bc, mesh, guess, 1.0e-8, 1.0e-8);
+        collocation.set_singular_term(DMatrix::from_row_slice(2, 2, &[0.0, 0.0, 0.0, 1.0]));
+        match collocation.solve() {
+            Err(BvpError::SingularTerm) => {}
+            _ => panic!("I - S is singular"),
+        }
+    }
+}
@@ -51,6 +51,8 @@ use butcher_tableau::Dopri853;
 use controller::Controller;
 use dop_shared::*;
 use na;
+use na::DVector;
+use sensitivity::{combine, error_sum, Sensitivity};
 use std::f64;
 use std::f64::EPSILON;
 
@@ -77,7 +79,7 @@ pub struct Dop853<V>
 where
     V: FiniteDimInnerSpace + Copy,
 {
-    f: fn(f64, &V, &mut V),
+    f: System<V>,
     x: f64,
     x0: f64,
     x_old: f64,
@@ -99,12 +101,17 @@ where
     out_type: OutputType,
     rcont: [V; 8],
     stats: Stats,
+    sensitivity: Option<Sensitivity<V>>,
+    s: Vec<V>,
+    s_out: Vec<Vec<V>>,
+    rcont_s: Vec<[V; 8]>,
+    continuous: Option<ContinuousOutput<V>>,
 }
 
 impl<V> Dop853<V>
 where
     V: FiniteDimInnerSpace + Copy,
-    <V as InnerSpace>::Real: SubsetOf<f64>,
+    <V as InnerSpace>::Real: Value,
 {
     /// Default initializer for the structure.
     ///
@@ -126,6 +133,35 @@ where
         y: V,
         rtol: f64,
         atol: f64,
+    ) -> Dop853<V> {
+        Dop853::from_system(System::Plain(f), x, x_end, dx, y, rtol, atol)
+    }
+
+    /// Initializer for a system depending on parameters p, which are passed to f at every
+    /// evaluation. The other arguments are the same as in `new`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn with_parameters(
+        f: fn(f64, &V, &DVector<f64>, &mut V),
+        p: DVector<f64>,
+        x: f64,
+        x_end: f64,
+        dx: f64,
+        y: V,
+        rtol: f64,
+        atol: f64,
+    ) -> Dop853<V> {
+        Dop853::from_system(System::Parametric(f, p), x, x_end, dx, y, rtol, atol)
+    }
+
+    /// Initializer with the default parameters for any kind of system.
+    fn from_system(
+        f: System<V>,
+        x: f64,
+        x_end: f64,
+        dx: f64,
+        y: V,
+        rtol: f64,
+        atol: f64,
     ) -> Dop853<V> {
         Dop853 {
             f,
@@ -150,6 +186,11 @@ where
             out_type: OutputType::Dense,
             rcont: [V::zero(); 8],
             stats: Stats::new(),
+            sensitivity: None,
+            s: Vec::new(),
+            s_out: Vec::new(),
+            rcont_s: Vec::new(),
+            continuous: None,
         }
     }
 
@@ -195,7 +236,7 @@ where
     ) -> Dop853<V> {
         let alpha = 1.0 / 8.0 - beta * 0.2;
         Dop853 {
-            f,
+            f: System::Plain(f),
             x,
             x0: x,
             xd: x,
@@ -225,13 +266,35 @@ where
             out_type,
             rcont: [V::zero(); 8],
             stats: Stats::new(),
+            sensitivity: None,
+            s: Vec::new(),
+            s_out: Vec::new(),
+            rcont_s: Vec::new(),
+            continuous: None,
         }
     }
 
+    /// Initializer with the default parameters and a sparse output, for the solvers of the crate
+    /// built on Dop853.
+    pub(crate) fn sparse(
+        f: System<V>,
+        x: f64,
+        x_end: f64,
+        y: V,
+        rtol: f64,
+        atol: f64,
+        n_max: u32,
+    ) -> Dop853<V> {
+        let mut stepper = Dop853::from_system(f, x, x_end, x_end - x, y, rtol, atol);
+        stepper.out_type = OutputType::Sparse;
+        stepper.n_max = n_max;
+        stepper
+    }
+
     /// Compute the initial stepsize
     fn hinit(&self) -> f64 {
         let mut f0 = V::zero();
-        (self.f)(self.x, &self.y, &mut f0);
+        self.f.eval(self.x, &self.y, &mut f0);
         let posneg = sign(1.0, self.x_end - self.x);
 
         // Compute the norm of y0 and f0
@@ -239,10 +302,10 @@ where
         let mut d0 = 0.0;
         let mut d1 = 0.0;
         for i in 0..dim {
-            let y_i: f64 = na::convert(self.y[i]);
+            let y_i = self.y[i].value();
             let sci: f64 = self.atol + y_i.abs() * self.rtol;
             d0 += (y_i / sci) * (y_i / sci);
-            let f0_i: f64 = na::convert(f0[i]);
+            let f0_i = f0[i].value();
             d1 += (f0_i / sci) * (f0_i / sci);
         }
 
@@ -258,14 +321,14 @@ where
 
         let y1 = self.y + f0 * na::convert(h0);
         let mut f1 = V::zero();
-        (self.f)(self.x + h0, &y1, &mut f1);
+        self.f.eval(self.x + h0, &y1, &mut f1);
 
         // Compute the norm of f1-f0 divided by h0
         let mut d2: f64 = 0.0;
         for i in 0..dim {
-            let f0_i: f64 = na::convert(f0[i]);
-            let f1_i: f64 = na::convert(f1[i]);
-            let y_i: f64 = na::convert(self.y[i]);
+            let f0_i = f0[i].value();
+            let f1_i = f1[i].value();
+            let y_i = self.y[i].value();
             let sci: f64 = self.atol + y_i.abs() * self.rtol;
             d2 += ((f1_i - f0_i) / sci) * ((f1_i - f0_i) / sci);
         }
@@ -283,6 +346,25 @@ where
         )
     }
 
+    /// Stores (or not) the continuous extension of every accepted step, so that the solution can be
+    /// evaluated at any point after the integration.
+    pub fn set_continuous_output(&mut self, store: bool) {
+        self.continuous = if store {
+            Some(ContinuousOutput::new(sign(1.0, self.x_end - self.x), 8))
+        } else {
+            None
+        };
+    }
+
+    /// Computes the derivatives of the sensitivities s at (x, y), if any.
+    fn sensitivity_derivatives(&mut self, x: f64, y: &V, fy: &V, s: &[V], ds: &mut Vec<V>) {
+        if let Some(ref mut sensitivity) = self.sensitivity {
+            let f = &self.f;
+            self.stats.num_eval +=
+                sensitivity.derivatives(|x, y: &V, dy: &mut V| f.eval(x, y, dy), x, y, fy, s, ds);
+        }
+    }
+
     /// Core integration method.
     pub fn integrate(&mut self) -> Result<Stats, IntegrationError> {
         // Initialization
@@ -301,14 +383,27 @@ where
         }
         self.h_old = self.h;
 
+        let (n_s, error_control) = match self.sensitivity {
+            Some(ref sensitivity) => {
+                self.s = sensitivity.initial_values();
+                (self.s.len(), sensitivity.error_control())
+            }
+            None => (0, false),
+        };
+        self.rcont_s = vec![[V::zero(); 8]; n_s];
+
         // Save initial values
         let y_tmp = self.y;
         self.solution_output(y_tmp);
 
         let mut k: Vec<V> = vec![V::zero(); 12];
-        (self.f)(self.x, &self.y, &mut k[0]);
+        self.f.eval(self.x, &self.y, &mut k[0]);
         self.stats.num_eval += 1;
 
+        let mut ks: Vec<Vec<V>> = vec![Vec::new(); 12];
+        let (x, y, s) = (self.x, self.y, self.s.clone());
+        self.sensitivity_derivatives(x, &y, &k[0], &s, &mut ks[0]);
+
         // Main loop
         while !last {
             // Check if step number is within allowed range
@@ -337,10 +432,19 @@ where
                 for j in 0..s {
                     y_next += k[j] * na::convert(self.h * self.coeffs.a(s + 1, j + 1));
                 }
-                (self.f)(self.x + self.h * self.coeffs.c(s + 1), &y_next, &mut k[s]);
+                self.f.eval(self.x + self.h * self.coeffs.c(s + 1), &y_next, &mut k[s]);
+                if n_s > 0 {
+                    let weights: Vec<(usize, f64)> =
+                        (0..s).map(|j| (j, self.coeffs.a(s + 1, j + 1))).collect();
+                    let s_stage = combine(&self.s, &ks, &weights, self.h);
+                    let x_s = self.x + self.h * self.coeffs.c(s + 1);
+                    self.sensitivity_derivatives(x_s, &y_next, &k[s], &s_stage, &mut ks[s]);
+                }
             }
             k[1] = k[10];
             k[2] = k[11];
+            ks[1] = ks[10].clone();
+            ks[2] = ks[11].clone();
             self.stats.num_eval += 11;
 
             k[3] = k[0] * na::convert(self.coeffs.b(1))
@@ -352,6 +456,17 @@ where
                 + k[1] * na::convert(self.coeffs.b(11))
                 + k[2] * na::convert(self.coeffs.b(12));
             k[4] = self.y + k[3] * na::convert(self.h);
+            let weights = [
+                (0, self.coeffs.b(1)),
+                (5, self.coeffs.b(6)),
+                (6, self.coeffs.b(7)),
+                (7, self.coeffs.b(8)),
+                (8, self.coeffs.b(9)),
+                (9, self.coeffs.b(10)),
+                (1, self.coeffs.b(11)),
+                (2, self.coeffs.b(12)),
+            ];
+            let s_next = combine(&self.s, &ks, &weights, self.h);
 
             // Error estimate
             let mut err_est = V::zero();
@@ -367,34 +482,56 @@ where
                 - k[8] * na::convert(self.coeffs.bhh(2))
                 - k[2] * na::convert(self.coeffs.bhh(3));
             for i in 0..dim {
-                let y_i: f64 = na::convert(self.y[i]);
-                let k5_i: f64 = na::convert(k[4][i]);
+                let y_i = self.y[i].value();
+                let k5_i = k[4][i].value();
                 let sc_i: f64 = self.atol + y_i.abs().max(k5_i.abs()) * self.rtol;
 
-                let err_est_i: f64 = na::convert(err_est[i]);
+                let err_est_i = err_est[i].value();
                 err += (err_est_i / sc_i) * (err_est_i / sc_i);
 
-                let erri: f64 = na::convert(err_bhh[i]);
+                let erri = err_bhh[i].value();
                 err2 += (erri / sc_i) * (erri / sc_i);
             }
+            let mut n_err = dim;
+            if error_control {
+                let zeros = vec![V::zero(); n_s];
+                let weights: Vec<(usize, f64)> = (0..12).map(|i| (i, self.coeffs.e(i + 1))).collect();
+                let err_est_s = combine(&zeros, &ks, &weights, 1.0);
+                let weights = [
+                    (0, self.coeffs.b(1) - self.coeffs.bhh(1)),
+                    (5, self.coeffs.b(6)),
+                    (6, self.coeffs.b(7)),
+                    (7, self.coeffs.b(8)),
+                    (8, self.coeffs.b(9) - self.coeffs.bhh(2)),
+                    (9, self.coeffs.b(10)),
+                    (1, self.coeffs.b(11)),
+                    (2, self.coeffs.b(12) - self.coeffs.bhh(3)),
+                ];
+                let err_bhh_s = combine(&zeros, &ks, &weights, 1.0);
+                err += error_sum(&self.s, &s_next, &err_est_s, self.rtol, self.atol);
+                err2 += error_sum(&self.s, &s_next, &err_bhh_s, self.rtol, self.atol);
+                n_err += dim * n_s;
+            }
             let mut deno = err + 0.01 * err2;
             if deno <= 0.0 {
                 deno = 1.0;
             }
 
-            err = self.h.abs() * err * (1.0 / (deno * dim as f64)).sqrt();
+            err = self.h.abs() * err * (1.0 / (deno * n_err as f64)).sqrt();
 
             // Step size control
             if self.controller.accept(err, self.h, &mut h_new) {
                 self.stats.accepted_steps += 1;
                 let y_tmp = k[4];
-                (self.f)(self.x + self.h, &y_tmp, &mut k[3]);
+                self.f.eval(self.x + self.h, &y_tmp, &mut k[3]);
                 self.stats.num_eval += 1;
+                let (x_h, f_h) = (self.x + self.h, k[3]);
+                self.sensitivity_derivatives(x_h, &y_tmp, &f_h, &s_next, &mut ks[3]);
 
                 // Stifness detection
                 if (self.stats.accepted_steps % self.n_stiff != 0) || dim > 0 {
-                    let num: f64 = na::convert((k[3] - k[2]).dot(&(k[3] - k[2])));
-                    let den: f64 = na::convert((k[4] - y_next).dot(&(k[4] - y_next)));
+                    let num = (k[3] - k[2]).dot(&(k[3] - k[2])).value();
+                    let den = (k[4] - y_next).dot(&(k[4] - y_next)).value();
                     let h_lamb = if den > 0.0 {
                         self.h * (num / den).sqrt()
                     } else {
@@ -412,7 +549,7 @@ where
                     }
                 }
 
-                if self.out_type == OutputType::Dense {
+                if self.out_type == OutputType::Dense || self.continuous.is_some() {
                     self.rcont[0] = self.y;
                     let y_diff = k[4] - self.y;
                     self.rcont[1] = y_diff;
@@ -425,6 +562,20 @@ where
                             self.rcont[i] += k[j - 1] * na::convert(self.coeffs.d(i, j));
                         }
                     }
+                    for c in 0..n_s {
+                        let s_diff = s_next[c] - self.s[c];
+                        let bspl = ks[0][c] * na::convert(self.h) - s_diff;
+                        self.rcont_s[c][0] = self.s[c];
+                        self.rcont_s[c][1] = s_diff;
+                        self.rcont_s[c][2] = bspl;
+                        self.rcont_s[c][3] = s_diff - ks[3][c] * na::convert(self.h) - bspl;
+                        for i in 4..8 {
+                            self.rcont_s[c][i] = V::zero();
+                            for j in 1..13 {
+                                self.rcont_s[c][i] += ks[j - 1][c] * na::convert(self.coeffs.d(i, j));
+                            }
+                        }
+                    }
 
                     // Next three function evaluations
                     let mut y_next = self.y
@@ -437,7 +588,22 @@ where
                             + k[2] * na::convert(self.coeffs.a(14, 12))
                             + k[3] * na::convert(self.coeffs.a(14, 13)))
                             * na::convert(self.h);
-                    (self.f)(self.x + self.h * self.coeffs.c(14), &y_next, &mut k[9]);
+                    self.f.eval(self.x + self.h * self.coeffs.c(14), &y_next, &mut k[9]);
+                    if n_s > 0 {
+                        let weights = [
+                            (0, self.coeffs.a(14, 1)),
+                            (6, self.coeffs.a(14, 7)),
+                            (7, self.coeffs.a(14, 8)),
+                            (8, self.coeffs.a(14, 9)),
+                            (9, self.coeffs.a(14, 10)),
+                            (1, self.coeffs.a(14, 11)),
+                            (2, self.coeffs.a(14, 12)),
+                            (3, self.coeffs.a(14, 13)),
+                        ];
+                        let s_stage = combine(&self.s, &ks, &weights, self.h);
+                        let (x_s, f_s) = (self.x + self.h * self.coeffs.c(14), k[9]);
+                        self.sensitivity_derivatives(x_s, &y_next, &f_s, &s_stage, &mut ks[9]);
+                    }
 
                     y_next = self.y
                         + (k[0] * na::convert(self.coeffs.a(15, 1))
@@ -449,7 +615,22 @@ where
                             + k[3] * na::convert(self.coeffs.a(15, 13))
                             + k[9] * na::convert(self.coeffs.a(15, 14)))
                             * na::convert(self.h);
-                    (self.f)(self.x + self.h * self.coeffs.c(15), &y_next, &mut k[1]);
+                    self.f.eval(self.x + self.h * self.coeffs.c(15), &y_next, &mut k[1]);
+                    if n_s > 0 {
+                        let weights = [
+                            (0, self.coeffs.a(15, 1)),
+                            (5, self.coeffs.a(15, 6)),
+                            (6, self.coeffs.a(15, 7)),
+                            (7, self.coeffs.a(15, 8)),
+                            (1, self.coeffs.a(15, 11)),
+                            (2, self.coeffs.a(15, 12)),
+                            (3, self.coeffs.a(15, 13)),
+                            (9, self.coeffs.a(15, 14)),
+                        ];
+                        let s_stage = combine(&self.s, &ks, &weights, self.h);
+                        let (x_s, f_s) = (self.x + self.h * self.coeffs.c(15), k[1]);
+                        self.sensitivity_derivatives(x_s, &y_next, &f_s, &s_stage, &mut ks[1]);
+                    }
 
                     y_next = self.y
                         + (k[0] * na::convert(self.coeffs.a(16, 1))
@@ -461,7 +642,22 @@ where
                             + k[9] * na::convert(self.coeffs.a(16, 14))
                             + k[1] * na::convert(self.coeffs.a(16, 15)))
                             * na::convert(self.h);
-                    (self.f)(self.x + self.h * self.coeffs.c(16), &y_next, &mut k[2]);
+                    self.f.eval(self.x + self.h * self.coeffs.c(16), &y_next, &mut k[2]);
+                    if n_s > 0 {
+                        let weights = [
+                            (0, self.coeffs.a(16, 1)),
+                            (5, self.coeffs.a(16, 6)),
+                            (6, self.coeffs.a(16, 7)),
+                            (7, self.coeffs.a(16, 8)),
+                            (8, self.coeffs.a(16, 9)),
+                            (3, self.coeffs.a(16, 13)),
+                            (9, self.coeffs.a(16, 14)),
+                            (1, self.coeffs.a(16, 15)),
+                        ];
+                        let s_stage = combine(&self.s, &ks, &weights, self.h);
+                        let (x_s, f_s) = (self.x + self.h * self.coeffs.c(16), k[2]);
+                        self.sensitivity_derivatives(x_s, &y_next, &f_s, &s_stage, &mut ks[2]);
+                    }
 
                     self.stats.num_eval += 3;
 
@@ -489,10 +685,26 @@ where
                         + k[1] * na::convert(self.coeffs.d(7, 15))
                         + k[2] * na::convert(self.coeffs.d(7, 16)))
                         * na::convert(self.h);
+                    for c in 0..n_s {
+                        for i in 4..8 {
+                            self.rcont_s[c][i] = (self.rcont_s[c][i]
+                                + ks[3][c] * na::convert(self.coeffs.d(i, 13))
+                                + ks[9][c] * na::convert(self.coeffs.d(i, 14))
+                                + ks[1][c] * na::convert(self.coeffs.d(i, 15))
+                                + ks[2][c] * na::convert(self.coeffs.d(i, 16)))
+                                * na::convert(self.h);
+                        }
+                    }
+
+                    if let Some(ref mut continuous) = self.continuous {
+                        continuous.push(self.x, self.h, &self.rcont);
+                    }
                 }
 
                 k[0] = k[3];
+                ks[0] = ks[3].clone();
                 self.y = k[4];
+                self.s = s_next;
                 self.x_old = self.x;
                 self.x += self.h;
                 self.h_old = self.h;
@@ -504,8 +716,12 @@ where
                     self.h_old = posneg * h_new;
                     return Ok(self.stats);
                 }
-            } else if self.stats.accepted_steps >= 1 {
-                self.stats.rejected_steps += 1;
+            } else {
+                if self.stats.accepted_steps >= 1 {
+                    self.stats.rejected_steps += 1;
+                }
+                // The step reaching x_end was rejected
+                last = false;
             }
             self.h = h_new;
         }
@@ -518,6 +734,9 @@ where
             if (self.xd - self.x0).abs() < EPSILON {
                 self.x_out.push(self.x0);
                 self.y_out.push(self.y);
+                if self.sensitivity.is_some() {
+                    self.s_out.push(self.s.clone());
+                }
                 self.xd += self.dx;
             } else {
                 while self.xd.abs() <= self.x.abs() {
@@ -541,6 +760,27 @@ where
                                         * na::convert(theta1))
                                     * na::convert(theta),
                         );
+                        if self.sensitivity.is_some() {
+                            let s_d = self
+                                .rcont_s
+                                .iter()
+                                .map(|r| {
+                                    r[0] + (r[1]
+                                        + (r[2]
+                                            + (r[3]
+                                                + (r[4]
+                                                    + (r[5]
+                                                        + (r[6] + r[7] * na::convert(theta))
+                                                            * na::convert(theta1))
+                                                        * na::convert(theta))
+                                                    * na::convert(theta1))
+                                                * na::convert(theta))
+                                            * na::convert(theta1))
+                                        * na::convert(theta)
+                                })
+                                .collect();
+                            self.s_out.push(s_d);
+                        }
                         self.xd += self.dx;
                     }
                 }
@@ -548,6 +788,9 @@ where
         } else {
             self.x_out.push(self.x);
             self.y_out.push(y_next);
+            if self.sensitivity.is_some() {
+                self.s_out.push(self.s.clone());
+            }
         }
     }
 
@@ -560,6 +803,40 @@ where
     pub fn y_out(&self) -> &Vec<V> {
         &self.y_out
     }
+
+    /// Getter for the continuous extension of the solution, if it was stored.
+    pub fn continuous_output(&self) -> Option<&ContinuousOutput<V>> {
+        self.continuous.as_ref()
+    }
+
+    /// Takes the continuous extension of the solution out of the stepper, if it was stored.
+    pub(crate) fn take_continuous_output(&mut self) -> Option<ContinuousOutput<V>> {
+        self.continuous.take()
+    }
+
+    /// Getter for the sensitivities' output. The element i contains the sensitivity columns at
+    /// `x_out()[i]`, as described in [`Sensitivity`](../sensitivity/struct.Sensitivity.html).
+    pub fn s_out(&self) -> &Vec<Vec<V>> {
+        &self.s_out
+    }
+}
+
+// The sensitivities are computed in f64 and are not available for dual numbers
+impl<V> Dop853<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: SubsetOf<f64>,
+{
+    /// Sets the sensitivities to integrate along with the solution.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the sensitivities are created with `Sensitivity::with_parameters` and the system
+    /// is not created with `with_parameters`.
+    pub fn set_sensitivity(&mut self, mut sensitivity: Sensitivity<V>) {
+        sensitivity.set_parameters(self.f.parameters());
+        self.sensitivity = Some(sensitivity);
+    }
 }
 
 fn sign(a: f64, b: f64) -> f64 {
@@ -569,3 +846,66 @@ fn sign(a: f64, b: f64) -> f64 {
         -a.abs()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use na::{Vector1, Vector2};
+
+    fn oscillator(_x: f64, y: &Vector2<f64>, dy: &mut Vector2<f64>) {
+        dy[0] = y[1];
+        dy[1] = -y[0];
+    }
+
+    #[test]
+    fn rejected_last_step() {
+        // The initial step reaches x_end and is rejected
+        let mut stepper = Dop853::from_param(
+            oscillator,
+            0.0,
+            10.0,
+            10.0,
+            Vector2::new(1.0, 0.0),
+            1.0e-10,
+            1.0e-10,
+            0.9,
+            0.0,
+            0.333,
+            6.0,
+            10.0,
+            10.0,
+            100000,
+            1000,
+            OutputType::Sparse,
+        );
+        let stats = stepper.integrate().unwrap();
+        assert!(stats.accepted_steps > 1);
+        assert_eq!(*stepper.x_out().last().unwrap(), 10.0);
+        let y = stepper.y_out().last().unwrap();
+        assert!((y - Vector2::new(10.0_f64.cos(), -10.0_f64.sin())).norm() < 1.0e-8);
+    }
+
+    // y' = cos(x) y, solved by y = exp(sin(x))
+    fn non_autonomous(x: f64, y: &Vector1<f64>, dy: &mut Vector1<f64>) {
+        dy[0] = x.cos() * y[0];
+    }
+
+    #[test]
+    fn non_autonomous_system() {
+        // Every stage depends on its node, including the last one at x + h
+        let mut stepper = Dop853::new(
+            non_autonomous,
+            0.0,
+            20.0,
+            0.5,
+            Vector1::new(1.0),
+            1.0e-10,
+            1.0e-10,
+        );
+        stepper.integrate().unwrap();
+        assert_eq!(stepper.x_out().len(), 41);
+        for (x, y) in stepper.x_out().iter().zip(stepper.y_out()) {
+            assert!((y[0] - x.sin().exp()).abs() < 1.0e-8);
+        }
+    }
+}
@@ -1,5 +1,55 @@
+use alga::general::SubsetOf;
+use alga::linear::{FiniteDimInnerSpace, InnerSpace};
+use na;
+use na::DVector;
 use std::error::Error;
 use std::fmt;
+use std::ops::AddAssign;
+
+/// Scalar of the states, whose value is read as an f64 to control the step size.
+///
+/// It is implemented for the real types which can be converted to f64, which all the solvers
+/// accept, and for [`Dual`](../dual/struct.Dual.html) numbers, which only the explicit solvers
+/// propagating their derivative parts accept.
+pub trait Value {
+    /// Returns the value as an f64.
+    fn value(&self) -> f64;
+}
+
+impl<T: SubsetOf<f64>> Value for T {
+    fn value(&self) -> f64 {
+        self.to_superset()
+    }
+}
+
+/// Function integrated by the systems internal to the crate.
+pub(crate) type Closure<V> = Box<dyn Fn(f64, &V, &mut V)>;
+
+/// Function to integrate, with or without parameters. The systems integrated internally by the
+/// crate, such as the adjoint equations, are closures capturing the data they need.
+pub(crate) enum System<V> {
+    Plain(fn(f64, &V, &mut V)),
+    Parametric(fn(f64, &V, &DVector<f64>, &mut V), DVector<f64>),
+    Closure(Closure<V>),
+}
+
+impl<V> System<V> {
+    pub(crate) fn eval(&self, x: f64, y: &V, dy: &mut V) {
+        match self {
+            System::Plain(f) => f(x, y, dy),
+            System::Parametric(f, p) => f(x, y, p, dy),
+            System::Closure(f) => f(x, y, dy),
+        }
+    }
+
+    /// Parameters of a parametric system.
+    pub(crate) fn parameters(&self) -> Option<&DVector<f64>> {
+        match self {
+            System::Parametric(_, p) => Some(p),
+            _ => None,
+        }
+    }
+}
 
 /// Enumeration of the types of the integration output.
 #[derive(PartialEq, Eq)]
@@ -9,11 +59,13 @@ pub enum OutputType {
 }
 
 /// Enumeration of the errors that may arise during integration.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum IntegrationError {
     MaxNumStepReached { x: f64, n_step: u32 },
     StepSizeUnderflow { x: f64 },
     StiffnessDetected { x: f64 },
+    BeforeInitialPoint { x: f64 },
+    NonFiniteState { x: f64 },
 }
 
 impl Error for IntegrationError {}
@@ -30,10 +82,91 @@ impl fmt::Display for IntegrationError {
             IntegrationError::StiffnessDetected { x } => {
                 write!(f, "The problem seems to become stiff at x = {}", x)
             }
+            IntegrationError::BeforeInitialPoint { x } => {
+                write!(
+                    f,
+                    "Stopped at x = {}, before the initial point of the Brownian path",
+                    x
+                )
+            }
+            IntegrationError::NonFiniteState { x } => {
+                write!(f, "Stopped at x = {}. The state is not finite", x)
+            }
         }
     }
 }
 
+/// Continuous extension of the accepted steps, which gives the solution at any point of the
+/// integration interval.
+pub struct ContinuousOutput<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+{
+    posneg: f64,
+    n_coeffs: usize,
+    x_step: Vec<f64>,
+    h_step: Vec<f64>,
+    rcont: Vec<V>,
+}
+
+impl<V> ContinuousOutput<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: Value,
+{
+    pub(crate) fn new(posneg: f64, n_coeffs: usize) -> ContinuousOutput<V> {
+        ContinuousOutput {
+            posneg,
+            n_coeffs,
+            x_step: Vec::new(),
+            h_step: Vec::new(),
+            rcont: Vec::new(),
+        }
+    }
+
+    /// Returns the value of the solution at x. Outside of the integration interval, the
+    /// continuous extension of the first or last step is extrapolated.
+    pub fn y(&self, x: f64) -> V {
+        // Find the last step starting before x
+        let mut lo = 0;
+        let mut hi = self.x_step.len();
+        while hi - lo > 1 {
+            let mid = (lo + hi) / 2;
+            if (x - self.x_step[mid]) * self.posneg >= 0.0 {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        let theta = (x - self.x_step[lo]) / self.h_step[lo];
+        let theta1 = 1.0 - theta;
+        let rcont = &self.rcont[lo * self.n_coeffs..(lo + 1) * self.n_coeffs];
+        // Horner scheme in which the factors alternate between theta and 1 - theta
+        let mut y = rcont[self.n_coeffs - 1];
+        for i in (0..self.n_coeffs - 1).rev() {
+            let factor = if i % 2 == 0 { theta } else { theta1 };
+            y = rcont[i] + y * na::convert(factor);
+        }
+        y
+    }
+
+    /// Number of stored steps.
+    pub fn num_steps(&self) -> usize {
+        self.x_step.len()
+    }
+
+    /// Start and end of the i-th stored step.
+    pub fn step(&self, i: usize) -> (f64, f64) {
+        (self.x_step[i], self.x_step[i] + self.h_step[i])
+    }
+
+    pub(crate) fn push(&mut self, x: f64, h: f64, rcont: &[V]) {
+        self.x_step.push(x);
+        self.h_step.push(h);
+        self.rcont.extend_from_slice(rcont);
+    }
+}
+
 /// Contains some statistics of the integration.
 #[derive(Clone, Copy, Debug)]
 pub struct Stats {
@@ -58,6 +191,14 @@ impl Stats {
     }
 }
 
+impl AddAssign for Stats {
+    fn add_assign(&mut self, other: Stats) {
+        self.num_eval += other.num_eval;
+        self.accepted_steps += other.accepted_steps;
+        self.rejected_steps += other.rejected_steps;
+    }
+}
+
 impl fmt::Display for Stats {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         writeln!(f, "Number of function evaluations: {}", self.num_eval)?;
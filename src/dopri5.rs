@@ -54,6 +54,8 @@ use butcher_tableau::Dopri54;
 use controller::Controller;
 use dop_shared::*;
 use na;
+use na::DVector;
+use sensitivity::{combine, error_sum, Sensitivity};
 use std::f64;
 
 trait DefaultController {
@@ -80,7 +82,7 @@ pub struct Dopri5<V>
 where
     V: FiniteDimInnerSpace + Copy,
 {
-    f: fn(f64, &V, &mut V),
+    f: System<V>,
     x: f64,
     x_old: f64,
     x_end: f64,
@@ -102,12 +104,17 @@ where
     rcont: [V; 5],
     stats: Stats,
     solout: fn(f64, &V, &V) -> bool,
+    sensitivity: Option<Sensitivity<V>>,
+    s: Vec<V>,
+    s_out: Vec<Vec<V>>,
+    rcont_s: Vec<[V; 5]>,
+    continuous: Option<ContinuousOutput<V>>,
 }
 
 impl<V> Dopri5<V>
 where
     V: FiniteDimInnerSpace + Copy,
-    <V as InnerSpace>::Real: SubsetOf<f64>,
+    <V as InnerSpace>::Real: Value,
 {
     /// Default initializer for the structure
     ///
@@ -130,30 +137,20 @@ where
         rtol: f64,
         atol: f64,
     ) -> Dopri5<V> {
-        Dopri5 {
-            f,
+        Dopri5::from_system(
+            System::Plain(f),
             x,
-            xd: x,
-            dx,
-            x_old: x,
             x_end,
+            dx,
             y,
             rtol,
             atol,
-            x_out: Vec::<f64>::new(),
-            y_out: Vec::<V>::new(),
-            uround: f64::EPSILON,
-            h: 0.0,
-            h_old: 0.0,
-            n_max: 100000,
-            n_stiff: 1000,
-            coeffs: Dopri54::new(),
-            controller: Controller::default(x, x_end),
-            out_type: OutputType::Dense,
-            rcont: [V::zero(); 5],
-            stats: Stats::new(),
-            solout: |_, _, _| { false },
-        }
+            Controller::default(x, x_end),
+            0.0,
+            100000,
+            1000,
+            OutputType::Dense,
+        )
     }
 
     /// Advanced initializer for the structure.
@@ -197,13 +194,109 @@ where
         out_type: OutputType,
     ) -> Dopri5<V> {
         let alpha = 0.2 - beta * 0.75;
+        let controller = Controller::new(
+            alpha,
+            beta,
+            fac_max,
+            fac_min,
+            h_max,
+            safety_factor,
+            sign(1.0, x_end - x),
+        );
+        Dopri5::from_system(
+            System::Plain(f),
+            x,
+            x_end,
+            dx,
+            y,
+            rtol,
+            atol,
+            controller,
+            h,
+            n_max,
+            n_stiff,
+            out_type,
+        )
+    }
+
+    /// Initializer for a system depending on parameters p, which are passed to f at every
+    /// evaluation. The other arguments are the same as in `new`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn with_parameters(
+        f: fn(f64, &V, &DVector<f64>, &mut V),
+        p: DVector<f64>,
+        x: f64,
+        x_end: f64,
+        dx: f64,
+        y: V,
+        rtol: f64,
+        atol: f64,
+    ) -> Dopri5<V> {
+        Dopri5::from_system(
+            System::Parametric(f, p),
+            x,
+            x_end,
+            dx,
+            y,
+            rtol,
+            atol,
+            Controller::default(x, x_end),
+            0.0,
+            100000,
+            1000,
+            OutputType::Dense,
+        )
+    }
+
+    /// Initializer for any kind of system, with a sparse output and at most `n_max` steps.
+    pub(crate) fn sparse(
+        f: System<V>,
+        x: f64,
+        x_end: f64,
+        y: V,
+        rtol: f64,
+        atol: f64,
+        n_max: u32,
+    ) -> Dopri5<V> {
+        Dopri5::from_system(
+            f,
+            x,
+            x_end,
+            x_end - x,
+            y,
+            rtol,
+            atol,
+            Controller::default(x, x_end),
+            0.0,
+            n_max,
+            1000,
+            OutputType::Sparse,
+        )
+    }
+
+    /// Initializer shared by the public ones, for any kind of system.
+    #[allow(clippy::too_many_arguments)]
+    fn from_system(
+        f: System<V>,
+        x: f64,
+        x_end: f64,
+        dx: f64,
+        y: V,
+        rtol: f64,
+        atol: f64,
+        controller: Controller,
+        h: f64,
+        n_max: u32,
+        n_stiff: u32,
+        out_type: OutputType,
+    ) -> Dopri5<V> {
         Dopri5 {
             f,
             x,
             xd: x,
-            x_old: 0.0,
-            x_end,
             dx,
+            x_old: x,
+            x_end,
             y,
             rtol,
             atol,
@@ -215,26 +308,23 @@ where
             n_max,
             n_stiff,
             coeffs: Dopri54::new(),
-            controller: Controller::new(
-                alpha,
-                beta,
-                fac_max,
-                fac_min,
-                h_max,
-                safety_factor,
-                sign(1.0, x_end - x),
-            ),
+            controller,
             out_type,
             rcont: [V::zero(); 5],
             stats: Stats::new(),
             solout: |_, _, _| { false },
+            sensitivity: None,
+            s: Vec::new(),
+            s_out: Vec::new(),
+            rcont_s: Vec::new(),
+            continuous: None,
         }
     }
 
     /// Compute the initial stepsize
     fn hinit(&self) -> f64 {
         let mut f0 = V::zero();
-        (self.f)(self.x, &self.y, &mut f0);
+        self.f.eval(self.x, &self.y, &mut f0);
         let posneg = sign(1.0, self.x_end - self.x);
 
         // Compute the norm of y0 and f0
@@ -242,10 +332,10 @@ where
         let mut d0 = 0.0;
         let mut d1 = 0.0;
         for i in 0..dim {
-            let y_i: f64 = na::convert(self.y[i]);
+            let y_i = self.y[i].value();
             let sci: f64 = self.atol + y_i.abs() * self.rtol;
             d0 += (y_i / sci) * (y_i / sci);
-            let f0_i: f64 = na::convert(f0[i]);
+            let f0_i = f0[i].value();
             d1 += (f0_i / sci) * (f0_i / sci);
         }
 
@@ -261,14 +351,14 @@ where
 
         let y1 = self.y + f0 * na::convert(h0);
         let mut f1 = V::zero();
-        (self.f)(self.x + h0, &y1, &mut f1);
+        self.f.eval(self.x + h0, &y1, &mut f1);
 
         // Compute the norm of f1-f0 divided by h0
         let mut d2: f64 = 0.0;
         for i in 0..dim {
-            let f0_i: f64 = na::convert(f0[i]);
-            let f1_i: f64 = na::convert(f1[i]);
-            let y_i: f64 = na::convert(self.y[i]);
+            let f0_i = f0[i].value();
+            let f1_i = f1[i].value();
+            let y_i = self.y[i].value();
             let sci: f64 = self.atol + y_i.abs() * self.rtol;
             d2 += ((f1_i - f0_i) / sci) * ((f1_i - f0_i) / sci);
         }
@@ -291,6 +381,25 @@ where
         self.solout = solout;
     }
 
+    /// Stores (or not) the continuous extension of every accepted step, so that the solution can be
+    /// evaluated at any point after the integration.
+    pub fn set_continuous_output(&mut self, store: bool) {
+        self.continuous = if store {
+            Some(ContinuousOutput::new(sign(1.0, self.x_end - self.x), 5))
+        } else {
+            None
+        };
+    }
+
+    /// Computes the derivatives of the sensitivities s at (x, y), if any.
+    fn sensitivity_derivatives(&mut self, x: f64, y: &V, fy: &V, s: &[V], ds: &mut Vec<V>) {
+        if let Some(ref mut sensitivity) = self.sensitivity {
+            let f = &self.f;
+            self.stats.num_eval +=
+                sensitivity.derivatives(|x, y: &V, dy: &mut V| f.eval(x, y, dy), x, y, fy, s, ds);
+        }
+    }
+
     /// Core integration method.
     pub fn integrate(&mut self) -> Result<Stats, IntegrationError> {
         // Initilization
@@ -309,16 +418,32 @@ where
         }
         self.h_old = self.h;
 
+        let (n_s, error_control) = match self.sensitivity {
+            Some(ref sensitivity) => {
+                self.s = sensitivity.initial_values();
+                (self.s.len(), sensitivity.error_control())
+            }
+            None => (0, false),
+        };
+        self.rcont_s = vec![[V::zero(); 5]; n_s];
+
         // Save initial values
         if self.out_type == OutputType::Sparse {
             self.x_out.push(self.x);
             self.y_out.push(self.y);
+            if self.sensitivity.is_some() {
+                self.s_out.push(self.s.clone());
+            }
         }
 
         let mut k: Vec<V> = vec![V::zero(); 7];
-        (self.f)(self.x, &self.y, &mut k[0]);
+        self.f.eval(self.x, &self.y, &mut k[0]);
         self.stats.num_eval += 1;
 
+        let mut ks: Vec<Vec<V>> = vec![Vec::new(); 7];
+        let (x, y, s) = (self.x, self.y, self.s.clone());
+        self.sensitivity_derivatives(x, &y, &k[0], &s, &mut ks[0]);
+
         // Main loop
         while !last {
             // Check if step number is within allowed range
@@ -343,21 +468,30 @@ where
             // 6 Stages
             let mut y_next = V::zero();
             let mut y_stiff = V::zero();
+            let mut s_next = Vec::new();
             for s in 1..7 {
                 y_next = self.y;
                 for j in 0..s {
                     y_next += k[j] * na::convert(self.h * self.coeffs.a(s + 1, j + 1));
                 }
-                (self.f)(self.x + self.h * self.coeffs.c(s + 1), &y_next, &mut k[s]);
+                self.f.eval(self.x + self.h * self.coeffs.c(s + 1), &y_next, &mut k[s]);
                 if s == 5 {
                     y_stiff = y_next;
                 }
+                if n_s > 0 {
+                    let weights: Vec<(usize, f64)> =
+                        (0..s).map(|j| (j, self.coeffs.a(s + 1, j + 1))).collect();
+                    s_next = combine(&self.s, &ks, &weights, self.h);
+                    let x_s = self.x + self.h * self.coeffs.c(s + 1);
+                    self.sensitivity_derivatives(x_s, &y_next, &k[s], &s_next, &mut ks[s]);
+                }
             }
             k[1] = k[6];
+            ks[1] = ks[6].clone();
             self.stats.num_eval += 6;
 
             // Prepare dense output
-            if self.out_type == OutputType::Dense {
+            if self.out_type == OutputType::Dense || self.continuous.is_some() {
                 self.rcont[4] = (k[0] * na::convert(self.coeffs.d(1))
                     + k[2] * na::convert(self.coeffs.d(3))
                     + k[3] * na::convert(self.coeffs.d(4))
@@ -365,6 +499,18 @@ where
                     + k[5] * na::convert(self.coeffs.d(6))
                     + k[1] * na::convert(self.coeffs.d(7)))
                     * na::convert(self.h);
+                let weights = [
+                    (0, self.coeffs.d(1)),
+                    (2, self.coeffs.d(3)),
+                    (3, self.coeffs.d(4)),
+                    (4, self.coeffs.d(5)),
+                    (5, self.coeffs.d(6)),
+                    (1, self.coeffs.d(7)),
+                ];
+                let r4 = combine(&vec![V::zero(); n_s], &ks, &weights, self.h);
+                for c in 0..n_s {
+                    self.rcont_s[c][4] = r4[c];
+                }
             }
 
             // Compute error estimate
@@ -380,13 +526,28 @@ where
             // Compute error
             let mut err = 0.0;
             for i in 0..dim {
-                let y_i: f64 = na::convert(self.y[i]);
-                let y_next_i: f64 = na::convert(y_next[i]);
+                let y_i = self.y[i].value();
+                let y_next_i = y_next[i].value();
                 let sc_i: f64 = self.atol + y_i.abs().max(y_next_i.abs()) * self.rtol;
-                let err_est_i: f64 = na::convert(k[3][i]);
+                let err_est_i = k[3][i].value();
                 err += (err_est_i / sc_i) * (err_est_i / sc_i);
             }
-            err = (err / dim as f64).sqrt();
+            let mut n_err = dim;
+            if error_control {
+                let weights = [
+                    (0, self.coeffs.e(1)),
+                    (1, self.coeffs.e(2)),
+                    (2, self.coeffs.e(3)),
+                    (3, self.coeffs.e(4)),
+                    (4, self.coeffs.e(5)),
+                    (5, self.coeffs.e(6)),
+                    (1, self.coeffs.e(7)),
+                ];
+                let err_s = combine(&vec![V::zero(); n_s], &ks, &weights, self.h);
+                err += error_sum(&self.s, &s_next, &err_s, self.rtol, self.atol);
+                n_err += dim * n_s;
+            }
+            err = (err / n_err as f64).sqrt();
 
             // Step size control
             if self.controller.accept(err, self.h, &mut h_new) {
@@ -394,8 +555,8 @@ where
 
                 // Stifness detection
                 if (self.stats.accepted_steps % self.n_stiff != 0) || dim > 0 {
-                    let num: f64 = na::convert((k[1] - k[5]).dot(&(k[1] - k[5])));
-                    let den: f64 = na::convert((y_next - y_stiff).dot(&(y_next - y_stiff)));
+                    let num = (k[1] - k[5]).dot(&(k[1] - k[5])).value();
+                    let den = (y_next - y_stiff).dot(&(y_next - y_stiff)).value();
                     let h_lamb = if den > 0.0 {
                         self.h * (num / den).sqrt()
                     } else {
@@ -414,17 +575,31 @@ where
                 }
 
                 // Prepare dense output
-                if self.out_type == OutputType::Dense {
+                if self.out_type == OutputType::Dense || self.continuous.is_some() {
                     let ydiff = y_next - self.y;
                     let bspl = k[0] * na::convert(self.h) - ydiff;
                     self.rcont[0] = self.y;
                     self.rcont[1] = ydiff;
                     self.rcont[2] = bspl;
                     self.rcont[3] = -k[1] * na::convert(self.h) + ydiff - bspl;
+                    for c in 0..n_s {
+                        let sdiff = s_next[c] - self.s[c];
+                        let bspl = ks[0][c] * na::convert(self.h) - sdiff;
+                        self.rcont_s[c][0] = self.s[c];
+                        self.rcont_s[c][1] = sdiff;
+                        self.rcont_s[c][2] = bspl;
+                        self.rcont_s[c][3] = -ks[1][c] * na::convert(self.h) + sdiff - bspl;
+                    }
+                }
+
+                if let Some(ref mut continuous) = self.continuous {
+                    continuous.push(self.x, self.h, &self.rcont);
                 }
 
                 k[0] = k[1];
+                ks[0] = ks[1].clone();
                 self.y = y_next;
+                self.s = s_next;
                 self.x_old = self.x;
                 self.x += self.h;
                 self.h_old = self.h;
@@ -440,8 +615,12 @@ where
                     self.h_old = posneg * h_new;
                     return Ok(self.stats);
                 }
-            } else if self.stats.accepted_steps >= 1 {
-                self.stats.rejected_steps += 1;
+            } else {
+                if self.stats.accepted_steps >= 1 {
+                    self.stats.rejected_steps += 1;
+                }
+                // The step reaching x_end was rejected
+                last = false;
             }
             self.h = h_new;
         }
@@ -464,6 +643,20 @@ where
                                     * na::convert(theta1))
                                 * na::convert(theta),
                     );
+                    if self.sensitivity.is_some() {
+                        let s_d = self
+                            .rcont_s
+                            .iter()
+                            .map(|r| {
+                                r[0] + (r[1]
+                                    + (r[2] + (r[3] + r[4] * na::convert(theta1))
+                                        * na::convert(theta))
+                                        * na::convert(theta1))
+                                    * na::convert(theta)
+                            })
+                            .collect();
+                        self.s_out.push(s_d);
+                    }
                     self.xd += self.dx;
                     if (self.solout)(self.x, &self.y_out.last().unwrap(), &k[0]) {
                         break;
@@ -473,6 +666,9 @@ where
         } else {
             self.x_out.push(self.x);
             self.y_out.push(y_next);
+            if self.sensitivity.is_some() {
+                self.s_out.push(self.s.clone());
+            }
         }
     }
 
@@ -485,6 +681,40 @@ where
     pub fn y_out(&self) -> &Vec<V> {
         &self.y_out
     }
+
+    /// Getter for the continuous extension of the solution, if it was stored.
+    pub fn continuous_output(&self) -> Option<&ContinuousOutput<V>> {
+        self.continuous.as_ref()
+    }
+
+    /// Takes the continuous extension of the solution out of the stepper, if it was stored.
+    pub(crate) fn take_continuous_output(&mut self) -> Option<ContinuousOutput<V>> {
+        self.continuous.take()
+    }
+
+    /// Getter for the sensitivities' output. The element i contains the sensitivity columns at
+    /// `x_out()[i]`, as described in [`Sensitivity`](../sensitivity/struct.Sensitivity.html).
+    pub fn s_out(&self) -> &Vec<Vec<V>> {
+        &self.s_out
+    }
+}
+
+// The sensitivities are computed in f64 and are not available for dual numbers
+impl<V> Dopri5<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: SubsetOf<f64>,
+{
+    /// Sets the sensitivities to integrate along with the solution.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the sensitivities are created with `Sensitivity::with_parameters` and the system
+    /// is not created with `with_parameters`.
+    pub fn set_sensitivity(&mut self, mut sensitivity: Sensitivity<V>) {
+        sensitivity.set_parameters(self.f.parameters());
+        self.sensitivity = Some(sensitivity);
+    }
 }
 
 fn sign(a: f64, b: f64) -> f64 {
@@ -494,3 +724,42 @@ fn sign(a: f64, b: f64) -> f64 {
         -a.abs()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use na::Vector2;
+
+    fn oscillator(_x: f64, y: &Vector2<f64>, dy: &mut Vector2<f64>) {
+        dy[0] = y[1];
+        dy[1] = -y[0];
+    }
+
+    #[test]
+    fn rejected_last_step() {
+        // The initial step reaches x_end and is rejected
+        let mut stepper = Dopri5::from_param(
+            oscillator,
+            0.0,
+            10.0,
+            10.0,
+            Vector2::new(1.0, 0.0),
+            1.0e-10,
+            1.0e-10,
+            0.9,
+            0.04,
+            0.2,
+            10.0,
+            10.0,
+            10.0,
+            100000,
+            1000,
+            OutputType::Sparse,
+        );
+        let stats = stepper.integrate().unwrap();
+        assert!(stats.accepted_steps > 1);
+        assert_eq!(*stepper.x_out().last().unwrap(), 10.0);
+        let y = stepper.y_out().last().unwrap();
+        assert!((y - Vector2::new(10.0_f64.cos(), -10.0_f64.sin())).norm() < 1.0e-8);
+    }
+}
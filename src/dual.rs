@@ -0,0 +1,701 @@
+//! Dual numbers for forward-mode automatic differentiation.
+//!
+//! A dual number _a + b ε_ with _ε² = 0_ carries a value together with its derivative with respect
+//! to one chosen variable. [`Dual`](struct.Dual.html) implements the `Real` trait of alga, so that
+//! states such as `Vector3<Dual>` can be integrated by the solvers. The step sizes are selected
+//! from the values only, and the derivative parts of the solution are the derivatives of the
+//! numerical solution with respect to the chosen variable, e.g. an initial value or a parameter
+//! appended to the state with a zero derivative.
+//!
+//! The derivatives are propagated by the explicit solvers, which only combine states linearly:
+//! dopri5, dop853, odex, rkn64, adams and retard. They require the scalar of the states to
+//! implement [`Value`](../dop_shared/trait.Value.html), which reads the value used to control the
+//! step size. The other solvers convert the state to `f64` in their linear algebra or random
+//! increments, which would drop the derivative parts, and require a scalar convertible to `f64`.
+//! Dual numbers are not, so that these solvers reject them at compile time:
+//!
+//! ```compile_fail
+//! extern crate ode_solvers;
+//! use ode_solvers::dual::Dual;
+//! use ode_solvers::seulex::Seulex;
+//! use ode_solvers::Vector1;
+//!
+//! fn system(_x: f64, y: &Vector1<Dual>, dy: &mut Vector1<Dual>) {
+//!     dy[0] = -y[0];
+//! }
+//!
+//! let y0 = Vector1::new(Dual::variable(1.0));
+//! let mut stepper = Seulex::new(system, 0.0, 1.0, 0.1, y0, 1e-6, 1e-6);
+//! ```
+
+use alga::general::{
+    AbstractField, AbstractGroup, AbstractGroupAbelian, AbstractLoop, AbstractMagma,
+    AbstractMonoid, AbstractQuasigroup, AbstractRing, AbstractRingCommutative, AbstractSemigroup,
+    Additive, Identity, JoinSemilattice, Lattice, MeetSemilattice, Multiplicative, Real,
+    SubsetOf, TwoSidedInverse,
+};
+use approx::{AbsDiffEq, RelativeEq, UlpsEq};
+use dop_shared::Value;
+use num_traits::{Bounded, FromPrimitive, Num, One, Signed, Zero};
+use std::cmp::Ordering;
+use std::f64;
+use std::fmt;
+use std::ops::{
+    Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Rem, RemAssign, Sub, SubAssign,
+};
+
+/// Dual number holding a value and its derivative.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct Dual {
+    re: f64,
+    eps: f64,
+}
+
+impl Dual {
+    /// Creates a dual number from its value and its derivative.
+    pub fn new(re: f64, eps: f64) -> Dual {
+        Dual { re, eps }
+    }
+
+    /// Creates the variable with respect to which the derivatives are computed, i.e. a dual
+    /// number with a unit derivative.
+    pub fn variable(re: f64) -> Dual {
+        Dual { re, eps: 1.0 }
+    }
+
+    /// Creates a constant, i.e. a dual number with a zero derivative.
+    pub fn constant(re: f64) -> Dual {
+        Dual { re, eps: 0.0 }
+    }
+
+    /// Returns the value.
+    pub fn re(&self) -> f64 {
+        self.re
+    }
+
+    /// Returns the derivative.
+    pub fn eps(&self) -> f64 {
+        self.eps
+    }
+
+    /// Applies a function of value `f` and derivative `df` at the value of self.
+    fn chain(self, f: f64, df: f64) -> Dual {
+        Dual {
+            re: f,
+            eps: df * self.eps,
+        }
+    }
+}
+
+impl fmt::Display for Dual {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.eps < 0.0 {
+            write!(f, "{} - {}ε", self.re, -self.eps)
+        } else {
+            write!(f, "{} + {}ε", self.re, self.eps)
+        }
+    }
+}
+
+impl PartialOrd for Dual {
+    fn partial_cmp(&self, other: &Dual) -> Option<Ordering> {
+        self.re.partial_cmp(&other.re)
+    }
+}
+
+impl Add for Dual {
+    type Output = Dual;
+    fn add(self, rhs: Dual) -> Dual {
+        Dual::new(self.re + rhs.re, self.eps + rhs.eps)
+    }
+}
+
+impl Sub for Dual {
+    type Output = Dual;
+    fn sub(self, rhs: Dual) -> Dual {
+        Dual::new(self.re - rhs.re, self.eps - rhs.eps)
+    }
+}
+
+impl Mul for Dual {
+    type Output = Dual;
+    fn mul(self, rhs: Dual) -> Dual {
+        Dual::new(self.re * rhs.re, self.eps * rhs.re + self.re * rhs.eps)
+    }
+}
+
+impl Div for Dual {
+    type Output = Dual;
+    fn div(self, rhs: Dual) -> Dual {
+        Dual::new(
+            self.re / rhs.re,
+            (self.eps * rhs.re - self.re * rhs.eps) / (rhs.re * rhs.re),
+        )
+    }
+}
+
+impl Rem for Dual {
+    type Output = Dual;
+    fn rem(self, rhs: Dual) -> Dual {
+        Dual::new(
+            self.re % rhs.re,
+            self.eps - rhs.eps * (self.re / rhs.re).trunc(),
+        )
+    }
+}
+
+impl Neg for Dual {
+    type Output = Dual;
+    fn neg(self) -> Dual {
+        Dual::new(-self.re, -self.eps)
+    }
+}
+
+impl AddAssign for Dual {
+    fn add_assign(&mut self, rhs: Dual) {
+        *self = *self + rhs;
+    }
+}
+
+impl SubAssign for Dual {
+    fn sub_assign(&mut self, rhs: Dual) {
+        *self = *self - rhs;
+    }
+}
+
+impl MulAssign for Dual {
+    fn mul_assign(&mut self, rhs: Dual) {
+        *self = *self * rhs;
+    }
+}
+
+impl DivAssign for Dual {
+    fn div_assign(&mut self, rhs: Dual) {
+        *self = *self / rhs;
+    }
+}
+
+impl RemAssign for Dual {
+    fn rem_assign(&mut self, rhs: Dual) {
+        *self = *self % rhs;
+    }
+}
+
+impl Zero for Dual {
+    fn zero() -> Dual {
+        Dual::constant(0.0)
+    }
+
+    fn is_zero(&self) -> bool {
+        self.re == 0.0 && self.eps == 0.0
+    }
+}
+
+impl One for Dual {
+    fn one() -> Dual {
+        Dual::constant(1.0)
+    }
+}
+
+impl Num for Dual {
+    type FromStrRadixErr = <f64 as Num>::FromStrRadixErr;
+
+    fn from_str_radix(s: &str, radix: u32) -> Result<Dual, Self::FromStrRadixErr> {
+        f64::from_str_radix(s, radix).map(Dual::constant)
+    }
+}
+
+impl FromPrimitive for Dual {
+    fn from_i64(n: i64) -> Option<Dual> {
+        Some(Dual::constant(n as f64))
+    }
+
+    fn from_u64(n: u64) -> Option<Dual> {
+        Some(Dual::constant(n as f64))
+    }
+
+    fn from_f64(n: f64) -> Option<Dual> {
+        Some(Dual::constant(n))
+    }
+}
+
+impl Signed for Dual {
+    fn abs(&self) -> Dual {
+        Real::abs(*self)
+    }
+
+    fn abs_sub(&self, other: &Dual) -> Dual {
+        if self.re <= other.re {
+            Dual::zero()
+        } else {
+            *self - *other
+        }
+    }
+
+    fn signum(&self) -> Dual {
+        Real::signum(*self)
+    }
+
+    fn is_positive(&self) -> bool {
+        self.re > 0.0
+    }
+
+    fn is_negative(&self) -> bool {
+        self.re < 0.0
+    }
+}
+
+impl Bounded for Dual {
+    fn min_value() -> Dual {
+        Dual::constant(f64::MIN)
+    }
+
+    fn max_value() -> Dual {
+        Dual::constant(f64::MAX)
+    }
+}
+
+impl AbsDiffEq for Dual {
+    type Epsilon = Dual;
+
+    fn default_epsilon() -> Dual {
+        Dual::constant(f64::EPSILON)
+    }
+
+    fn abs_diff_eq(&self, other: &Dual, epsilon: Dual) -> bool {
+        self.re.abs_diff_eq(&other.re, epsilon.re)
+    }
+}
+
+impl RelativeEq for Dual {
+    fn default_max_relative() -> Dual {
+        Dual::constant(f64::EPSILON)
+    }
+
+    fn relative_eq(&self, other: &Dual, epsilon: Dual, max_relative: Dual) -> bool {
+        self.re.relative_eq(&other.re, epsilon.re, max_relative.re)
+    }
+}
+
+impl UlpsEq for Dual {
+    fn default_max_ulps() -> u32 {
+        4
+    }
+
+    fn ulps_eq(&self, other: &Dual, epsilon: Dual, max_ulps: u32) -> bool {
+        self.re.ulps_eq(&other.re, epsilon.re, max_ulps)
+    }
+}
+
+impl MeetSemilattice for Dual {
+    fn meet(&self, other: &Dual) -> Dual {
+        if self.re <= other.re {
+            *self
+        } else {
+            *other
+        }
+    }
+}
+
+impl JoinSemilattice for Dual {
+    fn join(&self, other: &Dual) -> Dual {
+        if self.re >= other.re {
+            *self
+        } else {
+            *other
+        }
+    }
+}
+
+impl Lattice for Dual {}
+
+impl SubsetOf<Dual> for Dual {
+    fn to_superset(&self) -> Dual {
+        *self
+    }
+
+    unsafe fn from_superset_unchecked(element: &Dual) -> Dual {
+        *element
+    }
+
+    fn is_in_subset(_: &Dual) -> bool {
+        true
+    }
+}
+
+impl SubsetOf<Dual> for f64 {
+    fn to_superset(&self) -> Dual {
+        Dual::constant(*self)
+    }
+
+    unsafe fn from_superset_unchecked(element: &Dual) -> f64 {
+        element.re
+    }
+
+    fn is_in_subset(element: &Dual) -> bool {
+        element.eps == 0.0
+    }
+}
+
+// The solvers accepting dual numbers control the step size with their values. Dual is not a
+// subset of f64, so that the solvers which would drop the derivative parts reject it.
+impl Value for Dual {
+    fn value(&self) -> f64 {
+        self.re
+    }
+}
+
+impl AbstractMagma<Additive> for Dual {
+    fn operate(&self, right: &Dual) -> Dual {
+        *self + *right
+    }
+}
+
+impl AbstractMagma<Multiplicative> for Dual {
+    fn operate(&self, right: &Dual) -> Dual {
+        *self * *right
+    }
+}
+
+impl TwoSidedInverse<Additive> for Dual {
+    fn two_sided_inverse(&self) -> Dual {
+        -*self
+    }
+}
+
+impl TwoSidedInverse<Multiplicative> for Dual {
+    fn two_sided_inverse(&self) -> Dual {
+        Real::recip(*self)
+    }
+}
+
+impl Identity<Additive> for Dual {
+    fn identity() -> Dual {
+        Dual::zero()
+    }
+}
+
+impl Identity<Multiplicative> for Dual {
+    fn identity() -> Dual {
+        Dual::one()
+    }
+}
+
+impl AbstractQuasigroup<Additive> for Dual {}
+impl AbstractSemigroup<Additive> for Dual {}
+impl AbstractLoop<Additive> for Dual {}
+impl AbstractMonoid<Additive> for Dual {}
+impl AbstractGroup<Additive> for Dual {}
+impl AbstractGroupAbelian<Additive> for Dual {}
+impl AbstractQuasigroup<Multiplicative> for Dual {}
+impl AbstractSemigroup<Multiplicative> for Dual {}
+impl AbstractLoop<Multiplicative> for Dual {}
+impl AbstractMonoid<Multiplicative> for Dual {}
+impl AbstractGroup<Multiplicative> for Dual {}
+impl AbstractGroupAbelian<Multiplicative> for Dual {}
+impl AbstractRing for Dual {}
+impl AbstractRingCommutative for Dual {}
+impl AbstractField for Dual {}
+
+impl Real for Dual {
+    fn floor(self) -> Dual {
+        Dual::constant(self.re.floor())
+    }
+
+    fn ceil(self) -> Dual {
+        Dual::constant(self.re.ceil())
+    }
+
+    fn round(self) -> Dual {
+        Dual::constant(self.re.round())
+    }
+
+    fn trunc(self) -> Dual {
+        Dual::constant(self.re.trunc())
+    }
+
+    fn fract(self) -> Dual {
+        Dual::new(self.re.fract(), self.eps)
+    }
+
+    fn abs(self) -> Dual {
+        if self.re < 0.0 {
+            -self
+        } else {
+            self
+        }
+    }
+
+    fn signum(self) -> Dual {
+        Dual::constant(self.re.signum())
+    }
+
+    fn is_sign_positive(self) -> bool {
+        self.re.is_sign_positive()
+    }
+
+    fn is_sign_negative(self) -> bool {
+        self.re.is_sign_negative()
+    }
+
+    fn mul_add(self, a: Dual, b: Dual) -> Dual {
+        self * a + b
+    }
+
+    fn recip(self) -> Dual {
+        self.chain(self.re.recip(), -1.0 / (self.re * self.re))
+    }
+
+    fn powi(self, n: i32) -> Dual {
+        if n == 0 {
+            return Dual::one();
+        }
+        self.chain(self.re.powi(n), f64::from(n) * self.re.powi(n - 1))
+    }
+
+    fn powf(self, n: Dual) -> Dual {
+        let re = self.re.powf(n.re);
+        let mut eps = n.re * self.re.powf(n.re - 1.0) * self.eps;
+        if n.eps != 0.0 {
+            eps += re * self.re.ln() * n.eps;
+        }
+        Dual::new(re, eps)
+    }
+
+    fn sqrt(self) -> Dual {
+        let sqrt = self.re.sqrt();
+        self.chain(sqrt, 0.5 / sqrt)
+    }
+
+    fn exp(self) -> Dual {
+        let exp = self.re.exp();
+        self.chain(exp, exp)
+    }
+
+    fn exp2(self) -> Dual {
+        let exp2 = self.re.exp2();
+        self.chain(exp2, exp2 * f64::consts::LN_2)
+    }
+
+    fn ln(self) -> Dual {
+        self.chain(self.re.ln(), 1.0 / self.re)
+    }
+
+    fn log(self, base: Dual) -> Dual {
+        self.ln() / base.ln()
+    }
+
+    fn log2(self) -> Dual {
+        self.chain(self.re.log2(), 1.0 / (self.re * f64::consts::LN_2))
+    }
+
+    fn log10(self) -> Dual {
+        self.chain(self.re.log10(), 1.0 / (self.re * f64::consts::LN_10))
+    }
+
+    fn max(self, other: Dual) -> Dual {
+        self.join(&other)
+    }
+
+    fn min(self, other: Dual) -> Dual {
+        self.meet(&other)
+    }
+
+    fn cbrt(self) -> Dual {
+        let cbrt = self.re.cbrt();
+        self.chain(cbrt, 1.0 / (3.0 * cbrt * cbrt))
+    }
+
+    fn hypot(self, other: Dual) -> Dual {
+        (self * self + other * other).sqrt()
+    }
+
+    fn sin(self) -> Dual {
+        self.chain(self.re.sin(), self.re.cos())
+    }
+
+    fn cos(self) -> Dual {
+        self.chain(self.re.cos(), -self.re.sin())
+    }
+
+    fn tan(self) -> Dual {
+        let tan = self.re.tan();
+        self.chain(tan, 1.0 + tan * tan)
+    }
+
+    fn asin(self) -> Dual {
+        self.chain(self.re.asin(), 1.0 / (1.0 - self.re * self.re).sqrt())
+    }
+
+    fn acos(self) -> Dual {
+        self.chain(self.re.acos(), -1.0 / (1.0 - self.re * self.re).sqrt())
+    }
+
+    fn atan(self) -> Dual {
+        self.chain(self.re.atan(), 1.0 / (1.0 + self.re * self.re))
+    }
+
+    fn atan2(self, other: Dual) -> Dual {
+        let r2 = self.re * self.re + other.re * other.re;
+        Dual::new(
+            self.re.atan2(other.re),
+            (other.re * self.eps - self.re * other.eps) / r2,
+        )
+    }
+
+    fn sin_cos(self) -> (Dual, Dual) {
+        (self.sin(), self.cos())
+    }
+
+    fn exp_m1(self) -> Dual {
+        self.chain(self.re.exp_m1(), self.re.exp())
+    }
+
+    fn ln_1p(self) -> Dual {
+        self.chain(self.re.ln_1p(), 1.0 / (1.0 + self.re))
+    }
+
+    fn sinh(self) -> Dual {
+        self.chain(self.re.sinh(), self.re.cosh())
+    }
+
+    fn cosh(self) -> Dual {
+        self.chain(self.re.cosh(), self.re.sinh())
+    }
+
+    fn tanh(self) -> Dual {
+        let tanh = self.re.tanh();
+        self.chain(tanh, 1.0 - tanh * tanh)
+    }
+
+    fn asinh(self) -> Dual {
+        self.chain(self.re.asinh(), 1.0 / (self.re * self.re + 1.0).sqrt())
+    }
+
+    fn acosh(self) -> Dual {
+        self.chain(self.re.acosh(), 1.0 / (self.re * self.re - 1.0).sqrt())
+    }
+
+    fn atanh(self) -> Dual {
+        self.chain(self.re.atanh(), 1.0 / (1.0 - self.re * self.re))
+    }
+
+    fn pi() -> Dual {
+        Dual::constant(f64::consts::PI)
+    }
+
+    fn two_pi() -> Dual {
+        Dual::constant(2.0 * f64::consts::PI)
+    }
+
+    fn frac_pi_2() -> Dual {
+        Dual::constant(f64::consts::FRAC_PI_2)
+    }
+
+    fn frac_pi_3() -> Dual {
+        Dual::constant(f64::consts::FRAC_PI_3)
+    }
+
+    fn frac_pi_4() -> Dual {
+        Dual::constant(f64::consts::FRAC_PI_4)
+    }
+
+    fn frac_pi_6() -> Dual {
+        Dual::constant(f64::consts::FRAC_PI_6)
+    }
+
+    fn frac_pi_8() -> Dual {
+        Dual::constant(f64::consts::FRAC_PI_8)
+    }
+
+    fn frac_1_pi() -> Dual {
+        Dual::constant(f64::consts::FRAC_1_PI)
+    }
+
+    fn frac_2_pi() -> Dual {
+        Dual::constant(f64::consts::FRAC_2_PI)
+    }
+
+    fn frac_2_sqrt_pi() -> Dual {
+        Dual::constant(f64::consts::FRAC_2_SQRT_PI)
+    }
+
+    fn e() -> Dual {
+        Dual::constant(f64::consts::E)
+    }
+
+    fn log2_e() -> Dual {
+        Dual::constant(f64::consts::LOG2_E)
+    }
+
+    fn log10_e() -> Dual {
+        Dual::constant(f64::consts::LOG10_E)
+    }
+
+    fn ln_2() -> Dual {
+        Dual::constant(f64::consts::LN_2)
+    }
+
+    fn ln_10() -> Dual {
+        Dual::constant(f64::consts::LN_10)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use adams::Adams;
+    use dop853::Dop853;
+    use dopri5::Dopri5;
+    use na::Vector2;
+    use odex::Odex;
+
+    #[test]
+    fn derivatives_of_elementary_functions() {
+        let x = Dual::variable(0.3);
+        let y = (x.sin() * x.exp()) / (x * x + Dual::one()).sqrt();
+        let (s, c, e) = (0.3_f64.sin(), 0.3_f64.cos(), 0.3_f64.exp());
+        let r = (1.09_f64).sqrt();
+        let expected = ((c * e + s * e) * r - s * e * 0.3 / r) / 1.09;
+        assert!((y.re() - s * e / r).abs() < 1.0e-15);
+        assert!((y.eps() - expected).abs() < 1.0e-14);
+    }
+
+    #[test]
+    fn conversions_keep_the_value() {
+        let x = Dual::new(2.5, 1.0);
+        let c: Dual = ::na::convert(1.5);
+        assert_eq!(x.value(), 2.5);
+        assert_eq!(c, Dual::constant(1.5));
+    }
+
+    fn decay(_x: f64, y: &Vector2<Dual>, dy: &mut Vector2<Dual>) {
+        dy[0] = -y[1] * y[0];
+        dy[1] = Dual::zero();
+    }
+
+    #[test]
+    fn derivatives_through_the_explicit_solvers() {
+        // y' = -a y with y(0) = 1, whose derivative with respect to a is -x exp(-a x)
+        let (a, x_end) = (0.7, 2.0);
+        let y0 = Vector2::new(Dual::constant(1.0), Dual::variable(a));
+        let mut dopri5 = Dopri5::new(decay, 0.0, x_end, x_end, y0, 1e-12, 1e-12);
+        dopri5.integrate().unwrap();
+        let mut dop853 = Dop853::new(decay, 0.0, x_end, x_end, y0, 1e-12, 1e-12);
+        dop853.integrate().unwrap();
+        let mut odex = Odex::new(decay, 0.0, x_end, x_end, y0, 1e-12, 1e-12);
+        odex.integrate().unwrap();
+        let mut adams = Adams::new(decay, 0.0, x_end, x_end, y0, 1e-12, 1e-12);
+        adams.integrate().unwrap();
+        let ends = [
+            dopri5.y_out().last().unwrap()[0],
+            dop853.y_out().last().unwrap()[0],
+            odex.y_out().last().unwrap()[0],
+            adams.y_out().last().unwrap()[0],
+        ];
+        for y in &ends {
+            assert!((y.re() - (-a * x_end).exp()).abs() < 1e-9);
+            assert!((y.eps() + x_end * (-a * x_end).exp()).abs() < 1e-8);
+        }
+    }
+}
@@ -0,0 +1,504 @@
+//! Ensembles of trajectories.
+//!
+//! [`Ensemble`](struct.Ensemble.html) integrates a system for many initial conditions and
+//! parameters, as in Monte Carlo studies. The problem of the i-th trajectory is given by a
+//! generator `i -> (y0, p)`, and each trajectory is integrated with its own Dopri5 instance and
+//! reduced to an output by a user function, so that only the quantities of interest are kept in
+//! memory. With the `rayon` feature, the trajectories are integrated in parallel. The results are
+//! always returned in the order of the trajectories, and an integration error only affects the
+//! result of the trajectory where it occurred.
+//!
+//! [`Statistics`](struct.Statistics.html) summarizes the states of the trajectories at the common
+//! output points: mean, variance, extrema, and quantiles estimated with the P² algorithm of Jain
+//! and Chlamtac. They are updated online, one trajectory at a time, so that the memory does not
+//! grow with the number of trajectories.
+//!
+//! For reproducible Monte Carlo studies, the generator should draw its random numbers from a
+//! generator seeded with the index of the trajectory, which makes the ensemble independent of the
+//! order of execution.
+
+use alga::general::SubsetOf;
+use alga::linear::{FiniteDimInnerSpace, InnerSpace};
+use dop_shared::*;
+use dopri5::Dopri5;
+use na;
+use na::DVector;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+use std::f64;
+
+/// Number of trajectories integrated together between two updates of the statistics.
+const BATCH: usize = 1024;
+
+/// Result, trajectory if kept and statistics of the integration of one member of the ensemble.
+type Output<V, R> = Result<(R, Option<(Vec<f64>, Vec<V>)>, Stats), IntegrationError>;
+
+/// Generator of the initial state and the parameters of the i-th trajectory.
+type Generator<V> = Box<dyn Fn(usize) -> (V, DVector<f64>) + Send + Sync>;
+
+/// Structure containing the parameters of the ensemble.
+pub struct Ensemble<V, R>
+where
+    V: FiniteDimInnerSpace + Copy,
+{
+    f: fn(f64, &V, &DVector<f64>, &mut V),
+    generator: Generator<V>,
+    reducer: fn(usize, &[f64], &[V]) -> R,
+    n: usize,
+    x: f64,
+    x_end: f64,
+    dx: f64,
+    rtol: f64,
+    atol: f64,
+    results: Vec<Result<R, IntegrationError>>,
+}
+
+impl<V, R> Ensemble<V, R>
+where
+    V: FiniteDimInnerSpace + Copy + Send + 'static,
+    <V as InnerSpace>::Real: SubsetOf<f64>,
+    R: Send,
+{
+    /// Default initializer for the structure.
+    ///
+    /// # Arguments
+    ///
+    /// * `f`         - Pointer to the function to integrate, which depends on parameters p
+    /// * `generator` - Function giving the initial state and the parameters of the i-th trajectory
+    /// * `reducer`   - Function reducing the i-th trajectory, given by its output points and
+    ///   states, to the result kept in the ensemble
+    /// * `n`         - Number of trajectories
+    /// * `x`         - Initial value of the independent variable (usually time)
+    /// * `x_end`     - Final value of the independent variable
+    ///
+    pub fn new(
+        f: fn(f64, &V, &DVector<f64>, &mut V),
+        generator: fn(usize) -> (V, DVector<f64>),
+        reducer: fn(usize, &[f64], &[V]) -> R,
+        n: usize,
+        x: f64,
+        x_end: f64,
+    ) -> Ensemble<V, R> {
+        Ensemble::with_generator(f, Box::new(generator), reducer, n, x, x_end)
+    }
+
+    /// Initializer taking a generator which may capture its environment, such as precomputed
+    /// samples.
+    pub(crate) fn with_generator(
+        f: fn(f64, &V, &DVector<f64>, &mut V),
+        generator: Generator<V>,
+        reducer: fn(usize, &[f64], &[V]) -> R,
+        n: usize,
+        x: f64,
+        x_end: f64,
+    ) -> Ensemble<V, R> {
+        Ensemble {
+            f,
+            generator,
+            reducer,
+            n,
+            x,
+            x_end,
+            dx: (x_end - x) / 100.0,
+            rtol: 1.0e-8,
+            atol: 1.0e-8,
+            results: Vec::new(),
+        }
+    }
+
+    /// Sets the increment of the output points passed to the reducer. Default is a hundredth of
+    /// the interval.
+    pub fn set_dx(&mut self, dx: f64) {
+        self.dx = dx;
+    }
+
+    /// Sets the relative and absolute tolerances of the integrations. Default is 1e-8 for both.
+    pub fn set_tolerances(&mut self, rtol: f64, atol: f64) {
+        self.rtol = rtol;
+        self.atol = atol;
+    }
+
+    /// Integrates and reduces all the trajectories. The returned statistics are summed over the
+    /// successful integrations.
+    pub fn integrate(&mut self) -> Stats {
+        self.run(None)
+    }
+
+    /// Integrates and reduces all the trajectories like `integrate`, and adds the successful ones
+    /// to the given statistics.
+    pub fn accumulate(&mut self, statistics: &mut Statistics<V>) -> Stats {
+        self.run(Some(statistics))
+    }
+
+    /// Runs the trajectories by batches, so that at most one batch of trajectories is kept in
+    /// memory when statistics are accumulated. The trajectories are always added in their order.
+    fn run(&mut self, mut statistics: Option<&mut Statistics<V>>) -> Stats {
+        let (f, generator, reducer) = (self.f, &self.generator, self.reducer);
+        let (x, x_end, dx, rtol, atol) = (self.x, self.x_end, self.dx, self.rtol, self.atol);
+        let keep = statistics.is_some();
+        let run = move |i: usize| {
+            let (y0, p) = generator(i);
+            let mut stepper = Dopri5::with_parameters(f, p, x, x_end, dx, y0, rtol, atol);
+            let stats = stepper.integrate()?;
+            let result = reducer(i, stepper.x_out(), stepper.y_out());
+            let trajectory = if keep {
+                Some((stepper.x_out().clone(), stepper.y_out().clone()))
+            } else {
+                None
+            };
+            Ok((result, trajectory, stats))
+        };
+
+        let mut stats = Stats::new();
+        self.results = Vec::with_capacity(self.n);
+        let mut start = 0;
+        while start < self.n {
+            let end = (start + BATCH).min(self.n);
+
+            #[cfg(feature = "rayon")]
+            let outputs: Vec<Output<V, R>> = (start..end).into_par_iter().map(run).collect();
+            #[cfg(not(feature = "rayon"))]
+            let outputs: Vec<Output<V, R>> = (start..end).map(run).collect();
+
+            for output in outputs {
+                self.results
+                    .push(output.map(|(result, trajectory, trajectory_stats)| {
+                        stats += trajectory_stats;
+                        if let (Some(ref mut statistics), Some((x_out, y_out))) =
+                            (statistics.as_mut(), trajectory)
+                        {
+                            statistics.add(&x_out, &y_out);
+                        }
+                        result
+                    }));
+            }
+            start = end;
+        }
+        stats
+    }
+
+    /// Getter for the results of the trajectories, in their order. A trajectory whose integration
+    /// failed gives the corresponding error.
+    pub fn results(&self) -> &Vec<Result<R, IntegrationError>> {
+        &self.results
+    }
+
+    /// Number of trajectories whose integration failed.
+    pub fn num_failed(&self) -> usize {
+        self.results.iter().filter(|r| r.is_err()).count()
+    }
+}
+
+/// Structure containing the statistics of the states at the output points, per component.
+pub struct Statistics<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+{
+    quantiles: Vec<f64>,
+    count: u32,
+    x_out: Vec<f64>,
+    mean: Vec<V>,
+    m2: Vec<V>,
+    min: Vec<V>,
+    max: Vec<V>,
+    estimators: Vec<Vec<Vec<P2>>>,
+}
+
+impl<V> Statistics<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: SubsetOf<f64>,
+{
+    /// Default initializer for the structure.
+    ///
+    /// # Arguments
+    ///
+    /// * `quantiles` - Probabilities, between 0 and 1, of the quantiles to estimate
+    ///
+    pub fn new(quantiles: Vec<f64>) -> Statistics<V> {
+        Statistics {
+            quantiles,
+            count: 0,
+            x_out: Vec::new(),
+            mean: Vec::new(),
+            m2: Vec::new(),
+            min: Vec::new(),
+            max: Vec::new(),
+            estimators: Vec::new(),
+        }
+    }
+
+    /// Adds a trajectory. All the trajectories must have the same output points. The values which
+    /// are NaN or infinite are left out of the quantile estimates.
+    pub fn add(&mut self, x_out: &[f64], y_out: &[V]) {
+        let dim = na::dimension::<V>();
+        if self.count == 0 {
+            self.x_out = x_out.to_vec();
+            self.mean = vec![V::zero(); y_out.len()];
+            self.m2 = vec![V::zero(); y_out.len()];
+            self.min = y_out.to_vec();
+            self.max = y_out.to_vec();
+            let estimators: Vec<P2> = self.quantiles.iter().map(|&p| P2::new(p)).collect();
+            self.estimators = vec![vec![estimators; dim]; y_out.len()];
+        }
+        assert_eq!(
+            y_out.len(),
+            self.x_out.len(),
+            "the trajectories must have the same output points"
+        );
+        self.count += 1;
+        let n = f64::from(self.count);
+
+        for (k, y) in y_out.iter().enumerate() {
+            for i in 0..dim {
+                let y_i: f64 = na::convert(y[i]);
+                let mean_i: f64 = na::convert(self.mean[k][i]);
+                let m2_i: f64 = na::convert(self.m2[k][i]);
+                let min_i: f64 = na::convert(self.min[k][i]);
+                let max_i: f64 = na::convert(self.max[k][i]);
+
+                // Update of Welford
+                let delta = y_i - mean_i;
+                let mean_i = mean_i + delta / n;
+                self.mean[k][i] = na::convert(mean_i);
+                self.m2[k][i] = na::convert(m2_i + delta * (y_i - mean_i));
+                self.min[k][i] = na::convert(min_i.min(y_i));
+                self.max[k][i] = na::convert(max_i.max(y_i));
+
+                for estimator in self.estimators[k][i].iter_mut() {
+                    estimator.add(y_i);
+                }
+            }
+        }
+    }
+
+    /// Number of trajectories added.
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Getter for the output points.
+    pub fn x_out(&self) -> &Vec<f64> {
+        &self.x_out
+    }
+
+    /// Getter for the mean of the states at the output points.
+    pub fn mean_out(&self) -> &Vec<V> {
+        &self.mean
+    }
+
+    /// Sample variance of the states at the output points.
+    pub fn variance_out(&self) -> Vec<V> {
+        let n = f64::from(self.count.max(2) - 1);
+        self.m2
+            .iter()
+            .map(|m2| *m2 * na::convert(1.0 / n))
+            .collect()
+    }
+
+    /// Getter for the minimum of the states at the output points.
+    pub fn min_out(&self) -> &Vec<V> {
+        &self.min
+    }
+
+    /// Getter for the maximum of the states at the output points.
+    pub fn max_out(&self) -> &Vec<V> {
+        &self.max
+    }
+
+    /// Estimate of the j-th quantile given to `new`, at the output points.
+    pub fn quantile_out(&self, j: usize) -> Vec<V> {
+        let dim = na::dimension::<V>();
+        self.estimators
+            .iter()
+            .map(|estimators| {
+                let mut y = V::zero();
+                for i in 0..dim {
+                    y[i] = na::convert(estimators[i][j].value());
+                }
+                y
+            })
+            .collect()
+    }
+}
+
+/// Estimator of a quantile with the P² algorithm, which follows five markers of the distribution
+/// without storing the observations.
+#[derive(Clone)]
+struct P2 {
+    p: f64,
+    count: usize,
+    q: [f64; 5],
+    n: [f64; 5],
+    n_desired: [f64; 5],
+    dn: [f64; 5],
+}
+
+impl P2 {
+    fn new(p: f64) -> P2 {
+        P2 {
+            p,
+            count: 0,
+            q: [0.0; 5],
+            n: [1.0, 2.0, 3.0, 4.0, 5.0],
+            n_desired: [1.0, 1.0 + 2.0 * p, 1.0 + 4.0 * p, 3.0 + 2.0 * p, 5.0],
+            dn: [0.0, p / 2.0, p, (1.0 + p) / 2.0, 1.0],
+        }
+    }
+
+    fn add(&mut self, x: f64) {
+        // A NaN or an infinity would break the order of the markers
+        if !x.is_finite() {
+            return;
+        }
+        if self.count < 5 {
+            self.q[self.count] = x;
+            self.count += 1;
+            if self.count == 5 {
+                self.q.sort_by(f64::total_cmp);
+            }
+            return;
+        }
+        self.count += 1;
+
+        // Cell of the observation
+        let k = if x < self.q[0] {
+            self.q[0] = x;
+            0
+        } else if x >= self.q[4] {
+            self.q[4] = x;
+            3
+        } else {
+            self.q[1..4].iter().filter(|&&q| q <= x).count()
+        };
+        for i in k + 1..5 {
+            self.n[i] += 1.0;
+        }
+        for i in 0..5 {
+            self.n_desired[i] += self.dn[i];
+        }
+
+        // Adjustment of the inner markers
+        for i in 1..4 {
+            let d = self.n_desired[i] - self.n[i];
+            if (d >= 1.0 && self.n[i + 1] - self.n[i] > 1.0)
+                || (d <= -1.0 && self.n[i - 1] - self.n[i] < -1.0)
+            {
+                let d = d.signum();
+                let q = self.parabolic(i, d);
+                self.q[i] = if self.q[i - 1] < q && q < self.q[i + 1] {
+                    q
+                } else {
+                    self.linear(i, d)
+                };
+                self.n[i] += d;
+            }
+        }
+    }
+
+    fn parabolic(&self, i: usize, d: f64) -> f64 {
+        let (q, n) = (&self.q, &self.n);
+        q[i] + d / (n[i + 1] - n[i - 1])
+            * ((n[i] - n[i - 1] + d) * (q[i + 1] - q[i]) / (n[i + 1] - n[i])
+                + (n[i + 1] - n[i] - d) * (q[i] - q[i - 1]) / (n[i] - n[i - 1]))
+    }
+
+    fn linear(&self, i: usize, d: f64) -> f64 {
+        let j = if d > 0.0 { i + 1 } else { i - 1 };
+        self.q[i] + d * (self.q[j] - self.q[i]) / (self.n[j] - self.n[i])
+    }
+
+    fn value(&self) -> f64 {
+        if self.count >= 5 {
+            return self.q[2];
+        }
+        if self.count == 0 {
+            return f64::NAN;
+        }
+        // Few observations: quantile of the sample
+        let mut sorted = self.q[..self.count].to_vec();
+        sorted.sort_by(f64::total_cmp);
+        sorted[(self.p * (self.count - 1) as f64).round() as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use na::Vector1;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn statistics_of_uniform_samples() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut statistics = Statistics::new(vec![0.1, 0.5, 0.9]);
+        for _ in 0..20000 {
+            let y: Vector1<f64> = Vector1::new(rng.gen_range(0.0, 1.0));
+            statistics.add(&[0.0], &[y]);
+        }
+        assert_eq!(statistics.count(), 20000);
+        assert!((statistics.mean_out()[0][0] - 0.5).abs() < 0.01);
+        assert!((statistics.variance_out()[0][0] - 1.0 / 12.0).abs() < 0.002);
+        for (j, &p) in [0.1, 0.5, 0.9].iter().enumerate() {
+            assert!((statistics.quantile_out(j)[0][0] - p).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn quantiles_without_non_finite_values() {
+        let mut statistics = Statistics::new(vec![0.25, 0.5]);
+        for i in 0..100 {
+            statistics.add(&[0.0], &[Vector1::new(f64::from(i))]);
+            statistics.add(&[0.0], &[Vector1::new(f64::NAN)]);
+            statistics.add(&[0.0], &[Vector1::new(f64::INFINITY)]);
+        }
+        assert!((statistics.quantile_out(0)[0][0] - 24.75).abs() < 1.0);
+        assert!((statistics.quantile_out(1)[0][0] - 49.5).abs() < 1.0);
+    }
+
+    // y' = -p0 y + p1 y², which blows up before x = 1 for p = (0, 1) and y0 = 2
+    fn system(_x: f64, y: &Vector1<f64>, p: &DVector<f64>, dy: &mut Vector1<f64>) {
+        dy[0] = -p[0] * y[0] + p[1] * y[0] * y[0];
+    }
+
+    // Decay of y0 = 1 + i / 100 at the rate i / 1000, except for every seventh trajectory
+    fn generator(i: usize) -> (Vector1<f64>, DVector<f64>) {
+        if i % 7 == 3 {
+            (Vector1::new(2.0), DVector::from_column_slice(&[0.0, 1.0]))
+        } else {
+            let y0 = Vector1::new(1.0 + i as f64 / 100.0);
+            (y0, DVector::from_column_slice(&[i as f64 / 1000.0, 0.0]))
+        }
+    }
+
+    fn reducer(i: usize, x_out: &[f64], y_out: &[Vector1<f64>]) -> (usize, f64, f64) {
+        (i, *x_out.last().unwrap(), y_out.last().unwrap()[0])
+    }
+
+    #[test]
+    fn order_and_errors_of_the_trajectories() {
+        // More trajectories than in a batch
+        let n = BATCH + 100;
+        let mut ensemble = Ensemble::new(system, generator, reducer, n, 0.0, 1.0);
+        ensemble.set_tolerances(1.0e-10, 1.0e-10);
+        let mut statistics = Statistics::new(vec![0.5]);
+        let stats = ensemble.accumulate(&mut statistics);
+        assert!(stats.num_eval > 0);
+
+        assert_eq!(ensemble.results().len(), n);
+        let failed = (0..n).filter(|i| i % 7 == 3).count();
+        assert_eq!(ensemble.num_failed(), failed);
+        assert_eq!(statistics.count() as usize, n - failed);
+        for (i, result) in ensemble.results().iter().enumerate() {
+            match result {
+                Ok((j, x, y)) => {
+                    assert_eq!(*j, i);
+                    let exact = (1.0 + i as f64 / 100.0) * (-(i as f64) / 1000.0 * x).exp();
+                    assert!((y - exact).abs() < 1.0e-8);
+                }
+                Err(_) => assert_eq!(i % 7, 3),
+            }
+        }
+    }
+}
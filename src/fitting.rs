@@ -0,0 +1,623 @@
+#![allow(clippy::needless_range_loop)]
+
+//! Parameter estimation.
+//!
+//! [`Fit`](struct.Fit.html) estimates the parameters p of a system _y' = f(x, y, p)_ from
+//! measurements m<sub>k</sub> of the solution at the observation times x<sub>k</sub> by minimizing
+//! the weighted sum of squared residuals _½ Σ w<sub>k</sub> (y(x<sub>k</sub>, p) - m<sub>k</sub>)²_
+//! with the Levenberg-Marquardt method. The Jacobian matrix of the residuals is given by the
+//! forward sensitivities ∂y/∂p, which are integrated together with the solution with the
+//! Dormand-Prince method. A trial step for which the integration fails is rejected and the
+//! damping is increased, as for a step which does not reduce the residuals.
+//!
+//! Once converged, the covariance matrix of the parameters is estimated from the Jacobian matrix
+//! and the variance of the residuals, from which standard errors and confidence intervals follow.
+
+use alga::general::SubsetOf;
+use alga::linear::{FiniteDimInnerSpace, InnerSpace};
+use dop_shared::*;
+use dopri5::Dopri5;
+use na;
+use na::{DMatrix, DVector};
+use sensitivity::Sensitivity;
+use std::error::Error;
+use std::f64;
+use std::fmt;
+
+/// Enumeration of the errors that may arise during the estimation.
+#[derive(Debug)]
+pub enum FitError {
+    Integration(IntegrationError),
+    MaxIterationsReached { iterations: u32 },
+}
+
+impl Error for FitError {}
+
+impl fmt::Display for FitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FitError::Integration(e) => write!(f, "Integration failed at the initial guess. {}", e),
+            FitError::MaxIterationsReached { iterations } => {
+                write!(f, "No convergence after {} iterations", iterations)
+            }
+        }
+    }
+}
+
+/// Contains some statistics of the estimation.
+#[derive(Clone, Copy, Debug)]
+pub struct FitStats {
+    pub iterations: u32,
+    pub num_integrations: u32,
+    pub failed_integrations: u32,
+    pub num_eval: u32,
+}
+
+impl FitStats {
+    fn new() -> FitStats {
+        FitStats {
+            iterations: 0,
+            num_integrations: 0,
+            failed_integrations: 0,
+            num_eval: 0,
+        }
+    }
+}
+
+impl fmt::Display for FitStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Number of iterations: {}", self.iterations)?;
+        writeln!(f, "Number of integrations: {}", self.num_integrations)?;
+        writeln!(f, "Number of failed integrations: {}", self.failed_integrations)?;
+        write!(f, "Number of function evaluations: {}", self.num_eval)
+    }
+}
+
+/// Structure containing the system, the measurements and the parameters of the estimation.
+pub struct Fit<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+{
+    f: fn(f64, &V, &DVector<f64>, &mut V),
+    dfdp: fn(f64, &V, &DVector<f64>, &mut DMatrix<f64>),
+    #[allow(clippy::type_complexity)]
+    jac: Option<fn(f64, &V, &DVector<f64>, &mut DMatrix<f64>)>,
+    x0: f64,
+    y0: V,
+    x_obs: Vec<f64>,
+    y_obs: Vec<V>,
+    weights: Vec<V>,
+    rtol: f64,
+    atol: f64,
+    n_max: u32,
+    max_iterations: u32,
+    ftol: f64,
+    xtol: f64,
+    gtol: f64,
+    p: DVector<f64>,
+    cost: f64,
+    residuals: DVector<f64>,
+    y_out: Vec<V>,
+    covariance: Option<DMatrix<f64>>,
+    dof: usize,
+    stats: FitStats,
+}
+
+impl<V> Fit<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: SubsetOf<f64>,
+{
+    /// Default initializer for the structure. All the measurements have a unit weight.
+    ///
+    /// # Arguments
+    ///
+    /// * `f`       - Pointer to the function to integrate, depending on the parameters
+    /// * `dfdp`    - Function computing the n×n<sub>p</sub> matrix ∂f/∂p at (x, y, p)
+    /// * `x0`      - Initial value of the independent variable (usually time)
+    /// * `y0`      - Initial value of the dependent variable(s)
+    /// * `x_obs`   - Observation times, sorted in the direction of integration
+    /// * `y_obs`   - Measured values at the observation times
+    /// * `p0`      - Initial guess of the parameters
+    ///
+    pub fn new(
+        f: fn(f64, &V, &DVector<f64>, &mut V),
+        dfdp: fn(f64, &V, &DVector<f64>, &mut DMatrix<f64>),
+        x0: f64,
+        y0: V,
+        x_obs: Vec<f64>,
+        y_obs: Vec<V>,
+        p0: DVector<f64>,
+    ) -> Fit<V> {
+        assert_eq!(x_obs.len(), y_obs.len(), "one measurement is needed per observation time");
+        let dim = na::dimension::<V>();
+        let mut ones = V::zero();
+        for i in 0..dim {
+            ones[i] = na::convert(1.0);
+        }
+        Fit {
+            f,
+            dfdp,
+            jac: None,
+            x0,
+            y0,
+            weights: vec![ones; x_obs.len()],
+            x_obs,
+            y_obs,
+            rtol: 1.0e-8,
+            atol: 1.0e-8,
+            n_max: 100000,
+            max_iterations: 100,
+            ftol: 1.0e-10,
+            xtol: 1.0e-8,
+            gtol: 1.0e-10,
+            p: p0,
+            cost: 0.0,
+            residuals: DVector::zeros(0),
+            y_out: Vec::new(),
+            covariance: None,
+            dof: 0,
+            stats: FitStats::new(),
+        }
+    }
+
+    /// Sets the weights of the components of the measurements, typically the inverse of their
+    /// variances. A zero weight removes a component from the estimation, e.g. for a missing value.
+    pub fn set_weights(&mut self, weights: Vec<V>) {
+        assert_eq!(weights.len(), self.x_obs.len(), "one weight is needed per observation time");
+        self.weights = weights;
+    }
+
+    /// Sets the function computing the Jacobian matrix of f with respect to y. If no Jacobian is
+    /// given, it is approximated by finite differences.
+    pub fn set_jacobian(&mut self, jac: fn(f64, &V, &DVector<f64>, &mut DMatrix<f64>)) {
+        self.jac = Some(jac);
+    }
+
+    /// Sets the relative and absolute tolerances of the integrations. Default is 1e-8 for both.
+    pub fn set_tolerances(&mut self, rtol: f64, atol: f64) {
+        self.rtol = rtol;
+        self.atol = atol;
+    }
+
+    /// Sets the maximum number of steps of each integration. Default is 100000.
+    pub fn set_n_max(&mut self, n_max: u32) {
+        self.n_max = n_max;
+    }
+
+    /// Sets the maximum number of Levenberg-Marquardt iterations. Default is 100.
+    pub fn set_max_iterations(&mut self, max_iterations: u32) {
+        self.max_iterations = max_iterations;
+    }
+
+    /// Sets the convergence criteria: the relative decrease of the sum of squares (default 1e-10),
+    /// the relative size of the step (default 1e-8) and the largest component of the gradient
+    /// (default 1e-10).
+    pub fn set_convergence(&mut self, ftol: f64, xtol: f64, gtol: f64) {
+        self.ftol = ftol;
+        self.xtol = xtol;
+        self.gtol = gtol;
+    }
+
+    /// Core estimation method.
+    pub fn fit(&mut self) -> Result<FitStats, FitError> {
+        let n_p = self.p.len();
+        let mut m = 0;
+        for w in &self.weights {
+            for i in 0..na::dimension::<V>() {
+                let w_i: f64 = na::convert(w[i]);
+                if w_i > 0.0 {
+                    m += 1;
+                }
+            }
+        }
+        assert!(m > n_p, "more measurements than parameters are needed");
+        self.dof = m - n_p;
+        self.stats = FitStats::new();
+        self.covariance = None;
+
+        let mut p = self.p.clone();
+        let (mut r, mut jm, mut y_out) = self.residuals(&p).map_err(FitError::Integration)?;
+        let mut cost = 0.5 * r.norm_squared();
+        let mut jtj = jm.tr_mul(&jm);
+        let mut g = jm.tr_mul(&r);
+        let mut lambda = 1.0e-3 * jtj.diagonal().amax();
+        let mut nu = 2.0;
+        let mut converged = false;
+
+        while self.stats.iterations < self.max_iterations {
+            if g.amax() <= self.gtol {
+                converged = true;
+                break;
+            }
+            self.stats.iterations += 1;
+
+            // Damped normal equations, scaled with the diagonal of JᵀJ
+            let scale = jtj.diagonal().map(|d| d.max(f64::EPSILON));
+            let mut a = jtj.clone();
+            for i in 0..n_p {
+                a[(i, i)] += lambda * scale[i];
+            }
+            let delta = match a.cholesky() {
+                Some(chol) => chol.solve(&-&g),
+                None => {
+                    lambda *= nu;
+                    nu *= 2.0;
+                    continue;
+                }
+            };
+            if delta.norm() <= self.xtol * (p.norm() + self.xtol) {
+                converged = true;
+                break;
+            }
+
+            let p_new = &p + &delta;
+            match self.residuals(&p_new) {
+                Ok((r_new, jm_new, y_new)) => {
+                    let cost_new = 0.5 * r_new.norm_squared();
+                    let predicted = 0.5 * delta.dot(&(delta.component_mul(&scale) * lambda - &g));
+                    let rho = (cost - cost_new) / predicted;
+                    if rho > 0.0 {
+                        let reduction = cost - cost_new;
+                        p = p_new;
+                        r = r_new;
+                        jm = jm_new;
+                        y_out = y_new;
+                        jtj = jm.tr_mul(&jm);
+                        g = jm.tr_mul(&r);
+                        lambda *= (1.0 / 3.0_f64).max(1.0 - (2.0 * rho - 1.0).powi(3));
+                        nu = 2.0;
+                        if reduction <= self.ftol * cost {
+                            cost = cost_new;
+                            converged = true;
+                            break;
+                        }
+                        cost = cost_new;
+                    } else {
+                        lambda *= nu;
+                        nu *= 2.0;
+                    }
+                }
+                Err(_) => {
+                    self.stats.failed_integrations += 1;
+                    lambda *= nu;
+                    nu *= 2.0;
+                }
+            }
+        }
+
+        self.p = p;
+        self.cost = cost;
+        self.residuals = r;
+        self.y_out = y_out;
+        let variance = 2.0 * cost / self.dof as f64;
+        self.covariance = jtj.try_inverse().map(|inv| inv * variance);
+
+        if converged {
+            Ok(self.stats)
+        } else {
+            Err(FitError::MaxIterationsReached {
+                iterations: self.stats.iterations,
+            })
+        }
+    }
+
+    /// Computes the weighted residuals, their Jacobian matrix with respect to the parameters and
+    /// the solution at the observation times.
+    #[allow(clippy::type_complexity)]
+    fn residuals(
+        &mut self,
+        p: &DVector<f64>,
+    ) -> Result<(DVector<f64>, DMatrix<f64>, Vec<V>), IntegrationError> {
+        self.stats.num_integrations += 1;
+        let (y_out, s_out) = self.solve(p)?;
+        let dim = na::dimension::<V>();
+        let n_obs = self.x_obs.len();
+        let mut r = DVector::zeros(n_obs * dim);
+        let mut jm = DMatrix::zeros(n_obs * dim, p.len());
+        for k in 0..n_obs {
+            for i in 0..dim {
+                let w_i: f64 = na::convert(self.weights[k][i]);
+                let sw = w_i.max(0.0).sqrt();
+                let y_i: f64 = na::convert(y_out[k][i]);
+                let m_i: f64 = na::convert(self.y_obs[k][i]);
+                r[k * dim + i] = sw * (y_i - m_i);
+                for c in 0..p.len() {
+                    let s_i: f64 = na::convert(s_out[k][c][i]);
+                    jm[(k * dim + i, c)] = sw * s_i;
+                }
+            }
+        }
+        Ok((r, jm, y_out))
+    }
+
+    /// Integrates the solution and its sensitivities with the Dormand-Prince method, from one
+    /// observation time to the next. The steps of all the intervals count towards `n_max`.
+    #[allow(clippy::type_complexity)]
+    fn solve(&mut self, p: &DVector<f64>) -> Result<(Vec<V>, Vec<Vec<V>>), IntegrationError> {
+        let n_obs = self.x_obs.len();
+        let mut y_out = Vec::with_capacity(n_obs);
+        let mut s_out = Vec::with_capacity(n_obs);
+
+        let mut x = self.x0;
+        let mut y = self.y0;
+        let mut s = vec![V::zero(); p.len()];
+        let mut n_step = 0;
+        for k in 0..n_obs {
+            let x_obs = self.x_obs[k];
+            if x_obs != x {
+                let mut sensitivity = Sensitivity::with_parameters(self.dfdp);
+                sensitivity.set_initial_values(s);
+                if let Some(jac) = self.jac {
+                    sensitivity.set_parametric_jacobian(jac);
+                }
+                let mut stepper = Dopri5::sparse(
+                    System::Parametric(self.f, p.clone()),
+                    x,
+                    x_obs,
+                    y,
+                    self.rtol,
+                    self.atol,
+                    self.n_max.saturating_sub(n_step),
+                );
+                stepper.set_sensitivity(sensitivity);
+                let stats = stepper.integrate()?;
+                self.stats.num_eval += stats.num_eval;
+                n_step += stats.accepted_steps + stats.rejected_steps;
+                x = x_obs;
+                y = *stepper.y_out().last().unwrap();
+                s = stepper.s_out().last().unwrap().clone();
+            }
+            y_out.push(y);
+            s_out.push(s.clone());
+        }
+        Ok((y_out, s_out))
+    }
+
+    /// Getter for the estimated parameters.
+    pub fn parameters(&self) -> &DVector<f64> {
+        &self.p
+    }
+
+    /// Half the weighted sum of squared residuals at the estimated parameters.
+    pub fn cost(&self) -> f64 {
+        self.cost
+    }
+
+    /// Getter for the weighted residuals, ordered by observation time and then by component.
+    pub fn residuals_out(&self) -> &DVector<f64> {
+        &self.residuals
+    }
+
+    /// Getter for the solution at the observation times for the estimated parameters.
+    pub fn y_out(&self) -> &Vec<V> {
+        &self.y_out
+    }
+
+    /// Estimated covariance matrix of the parameters, σ² (JᵀJ)⁻¹, where σ² is the variance of the
+    /// weighted residuals. None if JᵀJ is singular, i.e. if some parameters cannot be identified
+    /// from the measurements.
+    pub fn covariance(&self) -> Option<&DMatrix<f64>> {
+        self.covariance.as_ref()
+    }
+
+    /// Standard errors of the parameters.
+    pub fn standard_errors(&self) -> Option<DVector<f64>> {
+        self.covariance
+            .as_ref()
+            .map(|cov| cov.diagonal().map(|v| v.max(0.0).sqrt()))
+    }
+
+    /// Confidence intervals of the parameters at the given level (e.g. 0.95), from the Student
+    /// distribution with m - n<sub>p</sub> degrees of freedom.
+    pub fn confidence_intervals(&self, level: f64) -> Option<Vec<(f64, f64)>> {
+        let t = student_t_quantile(0.5 + 0.5 * level, self.dof as f64);
+        self.standard_errors().map(|se| {
+            (0..self.p.len())
+                .map(|i| (self.p[i] - t * se[i], self.p[i] + t * se[i]))
+                .collect()
+        })
+    }
+}
+
+/// Quantile of the Student distribution with `nu` degrees of freedom, for q in (0.5, 1).
+fn student_t_quantile(q: f64, nu: f64) -> f64 {
+    let cdf = |t: f64| 1.0 - 0.5 * incomplete_beta(nu / (nu + t * t), 0.5 * nu, 0.5);
+    let mut hi = 1.0;
+    while cdf(hi) < q {
+        hi *= 2.0;
+    }
+    let mut lo = 0.0;
+    for _ in 0..100 {
+        let mid = 0.5 * (lo + hi);
+        if cdf(mid) < q {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    0.5 * (lo + hi)
+}
+
+/// Regularized incomplete beta function I<sub>x</sub>(a, b).
+fn incomplete_beta(x: f64, a: f64, b: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+    let front = (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_fraction(x, a, b) / a
+    } else {
+        1.0 - front * beta_fraction(1.0 - x, b, a) / b
+    }
+}
+
+/// Continued fraction of the incomplete beta function, evaluated with the method of Lentz.
+fn beta_fraction(x: f64, a: f64, b: f64) -> f64 {
+    let tiny = 1.0e-300;
+    let mut c = 1.0;
+    let mut d = 1.0 - (a + b) * x / (a + 1.0);
+    if d.abs() < tiny {
+        d = tiny;
+    }
+    d = 1.0 / d;
+    let mut h = d;
+    for m in 1..300 {
+        let m = f64::from(m);
+        let m2 = 2.0 * m;
+        let aa = m * (b - m) * x / ((a + m2 - 1.0) * (a + m2));
+        d = 1.0 + aa * d;
+        if d.abs() < tiny {
+            d = tiny;
+        }
+        c = 1.0 + aa / c;
+        if c.abs() < tiny {
+            c = tiny;
+        }
+        d = 1.0 / d;
+        h *= d * c;
+        let aa = -(a + m) * (a + b + m) * x / ((a + m2) * (a + m2 + 1.0));
+        d = 1.0 + aa * d;
+        if d.abs() < tiny {
+            d = tiny;
+        }
+        c = 1.0 + aa / c;
+        if c.abs() < tiny {
+            c = tiny;
+        }
+        d = 1.0 / d;
+        let del = d * c;
+        h *= del;
+        if (del - 1.0).abs() < 1.0e-15 {
+            break;
+        }
+    }
+    h
+}
+
+/// Logarithm of the gamma function, from the approximation of Lanczos.
+fn ln_gamma(x: f64) -> f64 {
+    let coeffs = [
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+    let x = x - 1.0;
+    let mut sum = 0.999_999_999_999_809_9;
+    for (i, c) in coeffs.iter().enumerate() {
+        sum += c / (x + i as f64 + 1.0);
+    }
+    let t = x + 7.5;
+    0.5 * (2.0 * f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use na::Vector1;
+
+    #[test]
+    fn student_quantiles() {
+        assert!((student_t_quantile(0.975, 1.0) - 12.706_204_736).abs() < 1.0e-6);
+        assert!((student_t_quantile(0.975, 10.0) - 2.228_138_852).abs() < 1.0e-8);
+        assert!((student_t_quantile(0.95, 1000.0) - 1.646_379_59).abs() < 1.0e-6);
+    }
+
+    // Decay towards an equilibrium, y' = -p0 (y - p1)
+    fn decay(_x: f64, y: &Vector1<f64>, p: &DVector<f64>, dy: &mut Vector1<f64>) {
+        dy[0] = -p[0] * (y[0] - p[1]);
+    }
+
+    fn decay_dfdp(_x: f64, y: &Vector1<f64>, p: &DVector<f64>, dfdp: &mut DMatrix<f64>) {
+        dfdp[(0, 0)] = p[1] - y[0];
+        dfdp[(0, 1)] = p[0];
+    }
+
+    #[test]
+    fn exponential_decay() {
+        // Measurements of y = 2 + 8 exp(-x / 2) with a deterministic noise of amplitude 0.05
+        let x_obs: Vec<f64> = (1..41).map(|k| 0.25 * k as f64).collect();
+        let y_obs = x_obs
+            .iter()
+            .enumerate()
+            .map(|(k, x)| {
+                Vector1::new(2.0 + 8.0 * (-0.5 * x).exp() + 0.05 * (7.3 * k as f64).sin())
+            })
+            .collect();
+        let p0 = DVector::from_column_slice(&[1.0, 0.0]);
+        let mut fit = Fit::new(
+            decay,
+            decay_dfdp,
+            0.0,
+            Vector1::new(10.0),
+            x_obs.clone(),
+            y_obs,
+            p0,
+        );
+        fit.set_tolerances(1.0e-10, 1.0e-10);
+        fit.fit().unwrap();
+
+        let p = fit.parameters();
+        let se = fit.standard_errors().unwrap();
+        assert!(se[0] > 0.0 && se[0] < 0.01 && se[1] > 0.0 && se[1] < 0.05);
+        assert!((p[0] - 0.5).abs() < 3.0 * se[0]);
+        assert!((p[1] - 2.0).abs() < 3.0 * se[1]);
+
+        // Covariance σ² (JᵀJ)⁻¹ from the closed-form derivatives of the solution
+        let mut jtj = DMatrix::zeros(2, 2);
+        for x in &x_obs {
+            let e = (-p[0] * x).exp();
+            let j = [-x * (10.0 - p[1]) * e, 1.0 - e];
+            for a in 0..2 {
+                for b in 0..2 {
+                    jtj[(a, b)] += j[a] * j[b];
+                }
+            }
+        }
+        let expected = jtj.try_inverse().unwrap() * (2.0 * fit.cost() / 38.0);
+        let covariance = fit.covariance().unwrap();
+        assert!((covariance - &expected).norm() < 1.0e-5 * expected.norm());
+    }
+
+    // y' = p0 y², whose solution 1 / (1 - p0 x) blows up at x = 1 / p0
+    fn blow_up(_x: f64, y: &Vector1<f64>, p: &DVector<f64>, dy: &mut Vector1<f64>) {
+        dy[0] = p[0] * y[0] * y[0];
+    }
+
+    fn blow_up_dfdp(_x: f64, y: &Vector1<f64>, _p: &DVector<f64>, dfdp: &mut DMatrix<f64>) {
+        dfdp[(0, 0)] = y[0] * y[0];
+    }
+
+    #[test]
+    fn failed_integration_in_the_line_search() {
+        // The first trial steps from p0 = 0 move the singularity inside the observation interval
+        let x_obs: Vec<f64> = (1..11).map(|k| 0.1 * k as f64).collect();
+        let y_obs = x_obs
+            .iter()
+            .map(|x| Vector1::new(1.0 / (1.0 - 0.8 * x)))
+            .collect();
+        let p0 = DVector::from_column_slice(&[0.0]);
+        let mut fit = Fit::new(
+            blow_up,
+            blow_up_dfdp,
+            0.0,
+            Vector1::new(1.0),
+            x_obs,
+            y_obs,
+            p0,
+        );
+        fit.set_n_max(1000);
+        let stats = fit.fit().unwrap();
+        assert!(stats.failed_integrations > 0);
+        assert!((fit.parameters()[0] - 0.8).abs() < 1.0e-6);
+        assert!(fit.cost() < 1.0e-12);
+    }
+}
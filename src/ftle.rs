@@ -0,0 +1,248 @@
+#![allow(clippy::needless_range_loop)]
+
+//! Finite-time Lyapunov exponents.
+//!
+//! The finite-time Lyapunov exponent (FTLE) of an initial condition y0 over the horizon
+//! [x0, x_end] is the largest rate of separation of the neighbouring trajectories,
+//!
+//! _σ = ln ‖Φ(x_end, x0)‖ / |x_end - x0|_,
+//!
+//! where Φ is the gradient of the flow map and ‖Φ‖ its largest singular value, the square root of
+//! the largest eigenvalue of the Cauchy-Green tensor Φ<sup>T</sup> Φ. The ridges of the FTLE field
+//! over a grid of initial conditions approximate the Lagrangian coherent structures, the transport
+//! barriers of the flow. [`Ftle`](struct.Ftle.html) integrates the trajectories with Dop853, in
+//! parallel with the `rayon` feature.
+
+use alga::general::SubsetOf;
+use alga::linear::{FiniteDimInnerSpace, InnerSpace};
+use dop853::Dop853;
+use dop_shared::*;
+use na;
+use na::DMatrix;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+use stm::StateTransition;
+
+/// Enumeration of the methods computing the gradient of the flow map.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FlowGradient {
+    /// Integration of the variational equations
+    Variational,
+    /// Central differences of trajectories started at the given distance from the initial
+    /// condition along each axis
+    FiniteDifferences(f64),
+}
+
+/// Structure containing the parameters for the computation of an FTLE field.
+pub struct Ftle<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+{
+    f: fn(f64, &V, &mut V),
+    jac: Option<fn(f64, &V, &mut DMatrix<f64>)>,
+    x: f64,
+    x_end: f64,
+    grid: Vec<V>,
+    rtol: f64,
+    atol: f64,
+    gradient: FlowGradient,
+    ftle: Vec<f64>,
+}
+
+impl<V> Ftle<V>
+where
+    V: FiniteDimInnerSpace + Copy + Sync,
+    <V as InnerSpace>::Real: SubsetOf<f64>,
+{
+    /// Default initializer for the structure.
+    ///
+    /// # Arguments
+    ///
+    /// * `f`       - Pointer to the function to integrate
+    /// * `x`       - Initial value of the independent variable (usually time)
+    /// * `x_end`   - Final value of the independent variable, smaller than x for backward FTLE
+    /// * `grid`    - Initial conditions
+    /// * `rtol`    - Relative tolerance used in the computation of the adaptive step size
+    /// * `atol`    - Absolute tolerance used in the computation of the adaptive step size
+    ///
+    pub fn new(
+        f: fn(f64, &V, &mut V),
+        x: f64,
+        x_end: f64,
+        grid: Vec<V>,
+        rtol: f64,
+        atol: f64,
+    ) -> Ftle<V> {
+        Ftle {
+            f,
+            jac: None,
+            x,
+            x_end,
+            grid,
+            rtol,
+            atol,
+            gradient: FlowGradient::Variational,
+            ftle: Vec::new(),
+        }
+    }
+
+    /// Sets the function computing the Jacobian matrix of f with respect to y, used by the
+    /// variational equations. If no Jacobian is given, it is approximated by finite differences,
+    /// and the flow gradient is then excluded from the error control of the integration.
+    pub fn set_jacobian(&mut self, jac: fn(f64, &V, &mut DMatrix<f64>)) {
+        self.jac = Some(jac);
+    }
+
+    /// Sets the method computing the gradient of the flow map. Default is
+    /// `FlowGradient::Variational`.
+    pub fn set_flow_gradient(&mut self, gradient: FlowGradient) {
+        self.gradient = gradient;
+    }
+
+    /// Computes the FTLE at every point of the grid.
+    pub fn integrate(&mut self) -> Result<Stats, IntegrationError> {
+        let this = &*self;
+        #[cfg(feature = "rayon")]
+        let results: Vec<_> = this.grid.par_iter().map(|y0| this.point(y0)).collect();
+        #[cfg(not(feature = "rayon"))]
+        let results: Vec<_> = this.grid.iter().map(|y0| this.point(y0)).collect();
+
+        let mut stats = Stats::new();
+        let mut ftle = Vec::with_capacity(self.grid.len());
+        for result in results {
+            let (value, point_stats) = result?;
+            ftle.push(value);
+            stats += point_stats;
+        }
+        self.ftle = ftle;
+        Ok(stats)
+    }
+
+    /// FTLE of one initial condition.
+    fn point(&self, y0: &V) -> Result<(f64, Stats), IntegrationError> {
+        let mut stats = Stats::new();
+        let phi = match self.gradient {
+            FlowGradient::Variational => self.variational(y0, &mut stats)?,
+            FlowGradient::FiniteDifferences(delta) => {
+                self.finite_differences(y0, delta, &mut stats)?
+            }
+        };
+        let norm = phi.svd(false, false).singular_values.amax();
+        Ok((norm.ln() / (self.x_end - self.x).abs(), stats))
+    }
+
+    fn variational(&self, y0: &V, stats: &mut Stats) -> Result<DMatrix<f64>, IntegrationError> {
+        let mut stm = StateTransition::from_stepper(self.stepper(*y0));
+        match self.jac {
+            Some(jac) => stm.set_jacobian(jac),
+            None => stm.set_error_control(false),
+        }
+        *stats += stm.integrate()?;
+        Ok(stm.phi_out().last().unwrap().clone())
+    }
+
+    fn finite_differences(
+        &self,
+        y0: &V,
+        delta: f64,
+        stats: &mut Stats,
+    ) -> Result<DMatrix<f64>, IntegrationError> {
+        let dim = na::dimension::<V>();
+        let mut phi = DMatrix::zeros(dim, dim);
+        for j in 0..dim {
+            let y0_j: f64 = na::convert(y0[j]);
+            let mut y_pert = *y0;
+            y_pert[j] = na::convert(y0_j + delta);
+            let y_plus = self.flow(y_pert, stats)?;
+            y_pert[j] = na::convert(y0_j - delta);
+            let y_minus = self.flow(y_pert, stats)?;
+            for i in 0..dim {
+                let y_plus_i: f64 = na::convert(y_plus[i]);
+                let y_minus_i: f64 = na::convert(y_minus[i]);
+                phi[(i, j)] = (y_plus_i - y_minus_i) / (2.0 * delta);
+            }
+        }
+        Ok(phi)
+    }
+
+    fn flow(&self, y0: V, stats: &mut Stats) -> Result<V, IntegrationError> {
+        let mut stepper = self.stepper(y0);
+        *stats += stepper.integrate()?;
+        Ok(*stepper.y_out().last().unwrap())
+    }
+
+    fn stepper(&self, y0: V) -> Dop853<V> {
+        Dop853::sparse(
+            System::Plain(self.f),
+            self.x,
+            self.x_end,
+            y0,
+            self.rtol,
+            self.atol,
+            100000,
+        )
+    }
+
+    /// Getter for the initial conditions.
+    pub fn grid(&self) -> &Vec<V> {
+        &self.grid
+    }
+
+    /// Getter for the FTLE, in the order of the grid.
+    pub fn ftle(&self) -> &Vec<f64> {
+        &self.ftle
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use na::Vector2;
+
+    // Linear saddle y' = A y with A = [[1, 2], [0, -1]], whose flow gradient over a horizon T is
+    // [[exp(T), 2 sinh(T)], [0, exp(-T)]], with a unit determinant
+    fn saddle(_x: f64, y: &Vector2<f64>, dy: &mut Vector2<f64>) {
+        dy[0] = y[0] + 2.0 * y[1];
+        dy[1] = -y[1];
+    }
+
+    fn saddle_jacobian(_x: f64, _y: &Vector2<f64>, jac: &mut DMatrix<f64>) {
+        jac[(0, 0)] = 1.0;
+        jac[(0, 1)] = 2.0;
+        jac[(1, 0)] = 0.0;
+        jac[(1, 1)] = -1.0;
+    }
+
+    fn exact(t: f64) -> f64 {
+        // Largest singular value from the trace of ΦᵀΦ and its unit determinant
+        let s = t.exp().powi(2) + (2.0 * t.sinh()).powi(2) + (-t).exp().powi(2);
+        let sigma = (0.5 * (s + (s * s - 4.0).sqrt())).sqrt();
+        sigma.ln() / t
+    }
+
+    #[test]
+    fn linear_saddle() {
+        let grid: Vec<Vector2<f64>> = (0..9)
+            .map(|k| Vector2::new(k as f64 / 4.0 - 1.0, 1.0 - k as f64 / 8.0))
+            .collect();
+        let expected = exact(2.0);
+        // The finite differences of f or of the trajectories limit the accuracy
+        for &(x_end, jac, gradient, tol) in &[
+            (2.0, true, FlowGradient::Variational, 1.0e-10),
+            (-2.0, true, FlowGradient::Variational, 1.0e-10),
+            (2.0, false, FlowGradient::Variational, 1.0e-6),
+            (2.0, false, FlowGradient::FiniteDifferences(1.0e-3), 1.0e-6),
+        ] {
+            let mut ftle = Ftle::new(saddle, 0.0, x_end, grid.clone(), 1.0e-12, 1.0e-12);
+            if jac {
+                ftle.set_jacobian(saddle_jacobian);
+            }
+            ftle.set_flow_gradient(gradient);
+            ftle.integrate().unwrap();
+            assert_eq!(ftle.ftle().len(), grid.len());
+            for value in ftle.ftle() {
+                assert!((value - expected).abs() < tol);
+            }
+        }
+    }
+}
@@ -0,0 +1,54 @@
+//! Jacobian matrix of the right-hand side of the ODE(s), used by the implicit methods.
+
+use alga::linear::{FiniteDimInnerSpace, InnerSpace};
+use dop_shared::Value;
+use na;
+use na::{DMatrix, DVector};
+
+/// Approximates the Jacobian matrix of `f` at (x, y) by forward differences and stores it into `jac`.
+/// `f0` must contain the value of f(x, y). Returns the number of function evaluations.
+pub fn forward_differences<V, F>(f: F, x: f64, y: &V, f0: &V, jac: &mut DMatrix<f64>) -> u32
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: Value,
+    F: Fn(f64, &V, &mut V),
+{
+    let dim = na::dimension::<V>();
+    let mut y_pert = *y;
+    let mut f_pert = V::zero();
+    for j in 0..dim {
+        let y_j = y[j].value();
+        let delta = (f64::EPSILON * 1.0e-5_f64.max(y_j.abs())).sqrt();
+        y_pert[j] = na::convert(y_j + delta);
+        f(x, &y_pert, &mut f_pert);
+        for i in 0..dim {
+            let f_i = f_pert[i].value();
+            let f0_i = f0[i].value();
+            jac[(i, j)] = (f_i - f0_i) / delta;
+        }
+        y_pert[j] = y[j];
+    }
+    dim as u32
+}
+
+/// Copies the components of a state into a dynamically sized vector.
+pub(crate) fn to_dvector<V>(v: &V) -> DVector<f64>
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: Value,
+{
+    DVector::from_fn(na::dimension::<V>(), |i, _| v[i].value())
+}
+
+/// Copies the components of a dynamically sized vector into a state.
+pub(crate) fn from_dvector<V>(d: &DVector<f64>) -> V
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: Value,
+{
+    let mut v = V::zero();
+    for i in 0..na::dimension::<V>() {
+        v[i] = na::convert(d[i]);
+    }
+    v
+}
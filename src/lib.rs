@@ -2,14 +2,44 @@
 //! `ode-solvers` is a collection of numerical methods to solve ordinary differential equations (ODEs).
 
 extern crate alga;
+extern crate approx;
 extern crate nalgebra as na;
+extern crate num_traits;
+extern crate rand;
+#[cfg(feature = "rayon")]
+extern crate rayon;
 
 // Re-export from external crate
-pub use na::{Vector1, Vector2, Vector3, Vector4, Vector5, Vector6, VectorN};
+pub use na::{DMatrix, DVector, Vector1, Vector2, Vector3, Vector4, Vector5, Vector6, VectorN};
 
 // Declare modules
+pub mod adams;
+pub mod adjoint;
+pub mod batch;
 pub mod butcher_tableau;
+pub mod bvp;
+pub mod collocation;
 pub mod controller;
 pub mod dop853;
 pub mod dopri5;
 pub mod dop_shared;
+pub mod dual;
+pub mod ensemble;
+pub mod fitting;
+pub mod ftle;
+pub mod jacobian;
+pub mod lyapunov;
+pub mod odex;
+pub mod parareal;
+pub mod periodic;
+pub mod poincare;
+pub mod reaction;
+pub mod retard;
+pub mod rkn64;
+pub mod sde;
+pub mod sensitivity;
+pub mod seulex;
+pub mod sobol;
+pub mod ssa;
+pub mod stm;
+pub mod uncertainty;
@@ -0,0 +1,198 @@
+#![allow(clippy::needless_range_loop)]
+
+//! Lyapunov exponents.
+//!
+//! The Lyapunov exponents measure the mean exponential rates of growth of the perturbations of a
+//! trajectory, and a positive exponent is the signature of chaos. [`LyapunovSpectrum`]
+//! (struct.LyapunovSpectrum.html) integrates the solution together with its tangent dynamics, the
+//! state transition matrix, with Dop853. Since the tangent vectors grow exponentially and align
+//! with the most expanding direction, they are re-orthonormalized at regular intervals with a QR
+//! decomposition _Φ Q<sub>k</sub> = Q<sub>k+1</sub> R<sub>k+1</sub>_, and the exponents are
+//! estimated by
+//!
+//! _λ<sub>i</sub> = Σ<sub>k</sub> ln |R<sub>k, ii</sub>| / (x - x<sub>0</sub>)_.
+
+use alga::general::SubsetOf;
+use alga::linear::{FiniteDimInnerSpace, InnerSpace};
+use dop853::Dop853;
+use dop_shared::*;
+use na;
+use na::DMatrix;
+use stm::StateTransition;
+
+/// Structure containing the parameters for the computation of the Lyapunov spectrum.
+pub struct LyapunovSpectrum<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+{
+    f: fn(f64, &V, &mut V),
+    jac: Option<fn(f64, &V, &mut DMatrix<f64>)>,
+    x: f64,
+    x_end: f64,
+    y: V,
+    rtol: f64,
+    atol: f64,
+    interval: f64,
+    transient: f64,
+    exponents: Vec<f64>,
+    x_out: Vec<f64>,
+    exponents_out: Vec<Vec<f64>>,
+}
+
+impl<V> LyapunovSpectrum<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: SubsetOf<f64>,
+{
+    /// Default initializer for the structure.
+    ///
+    /// # Arguments
+    ///
+    /// * `f`       - Pointer to the function to integrate
+    /// * `x`       - Initial value of the independent variable (usually time)
+    /// * `x_end`   - Final value of the independent variable, larger than x
+    /// * `y`       - Initial value of the dependent variable(s)
+    /// * `rtol`    - Relative tolerance used in the computation of the adaptive step size
+    /// * `atol`    - Absolute tolerance used in the computation of the adaptive step size
+    ///
+    pub fn new(
+        f: fn(f64, &V, &mut V),
+        x: f64,
+        x_end: f64,
+        y: V,
+        rtol: f64,
+        atol: f64,
+    ) -> LyapunovSpectrum<V> {
+        LyapunovSpectrum {
+            f,
+            jac: None,
+            x,
+            x_end,
+            y,
+            rtol,
+            atol,
+            interval: 1.0,
+            transient: 0.0,
+            exponents: Vec::new(),
+            x_out: Vec::new(),
+            exponents_out: Vec::new(),
+        }
+    }
+
+    /// Sets the function computing the Jacobian matrix of f with respect to y. If no Jacobian is
+    /// given, it is approximated by finite differences, and the tangent dynamics is then excluded
+    /// from the error control of the integration.
+    pub fn set_jacobian(&mut self, jac: fn(f64, &V, &mut DMatrix<f64>)) {
+        self.jac = Some(jac);
+    }
+
+    /// Sets the interval between two re-orthonormalizations. It should be short enough for the
+    /// tangent vectors not to grow by many orders of magnitude. Default is 1.
+    pub fn set_interval(&mut self, interval: f64) {
+        self.interval = interval;
+    }
+
+    /// Sets the duration of the initial transient, integrated without the tangent dynamics and
+    /// excluded from the averages, to let the trajectory reach the attractor. Default is 0.
+    pub fn set_transient(&mut self, transient: f64) {
+        self.transient = transient;
+    }
+
+    /// Computes the Lyapunov spectrum.
+    pub fn integrate(&mut self) -> Result<Stats, IntegrationError> {
+        let dim = na::dimension::<V>();
+        let mut stats = Stats::new();
+        let mut y = self.y;
+        let mut x = self.x;
+
+        if self.transient > 0.0 {
+            let x_transient = x + self.transient;
+            let mut stepper = self.stepper(x, x_transient, y);
+            stats += stepper.integrate()?;
+            y = *stepper.y_out().last().unwrap();
+            x = x_transient;
+        }
+
+        let x_start = x;
+        let mut q = DMatrix::identity(dim, dim);
+        let mut sums = vec![0.0; dim];
+        self.x_out.clear();
+        self.exponents_out.clear();
+
+        while x < self.x_end {
+            let x_next = (x + self.interval).min(self.x_end);
+            let mut stm = StateTransition::from_stepper(self.stepper(x, x_next, y));
+            match self.jac {
+                Some(jac) => stm.set_jacobian(jac),
+                None => stm.set_error_control(false),
+            }
+            stats += stm.integrate()?;
+            y = *stm.y_out().last().unwrap();
+
+            let qr = (stm.phi_out().last().unwrap() * q).qr();
+            let r = qr.r();
+            q = qr.q();
+            for i in 0..dim {
+                sums[i] += r[(i, i)].abs().ln();
+            }
+            x = x_next;
+
+            self.x_out.push(x);
+            self.exponents_out.push(sums.iter().map(|s| s / (x - x_start)).collect());
+        }
+
+        self.exponents = match self.exponents_out.last() {
+            Some(exponents) => exponents.clone(),
+            None => vec![0.0; dim],
+        };
+        Ok(stats)
+    }
+
+    fn stepper(&self, x: f64, x_end: f64, y: V) -> Dop853<V> {
+        Dop853::sparse(System::Plain(self.f), x, x_end, y, self.rtol, self.atol, 100000)
+    }
+
+    /// Getter for the Lyapunov exponents at the end of the integration.
+    pub fn exponents(&self) -> &Vec<f64> {
+        &self.exponents
+    }
+
+    /// Getter for the times of the re-orthonormalizations.
+    pub fn x_out(&self) -> &Vec<f64> {
+        &self.x_out
+    }
+
+    /// Getter for the estimates of the exponents after each re-orthonormalization, which show the
+    /// convergence of the averages.
+    pub fn exponents_out(&self) -> &Vec<Vec<f64>> {
+        &self.exponents_out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use na::Vector2;
+
+    fn shear(_x: f64, y: &Vector2<f64>, dy: &mut Vector2<f64>) {
+        dy[0] = y[0] + 3.0 * y[1];
+        dy[1] = -2.0 * y[1];
+    }
+
+    #[test]
+    fn exponents_of_a_linear_system() {
+        // The exponents are the eigenvalues of the triangular matrix of the system
+        let mut lyapunov =
+            LyapunovSpectrum::new(shear, 0.0, 20.0, Vector2::new(1.0, 1.0), 1.0e-10, 1.0e-10);
+        lyapunov.set_jacobian(|_x, _y, jac| {
+            jac[(0, 0)] = 1.0;
+            jac[(0, 1)] = 3.0;
+            jac[(1, 0)] = 0.0;
+            jac[(1, 1)] = -2.0;
+        });
+        lyapunov.integrate().unwrap();
+        assert!((lyapunov.exponents()[0] - 1.0).abs() < 1.0e-8);
+        assert!((lyapunov.exponents()[1] + 2.0).abs() < 1.0e-8);
+        assert_eq!(lyapunov.x_out().len(), 20);
+    }
+}
@@ -0,0 +1,847 @@
+//===================================================================//
+// Copyright (c) 2004, Ernst Hairer
+
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+
+// - Redistributions of source code must retain the above copyright
+// notice, this list of conditions and the following disclaimer.
+
+// - Redistributions in binary form must reproduce the above copyright
+// notice, this list of conditions and the following disclaimer in the
+// documentation and/or other materials provided with the distribution.
+
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS “AS
+// IS” AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED
+// TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A
+// PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE REGENTS OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+// This code is a Rust adaptation of the code ODEX written originally
+// in Fortran by:
+//
+//      E. Hairer & G. Wanner
+//      Université de Genève, dept. de Mathématiques
+//      CH-1211 Genève 4, Swizerland
+//      E-mail : hairer@divsun.unige.ch, wanner@divsun.unige.ch
+//
+//===================================================================//
+
+#![allow(clippy::needless_range_loop)]
+
+//! Extrapolation method of Gragg-Bulirsch-Stoer based on the explicit midpoint rule, with order and step size control and dense output.
+
+use alga::linear::{FiniteDimInnerSpace, InnerSpace};
+use dop_shared::*;
+use na;
+use std::f64;
+
+// Parameters of the order selection
+const FAC3: f64 = 0.9;
+const FAC4: f64 = 0.8;
+
+// Safety factors of the step size selection
+const SAFE1: f64 = 0.65;
+const SAFE2: f64 = 0.94;
+
+// The stability check is activated at most MSTAB times in the lines 1 to JSTAB of the extrapolation table
+const MSTAB: usize = 1;
+const JSTAB: usize = 2;
+
+/// Outcome of an attempted step.
+enum Step {
+    Accepted(usize),
+    Rejected(usize),
+    Unstable,
+}
+
+/// Structure containing the parameters for the numerical integration.
+pub struct Odex<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+{
+    f: fn(f64, &V, &mut V),
+    x: f64,
+    x0: f64,
+    x_old: f64,
+    x_end: f64,
+    xd: f64,
+    dx: f64,
+    y: V,
+    rtol: f64,
+    atol: f64,
+    x_out: Vec<f64>,
+    y_out: Vec<V>,
+    uround: f64,
+    h: f64,
+    h_old: f64,
+    h_max: f64,
+    n_max: u32,
+    km: usize,
+    fac_min: f64,
+    fac_max: f64,
+    out_type: OutputType,
+    // The arrays below are indexed from 1 as in the original code
+    nj: Vec<usize>,
+    a: Vec<f64>,
+    hh: Vec<f64>,
+    w: Vec<f64>,
+    t: Vec<V>,
+    ysafe: Vec<V>,
+    fsafe: Vec<V>,
+    ipoint: Vec<usize>,
+    errfac: Vec<f64>,
+    dens: Vec<V>,
+    kmit: usize,
+    dz: V,
+    scal: Vec<f64>,
+    err: f64,
+    err_old: f64,
+    ipt: usize,
+    reject: bool,
+    stats: Stats,
+}
+
+impl<V> Odex<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: Value,
+{
+    /// Default initializer for the structure.
+    ///
+    /// # Arguments
+    ///
+    /// * `f`       - Pointer to the function to integrate
+    /// * `x`       - Initial value of the independent variable (usually time)
+    /// * `x_end`   - Final value of the independent variable
+    /// * `dx`      - Increment in the dense output. This argument has no effect if the output type is Sparse
+    /// * `y`       - Initial value of the dependent variable(s)
+    /// * `rtol`    - Relative tolerance used in the computation of the adaptive step size
+    /// * `atol`    - Absolute tolerance used in the computation of the adaptive step size
+    ///
+    pub fn new(
+        f: fn(f64, &V, &mut V),
+        x: f64,
+        x_end: f64,
+        dx: f64,
+        y: V,
+        rtol: f64,
+        atol: f64,
+    ) -> Odex<V> {
+        Odex::from_param(
+            f,
+            x,
+            x_end,
+            dx,
+            y,
+            rtol,
+            atol,
+            0.02,
+            4.0,
+            x_end - x,
+            0.0,
+            10000,
+            9,
+            OutputType::Dense,
+        )
+    }
+
+    /// Advanced initializer for the structure.
+    ///
+    /// # Arguments
+    ///
+    /// * `f`       - Pointer to the function to integrate
+    /// * `x`       - Initial value of the independent variable (usually time)
+    /// * `x_end`   - Final value of the independent variable
+    /// * `dx`      - Increment in the dense output. This argument has no effect if the output type is Sparse
+    /// * `y`       - Initial value of the dependent variable(s)
+    /// * `rtol`    - Relative tolerance used in the computation of the adaptive step size
+    /// * `atol`    - Absolute tolerance used in the computation of the adaptive step size
+    /// * `fac_min` - The step size of column j of the extrapolation table is decreased by at most fac_min^(1/(2j-1)). Default is 0.02
+    /// * `fac_max` - The step size of column j of the extrapolation table is increased by at most fac_max/fac_min^(1/(2j-1)). Default is 4.0
+    /// * `h_max`   - Maximum step size. Default is `x_end-x`
+    /// * `h`       - Initial value of the step size. If h = 0.0, h = 1.0e-4 is used and adapted during the first step
+    /// * `n_max`   - Maximum number of iterations. Default is 10000
+    /// * `km`      - Maximum number of columns in the extrapolation table. Default is 9
+    /// * `out_type`    - Type of the output. Must be a variant of the OutputType enum. Default is Dense
+    ///
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_param(
+        f: fn(f64, &V, &mut V),
+        x: f64,
+        x_end: f64,
+        dx: f64,
+        y: V,
+        rtol: f64,
+        atol: f64,
+        fac_min: f64,
+        fac_max: f64,
+        h_max: f64,
+        h: f64,
+        n_max: u32,
+        km: usize,
+        out_type: OutputType,
+    ) -> Odex<V> {
+        Odex {
+            f,
+            x,
+            x0: x,
+            xd: x,
+            dx,
+            x_old: x,
+            x_end,
+            y,
+            rtol,
+            atol,
+            x_out: Vec::<f64>::new(),
+            y_out: Vec::<V>::new(),
+            uround: f64::EPSILON,
+            h,
+            h_old: h,
+            h_max,
+            n_max,
+            km: km.max(3),
+            fac_min,
+            fac_max,
+            out_type,
+            nj: Vec::new(),
+            a: Vec::new(),
+            hh: Vec::new(),
+            w: Vec::new(),
+            t: Vec::new(),
+            ysafe: Vec::new(),
+            fsafe: Vec::new(),
+            ipoint: Vec::new(),
+            errfac: Vec::new(),
+            dens: Vec::new(),
+            kmit: 0,
+            dz: V::zero(),
+            scal: Vec::new(),
+            err: 0.0,
+            err_old: 0.0,
+            ipt: 0,
+            reject: false,
+            stats: Stats::new(),
+        }
+    }
+
+    /// Allocates the extrapolation table and the arrays used by the dense output.
+    fn init_tables(&mut self) {
+        let km = self.km;
+        let dim = na::dimension::<V>();
+
+        // Step number sequence: 2, 6, 10, 14, ... for the dense output, 2, 4, 8, 12, ... otherwise
+        self.nj = vec![0; km + 1];
+        for j in 1..=km {
+            self.nj[j] = if self.out_type == OutputType::Dense {
+                4 * j - 2
+            } else if j == 1 {
+                2
+            } else {
+                4 * (j - 1)
+            };
+        }
+
+        // Work required to compute the columns of the extrapolation table
+        self.a = vec![0.0; km + 1];
+        self.a[1] = (self.nj[1] + 1) as f64;
+        for j in 2..=km {
+            self.a[j] = self.a[j - 1] + self.nj[j] as f64;
+        }
+
+        self.hh = vec![0.0; km + 1];
+        self.w = vec![0.0; km + 1];
+        self.t = vec![V::zero(); km + 1];
+        self.scal = vec![0.0; dim];
+        for i in 0..dim {
+            let y_i = self.y[i].value();
+            self.scal[i] = self.atol + self.rtol * y_i.abs();
+        }
+
+        if self.out_type == OutputType::Dense {
+            self.ysafe = vec![V::zero(); km + 1];
+            self.ipoint = vec![0; km + 2];
+            for j in 1..=km {
+                let mut njadd = 4 * j - 2;
+                if self.nj[j] > njadd {
+                    njadd += 1;
+                }
+                self.ipoint[j + 1] = self.ipoint[j] + njadd;
+            }
+            self.fsafe = vec![V::zero(); self.ipoint[km + 1] + 1];
+
+            self.errfac = vec![0.0; 2 * km + 1];
+            for mu in 1..=2 * km {
+                let errx = (mu as f64 / (mu as f64 + 4.0)).sqrt() * 0.5;
+                let mut prod = 1.0 / (mu as f64 + 4.0).powi(2);
+                for j in 1..=mu {
+                    prod *= errx / j as f64;
+                }
+                self.errfac[mu] = prod;
+            }
+            self.dens = vec![V::zero(); 2 * km + 5];
+        }
+    }
+
+    /// Core integration method.
+    pub fn integrate(&mut self) -> Result<Stats, IntegrationError> {
+        // Initialization
+        self.init_tables();
+        self.x_old = self.x;
+        let km = self.km;
+        let mut n_step = 0;
+        let mut last;
+        let posneg = sign(1.0, self.x_end - self.x);
+        let h_max = self.h_max.abs().min((self.x_end - self.x).abs());
+        let mut k = ((-(self.rtol + 1.0e-40).log10() * 0.6 + 1.5) as usize)
+            .min(km - 1)
+            .max(2);
+        self.h = posneg * self.h.abs().max(1.0e-4).min(h_max);
+        self.h_old = self.h;
+        self.reject = false;
+
+        // Save initial values
+        let y_tmp = self.y;
+        self.solution_output(y_tmp);
+
+        (self.f)(self.x, &self.y, &mut self.dz);
+        self.stats.num_eval += 1;
+
+        // Main loop
+        'main: loop {
+            // Check for step size underflow
+            if 0.1 * self.h.abs() <= self.uround * self.x.abs() {
+                self.h_old = self.h;
+                return Err(IntegrationError::StepSizeUnderflow { x: self.x });
+            }
+
+            // Check if it's the last iteration
+            self.h = posneg * self.h.abs().min((self.x_end - self.x).abs()).min(h_max);
+            // A step restarted after an instability may no longer reach x_end
+            last = (self.x + 1.01 * self.h - self.x_end) * posneg > 0.0;
+            if last {
+                self.h = self.x_end - self.x;
+            }
+
+            // The first and the last steps compute the columns one after the other
+            let mut first = n_step == 0 || last;
+            loop {
+                // Check if step number is within allowed range
+                if n_step >= self.n_max {
+                    self.h_old = self.h;
+                    return Err(IntegrationError::MaxNumStepReached { x: self.x, n_step });
+                }
+                n_step += 1;
+                self.ipt = 0;
+                self.err_old = 1.0e10;
+
+                let kc = match self.step(k, first) {
+                    Step::Unstable => continue 'main,
+                    Step::Rejected(kc) => {
+                        k = k.min(kc).min(km - 1);
+                        if k > 2 && self.w[k - 1] < self.w[k] * FAC3 {
+                            k -= 1;
+                        }
+                        if self.stats.accepted_steps >= 1 {
+                            self.stats.rejected_steps += 1;
+                        }
+                        self.h = posneg * self.hh[k];
+                        self.reject = true;
+                        last = false;
+                        first = false;
+                        continue;
+                    }
+                    Step::Accepted(kc) => kc,
+                };
+
+                let x_new = self.x + self.h;
+                if self.out_type == OutputType::Dense {
+                    let mut f_new = V::zero();
+                    let y_new = self.t[1];
+                    (self.f)(x_new, &y_new, &mut f_new);
+                    self.stats.num_eval += 1;
+
+                    if !self.dense_output(kc, f_new) {
+                        if self.stats.accepted_steps >= 1 {
+                            self.stats.rejected_steps += 1;
+                        }
+                        self.reject = true;
+                        continue 'main;
+                    }
+                    self.dz = f_new;
+                }
+
+                self.stats.accepted_steps += 1;
+                self.y = self.t[1];
+                self.x_old = self.x;
+                self.x = x_new;
+                self.h_old = self.h;
+
+                let y_tmp = self.y;
+                self.solution_output(y_tmp);
+
+                // Normal exit
+                if last {
+                    return Ok(self.stats);
+                }
+
+                if self.out_type == OutputType::Sparse {
+                    (self.f)(self.x, &self.y, &mut self.dz);
+                    self.stats.num_eval += 1;
+                }
+
+                // Compute optimal order
+                let mut k_opt;
+                if kc == 2 {
+                    k_opt = 3.min(km - 1);
+                    if self.reject {
+                        k_opt = 2;
+                    }
+                } else if kc <= k {
+                    k_opt = kc;
+                    if self.w[kc - 1] < self.w[kc] * FAC3 {
+                        k_opt = kc - 1;
+                    }
+                    if self.w[kc] < self.w[kc - 1] * FAC4 {
+                        k_opt = (kc + 1).min(km - 1);
+                    }
+                } else {
+                    k_opt = kc - 1;
+                    if kc > 3 && self.w[kc - 2] < self.w[kc - 1] * FAC3 {
+                        k_opt = kc - 2;
+                    }
+                    if self.w[kc] < self.w[k_opt] * FAC4 {
+                        k_opt = kc.min(km - 1);
+                    }
+                }
+
+                // After a rejected step
+                if self.reject {
+                    k = k_opt.min(kc);
+                    self.h = posneg * self.h.abs().min(self.hh[k]);
+                    self.reject = false;
+                    continue 'main;
+                }
+
+                // Compute step size for next step
+                self.h = if k_opt <= kc {
+                    self.hh[k_opt]
+                } else if kc < k && self.w[kc] < self.w[kc - 1] * FAC4 {
+                    self.hh[kc] * self.a[k_opt + 1] / self.a[kc]
+                } else {
+                    self.hh[kc] * self.a[k_opt] / self.a[kc]
+                };
+                self.h *= posneg;
+                k = k_opt;
+                continue 'main;
+            }
+        }
+    }
+
+    /// Attempts a step of size h with k columns in the extrapolation table.
+    fn step(&mut self, k: usize, first: bool) -> Step {
+        if first {
+            for j in 1..=k {
+                if !self.midex(j) {
+                    return Step::Unstable;
+                }
+                if j > 1 && self.err <= 1.0 {
+                    return Step::Accepted(j);
+                }
+            }
+        } else {
+            for j in 1..k {
+                if !self.midex(j) {
+                    return Step::Unstable;
+                }
+            }
+
+            // Convergence monitor
+            if k != 2 && !self.reject {
+                if self.err <= 1.0 {
+                    return Step::Accepted(k - 1);
+                }
+                if self.err > ((self.nj[k + 1] * self.nj[k]) as f64 / 4.0).powi(2) {
+                    return Step::Rejected(k - 1);
+                }
+            }
+            if !self.midex(k) {
+                return Step::Unstable;
+            }
+            if self.err <= 1.0 {
+                return Step::Accepted(k);
+            }
+        }
+
+        // Hope for convergence in line k + 1
+        if self.err > (self.nj[k + 1] as f64 / 2.0).powi(2) {
+            return Step::Rejected(k);
+        }
+        if !self.midex(k + 1) {
+            return Step::Unstable;
+        }
+        if self.err > 1.0 {
+            Step::Rejected(k + 1)
+        } else {
+            Step::Accepted(k + 1)
+        }
+    }
+
+    /// Computes the j-th line of the extrapolation table and provides an estimation of the optimal step size.
+    /// Returns false if the step has to be restarted with a halved step size.
+    fn midex(&mut self, j: usize) -> bool {
+        let dim = na::dimension::<V>();
+        let dense = self.out_type == OutputType::Dense;
+        let nj = self.nj[j];
+        let hj = self.h / nj as f64;
+        let njmid = nj / 2;
+
+        // Euler starting step
+        let mut yh1 = self.y;
+        let mut yh2 = self.y + self.dz * na::convert(hj);
+
+        // Explicit midpoint rule
+        let mut dy = V::zero();
+        for mm in 1..nj {
+            if dense && mm == njmid {
+                self.ysafe[j] = yh2;
+            }
+            (self.f)(self.x + hj * mm as f64, &yh2, &mut dy);
+            self.stats.num_eval += 1;
+            if dense && (mm as isize - njmid as isize).abs() < 2 * j as isize {
+                self.ipt += 1;
+                self.fsafe[self.ipt] = dy;
+            }
+            let ys = yh1;
+            yh1 = yh2;
+            yh2 = ys + dy * na::convert(2.0 * hj);
+
+            // Stability check
+            if mm <= MSTAB && j <= JSTAB {
+                let mut del1 = 0.0;
+                let mut del2 = 0.0;
+                for i in 0..dim {
+                    let dz_i = self.dz[i].value();
+                    let dy_i = dy[i].value();
+                    del1 += (dz_i / self.scal[i]).powi(2);
+                    del2 += ((dy_i - dz_i) / self.scal[i]).powi(2);
+                }
+                if del2 / del1.max(self.uround) > 4.0 {
+                    return self.restart();
+                }
+            }
+        }
+
+        // Final smoothing step
+        (self.f)(self.x + self.h, &yh2, &mut dy);
+        self.stats.num_eval += 1;
+        if dense && njmid < 2 * j {
+            self.ipt += 1;
+            self.fsafe[self.ipt] = dy;
+        }
+        self.t[j] = (yh1 + yh2 + dy * na::convert(hj)) * na::convert(0.5);
+
+        if j == 1 {
+            return true;
+        }
+
+        // Polynomial extrapolation
+        for l in (2..=j).rev() {
+            let factor = (nj as f64 / self.nj[l - 1] as f64).powi(2) - 1.0;
+            self.t[l - 1] = self.t[l] + (self.t[l] - self.t[l - 1]) * na::convert(1.0 / factor);
+        }
+
+        // Compute error
+        self.err = 0.0;
+        for i in 0..dim {
+            let y_i = self.y[i].value();
+            let t1_i = self.t[1][i].value();
+            let t2_i = self.t[2][i].value();
+            self.scal[i] = self.atol + self.rtol * y_i.abs().max(t1_i.abs());
+            self.err += ((t1_i - t2_i) / self.scal[i]).powi(2);
+        }
+        self.err = (self.err / dim as f64).sqrt();
+        if self.err * self.uround >= 1.0 || (j > 2 && self.err >= self.err_old) {
+            return self.restart();
+        }
+        self.err_old = (4.0 * self.err).max(1.0);
+
+        // Compute optimal step size
+        let expo = 1.0 / (2 * j - 1) as f64;
+        let fac_min = self.fac_min.powf(expo);
+        let fac = (self.fac_max / fac_min).min(fac_min.max((self.err / SAFE1).powf(expo) / SAFE2));
+        self.hh[j] = (self.h.abs() / fac).min(self.h_max.abs());
+        self.w[j] = self.a[j] / self.hh[j];
+        true
+    }
+
+    /// Halves the step size after an unstable or diverging extrapolation.
+    fn restart(&mut self) -> bool {
+        self.h *= 0.5;
+        self.reject = true;
+        false
+    }
+
+    /// Computes the coefficients of the dense output from the values saved by midex.
+    /// Returns false if the step has to be rejected because of a large interpolation error.
+    fn dense_output(&mut self, kc: usize, f_new: V) -> bool {
+        let dim = na::dimension::<V>();
+        let h = self.h;
+        let kmit = 2 * kc - 3;
+
+        self.dens[0] = self.y;
+        self.dens[1] = self.dz * na::convert(h);
+        self.dens[2] = self.t[1];
+        self.dens[3] = f_new * na::convert(h);
+
+        // Compute solution at mid-point
+        for j in 2..=kc {
+            let dblenj = self.nj[j] as f64;
+            for l in (2..=j).rev() {
+                let factor = (dblenj / self.nj[l - 1] as f64).powi(2) - 1.0;
+                self.ysafe[l - 1] =
+                    self.ysafe[l] + (self.ysafe[l] - self.ysafe[l - 1]) * na::convert(1.0 / factor);
+            }
+        }
+        self.dens[4] = self.ysafe[1];
+
+        // Compute the derivatives at mid-point
+        for kmi in 1..=kmit {
+            let kbeg = kmi - kmi / 2;
+            for kk in kbeg..=kc {
+                let facnj = (self.nj[kk] as f64 / 2.0).powi(kmi as i32 - 1);
+                let ipt = self.ipoint[kk + 1] - 2 * kk + kmi;
+                self.ysafe[kk] = self.fsafe[ipt] * na::convert(facnj);
+            }
+            for j in kbeg + 1..=kc {
+                let dblenj = self.nj[j] as f64;
+                for l in (kbeg + 1..=j).rev() {
+                    let factor = (dblenj / self.nj[l - 1] as f64).powi(2) - 1.0;
+                    self.ysafe[l - 1] = self.ysafe[l]
+                        + (self.ysafe[l] - self.ysafe[l - 1]) * na::convert(1.0 / factor);
+                }
+            }
+            self.dens[kmi + 4] = self.ysafe[kbeg] * na::convert(h);
+            if kmi == kmit {
+                break;
+            }
+
+            // Compute differences
+            for kk in (kmi + 2) / 2..=kc {
+                let lbeg = self.ipoint[kk + 1];
+                let lend = self.ipoint[kk] + (2 * kmi).max(3);
+                for l in (lend..=lbeg).rev() {
+                    self.fsafe[l] = self.fsafe[l] - self.fsafe[l - 2];
+                }
+                // The first difference at the second point of the line involves f(x, y)
+                if kmi == 1 {
+                    let l = self.ipoint[kk] + 2;
+                    self.fsafe[l] -= self.dz;
+                }
+            }
+        }
+        self.interp(kmit);
+
+        // Estimation of the interpolation error
+        let mut errint = 0.0;
+        for i in 0..dim {
+            let d_i = self.dens[kmit + 4][i].value();
+            errint += (d_i / self.scal[i]).powi(2);
+        }
+        errint = (errint / dim as f64).sqrt() * self.errfac[kmit];
+        if errint > 10.0 {
+            self.h = h / errint.powf(1.0 / (kmit + 4) as f64).max(0.01);
+            return false;
+        }
+        self.kmit = kmit;
+        true
+    }
+
+    /// Computes the coefficients of the interpolation polynomial.
+    fn interp(&mut self, imit: usize) {
+        // Begin with Hermite interpolation
+        let y0 = self.dens[0];
+        let y1 = self.dens[2];
+        let yp0 = self.dens[1];
+        let yp1 = self.dens[3];
+        let y_diff = y1 - y0;
+        let aspl = y_diff - yp1;
+        let bspl = yp0 - y_diff;
+        self.dens[1] = y_diff;
+        self.dens[2] = aspl;
+        self.dens[3] = bspl;
+
+        // Compute the derivatives of the Hermite polynomial at mid-point
+        let ph0 = (y0 + y1) * na::convert(0.5) + (aspl + bspl) * na::convert(0.125);
+        let ph1 = y_diff + (aspl - bspl) * na::convert(0.25);
+        let ph2 = yp1 - yp0;
+        let ph3 = (bspl - aspl) * na::convert(6.0);
+
+        // Compute the further coefficients
+        let mut a = vec![V::zero(); imit + 1];
+        if imit >= 1 {
+            a[1] = (self.dens[5] - ph1) * na::convert(16.0);
+            if imit >= 3 {
+                a[3] = (self.dens[7] - ph3 + a[1] * na::convert(3.0)) * na::convert(16.0);
+                for im in (5..=imit).step_by(2) {
+                    let fac1 = (im * (im - 1)) as f64 / 2.0;
+                    let fac2 = fac1 * ((im - 2) * (im - 3)) as f64 * 2.0;
+                    a[im] = (self.dens[im + 4] + a[im - 2] * na::convert(fac1)
+                        - a[im - 4] * na::convert(fac2))
+                        * na::convert(16.0);
+                }
+            }
+        }
+        a[0] = (self.dens[4] - ph0) * na::convert(16.0);
+        if imit >= 2 {
+            a[2] = (self.dens[6] - ph2 + a[0]) * na::convert(16.0);
+            for im in (4..=imit).step_by(2) {
+                let fac1 = (im * (im - 1)) as f64 / 2.0;
+                let fac2 = (im * (im - 1) * (im - 2) * (im - 3)) as f64;
+                a[im] = (self.dens[im + 4] + a[im - 2] * na::convert(fac1)
+                    - a[im - 4] * na::convert(fac2))
+                    * na::convert(16.0);
+            }
+        }
+        self.dens[4..imit + 5].copy_from_slice(&a);
+    }
+
+    /// If a dense output is required, computes the solution and pushes it into the output vector. Else, pushes the solution into the output vector.
+    fn solution_output(&mut self, y_next: V) {
+        if self.out_type == OutputType::Dense {
+            let posneg = sign(1.0, self.x_end - self.x0);
+            if self.x == self.x0 {
+                self.x_out.push(self.x0);
+                self.y_out.push(y_next);
+                self.xd += self.dx;
+            } else {
+                while (self.xd - self.x) * posneg <= 0.0 {
+                    let theta = (self.xd - self.x_old) / self.h_old;
+                    let theta1 = 1.0 - theta;
+                    let hermite = self.dens[0]
+                        + (self.dens[1]
+                            + (self.dens[2] * na::convert(theta)
+                                + self.dens[3] * na::convert(theta1))
+                                * na::convert(theta1))
+                            * na::convert(theta);
+                    let theta_h = theta - 0.5;
+                    let mut poly = self.dens[self.kmit + 4];
+                    for im in (1..=self.kmit).rev() {
+                        poly = self.dens[im + 3] + poly * na::convert(theta_h / im as f64);
+                    }
+                    self.x_out.push(self.xd);
+                    self.y_out
+                        .push(hermite + poly * na::convert((theta * theta1).powi(2)));
+                    self.xd += self.dx;
+                }
+            }
+        } else {
+            self.x_out.push(self.x);
+            self.y_out.push(y_next);
+        }
+    }
+
+    /// Getter for the independent variable's output.
+    pub fn x_out(&self) -> &Vec<f64> {
+        &self.x_out
+    }
+
+    /// Getter for the dependent variables' output.
+    pub fn y_out(&self) -> &Vec<V> {
+        &self.y_out
+    }
+}
+
+fn sign(a: f64, b: f64) -> f64 {
+    if b > 0.0 {
+        a.abs()
+    } else {
+        -a.abs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use na::Vector2;
+
+    fn oscillator(_x: f64, y: &Vector2<f64>, dy: &mut Vector2<f64>) {
+        dy[0] = y[1];
+        dy[1] = -y[0];
+    }
+
+    fn van_der_pol(_x: f64, y: &Vector2<f64>, dy: &mut Vector2<f64>) {
+        dy[0] = y[1];
+        dy[1] = 5.0 * (1.0 - y[0] * y[0]) * y[1] - y[0];
+    }
+
+    fn exact(x: f64) -> Vector2<f64> {
+        Vector2::new(x.cos(), -x.sin())
+    }
+
+    #[test]
+    fn oscillator_dense_output() {
+        let mut stepper = Odex::new(oscillator, 0.0, 10.0, 0.5, exact(0.0), 1.0e-10, 1.0e-10);
+        stepper.integrate().unwrap();
+        assert_eq!(stepper.x_out().len(), 21);
+        for (x, y) in stepper.x_out().iter().zip(stepper.y_out().iter()) {
+            assert!((y - exact(*x)).norm() < 1.0e-8);
+        }
+    }
+
+    #[test]
+    fn oscillator_forward_and_backward() {
+        for &(x, x_end) in [(0.0, 10.0), (10.0, 0.0)].iter() {
+            let mut stepper = Odex::from_param(
+                oscillator,
+                x,
+                x_end,
+                0.0,
+                exact(x),
+                1.0e-10,
+                1.0e-10,
+                0.02,
+                4.0,
+                (x_end - x).abs(),
+                0.0,
+                10000,
+                9,
+                OutputType::Sparse,
+            );
+            stepper.integrate().unwrap();
+            assert_eq!(*stepper.x_out().last().unwrap(), x_end);
+            assert!((stepper.y_out().last().unwrap() - exact(x_end)).norm() < 1.0e-8);
+        }
+    }
+
+    #[test]
+    fn van_der_pol_reaches_x_end() {
+        // A step restarted after an instability with the last step size must not end the
+        // integration
+        let mut stepper = Odex::from_param(
+            van_der_pol,
+            0.0,
+            20.0,
+            0.0,
+            Vector2::new(2.0, 0.0),
+            1.0e-4,
+            1.0e-4,
+            0.02,
+            4.0,
+            20.0,
+            0.0,
+            10000,
+            9,
+            OutputType::Sparse,
+        );
+        stepper.integrate().unwrap();
+        assert_eq!(*stepper.x_out().last().unwrap(), 20.0);
+    }
+}
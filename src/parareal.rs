@@ -0,0 +1,315 @@
+//! Parallel-in-time integration.
+//!
+//! The Parareal algorithm of Lions, Maday and Turinici splits the integration interval into
+//! slices which are integrated in parallel with the `rayon` feature. A cheap coarse propagator G,
+//! the classical Runge-Kutta method of order 4 with a few fixed steps per slice, predicts the
+//! states at the boundaries of the slices sequentially, and an accurate fine propagator F, Dop853,
+//! corrects them in parallel:
+//!
+//! _U<sub>n+1</sub><sup>k+1</sup> = G(U<sub>n</sub><sup>k+1</sup>) + F(U<sub>n</sub><sup>k</sup>)
+//! \- G(U<sub>n</sub><sup>k</sup>)_.
+//!
+//! After k iterations, the states at the first k boundaries are the ones of the sequential fine
+//! integration, so that the iterations are stopped at the latest after as many iterations as
+//! slices. [`Parareal`](struct.Parareal.html) stops as soon as the residual, the largest change of
+//! the states at the boundaries between two iterations, is below a tolerance. The speedup over a
+//! sequential integration with Dop853 is at most the number of slices divided by the number of
+//! iterations.
+
+use alga::general::SubsetOf;
+use alga::linear::{FiniteDimInnerSpace, InnerSpace};
+use dop853::Dop853;
+use dop_shared::*;
+use na;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+/// Structure containing the parameters of the parallel-in-time integration.
+pub struct Parareal<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+{
+    f: fn(f64, &V, &mut V),
+    x: f64,
+    x_end: f64,
+    y: V,
+    slices: usize,
+    rtol: f64,
+    atol: f64,
+    coarse_steps: usize,
+    tol: f64,
+    max_iterations: usize,
+    iterations: usize,
+    residual: f64,
+    x_out: Vec<f64>,
+    y_out: Vec<V>,
+}
+
+impl<V> Parareal<V>
+where
+    V: FiniteDimInnerSpace + Copy + Send + Sync,
+    <V as InnerSpace>::Real: SubsetOf<f64>,
+{
+    /// Default initializer for the structure.
+    ///
+    /// # Arguments
+    ///
+    /// * `f`       - Pointer to the function to integrate
+    /// * `x`       - Initial value of the independent variable (usually time)
+    /// * `x_end`   - Final value of the independent variable
+    /// * `y`       - Initial value of the dependent variable(s)
+    /// * `slices`  - Number of time slices
+    /// * `rtol`    - Relative tolerance of the fine propagator
+    /// * `atol`    - Absolute tolerance of the fine propagator
+    ///
+    pub fn new(
+        f: fn(f64, &V, &mut V),
+        x: f64,
+        x_end: f64,
+        y: V,
+        slices: usize,
+        rtol: f64,
+        atol: f64,
+    ) -> Parareal<V> {
+        let slices = slices.max(1);
+        Parareal {
+            f,
+            x,
+            x_end,
+            y,
+            slices,
+            rtol,
+            atol,
+            coarse_steps: 1,
+            tol: 1.0e-8,
+            max_iterations: slices,
+            iterations: 0,
+            residual: 0.0,
+            x_out: Vec::new(),
+            y_out: Vec::new(),
+        }
+    }
+
+    /// Sets the number of steps of the coarse propagator in each slice. Default is 1.
+    pub fn set_coarse_steps(&mut self, coarse_steps: usize) {
+        self.coarse_steps = coarse_steps.max(1);
+    }
+
+    /// Sets the tolerance on the residual below which the iterations are stopped. Default is 1e-8.
+    pub fn set_tolerance(&mut self, tol: f64) {
+        self.tol = tol;
+    }
+
+    /// Sets the maximum number of iterations. Default is the number of slices, for which the
+    /// solution is the one of the sequential fine integration.
+    pub fn set_max_iterations(&mut self, max_iterations: usize) {
+        self.max_iterations = max_iterations;
+    }
+
+    /// Iterates until the residual is below the tolerance or the maximum number of iterations is
+    /// reached. The returned statistics sum the evaluations of both propagators, while the
+    /// accepted and rejected steps are the ones of the fine propagator. A state which is not
+    /// finite, usually because the coarse propagator diverged, stops the integration with
+    /// `IntegrationError::NonFiniteState`, in which case more coarse steps are needed.
+    pub fn integrate(&mut self) -> Result<Stats, IntegrationError> {
+        let n = self.slices;
+        let dx = (self.x_end - self.x) / n as f64;
+        let x_out: Vec<f64> = (0..=n).map(|i| self.x + i as f64 * dx).collect();
+        let mut stats = Stats::new();
+
+        // Initial prediction with the coarse propagator
+        let mut y_out = vec![self.y; n + 1];
+        let mut coarse = vec![self.y; n];
+        for i in 0..n {
+            coarse[i] = self.coarse(x_out[i], x_out[i + 1], &y_out[i], &mut stats);
+            y_out[i + 1] = coarse[i];
+            if !is_finite(&coarse[i]) {
+                return Err(IntegrationError::NonFiniteState { x: x_out[i + 1] });
+            }
+        }
+
+        self.iterations = 0;
+        self.residual = f64::INFINITY;
+        while self.iterations < self.max_iterations && self.residual > self.tol {
+            // The states at the first boundaries are exact and their slices need not be
+            // integrated again
+            let start = self.iterations;
+            let fine = self.fine(&x_out[start..], &y_out[start..n])?;
+            self.iterations += 1;
+
+            self.residual = 0.0;
+            for i in start..n {
+                let (y_fine, fine_stats) = fine[i - start];
+                stats += fine_stats;
+                let y_coarse = self.coarse(x_out[i], x_out[i + 1], &y_out[i], &mut stats);
+                let y_next = y_coarse + y_fine - coarse[i];
+                if !is_finite(&y_next) {
+                    return Err(IntegrationError::NonFiniteState { x: x_out[i + 1] });
+                }
+                let change: f64 = na::convert((y_next - y_out[i + 1]).norm());
+                self.residual = self.residual.max(change);
+                coarse[i] = y_coarse;
+                y_out[i + 1] = y_next;
+            }
+        }
+        self.x_out = x_out;
+        self.y_out = y_out;
+        Ok(stats)
+    }
+
+    /// Coarse propagation from x to x_end with the classical Runge-Kutta method.
+    fn coarse(&self, x: f64, x_end: f64, y: &V, stats: &mut Stats) -> V {
+        let h = (x_end - x) / self.coarse_steps as f64;
+        let half: V::Real = na::convert(0.5 * h);
+        let (h_r, sixth): (V::Real, V::Real) = (na::convert(h), na::convert(h / 6.0));
+        let two: V::Real = na::convert(2.0);
+        let mut y = *y;
+        let (mut k1, mut k2, mut k3, mut k4) = (V::zero(), V::zero(), V::zero(), V::zero());
+        for i in 0..self.coarse_steps {
+            let x = x + i as f64 * h;
+            (self.f)(x, &y, &mut k1);
+            (self.f)(x + 0.5 * h, &(y + k1 * half), &mut k2);
+            (self.f)(x + 0.5 * h, &(y + k2 * half), &mut k3);
+            (self.f)(x + h, &(y + k3 * h_r), &mut k4);
+            y += (k1 + (k2 + k3) * two + k4) * sixth;
+        }
+        stats.num_eval += 4 * self.coarse_steps as u32;
+        y
+    }
+
+    /// Fine propagation over the slices starting at the given boundaries.
+    fn fine(&self, x: &[f64], y: &[V]) -> Result<Vec<(V, Stats)>, IntegrationError> {
+        #[cfg(feature = "rayon")]
+        let fine = y
+            .par_iter()
+            .enumerate()
+            .map(|(i, y)| self.slice(x[i], x[i + 1], y))
+            .collect();
+        #[cfg(not(feature = "rayon"))]
+        let fine = y
+            .iter()
+            .enumerate()
+            .map(|(i, y)| self.slice(x[i], x[i + 1], y))
+            .collect();
+        fine
+    }
+
+    /// Fine propagation from x to x_end with Dop853.
+    fn slice(&self, x: f64, x_end: f64, y: &V) -> Result<(V, Stats), IntegrationError> {
+        // The last point of the sparse output is exactly at the end of the slice
+        let f = System::Plain(self.f);
+        let mut stepper = Dop853::sparse(f, x, x_end, *y, self.rtol, self.atol, 100000);
+        let stats = stepper.integrate()?;
+        Ok((*stepper.y_out().last().unwrap(), stats))
+    }
+
+    /// Number of iterations of the last integration.
+    pub fn iterations(&self) -> usize {
+        self.iterations
+    }
+
+    /// Residual of the last iteration, the largest norm of the change of the states at the
+    /// boundaries of the slices.
+    pub fn residual(&self) -> f64 {
+        self.residual
+    }
+
+    /// Getter for the boundaries of the slices.
+    pub fn x_out(&self) -> &Vec<f64> {
+        &self.x_out
+    }
+
+    /// Getter for the states at the boundaries of the slices.
+    pub fn y_out(&self) -> &Vec<V> {
+        &self.y_out
+    }
+}
+
+/// Returns true if every component of y is finite.
+fn is_finite<V>(y: &V) -> bool
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: SubsetOf<f64>,
+{
+    (0..na::dimension::<V>()).all(|i| {
+        let y_i: f64 = na::convert(y[i]);
+        y_i.is_finite()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use na::Vector2;
+
+    fn oscillator(_x: f64, y: &Vector2<f64>, dy: &mut Vector2<f64>) {
+        dy[0] = y[1];
+        dy[1] = -y[0];
+    }
+
+    #[test]
+    fn parareal_oscillator() {
+        let mut parareal = Parareal::new(
+            oscillator,
+            0.0,
+            20.0,
+            Vector2::new(1.0, 0.0),
+            20,
+            1.0e-12,
+            1.0e-12,
+        );
+        parareal.set_coarse_steps(4);
+        parareal.set_tolerance(1.0e-10);
+        parareal.integrate().unwrap();
+        assert!(parareal.iterations() < 20);
+        assert!(parareal.residual() <= 1.0e-10);
+        for (x, y) in parareal.x_out().iter().zip(parareal.y_out().iter()) {
+            assert!((y - Vector2::new(x.cos(), -x.sin())).norm() < 1.0e-9);
+        }
+    }
+
+    fn oscillator_parareal(tol: f64, max_iterations: usize) -> Parareal<Vector2<f64>> {
+        let y0 = Vector2::new(1.0, 0.0);
+        let mut parareal = Parareal::new(oscillator, 0.0, 20.0, y0, 20, 1.0e-12, 1.0e-12);
+        parareal.set_tolerance(tol);
+        parareal.set_max_iterations(max_iterations);
+        parareal.integrate().unwrap();
+        parareal
+    }
+
+    #[test]
+    fn fewer_iterations_than_slices() {
+        // The states at the first boundaries are the ones of the fine propagator
+        let parareal = oscillator_parareal(0.0, 3);
+        assert_eq!(parareal.iterations(), 3);
+        assert!(parareal.residual() > 0.0);
+        for (x, y) in parareal.x_out().iter().zip(parareal.y_out().iter()).take(4) {
+            assert!((y - Vector2::new(x.cos(), -x.sin())).norm() < 1.0e-9);
+        }
+        let (x, y) = (parareal.x_out()[20], parareal.y_out()[20]);
+        assert!((y - Vector2::new(x.cos(), -x.sin())).norm() > 1.0e-6);
+    }
+
+    #[test]
+    fn early_termination() {
+        let sequential = oscillator_parareal(0.0, 20);
+        assert_eq!(sequential.iterations(), 20);
+        let parareal = oscillator_parareal(1.0e-3, 20);
+        assert!(parareal.iterations() < sequential.iterations());
+        assert!(parareal.residual() <= 1.0e-3);
+    }
+
+    fn stiff_decay(_x: f64, y: &Vector2<f64>, dy: &mut Vector2<f64>) {
+        *dy = -1.0e5 * y;
+    }
+
+    #[test]
+    fn diverging_coarse_propagator() {
+        let y0 = Vector2::new(1.0, 0.0);
+        let mut parareal = Parareal::new(stiff_decay, 0.0, 20.0, y0, 20, 1.0e-6, 1.0e-6);
+        match parareal.integrate() {
+            Err(IntegrationError::NonFiniteState { x }) => assert!(x > 0.0 && x <= 20.0),
+            _ => panic!("the coarse propagator diverges"),
+        }
+    }
+}
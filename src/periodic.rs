@@ -0,0 +1,443 @@
+#![allow(clippy::needless_range_loop)]
+
+//! Periodic orbits.
+//!
+//! A periodic orbit is a fixed point of the Poincaré return map P, which sends a point of a
+//! section g(y) = 0 to the next crossing of the section by its trajectory. Starting from a guess
+//! of a point of the orbit and of its period, [`PeriodicOrbit`](struct.PeriodicOrbit.html)
+//! computes the return map with event location on the continuous extension of Dopri5, and solves
+//! _P(y0) - y0 = 0_, _g(y0) = 0_ with Newton's method. The Jacobian matrix of the return map is
+//! obtained from the monodromy matrix Φ(T), the state transition matrix over one period:
+//!
+//! _DP = (I - f(y(T)) ∇g<sup>T</sup> / (∇g · f(y(T)))) Φ(T)_.
+//!
+//! The Newton steps are computed in the least squares sense, which also handles the families of
+//! periodic orbits of conservative systems, where the correction is not unique. The eigenvalues
+//! of the monodromy matrix, the Floquet multipliers, determine the stability of the orbit: one of
+//! them is always equal to 1, and the orbit is stable if the others lie in the unit circle.
+
+use alga::general::SubsetOf;
+use alga::linear::{FiniteDimInnerSpace, InnerSpace};
+use dop853::Dop853;
+use dop_shared::*;
+use dopri5::Dopri5;
+use jacobian;
+use na;
+use na::{DMatrix, DVector};
+use poincare::{self, Direction};
+use std::error::Error;
+use std::f64;
+use std::fmt;
+use stm::StateTransition;
+
+/// Enumeration of the errors that may arise while computing a periodic orbit.
+#[derive(Debug)]
+pub enum OrbitError {
+    Integration(IntegrationError),
+    NoReturn { x_max: f64 },
+    MaxIterationsReached { iterations: u32 },
+    SingularJacobian { iterations: u32 },
+}
+
+impl Error for OrbitError {}
+
+impl fmt::Display for OrbitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OrbitError::Integration(e) => write!(f, "{}", e),
+            OrbitError::NoReturn { x_max } => {
+                write!(f, "The trajectory does not return to the section before x = {}", x_max)
+            }
+            OrbitError::MaxIterationsReached { iterations } => {
+                write!(f, "No convergence after {} iterations", iterations)
+            }
+            OrbitError::SingularJacobian { iterations } => {
+                write!(f, "Singular Jacobian matrix at iteration {}", iterations)
+            }
+        }
+    }
+}
+
+impl From<IntegrationError> for OrbitError {
+    fn from(e: IntegrationError) -> OrbitError {
+        OrbitError::Integration(e)
+    }
+}
+
+/// Structure containing the parameters of the periodic orbit correction.
+pub struct PeriodicOrbit<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+{
+    f: fn(f64, &V, &mut V),
+    jac: Option<fn(f64, &V, &mut DMatrix<f64>)>,
+    section: fn(&V) -> f64,
+    direction: Direction,
+    y0: V,
+    period: f64,
+    rtol: f64,
+    atol: f64,
+    tol: f64,
+    max_iterations: u32,
+    fixed: Vec<usize>,
+    iterations: u32,
+    monodromy: DMatrix<f64>,
+    multipliers: Vec<(f64, f64)>,
+    stats: Stats,
+}
+
+impl<V> PeriodicOrbit<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: SubsetOf<f64>,
+{
+    /// Default initializer for the structure.
+    ///
+    /// # Arguments
+    ///
+    /// * `f`       - Pointer to the function to integrate
+    /// * `section` - Function g defining the section g(y) = 0
+    /// * `direction` - Direction in which the orbit crosses the section
+    /// * `y0`      - Guess of the point of the orbit on the section
+    /// * `period`  - Guess of the period
+    /// * `rtol`    - Relative tolerance used in the computation of the adaptive step size
+    /// * `atol`    - Absolute tolerance used in the computation of the adaptive step size
+    ///
+    pub fn new(
+        f: fn(f64, &V, &mut V),
+        section: fn(&V) -> f64,
+        direction: Direction,
+        y0: V,
+        period: f64,
+        rtol: f64,
+        atol: f64,
+    ) -> PeriodicOrbit<V> {
+        let dim = na::dimension::<V>();
+        PeriodicOrbit {
+            f,
+            jac: None,
+            section,
+            direction,
+            y0,
+            period,
+            rtol,
+            atol,
+            tol: 1.0e-10,
+            max_iterations: 20,
+            fixed: Vec::new(),
+            iterations: 0,
+            monodromy: DMatrix::zeros(dim, dim),
+            multipliers: Vec::new(),
+            stats: Stats::new(),
+        }
+    }
+
+    /// Sets the function computing the Jacobian matrix of f with respect to y. If no Jacobian is
+    /// given, it is approximated by finite differences, and the monodromy matrix is then excluded
+    /// from the error control of the integration.
+    pub fn set_jacobian(&mut self, jac: fn(f64, &V, &mut DMatrix<f64>)) {
+        self.jac = Some(jac);
+    }
+
+    /// Sets the tolerance on the largest component of P(y0) - y0. Default is 1e-10.
+    pub fn set_tolerance(&mut self, tol: f64) {
+        self.tol = tol;
+    }
+
+    /// Sets the maximum number of Newton iterations. Default is 20.
+    pub fn set_max_iterations(&mut self, max_iterations: u32) {
+        self.max_iterations = max_iterations;
+    }
+
+    /// Keeps the given components of y0 at their initial value during the correction, e.g. to
+    /// select one orbit of a family.
+    pub fn set_fixed_components(&mut self, fixed: Vec<usize>) {
+        self.fixed = fixed;
+    }
+
+    /// Corrects the initial state and the period, and computes the Floquet multipliers.
+    pub fn solve(&mut self) -> Result<Stats, OrbitError> {
+        let dim = na::dimension::<V>();
+        let free: Vec<usize> = (0..dim).filter(|i| !self.fixed.contains(i)).collect();
+        self.stats = Stats::new();
+        self.iterations = 0;
+        let x_min = 0.5 * self.period;
+
+        loop {
+            let y0 = self.y0;
+            self.period = self.return_time(&y0, x_min)?;
+            let (y_t, phi) = self.monodromy(&y0)?;
+
+            let mut res = DVector::zeros(dim + 1);
+            for i in 0..dim {
+                let y_t_i: f64 = na::convert(y_t[i]);
+                let y0_i: f64 = na::convert(y0[i]);
+                res[i] = y_t_i - y0_i;
+            }
+            res[dim] = (self.section)(&y0);
+            if res.amax() <= self.tol {
+                self.monodromy = phi;
+                break;
+            }
+            if self.iterations >= self.max_iterations {
+                return Err(OrbitError::MaxIterationsReached {
+                    iterations: self.iterations,
+                });
+            }
+            self.iterations += 1;
+
+            // Jacobian matrix of the return map, projected along the flow onto the section
+            let mut f_t = V::zero();
+            (self.f)(self.period, &y_t, &mut f_t);
+            self.stats.num_eval += 1;
+            let f_t = jacobian::to_dvector(&f_t);
+            let grad_t = self.gradient(&y_t);
+            let projection = DMatrix::identity(dim, dim) - &f_t * grad_t.transpose() / grad_t.dot(&f_t);
+            let dp = projection * &phi;
+
+            let grad_0 = self.gradient(&y0);
+            let mut a = DMatrix::zeros(dim + 1, free.len());
+            for (c, &j) in free.iter().enumerate() {
+                for i in 0..dim {
+                    a[(i, c)] = dp[(i, j)];
+                }
+                a[(j, c)] -= 1.0;
+                a[(dim, c)] = grad_0[j];
+            }
+            // No correction is possible without free components or if every singular value is
+            // zero or not finite
+            let delta = if free.is_empty() {
+                None
+            } else {
+                let svd = a.svd(true, true);
+                let eps = 1.0e-12 * svd.singular_values.amax();
+                svd.solve(&-res, eps).ok().filter(|_| eps > 0.0)
+            };
+            let delta = match delta {
+                Some(delta) => delta,
+                None => {
+                    return Err(OrbitError::SingularJacobian {
+                        iterations: self.iterations,
+                    })
+                }
+            };
+            for (c, &j) in free.iter().enumerate() {
+                let y0_j: f64 = na::convert(self.y0[j]);
+                self.y0[j] = na::convert(y0_j + delta[c]);
+            }
+        }
+
+        self.multipliers = eigenvalues(self.monodromy.clone());
+        Ok(self.stats)
+    }
+
+    /// Time of the first return of the trajectory starting at y to the section after x_min.
+    fn return_time(&mut self, y: &V, x_min: f64) -> Result<f64, OrbitError> {
+        let x_max = 1.5 * self.period;
+        let mut stepper = Dopri5::from_param(
+            self.f,
+            0.0,
+            x_max,
+            x_max,
+            *y,
+            self.rtol,
+            self.atol,
+            0.9,
+            0.04,
+            0.2,
+            10.0,
+            x_max,
+            0.0,
+            100000,
+            1000,
+            OutputType::Sparse,
+        );
+        stepper.set_continuous_output(true);
+        self.stats += stepper.integrate()?;
+        let output = stepper.continuous_output().unwrap();
+        poincare::crossings(output, self.section, self.direction, x_min)
+            .first()
+            .cloned()
+            .ok_or(OrbitError::NoReturn { x_max })
+    }
+
+    /// State after one period and monodromy matrix.
+    fn monodromy(&mut self, y: &V) -> Result<(V, DMatrix<f64>), OrbitError> {
+        let f = System::Plain(self.f);
+        let stepper = Dop853::sparse(f, 0.0, self.period, *y, self.rtol, self.atol, 100000);
+        let mut stm = StateTransition::from_stepper(stepper);
+        match self.jac {
+            Some(jac) => stm.set_jacobian(jac),
+            // The noise of the finite differences would prevent the error control at tight tolerances
+            None => stm.set_error_control(false),
+        }
+        self.stats += stm.integrate()?;
+        let y_t = *stm.y_out().last().unwrap();
+        Ok((y_t, stm.phi_out().last().unwrap().clone()))
+    }
+
+    /// Gradient of the section function, approximated by central differences.
+    fn gradient(&self, y: &V) -> DVector<f64> {
+        let dim = na::dimension::<V>();
+        let mut grad = DVector::zeros(dim);
+        let mut y_pert = *y;
+        for j in 0..dim {
+            let y_j: f64 = na::convert(y[j]);
+            let delta = f64::EPSILON.cbrt() * y_j.abs().max(1.0);
+            y_pert[j] = na::convert(y_j + delta);
+            let g_plus = (self.section)(&y_pert);
+            y_pert[j] = na::convert(y_j - delta);
+            let g_minus = (self.section)(&y_pert);
+            y_pert[j] = y[j];
+            grad[j] = (g_plus - g_minus) / (2.0 * delta);
+        }
+        grad
+    }
+
+    /// Getter for the corrected point of the orbit on the section.
+    pub fn y0(&self) -> &V {
+        &self.y0
+    }
+
+    /// Getter for the period.
+    pub fn period(&self) -> f64 {
+        self.period
+    }
+
+    /// Number of Newton iterations of the last correction.
+    pub fn iterations(&self) -> u32 {
+        self.iterations
+    }
+
+    /// Getter for the monodromy matrix Φ(T).
+    pub fn monodromy_matrix(&self) -> &DMatrix<f64> {
+        &self.monodromy
+    }
+
+    /// Floquet multipliers, as pairs of real and imaginary parts.
+    pub fn floquet_multipliers(&self) -> &Vec<(f64, f64)> {
+        &self.multipliers
+    }
+}
+
+/// Eigenvalues of a real matrix, as pairs of real and imaginary parts, from its real Schur form.
+/// A 2×2 block of the form may have real eigenvalues when its subdiagonal element is tiny but not
+/// zero, e.g. for a monodromy matrix close to diagonal, which `complex_eigenvalues` turns into NaN.
+fn eigenvalues(m: DMatrix<f64>) -> Vec<(f64, f64)> {
+    let (_, t) = m.real_schur().unpack();
+    let dim = t.nrows();
+    let mut out = Vec::with_capacity(dim);
+    let mut i = 0;
+    while i < dim {
+        if i + 1 == dim || t[(i + 1, i)] == 0.0 {
+            out.push((t[(i, i)], 0.0));
+            i += 1;
+            continue;
+        }
+        let half_trace = 0.5 * (t[(i, i)] + t[(i + 1, i + 1)]);
+        let det = t[(i, i)] * t[(i + 1, i + 1)] - t[(i + 1, i)] * t[(i, i + 1)];
+        let discr = half_trace * half_trace - det;
+        if discr >= 0.0 {
+            // The smaller eigenvalue from the product, without cancellation
+            let large = half_trace + discr.sqrt().copysign(half_trace);
+            let small = if large == 0.0 { 0.0 } else { det / large };
+            out.push((large, 0.0));
+            out.push((small, 0.0));
+        } else {
+            out.push((half_trace, (-discr).sqrt()));
+            out.push((half_trace, -(-discr).sqrt()));
+        }
+        i += 2;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use na::Vector2;
+    use std::f64::consts::PI;
+
+    // Hopf normal form, whose limit cycle is the unit circle, with a period 2π and the Floquet
+    // multipliers 1 and exp(-4π)
+    fn hopf(_x: f64, y: &Vector2<f64>, dy: &mut Vector2<f64>) {
+        let r2 = y[0] * y[0] + y[1] * y[1];
+        dy[0] = y[0] - y[1] - y[0] * r2;
+        dy[1] = y[0] + y[1] - y[1] * r2;
+    }
+
+    fn hopf_jacobian(_x: f64, y: &Vector2<f64>, jac: &mut DMatrix<f64>) {
+        let r2 = y[0] * y[0] + y[1] * y[1];
+        jac[(0, 0)] = 1.0 - r2 - 2.0 * y[0] * y[0];
+        jac[(0, 1)] = -1.0 - 2.0 * y[0] * y[1];
+        jac[(1, 0)] = 1.0 - 2.0 * y[0] * y[1];
+        jac[(1, 1)] = 1.0 - r2 - 2.0 * y[1] * y[1];
+    }
+
+    fn section(y: &Vector2<f64>) -> f64 {
+        y[1]
+    }
+
+    fn check(orbit: &mut PeriodicOrbit<Vector2<f64>>, tol: f64) {
+        orbit.solve().unwrap();
+        assert!((orbit.y0() - Vector2::new(1.0, 0.0)).norm() < 1.0e-9);
+        assert!((orbit.period() - 2.0 * PI).abs() < 1.0e-9);
+
+        let mut multipliers: Vec<f64> = orbit.floquet_multipliers().iter().map(|z| z.0).collect();
+        multipliers.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert!(orbit.floquet_multipliers().iter().all(|z| z.1 == 0.0));
+        assert!((multipliers[0] - (-4.0 * PI).exp()).abs() < tol);
+        assert!((multipliers[1] - 1.0).abs() < tol);
+    }
+
+    #[test]
+    fn limit_cycle() {
+        let y0 = Vector2::new(1.3, 0.0);
+        let mut orbit = PeriodicOrbit::new(
+            hopf,
+            section,
+            Direction::Increasing,
+            y0,
+            6.0,
+            1.0e-12,
+            1.0e-12,
+        );
+        // The finite difference Jacobian limits the accuracy of the monodromy matrix
+        check(&mut orbit, 1.0e-6);
+    }
+
+    #[test]
+    fn limit_cycle_with_jacobian() {
+        let y0 = Vector2::new(0.6, 0.0);
+        let mut orbit = PeriodicOrbit::new(
+            hopf,
+            section,
+            Direction::Increasing,
+            y0,
+            7.0,
+            1.0e-12,
+            1.0e-12,
+        );
+        orbit.set_jacobian(hopf_jacobian);
+        check(&mut orbit, 1.0e-10);
+    }
+
+    #[test]
+    fn every_component_fixed() {
+        let y0 = Vector2::new(1.3, 0.0);
+        let mut orbit = PeriodicOrbit::new(
+            hopf,
+            section,
+            Direction::Increasing,
+            y0,
+            6.0,
+            1.0e-12,
+            1.0e-12,
+        );
+        orbit.set_fixed_components(vec![0, 1]);
+        match orbit.solve() {
+            Err(OrbitError::SingularJacobian { iterations: 1 }) => {}
+            _ => panic!("nothing can be corrected"),
+        }
+    }
+}
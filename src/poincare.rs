@@ -0,0 +1,285 @@
+//! Poincaré sections.
+//!
+//! A Poincaré section is a hypersurface _g(y) = 0_ of the state space, crossed transversally by the
+//! trajectories. The crossings are located on the continuous extension of the Dopri5 solution,
+//! without restricting the step size, by bracketing the sign changes of g and refining them with
+//! the Illinois variant of the regula falsi.
+//!
+//! [`PoincareSection`](struct.PoincareSection.html) records the crossings of a section during an
+//! integration with Dopri5. Long integrations can be split into several calls to `integrate`,
+//! the crossings being accumulated, so that the continuous extension of the whole trajectory does
+//! not need to be kept in memory.
+
+use alga::general::SubsetOf;
+use alga::linear::{FiniteDimInnerSpace, InnerSpace};
+use dop_shared::*;
+use dopri5::Dopri5;
+use jacobian;
+use na::DVector;
+use std::f64;
+
+/// Enumeration of the directions in which the section may be crossed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Direction {
+    /// g changes from negative to positive
+    Increasing,
+    /// g changes from positive to negative
+    Decreasing,
+    Both,
+}
+
+impl Direction {
+    fn crossed(self, g_a: f64, g_b: f64) -> bool {
+        let increasing = g_a < 0.0 && g_b >= 0.0;
+        let decreasing = g_a > 0.0 && g_b <= 0.0;
+        match self {
+            Direction::Increasing => increasing,
+            Direction::Decreasing => decreasing,
+            Direction::Both => increasing || decreasing,
+        }
+    }
+}
+
+/// Structure recording the crossings of a Poincaré section.
+pub struct PoincareSection<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+{
+    section: fn(&V) -> f64,
+    direction: Direction,
+    projection: Option<fn(&V) -> DVector<f64>>,
+    x_out: Vec<f64>,
+    y_out: Vec<V>,
+    coordinates_out: Vec<DVector<f64>>,
+}
+
+impl<V> PoincareSection<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: SubsetOf<f64>,
+{
+    /// Default initializer for the structure.
+    ///
+    /// # Arguments
+    ///
+    /// * `section`   - Function g defining the section g(y) = 0
+    /// * `direction` - Direction of the crossings to record
+    ///
+    pub fn new(section: fn(&V) -> f64, direction: Direction) -> PoincareSection<V> {
+        PoincareSection {
+            section,
+            direction,
+            projection: None,
+            x_out: Vec::new(),
+            y_out: Vec::new(),
+            coordinates_out: Vec::new(),
+        }
+    }
+
+    /// Sets the function giving the coordinates of a point on the section. By default, the
+    /// coordinates are the components of the state.
+    pub fn set_projection(&mut self, projection: fn(&V) -> DVector<f64>) {
+        self.projection = Some(projection);
+    }
+
+    /// Integrates with the given stepper, with continuous output, and records the crossings of
+    /// the section.
+    pub fn integrate(&mut self, stepper: &mut Dopri5<V>) -> Result<Stats, IntegrationError> {
+        stepper.set_continuous_output(true);
+        let stats = stepper.integrate()?;
+        self.record(stepper.continuous_output().unwrap());
+        Ok(stats)
+    }
+
+    /// Records the crossings of the section by a stored continuous extension.
+    pub fn record(&mut self, output: &ContinuousOutput<V>) {
+        if output.num_steps() == 0 {
+            return;
+        }
+        let (x_from, _) = output.step(0);
+        for x in crossings(output, self.section, self.direction, x_from) {
+            let y = output.y(x);
+            let coordinates = match self.projection {
+                Some(projection) => projection(&y),
+                None => jacobian::to_dvector(&y),
+            };
+            self.x_out.push(x);
+            self.y_out.push(y);
+            self.coordinates_out.push(coordinates);
+        }
+    }
+
+    /// Removes the recorded crossings.
+    pub fn clear(&mut self) {
+        self.x_out.clear();
+        self.y_out.clear();
+        self.coordinates_out.clear();
+    }
+
+    /// Getter for the times of the crossings.
+    pub fn x_out(&self) -> &Vec<f64> {
+        &self.x_out
+    }
+
+    /// Getter for the states at the crossings.
+    pub fn y_out(&self) -> &Vec<V> {
+        &self.y_out
+    }
+
+    /// Getter for the coordinates of the crossings on the section.
+    pub fn coordinates_out(&self) -> &Vec<DVector<f64>> {
+        &self.coordinates_out
+    }
+}
+
+/// Number of points at which g is sampled in each step, to detect two close crossings.
+const SAMPLES: usize = 4;
+
+/// Locates the crossings of the section in the given direction which lie beyond `x_from`, in the
+/// order of integration.
+pub(crate) fn crossings<V>(
+    output: &ContinuousOutput<V>,
+    section: fn(&V) -> f64,
+    direction: Direction,
+    x_from: f64,
+) -> Vec<f64>
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: SubsetOf<f64>,
+{
+    let g = |x: f64| section(&output.y(x));
+    let mut out = Vec::new();
+    for i in 0..output.num_steps() {
+        let (x_start, x_end) = output.step(i);
+        let h = x_end - x_start;
+        if (x_end - x_from) * h <= 0.0 {
+            continue;
+        }
+        let mut x_a = x_start;
+        let mut g_a = g(x_a);
+        for k in 1..=SAMPLES {
+            let x_b = if k == SAMPLES {
+                x_end
+            } else {
+                x_start + h * k as f64 / SAMPLES as f64
+            };
+            let g_b = g(x_b);
+            if direction.crossed(g_a, g_b) {
+                let x = illinois(&g, x_a, g_a, x_b, g_b);
+                if (x - x_from) * h > 0.0 {
+                    out.push(x);
+                }
+            }
+            x_a = x_b;
+            g_a = g_b;
+        }
+    }
+    out
+}
+
+/// Refines the root of g bracketed by x_a and x_b with the Illinois algorithm.
+fn illinois<G>(g: &G, mut x_a: f64, mut g_a: f64, mut x_b: f64, mut g_b: f64) -> f64
+where
+    G: Fn(f64) -> f64,
+{
+    if g_b == 0.0 {
+        return x_b;
+    }
+    let mut side = 0;
+    for _ in 0..100 {
+        let x = (x_a * g_b - x_b * g_a) / (g_b - g_a);
+        let g_x = g(x);
+        if g_x == 0.0 || (x_b - x_a).abs() <= 4.0 * f64::EPSILON * x.abs().max(1.0) {
+            return x;
+        }
+        if g_x * g_b > 0.0 {
+            x_b = x;
+            g_b = g_x;
+            if side == -1 {
+                g_a *= 0.5;
+            }
+            side = -1;
+        } else {
+            x_a = x;
+            g_a = g_x;
+            if side == 1 {
+                g_b *= 0.5;
+            }
+            side = 1;
+        }
+    }
+    (x_a * g_b - x_b * g_a) / (g_b - g_a)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use na::Vector2;
+    use std::f64::consts::PI;
+
+    // Rotation on the unit circle centered at (1, 0), y = (1 + cos x, -sin x)
+    fn rotation(_x: f64, y: &Vector2<f64>, dy: &mut Vector2<f64>) {
+        dy[0] = y[1];
+        dy[1] = 1.0 - y[0];
+    }
+
+    // Unit circle centered at the origin, g = 1 + 2 cos x along the trajectory
+    fn circle(y: &Vector2<f64>) -> f64 {
+        y.norm_squared() - 1.0
+    }
+
+    fn angle(y: &Vector2<f64>) -> DVector<f64> {
+        DVector::from_element(1, y[1].atan2(y[0]))
+    }
+
+    fn record(direction: Direction, x_end: f64) -> PoincareSection<Vector2<f64>> {
+        let mut section = PoincareSection::new(circle, direction);
+        let y0 = Vector2::new(2.0, 0.0);
+        let mut stepper = Dopri5::new(rotation, 0.0, x_end, x_end, y0, 1.0e-12, 1.0e-12);
+        section.integrate(&mut stepper).unwrap();
+        section
+    }
+
+    fn check(section: &PoincareSection<Vector2<f64>>, expected: &[f64]) {
+        assert_eq!(section.x_out().len(), expected.len());
+        for (i, &x) in expected.iter().enumerate() {
+            assert!((section.x_out()[i] - x).abs() < 1.0e-9);
+            let y = Vector2::new(1.0 + x.cos(), -x.sin());
+            assert!((section.y_out()[i] - y).norm() < 1.0e-9);
+        }
+    }
+
+    #[test]
+    fn crossings_of_a_circle() {
+        let decreasing = [2.0 * PI / 3.0, 8.0 * PI / 3.0];
+        let increasing = [4.0 * PI / 3.0, 10.0 * PI / 3.0];
+        check(&record(Direction::Decreasing, 4.0 * PI), &decreasing);
+        check(&record(Direction::Increasing, 4.0 * PI), &increasing);
+        let both = [decreasing[0], increasing[0], decreasing[1], increasing[1]];
+        check(&record(Direction::Both, 4.0 * PI), &both);
+    }
+
+    #[test]
+    fn integration_in_several_parts() {
+        let mut section = PoincareSection::new(circle, Direction::Both);
+        section.set_projection(angle);
+        let mut y = Vector2::new(2.0, 0.0);
+        for k in 0..4 {
+            let x = k as f64 * PI;
+            let mut stepper = Dopri5::new(rotation, x, x + PI, PI, y, 1.0e-12, 1.0e-12);
+            section.integrate(&mut stepper).unwrap();
+            y = *stepper.y_out().last().unwrap();
+        }
+        let both = [2.0, 4.0, 8.0, 10.0].iter().map(|k| k * PI / 3.0);
+        check(&section, &both.collect::<Vec<f64>>());
+
+        // The crossings are at the angles ∓π/3 on the section
+        for (i, coordinates) in section.coordinates_out().iter().enumerate() {
+            let expected = if i % 2 == 0 { -PI / 3.0 } else { PI / 3.0 };
+            assert!((coordinates[0] - expected).abs() < 1.0e-9);
+        }
+
+        section.clear();
+        assert!(section.x_out().is_empty() && section.coordinates_out().is_empty());
+    }
+}
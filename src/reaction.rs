@@ -0,0 +1,512 @@
+//! Description of reaction networks.
+//!
+//! A [`ReactionNetwork`](struct.ReactionNetwork.html) is a set of species, numbered from 0, and
+//! reactions given by their reactants and products with stoichiometric coefficients, such as
+//! 2 B -> B + C, and a [`RateLaw`](enum.RateLaw.html). It is shared by the stochastic simulation
+//! algorithms of the module [`ssa`](../ssa/index.html) and generates the reaction rate equations
+//! and their Jacobian matrix for the ODE solvers.
+//!
+//! The rate constants are the deterministic mass-action constants of the reaction rate
+//! equations: the rate of 2 B -> B + C with rate constant k is k \[B\]<sup>2</sup>. The
+//! stochastic simulation counts the molecules in the volume of the network, set with
+//! [`set_volume`](struct.ReactionNetwork.html#method.set_volume) and 1 by default, and the
+//! propensity of a reaction, its probability per unit time to occur, is its stochastic constant
+//! given by [`stochastic_rate`](fn.stochastic_rate.html) times the number of distinct combinations
+//! of reactant molecules. For n molecules of B in the volume V, the propensity of 2 B -> B + C is
+//! c n (n - 1) / 2 with c = 2 k / V.
+//!
+//! The rate equations and their Jacobian matrix are passed to the solvers accepting closures, which
+//! call [`rhs`](struct.ReactionNetwork.html#method.rhs) and
+//! [`jacobian`](struct.ReactionNetwork.html#method.jacobian) of a shared network:
+//!
+//! ```ignore
+//! let network = Rc::new(network);
+//! let rates = network.clone();
+//! let mut stepper = Seulex::with_closure(
+//!     move |_x, y: &State, dy: &mut State| rates.rhs(y, dy),
+//!     x0, x_end, dx, y0, rtol, atol,
+//! );
+//! stepper.set_jacobian(move |_x, y: &State, jac: &mut DMatrix<f64>| network.jacobian(y, jac));
+//! ```
+
+use alga::general::SubsetOf;
+use alga::linear::{FiniteDimInnerSpace, InnerSpace};
+use na;
+use na::{DMatrix, DVector};
+
+/// Rate law of a reaction.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RateLaw {
+    /// Mass action with the given deterministic rate constant.
+    MassAction(f64),
+    /// Michaelis-Menten kinetics v<sub>max</sub> s / (K<sub>m</sub> + s) of the single reactant
+    /// s, with the maximal rate v<sub>max</sub> and the Michaelis constant K<sub>m</sub>.
+    MichaelisMenten(f64, f64),
+}
+
+/// Reaction of a network.
+#[derive(Clone, Debug)]
+pub struct Reaction {
+    reactants: Vec<(usize, u32)>,
+    products: Vec<(usize, u32)>,
+    law: RateLaw,
+    change: Vec<(usize, f64)>,
+    volume: f64,
+}
+
+impl Reaction {
+    /// Getter for the reactants, as pairs of a species and its stoichiometric coefficient.
+    pub fn reactants(&self) -> &Vec<(usize, u32)> {
+        &self.reactants
+    }
+
+    /// Getter for the products, as pairs of a species and its stoichiometric coefficient.
+    pub fn products(&self) -> &Vec<(usize, u32)> {
+        &self.products
+    }
+
+    /// Getter for the rate law.
+    pub fn law(&self) -> RateLaw {
+        self.law
+    }
+
+    /// Getter for the rate constant of mass action, or the maximal rate of Michaelis-Menten
+    /// kinetics.
+    pub fn rate(&self) -> f64 {
+        match self.law {
+            RateLaw::MassAction(c) => c,
+            RateLaw::MichaelisMenten(v_max, _) => v_max,
+        }
+    }
+
+    /// Getter for the net change of the numbers of molecules when the reaction occurs, as pairs
+    /// of a species and its change.
+    pub fn change(&self) -> &Vec<(usize, f64)> {
+        &self.change
+    }
+
+    /// Propensity of the reaction for the numbers of molecules n in the volume of the network.
+    pub fn propensity(&self, n: &[f64]) -> f64 {
+        let volume = self.volume;
+        match self.law {
+            RateLaw::MassAction(rate) => {
+                // k V times the falling factorials n_i (n_i - 1) ... / V^k_i, which is the
+                // stochastic constant times the numbers of combinations of reactant molecules
+                let mut a = rate * volume;
+                for &(i, k) in &self.reactants {
+                    for l in 0..k {
+                        a *= (n[i] - f64::from(l)).max(0.0) / volume;
+                    }
+                }
+                a
+            }
+            RateLaw::MichaelisMenten(v_max, k_m) => {
+                let s = n[self.reactants[0].0].max(0.0);
+                v_max * s / (k_m + s / volume)
+            }
+        }
+    }
+
+    /// Deterministic rate of the reaction for the state y.
+    fn rate_of<V>(&self, y: &V) -> f64
+    where
+        V: FiniteDimInnerSpace + Copy,
+        <V as InnerSpace>::Real: SubsetOf<f64>,
+    {
+        match self.law {
+            RateLaw::MassAction(k) => {
+                let mut v = k;
+                for &(i, k) in &self.reactants {
+                    let y_i: f64 = na::convert(y[i]);
+                    v *= y_i.powi(k as i32);
+                }
+                v
+            }
+            RateLaw::MichaelisMenten(v_max, k_m) => {
+                let s: f64 = na::convert(y[self.reactants[0].0]);
+                v_max * s / (k_m + s)
+            }
+        }
+    }
+
+    /// Adds the derivatives of the deterministic rate of the reaction times the change of each
+    /// species to the Jacobian matrix.
+    fn add_derivatives<V>(&self, y: &V, jac: &mut DMatrix<f64>)
+    where
+        V: FiniteDimInnerSpace + Copy,
+        <V as InnerSpace>::Real: SubsetOf<f64>,
+    {
+        for (e, &(j, k)) in self.reactants.iter().enumerate() {
+            let y_j: f64 = na::convert(y[j]);
+            let dv = match self.law {
+                RateLaw::MassAction(c) => {
+                    let mut dv = c * f64::from(k) * y_j.powi(k as i32 - 1);
+                    for (l, &(i, k)) in self.reactants.iter().enumerate() {
+                        if l != e {
+                            let y_i: f64 = na::convert(y[i]);
+                            dv *= y_i.powi(k as i32);
+                        }
+                    }
+                    dv
+                }
+                RateLaw::MichaelisMenten(v_max, k_m) => v_max * k_m / (k_m + y_j).powi(2),
+            };
+            for &(i, c) in &self.change {
+                jac[(i, j)] += c * dv;
+            }
+        }
+    }
+}
+
+/// Structure containing the species and the reactions of a network.
+#[derive(Clone, Debug)]
+pub struct ReactionNetwork {
+    species: Vec<String>,
+    reactions: Vec<Reaction>,
+    volume: f64,
+}
+
+impl ReactionNetwork {
+    /// Default initializer for the structure.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_species` - Number of species of the network
+    ///
+    pub fn new(num_species: usize) -> ReactionNetwork {
+        ReactionNetwork {
+            species: (0..num_species).map(|i| i.to_string()).collect(),
+            reactions: Vec::new(),
+            volume: 1.0,
+        }
+    }
+
+    /// Initializer for a network of named species, numbered in the order of the names.
+    pub fn with_species(names: &[&str]) -> ReactionNetwork {
+        ReactionNetwork {
+            species: names.iter().map(|name| name.to_string()).collect(),
+            reactions: Vec::new(),
+            volume: 1.0,
+        }
+    }
+
+    /// Adds a reaction with mass-action kinetics and returns its index.
+    ///
+    /// # Arguments
+    ///
+    /// * `reactants`   - Reactants, as pairs of a species and its stoichiometric coefficient
+    /// * `products`    - Products, as pairs of a species and its stoichiometric coefficient
+    /// * `rate`        - Deterministic rate constant
+    ///
+    pub fn add_reaction(
+        &mut self,
+        reactants: &[(usize, u32)],
+        products: &[(usize, u32)],
+        rate: f64,
+    ) -> usize {
+        self.add_reaction_with_law(reactants, products, RateLaw::MassAction(rate))
+    }
+
+    /// Adds a reaction with the given rate law and returns its index.
+    ///
+    /// # Arguments
+    ///
+    /// * `reactants`   - Reactants, as pairs of a species and its stoichiometric coefficient
+    /// * `products`    - Products, as pairs of a species and its stoichiometric coefficient
+    /// * `law`         - Rate law
+    ///
+    pub fn add_reaction_with_law(
+        &mut self,
+        reactants: &[(usize, u32)],
+        products: &[(usize, u32)],
+        law: RateLaw,
+    ) -> usize {
+        let num_species = self.species.len();
+        if let RateLaw::MichaelisMenten(..) = law {
+            assert!(
+                reactants.len() == 1 && reactants[0].1 == 1,
+                "Michaelis-Menten kinetics requires a single reactant"
+            );
+        }
+        let mut change = vec![0.0; num_species];
+        for &(i, k) in reactants {
+            assert!(i < num_species, "unknown species {}", i);
+            change[i] -= f64::from(k);
+        }
+        for &(i, k) in products {
+            assert!(i < num_species, "unknown species {}", i);
+            change[i] += f64::from(k);
+        }
+        self.reactions.push(Reaction {
+            reactants: reactants.to_vec(),
+            products: products.to_vec(),
+            law,
+            change: change
+                .into_iter()
+                .enumerate()
+                .filter(|&(_, c)| c != 0.0)
+                .collect(),
+            volume: self.volume,
+        });
+        self.reactions.len() - 1
+    }
+
+    /// Sets the rate constant of mass action, or the maximal rate of Michaelis-Menten kinetics,
+    /// of the j-th reaction.
+    pub fn set_rate(&mut self, j: usize, rate: f64) {
+        match self.reactions[j].law {
+            RateLaw::MassAction(ref mut c) => *c = rate,
+            RateLaw::MichaelisMenten(ref mut v_max, _) => *v_max = rate,
+        }
+    }
+
+    /// Sets the volume in which the stochastic simulation counts the molecules. The numbers of
+    /// molecules are the volume times the concentrations of the reaction rate equations. Default
+    /// is 1.
+    pub fn set_volume(&mut self, volume: f64) {
+        assert!(volume > 0.0, "the volume must be positive");
+        self.volume = volume;
+        for r in &mut self.reactions {
+            r.volume = volume;
+        }
+    }
+
+    /// Getter for the volume.
+    pub fn volume(&self) -> f64 {
+        self.volume
+    }
+
+    /// Number of species.
+    pub fn num_species(&self) -> usize {
+        self.species.len()
+    }
+
+    /// Getter for the names of the species.
+    pub fn species(&self) -> &Vec<String> {
+        &self.species
+    }
+
+    /// Index of the species with the given name.
+    pub fn species_index(&self, name: &str) -> Option<usize> {
+        self.species.iter().position(|s| s == name)
+    }
+
+    /// Getter for the reactions.
+    pub fn reactions(&self) -> &Vec<Reaction> {
+        &self.reactions
+    }
+
+    /// Stoichiometry matrix, whose entry (i, j) is the net change of the number of molecules of
+    /// the i-th species when the j-th reaction occurs.
+    pub fn stoichiometry(&self) -> DMatrix<f64> {
+        let mut n = DMatrix::zeros(self.species.len(), self.reactions.len());
+        for (j, r) in self.reactions.iter().enumerate() {
+            for &(i, c) in &r.change {
+                n[(i, j)] = c;
+            }
+        }
+        n
+    }
+
+    /// Right-hand side of the reaction rate equations.
+    pub fn rhs<V>(&self, y: &V, dy: &mut V)
+    where
+        V: FiniteDimInnerSpace + Copy,
+        <V as InnerSpace>::Real: SubsetOf<f64>,
+    {
+        *dy = V::zero();
+        for r in &self.reactions {
+            let v = r.rate_of(y);
+            for &(i, c) in &r.change {
+                let dy_i: f64 = na::convert(dy[i]);
+                dy[i] = na::convert(dy_i + c * v);
+            }
+        }
+    }
+
+    /// Analytic Jacobian matrix of the right-hand side of the reaction rate equations.
+    pub fn jacobian<V>(&self, y: &V, jac: &mut DMatrix<f64>)
+    where
+        V: FiniteDimInnerSpace + Copy,
+        <V as InnerSpace>::Real: SubsetOf<f64>,
+    {
+        jac.fill(0.0);
+        for r in &self.reactions {
+            r.add_derivatives(y, jac);
+        }
+    }
+
+    /// Basis of the conservation laws of the network, the vectors w such that the total w · y is
+    /// constant for all the rates of the reactions. They span the left null space of the
+    /// stoichiometry matrix and are computed from the reduced row echelon form of its transpose,
+    /// each with a unit entry for a species which appears in no other law.
+    pub fn conservation_laws(&self) -> Vec<DVector<f64>> {
+        let mut a = self.stoichiometry().transpose();
+        let (rows, cols) = a.shape();
+        let tol = 1.0e-10 * a.amax().max(1.0);
+
+        // Gauss-Jordan elimination with partial pivoting
+        let mut pivots = Vec::new();
+        for j in 0..cols {
+            let r = pivots.len();
+            if r == rows {
+                break;
+            }
+            let p = (r..rows)
+                .max_by(|&k, &l| a[(k, j)].abs().partial_cmp(&a[(l, j)].abs()).unwrap())
+                .unwrap();
+            if a[(p, j)].abs() <= tol {
+                continue;
+            }
+            a.swap_rows(r, p);
+            let pivot = a[(r, j)];
+            for l in 0..cols {
+                a[(r, l)] /= pivot;
+            }
+            for k in 0..rows {
+                let factor = a[(k, j)];
+                if k != r && factor != 0.0 {
+                    for l in 0..cols {
+                        a[(k, l)] -= factor * a[(r, l)];
+                    }
+                }
+            }
+            pivots.push(j);
+        }
+
+        // One law per free species
+        (0..cols)
+            .filter(|j| !pivots.contains(j))
+            .map(|j| {
+                let mut w = DVector::zeros(cols);
+                w[j] = 1.0;
+                for (r, &p) in pivots.iter().enumerate() {
+                    let w_p = -a[(r, j)];
+                    w[p] = if w_p.abs() <= tol { 0.0 } else { w_p };
+                }
+                w
+            })
+            .collect()
+    }
+
+    /// For each reaction, the reactions whose propensity changes when it occurs, itself included
+    /// if its reactants change.
+    pub(crate) fn dependencies(&self) -> Vec<Vec<usize>> {
+        self.reactions
+            .iter()
+            .map(|r| {
+                (0..self.reactions.len())
+                    .filter(|&k| {
+                        self.reactions[k]
+                            .reactants
+                            .iter()
+                            .any(|&(i, _)| r.change.iter().any(|&(l, _)| l == i))
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// Stochastic mass-action rate constant of a reaction with the deterministic rate constant k for
+/// concentrations in the given volume.
+///
+/// # Arguments
+///
+/// * `k`           - Deterministic rate constant
+/// * `reactants`   - Reactants, as pairs of a species and its stoichiometric coefficient
+/// * `volume`      - Volume of the system
+///
+pub fn stochastic_rate(k: f64, reactants: &[(usize, u32)], volume: f64) -> f64 {
+    let order: u32 = reactants.iter().map(|&(_, k)| k).sum();
+    let combinations: f64 = reactants.iter().map(|&(_, k)| factorial(k)).product();
+    k * combinations / volume.powi(order as i32 - 1)
+}
+
+fn factorial(k: u32) -> f64 {
+    (2..=k).map(f64::from).product()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jacobian::forward_differences;
+    use na::{Vector3, Vector5};
+
+    // Robertson's reactions, A -> B, B + C -> A + C and 2 B -> B + C, whose rate equations are
+    // the ones of examples/chemical_reaction.rs
+    fn robertson() -> ReactionNetwork {
+        let mut network = ReactionNetwork::with_species(&["A", "B", "C"]);
+        network.add_reaction(&[(0, 1)], &[(1, 1)], 0.04);
+        network.add_reaction(&[(1, 1), (2, 1)], &[(0, 1), (2, 1)], 1.0e4);
+        network.add_reaction(&[(1, 2)], &[(1, 1), (2, 1)], 3.0e7);
+        network
+    }
+
+    #[test]
+    fn rate_equations_of_robertson() {
+        let network = robertson();
+        let y = Vector3::new(0.7, 2.0e-5, 0.3);
+        let mut dy = Vector3::zeros();
+        network.rhs(&y, &mut dy);
+        let expected = Vector3::new(
+            -0.04 * y[0] + 1.0e4 * y[1] * y[2],
+            0.04 * y[0] - 1.0e4 * y[1] * y[2] - 3.0e7 * y[1] * y[1],
+            3.0e7 * y[1] * y[1],
+        );
+        assert!((dy - expected).norm() < 1.0e-15);
+
+        let mut jac = DMatrix::zeros(3, 3);
+        network.jacobian(&y, &mut jac);
+        let mut approx = DMatrix::zeros(3, 3);
+        forward_differences(|_, y, dy| network.rhs(y, dy), 0.0, &y, &dy, &mut approx);
+        assert!((&jac - approx).amax() < 1.0e-6 * jac.amax());
+
+        let laws = network.conservation_laws();
+        assert_eq!(laws.len(), 1);
+        assert_eq!(laws[0], DVector::from_element(3, 1.0));
+    }
+
+    #[test]
+    fn propensities_in_a_volume() {
+        let mut network = ReactionNetwork::new(2);
+        network.add_reaction(&[], &[(0, 1)], 2.0);
+        network.add_reaction(&[(0, 2)], &[(1, 1)], 3.0);
+        network.add_reaction_with_law(&[(1, 1)], &[], RateLaw::MichaelisMenten(4.0, 0.5));
+        network.set_volume(2.0);
+        let n = [4.0, 3.0];
+        let reactions = network.reactions();
+        assert_eq!(reactions[0].propensity(&n), 4.0);
+        let c = stochastic_rate(3.0, reactions[1].reactants(), 2.0);
+        assert_eq!(reactions[1].propensity(&n), c * 6.0);
+        // Volume times the deterministic rate of the concentrations
+        let rate = 2.0 * 4.0 * 1.5 / (0.5 + 1.5);
+        assert!((reactions[2].propensity(&n) - rate).abs() < 1.0e-14);
+    }
+
+    #[test]
+    fn conservation_laws_of_enzyme_kinetics() {
+        let mut network = ReactionNetwork::with_species(&["E", "S", "ES", "P", "Q"]);
+        network.add_reaction(&[(0, 1), (1, 1)], &[(2, 1)], 1.0);
+        network.add_reaction(&[(2, 1)], &[(0, 1), (1, 1)], 0.5);
+        network.add_reaction(&[(2, 1)], &[(0, 1), (3, 1)], 0.1);
+        network.add_reaction_with_law(&[(3, 1)], &[(4, 1)], RateLaw::MichaelisMenten(2.0, 0.5));
+        assert_eq!(network.species_index("Q"), Some(4));
+
+        let n = network.stoichiometry();
+        let laws = network.conservation_laws();
+        assert_eq!(laws.len(), 2);
+        for w in &laws {
+            assert!((n.transpose() * w).amax() < 1.0e-12);
+        }
+
+        let y = Vector5::new(0.2, 1.0, 0.3, 0.4, 0.1);
+        let mut dy = Vector5::zeros();
+        network.rhs(&y, &mut dy);
+        let mut jac = DMatrix::zeros(5, 5);
+        network.jacobian(&y, &mut jac);
+        let mut approx = DMatrix::zeros(5, 5);
+        forward_differences(|_, y, dy| network.rhs(y, dy), 0.0, &y, &dy, &mut approx);
+        assert!((jac - approx).amax() < 1.0e-6);
+    }
+}
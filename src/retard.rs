@@ -0,0 +1,727 @@
+//===================================================================//
+// Copyright (c) 2004, Ernst Hairer
+
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+
+// - Redistributions of source code must retain the above copyright
+// notice, this list of conditions and the following disclaimer.
+
+// - Redistributions in binary form must reproduce the above copyright
+// notice, this list of conditions and the following disclaimer in the
+// documentation and/or other materials provided with the distribution.
+
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS “AS
+// IS” AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED
+// TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A
+// PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE REGENTS OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+// This code is a Rust adaptation of the code RETARD written originally
+// in Fortran by:
+//
+//      E. Hairer & G. Wanner
+//      Université de Genève, dept. de Mathématiques
+//      CH-1211 Genève 4, Swizerland
+//      E-mail : hairer@divsun.unige.ch, wanner@divsun.unige.ch
+//
+//===================================================================//
+
+#![allow(clippy::needless_range_loop)]
+
+//! Explicit Runge-Kutta method with Dormand-Prince coefficients of order 5(4) for delay differential equations _y'(x) = f(x, y(x), y(x - τ))_.
+//! The dense output of every step is stored and provides the delayed values.
+
+use alga::linear::{FiniteDimInnerSpace, InnerSpace};
+use butcher_tableau::Dopri54;
+use controller::Controller;
+use dop_shared::*;
+use na;
+use std::f64;
+
+// Discontinuities of the derivatives of order higher than the order of the method are not tracked
+const MAX_LEVEL: u32 = 5;
+
+trait DefaultController {
+    fn default(x: f64, x_end: f64) -> Self;
+}
+
+impl DefaultController for Controller {
+    fn default(x: f64, x_end: f64) -> Self {
+        let alpha = 0.2 - 0.04 * 0.75;
+        Controller::new(
+            alpha,
+            0.04,
+            10.0,
+            0.2,
+            x_end - x,
+            0.9,
+            sign(1.0, x_end - x),
+        )
+    }
+}
+
+/// Past values of the solution. Before the initial point, the initial function is used. After it,
+/// the continuous extension of the accepted steps is evaluated.
+pub struct History<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+{
+    x0: f64,
+    posneg: f64,
+    phi: fn(f64) -> V,
+    x_step: Vec<f64>,
+    h_step: Vec<f64>,
+    rcont: Vec<[V; 5]>,
+}
+
+impl<V> History<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: Value,
+{
+    fn new(x0: f64, posneg: f64, phi: fn(f64) -> V) -> History<V> {
+        History {
+            x0,
+            posneg,
+            phi,
+            x_step: Vec::new(),
+            h_step: Vec::new(),
+            rcont: Vec::new(),
+        }
+    }
+
+    /// Returns the value of the solution at x. If x lies beyond the last accepted step, the
+    /// continuous extension of this step is extrapolated.
+    pub fn y(&self, x: f64) -> V {
+        if (x - self.x0) * self.posneg < 0.0 || self.x_step.is_empty() {
+            return (self.phi)(x);
+        }
+
+        // Find the last step starting before x
+        let mut lo = 0;
+        let mut hi = self.x_step.len();
+        while hi - lo > 1 {
+            let mid = (lo + hi) / 2;
+            if (x - self.x_step[mid]) * self.posneg >= 0.0 {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        continuous_extension(&self.rcont[lo], (x - self.x_step[lo]) / self.h_step[lo])
+    }
+
+    fn push(&mut self, x: f64, h: f64, rcont: [V; 5]) {
+        self.x_step.push(x);
+        self.h_step.push(h);
+        self.rcont.push(rcont);
+    }
+}
+
+/// Structure containing the parameters for the numerical integration.
+pub struct Retard<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+{
+    f: fn(f64, &V, &History<V>, &mut V),
+    x: f64,
+    x0: f64,
+    x_old: f64,
+    x_end: f64,
+    xd: f64,
+    dx: f64,
+    y: V,
+    rtol: f64,
+    atol: f64,
+    x_out: Vec<f64>,
+    y_out: Vec<V>,
+    uround: f64,
+    h: f64,
+    h_old: f64,
+    n_max: u32,
+    coeffs: Dopri54,
+    controller: Controller,
+    out_type: OutputType,
+    rcont: [V; 5],
+    history: History<V>,
+    delays: Vec<fn(f64, &V) -> f64>,
+    breakpoints: Vec<(f64, u32)>,
+    crossings: Vec<(usize, usize)>,
+    stats: Stats,
+}
+
+impl<V> Retard<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: Value,
+{
+    /// Default initializer for the structure.
+    ///
+    /// # Arguments
+    ///
+    /// * `f`       - Pointer to the function to integrate. The delayed values are obtained from the history passed as third argument
+    /// * `phi`     - Initial function, giving the solution before the initial value of the independent variable
+    /// * `x`       - Initial value of the independent variable (usually time)
+    /// * `x_end`   - Final value of the independent variable
+    /// * `dx`      - Increment in the dense output. This argument has no effect if the output type is Sparse
+    /// * `y`       - Initial value of the dependent variable(s)
+    /// * `rtol`    - Relative tolerance used in the computation of the adaptive step size
+    /// * `atol`    - Absolute tolerance used in the computation of the adaptive step size
+    ///
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        f: fn(f64, &V, &History<V>, &mut V),
+        phi: fn(f64) -> V,
+        x: f64,
+        x_end: f64,
+        dx: f64,
+        y: V,
+        rtol: f64,
+        atol: f64,
+    ) -> Retard<V> {
+        Retard {
+            f,
+            x,
+            x0: x,
+            x_old: x,
+            x_end,
+            xd: x,
+            dx,
+            y,
+            rtol,
+            atol,
+            x_out: Vec::<f64>::new(),
+            y_out: Vec::<V>::new(),
+            uround: f64::EPSILON,
+            h: 0.0,
+            h_old: 0.0,
+            n_max: 100000,
+            coeffs: Dopri54::new(),
+            controller: Controller::default(x, x_end),
+            out_type: OutputType::Dense,
+            rcont: [V::zero(); 5],
+            history: History::new(x, sign(1.0, x_end - x), phi),
+            delays: Vec::new(),
+            breakpoints: vec![(x, 0)],
+            crossings: Vec::new(),
+            stats: Stats::new(),
+        }
+    }
+
+    /// Advanced initializer for the structure.
+    ///
+    /// # Arguments
+    ///
+    /// * `f`       - Pointer to the function to integrate. The delayed values are obtained from the history passed as third argument
+    /// * `phi`     - Initial function, giving the solution before the initial value of the independent variable
+    /// * `x`       - Initial value of the independent variable (usually time)
+    /// * `x_end`   - Final value of the independent variable
+    /// * `dx`      - Increment in the dense output. This argument has no effect if the output type is Sparse
+    /// * `y`       - Initial value of the dependent variable(s)
+    /// * `rtol`    - Relative tolerance used in the computation of the adaptive step size
+    /// * `atol`    - Absolute tolerance used in the computation of the adaptive step size
+    /// * `safety_factor`   - Safety factor used in the computation of the adaptive step size. Default is 0.9
+    /// * `beta`    - Value of the beta coefficient of the PI controller. Default is 0.04
+    /// * `fac_min` - Minimum factor between two successive steps. Default is 0.2
+    /// * `fac_max` - Maximum factor between two successive steps. Default is 10.0
+    /// * `h_max`   - Maximum step size. Default is `x_end-x`
+    /// * `h`       - Initial value of the step size. If h = 0.0, the intial value of h is computed automatically
+    /// * `n_max`   - Maximum number of iterations. Default is 100000
+    /// * `out_type`    - Type of the output. Must be a variant of the OutputType enum. Default is Dense
+    ///
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_param(
+        f: fn(f64, &V, &History<V>, &mut V),
+        phi: fn(f64) -> V,
+        x: f64,
+        x_end: f64,
+        dx: f64,
+        y: V,
+        rtol: f64,
+        atol: f64,
+        safety_factor: f64,
+        beta: f64,
+        fac_min: f64,
+        fac_max: f64,
+        h_max: f64,
+        h: f64,
+        n_max: u32,
+        out_type: OutputType,
+    ) -> Retard<V> {
+        let alpha = 0.2 - beta * 0.75;
+        Retard {
+            f,
+            x,
+            x0: x,
+            x_old: x,
+            x_end,
+            xd: x,
+            dx,
+            y,
+            rtol,
+            atol,
+            x_out: Vec::<f64>::new(),
+            y_out: Vec::<V>::new(),
+            uround: f64::EPSILON,
+            h,
+            h_old: 0.0,
+            n_max,
+            coeffs: Dopri54::new(),
+            controller: Controller::new(
+                alpha,
+                beta,
+                fac_max,
+                fac_min,
+                h_max,
+                safety_factor,
+                sign(1.0, x_end - x),
+            ),
+            out_type,
+            rcont: [V::zero(); 5],
+            history: History::new(x, sign(1.0, x_end - x), phi),
+            delays: Vec::new(),
+            breakpoints: vec![(x, 0)],
+            crossings: Vec::new(),
+            stats: Stats::new(),
+        }
+    }
+
+    /// Sets the delays τ(x, y) of the problem. They are used to locate the points where the
+    /// derivatives of the solution are discontinuous, which are then hit by the steps.
+    /// Constant delays are given by functions returning a constant.
+    pub fn set_delays(&mut self, delays: Vec<fn(f64, &V) -> f64>) {
+        self.delays = delays;
+    }
+
+    /// Compute the initial stepsize
+    fn hinit(&self) -> f64 {
+        let mut f0 = V::zero();
+        (self.f)(self.x, &self.y, &self.history, &mut f0);
+        let posneg = sign(1.0, self.x_end - self.x);
+
+        // Compute the norm of y0 and f0
+        let dim = na::dimension::<V>();
+        let mut d0 = 0.0;
+        let mut d1 = 0.0;
+        for i in 0..dim {
+            let y_i = self.y[i].value();
+            let sci: f64 = self.atol + y_i.abs() * self.rtol;
+            d0 += (y_i / sci) * (y_i / sci);
+            let f0_i = f0[i].value();
+            d1 += (f0_i / sci) * (f0_i / sci);
+        }
+
+        // Compute h0
+        let mut h0 = if d0 < 1.0E-10 || d1 < 1.0E-10 {
+            1.0E-6
+        } else {
+            0.01 * (d0 / d1).sqrt()
+        };
+
+        h0 = h0.min(self.controller.h_max());
+        h0 = sign(h0, posneg);
+
+        let y1 = self.y + f0 * na::convert(h0);
+        let mut f1 = V::zero();
+        (self.f)(self.x + h0, &y1, &self.history, &mut f1);
+
+        // Compute the norm of f1-f0 divided by h0
+        let mut d2: f64 = 0.0;
+        for i in 0..dim {
+            let f0_i = f0[i].value();
+            let f1_i = f1[i].value();
+            let y_i = self.y[i].value();
+            let sci: f64 = self.atol + y_i.abs() * self.rtol;
+            d2 += ((f1_i - f0_i) / sci) * ((f1_i - f0_i) / sci);
+        }
+        d2 = d2.sqrt() / h0;
+
+        let h1 = if d1.sqrt().max(d2.abs()) <= 1.0E-15 {
+            1.0E-6_f64.max(h0.abs() * 1.0E-3)
+        } else {
+            (0.01 / (d1.sqrt().max(d2))).powf(1.0 / 5.0)
+        };
+
+        sign(
+            (100.0 * h0.abs()).min(h1.min(self.controller.h_max())),
+            posneg,
+        )
+    }
+
+    /// Core integration method.
+    pub fn integrate(&mut self) -> Result<Stats, IntegrationError> {
+        // Initilization
+        self.x_old = self.x;
+        let mut n_step = 0;
+        let mut last = false;
+        let mut h_new = 0.0;
+        let dim = na::dimension::<V>();
+        let posneg = sign(1.0, self.x_end - self.x);
+
+        if self.h == 0.0 {
+            self.h = self.hinit();
+            self.stats.num_eval += 2;
+        }
+        self.h_old = self.h;
+
+        // Save initial values
+        let y_tmp = self.y;
+        self.solution_output(y_tmp);
+
+        let mut k: Vec<V> = vec![V::zero(); 7];
+        (self.f)(self.x, &self.y, &self.history, &mut k[0]);
+        self.stats.num_eval += 1;
+
+        // Main loop
+        while !last {
+            // Check if step number is within allowed range
+            if n_step >= self.n_max {
+                self.h_old = self.h;
+                return Err(IntegrationError::MaxNumStepReached { x: self.x, n_step });
+            }
+
+            // Check for step size underflow
+            if 0.1 * self.h.abs() <= self.uround * self.x.abs() {
+                self.h_old = self.h;
+                return Err(IntegrationError::StepSizeUnderflow { x: self.x });
+            }
+
+            // Hit the next discontinuity
+            let margin = self.margin();
+            let next_disc = self
+                .breakpoints
+                .iter()
+                .map(|bp| bp.0)
+                .filter(|&x_disc| (x_disc - self.x) * posneg > margin)
+                .fold(None, |next: Option<f64>, x_disc| match next {
+                    Some(x_next) if (x_next - x_disc) * posneg <= 0.0 => Some(x_next),
+                    _ => Some(x_disc),
+                });
+            if let Some(x_disc) = next_disc {
+                if (self.x + 1.01 * self.h - x_disc) * posneg > 0.0 {
+                    self.h = x_disc - self.x;
+                }
+            }
+
+            // Check if it's the last iteration
+            if (self.x + 1.01 * self.h - self.x_end) * posneg > 0.0 {
+                self.h = self.x_end - self.x;
+                last = true;
+            }
+            n_step += 1;
+
+            // 6 Stages
+            let mut y_next = V::zero();
+            for s in 1..7 {
+                y_next = self.y;
+                for j in 0..s {
+                    y_next += k[j] * na::convert(self.h * self.coeffs.a(s + 1, j + 1));
+                }
+                (self.f)(
+                    self.x + self.h * self.coeffs.c(s + 1),
+                    &y_next,
+                    &self.history,
+                    &mut k[s],
+                );
+            }
+            k[1] = k[6];
+            self.stats.num_eval += 6;
+
+            // Prepare dense output
+            self.rcont[4] = (k[0] * na::convert(self.coeffs.d(1))
+                + k[2] * na::convert(self.coeffs.d(3))
+                + k[3] * na::convert(self.coeffs.d(4))
+                + k[4] * na::convert(self.coeffs.d(5))
+                + k[5] * na::convert(self.coeffs.d(6))
+                + k[1] * na::convert(self.coeffs.d(7)))
+                * na::convert(self.h);
+
+            // Compute error estimate
+            k[3] = (k[0] * na::convert(self.coeffs.e(1))
+                + k[1] * na::convert(self.coeffs.e(2))
+                + k[2] * na::convert(self.coeffs.e(3))
+                + k[3] * na::convert(self.coeffs.e(4))
+                + k[4] * na::convert(self.coeffs.e(5))
+                + k[5] * na::convert(self.coeffs.e(6))
+                + k[1] * na::convert(self.coeffs.e(7)))
+                * na::convert(self.h);
+
+            // Compute error
+            let mut err = 0.0;
+            for i in 0..dim {
+                let y_i = self.y[i].value();
+                let y_next_i = y_next[i].value();
+                let sc_i: f64 = self.atol + y_i.abs().max(y_next_i.abs()) * self.rtol;
+                let err_est_i = k[3][i].value();
+                err += (err_est_i / sc_i) * (err_est_i / sc_i);
+            }
+            err = (err / dim as f64).sqrt();
+
+            // Step size control
+            if self.controller.accept(err, self.h, &mut h_new) {
+                // Prepare dense output
+                let ydiff = y_next - self.y;
+                let bspl = k[0] * na::convert(self.h) - ydiff;
+                self.rcont[0] = self.y;
+                self.rcont[1] = ydiff;
+                self.rcont[2] = bspl;
+                self.rcont[3] = -k[1] * na::convert(self.h) + ydiff - bspl;
+
+                // If a new discontinuity lies inside the step, redo the step up to the discontinuity
+                if let Some((x_disc, level, crossing)) = self.locate_discontinuity() {
+                    self.breakpoints.push((x_disc, level));
+                    self.crossings.push(crossing);
+                    if (self.x + self.h - x_disc).abs() > self.margin() {
+                        last = false;
+                        continue;
+                    }
+                }
+
+                self.stats.accepted_steps += 1;
+                self.history.push(self.x, self.h, self.rcont);
+                k[0] = k[1];
+                self.y = y_next;
+                self.x_old = self.x;
+                self.x += self.h;
+                self.h_old = self.h;
+
+                self.solution_output(y_next);
+
+                // Normal exit
+                if last {
+                    self.h_old = posneg * h_new;
+                    return Ok(self.stats);
+                }
+            } else {
+                if self.stats.accepted_steps >= 1 {
+                    self.stats.rejected_steps += 1;
+                }
+                // The step reaching x_end was rejected
+                last = false;
+            }
+            self.h = h_new;
+        }
+        Ok(self.stats)
+    }
+
+    /// Smallest distance between two distinct discontinuities.
+    fn margin(&self) -> f64 {
+        100.0 * self.uround * self.x.abs().max(self.h.abs())
+    }
+
+    /// Returns the first point of the current step, together with its level, at which a delayed
+    /// argument x - τ(x, y) crosses a known discontinuity, and the indices of the discontinuity and
+    /// of the delay. Crossings which have already been located are ignored.
+    fn locate_discontinuity(&self) -> Option<(f64, u32, (usize, usize))> {
+        let margin = self.margin();
+        let mut first: Option<(f64, u32, (usize, usize))> = None;
+        for (j, delay) in self.delays.iter().enumerate() {
+            let alpha = |theta: f64| {
+                let x = self.x + theta * self.h;
+                x - delay(x, &continuous_extension(&self.rcont, theta))
+            };
+            let alpha_start = alpha(0.0);
+            let alpha_end = alpha(1.0);
+            for (i, &(x_disc, level)) in self.breakpoints.iter().enumerate() {
+                if level >= MAX_LEVEL
+                    || (alpha_start - x_disc) * (alpha_end - x_disc) >= 0.0
+                    || self.crossings.contains(&(i, j))
+                {
+                    continue;
+                }
+
+                // Bisection on the dense output of the step
+                let (mut a, mut b) = (0.0, 1.0);
+                let sign_a = alpha_start - x_disc;
+                while (b - a) * self.h.abs() > self.uround * self.x.abs().max(1.0) {
+                    let mid = 0.5 * (a + b);
+                    if (alpha(mid) - x_disc) * sign_a > 0.0 {
+                        a = mid;
+                    } else {
+                        b = mid;
+                    }
+                }
+                let x_new = self.x + b * self.h;
+                if (x_new - self.x).abs() <= margin
+                    || self.breakpoints.iter().any(|bp| (bp.0 - x_new).abs() <= margin)
+                {
+                    continue;
+                }
+                let closer = match first {
+                    Some((x_first, _, _)) => (x_new - x_first) * self.h < 0.0,
+                    None => true,
+                };
+                if closer {
+                    first = Some((x_new, level + 1, (i, j)));
+                }
+            }
+        }
+        first
+    }
+
+    /// If a dense output is required, computes the solution and pushes it into the output vector. Else, pushes the solution into the output vector.
+    fn solution_output(&mut self, y_next: V) {
+        if self.out_type == OutputType::Dense {
+            let posneg = sign(1.0, self.x_end - self.x0);
+            if self.x == self.x0 {
+                self.x_out.push(self.x0);
+                self.y_out.push(y_next);
+                self.xd += self.dx;
+            } else {
+                while (self.xd - self.x) * posneg <= 0.0 {
+                    let theta = (self.xd - self.x_old) / self.h_old;
+                    self.x_out.push(self.xd);
+                    self.y_out.push(continuous_extension(&self.rcont, theta));
+                    self.xd += self.dx;
+                }
+            }
+        } else {
+            self.x_out.push(self.x);
+            self.y_out.push(y_next);
+        }
+    }
+
+    /// Getter for the independent variable's output.
+    pub fn x_out(&self) -> &Vec<f64> {
+        &self.x_out
+    }
+
+    /// Getter for the dependent variables' output.
+    pub fn y_out(&self) -> &Vec<V> {
+        &self.y_out
+    }
+
+    /// Getter for the history of the solution, which can be evaluated at any point of the integration interval.
+    pub fn history(&self) -> &History<V> {
+        &self.history
+    }
+
+    /// Getter for the discontinuities of the derivatives of the solution which have been hit by the steps.
+    pub fn breakpoints(&self) -> Vec<f64> {
+        self.breakpoints.iter().map(|bp| bp.0).collect()
+    }
+}
+
+/// Evaluates the continuous extension of a step at theta in [0, 1].
+fn continuous_extension<V>(rcont: &[V; 5], theta: f64) -> V
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: Value,
+{
+    let theta1 = 1.0 - theta;
+    rcont[0]
+        + (rcont[1]
+            + (rcont[2] + (rcont[3] + rcont[4] * na::convert(theta1)) * na::convert(theta))
+                * na::convert(theta1))
+            * na::convert(theta)
+}
+
+fn sign(a: f64, b: f64) -> f64 {
+    if b > 0.0 {
+        a.abs()
+    } else {
+        -a.abs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use na::{Vector1, Vector2};
+
+    fn delayed_decay(
+        x: f64,
+        _y: &Vector1<f64>,
+        history: &History<Vector1<f64>>,
+        dy: &mut Vector1<f64>,
+    ) {
+        dy[0] = -history.y(x - 1.0)[0];
+    }
+
+    fn unit_delay(_x: f64, _y: &Vector1<f64>) -> f64 {
+        1.0
+    }
+
+    fn one(_x: f64) -> Vector1<f64> {
+        Vector1::new(1.0)
+    }
+
+    // Solution of y'(x) = -y(x - 1) with y = 1 for x <= 0, obtained by the method of steps
+    fn method_of_steps(x: f64) -> f64 {
+        let mut y = 1.0 - x;
+        if x > 1.0 {
+            y += (x - 1.0).powi(2) / 2.0;
+        }
+        if x > 2.0 {
+            y -= (x - 2.0).powi(3) / 6.0;
+        }
+        y
+    }
+
+    #[test]
+    fn method_of_steps_solution() {
+        let y0 = Vector1::new(1.0);
+        let mut stepper = Retard::new(delayed_decay, one, 0.0, 3.0, 0.25, y0, 1e-10, 1e-10);
+        stepper.set_delays(vec![unit_delay]);
+        stepper.integrate().unwrap();
+        assert_eq!(*stepper.x_out().last().unwrap(), 3.0);
+        for (x, y) in stepper.x_out().iter().zip(stepper.y_out()) {
+            assert!((y[0] - method_of_steps(*x)).abs() < 1e-9);
+        }
+        let breakpoints = stepper.breakpoints();
+        for x_disc in &[1.0, 2.0] {
+            assert!(breakpoints.iter().any(|x| (x - x_disc).abs() < 1e-12));
+        }
+        assert!((stepper.history().y(1.5)[0] - method_of_steps(1.5)).abs() < 1e-9);
+    }
+
+    fn oscillator(
+        _x: f64,
+        y: &Vector2<f64>,
+        _history: &History<Vector2<f64>>,
+        dy: &mut Vector2<f64>,
+    ) {
+        dy[0] = y[1];
+        dy[1] = -y[0];
+    }
+
+    fn initial_function(x: f64) -> Vector2<f64> {
+        Vector2::new(x.cos(), -x.sin())
+    }
+
+    #[test]
+    fn rejected_last_step() {
+        // The initial step reaches x_end and is rejected
+        let mut stepper = Retard::from_param(
+            oscillator,
+            initial_function,
+            0.0,
+            10.0,
+            10.0,
+            Vector2::new(1.0, 0.0),
+            1e-10,
+            1e-10,
+            0.9,
+            0.04,
+            0.2,
+            10.0,
+            10.0,
+            10.0,
+            100000,
+            OutputType::Sparse,
+        );
+        let stats = stepper.integrate().unwrap();
+        assert!(stats.accepted_steps > 1);
+        assert_eq!(*stepper.x_out().last().unwrap(), 10.0);
+        let y = stepper.y_out().last().unwrap();
+        assert!((y - initial_function(10.0)).norm() < 1e-8);
+    }
+}
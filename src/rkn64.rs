@@ -0,0 +1,638 @@
+#![allow(clippy::needless_range_loop)]
+
+//! Explicit Runge-Kutta-Nyström method of order 6(4) with dense output of order 5 for second order systems _y'' = f(x, y)_.
+//!
+//! Only the 6(4) pair is provided. The 12(10) pair of Dormand, El-Mikkawy and Prince (DOPRI-N) is
+//! out of the scope of this crate: for very stringent tolerances, use Dop853 on the equivalent
+//! first order system.
+
+use alga::linear::{FiniteDimInnerSpace, InnerSpace};
+use butcher_tableau::Nystrom64;
+use controller::Controller;
+use dop_shared::*;
+use na;
+use std::f64;
+
+trait DefaultController {
+    fn default(x: f64, x_end: f64) -> Self;
+}
+
+impl DefaultController for Controller {
+    fn default(x: f64, x_end: f64) -> Self {
+        let alpha = 0.2 - 0.04 * 0.75;
+        Controller::new(
+            alpha,
+            0.04,
+            10.0,
+            0.2,
+            x_end - x,
+            0.9,
+            sign(1.0, x_end - x),
+        )
+    }
+}
+
+/// Structure containing the parameters for the numerical integration.
+pub struct Rkn64<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+{
+    f: fn(f64, &V, &mut V),
+    x: f64,
+    x0: f64,
+    x_old: f64,
+    x_end: f64,
+    xd: f64,
+    dx: f64,
+    y: V,
+    dy: V,
+    rtol: f64,
+    atol: f64,
+    x_out: Vec<f64>,
+    y_out: Vec<V>,
+    dy_out: Vec<V>,
+    uround: f64,
+    h: f64,
+    h_old: f64,
+    n_max: u32,
+    coeffs: Nystrom64,
+    controller: Controller,
+    out_type: OutputType,
+    rcont: [V; 6],
+    stats: Stats,
+    solout: fn(f64, &V, &V) -> bool,
+}
+
+impl<V> Rkn64<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: Value,
+{
+    /// Default initializer for the structure.
+    ///
+    /// # Arguments
+    ///
+    /// * `f`       - Pointer to the function returning the second derivative _y''_
+    /// * `x`       - Initial value of the independent variable (usually time)
+    /// * `x_end`   - Final value of the independent variable
+    /// * `dx`      - Increment in the dense output. This argument has no effect if the output type is Sparse
+    /// * `y`       - Initial value of the dependent variable(s) (usually positions)
+    /// * `dy`      - Initial value of the first derivative of the dependent variable(s) (usually velocities)
+    /// * `rtol`    - Relative tolerance used in the computation of the adaptive step size
+    /// * `atol`    - Absolute tolerance used in the computation of the adaptive step size
+    ///
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        f: fn(f64, &V, &mut V),
+        x: f64,
+        x_end: f64,
+        dx: f64,
+        y: V,
+        dy: V,
+        rtol: f64,
+        atol: f64,
+    ) -> Rkn64<V> {
+        Rkn64 {
+            f,
+            x,
+            x0: x,
+            x_old: x,
+            x_end,
+            xd: x,
+            dx,
+            y,
+            dy,
+            rtol,
+            atol,
+            x_out: Vec::<f64>::new(),
+            y_out: Vec::<V>::new(),
+            dy_out: Vec::<V>::new(),
+            uround: f64::EPSILON,
+            h: 0.0,
+            h_old: 0.0,
+            n_max: 100000,
+            coeffs: Nystrom64::new(),
+            controller: Controller::default(x, x_end),
+            out_type: OutputType::Dense,
+            rcont: [V::zero(); 6],
+            stats: Stats::new(),
+            solout: |_, _, _| false,
+        }
+    }
+
+    /// Advanced initializer for the structure.
+    ///
+    /// # Arguments
+    ///
+    /// * `f`       - Pointer to the function returning the second derivative _y''_
+    /// * `x`       - Initial value of the independent variable (usually time)
+    /// * `x_end`   - Final value of the independent variable
+    /// * `dx`      - Increment in the dense output. This argument has no effect if the output type is Sparse
+    /// * `y`       - Initial value of the dependent variable(s) (usually positions)
+    /// * `dy`      - Initial value of the first derivative of the dependent variable(s) (usually velocities)
+    /// * `rtol`    - Relative tolerance used in the computation of the adaptive step size
+    /// * `atol`    - Absolute tolerance used in the computation of the adaptive step size
+    /// * `safety_factor`   - Safety factor used in the computation of the adaptive step size. Default is 0.9
+    /// * `beta`    - Value of the beta coefficient of the PI controller. Default is 0.04
+    /// * `fac_min` - Minimum factor between two successive steps. Default is 0.2
+    /// * `fac_max` - Maximum factor between two successive steps. Default is 10.0
+    /// * `h_max`   - Maximum step size. Default is `x_end-x`
+    /// * `h`       - Initial value of the step size. If h = 0.0, the intial value of h is computed automatically
+    /// * `n_max`   - Maximum number of iterations. Default is 100000
+    /// * `out_type`    - Type of the output. Must be a variant of the OutputType enum. Default is Dense
+    ///
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_param(
+        f: fn(f64, &V, &mut V),
+        x: f64,
+        x_end: f64,
+        dx: f64,
+        y: V,
+        dy: V,
+        rtol: f64,
+        atol: f64,
+        safety_factor: f64,
+        beta: f64,
+        fac_min: f64,
+        fac_max: f64,
+        h_max: f64,
+        h: f64,
+        n_max: u32,
+        out_type: OutputType,
+    ) -> Rkn64<V> {
+        let alpha = 0.2 - beta * 0.75;
+        Rkn64 {
+            f,
+            x,
+            x0: x,
+            x_old: x,
+            x_end,
+            xd: x,
+            dx,
+            y,
+            dy,
+            rtol,
+            atol,
+            x_out: Vec::<f64>::new(),
+            y_out: Vec::<V>::new(),
+            dy_out: Vec::<V>::new(),
+            uround: f64::EPSILON,
+            h,
+            h_old: h,
+            n_max,
+            coeffs: Nystrom64::new(),
+            controller: Controller::new(
+                alpha,
+                beta,
+                fac_max,
+                fac_min,
+                h_max,
+                safety_factor,
+                sign(1.0, x_end - x),
+            ),
+            out_type,
+            rcont: [V::zero(); 6],
+            stats: Stats::new(),
+            solout: |_, _, _| false,
+        }
+    }
+
+    /// Compute the initial stepsize. The norms are those of the equivalent first order system (y, y').
+    fn hinit(&self) -> f64 {
+        let mut f0 = V::zero();
+        (self.f)(self.x, &self.y, &mut f0);
+        let posneg = sign(1.0, self.x_end - self.x);
+
+        // Compute the norm of (y0, y0') and (y0', y0'')
+        let dim = na::dimension::<V>();
+        let mut d0 = 0.0;
+        let mut d1 = 0.0;
+        for i in 0..dim {
+            let y_i = self.y[i].value();
+            let dy_i = self.dy[i].value();
+            let f0_i = f0[i].value();
+            let sci: f64 = self.atol + y_i.abs() * self.rtol;
+            let scdi: f64 = self.atol + dy_i.abs() * self.rtol;
+            d0 += (y_i / sci) * (y_i / sci) + (dy_i / scdi) * (dy_i / scdi);
+            d1 += (dy_i / sci) * (dy_i / sci) + (f0_i / scdi) * (f0_i / scdi);
+        }
+
+        // Compute h0
+        let mut h0 = if d0 < 1.0E-10 || d1 < 1.0E-10 {
+            1.0E-6
+        } else {
+            0.01 * (d0 / d1).sqrt()
+        };
+
+        h0 = h0.min(self.controller.h_max());
+        h0 = sign(h0, posneg);
+
+        let y1 = self.y + self.dy * na::convert(h0);
+        let dy1 = self.dy + f0 * na::convert(h0);
+        let mut f1 = V::zero();
+        (self.f)(self.x + h0, &y1, &mut f1);
+
+        // Compute the norm of the second derivative estimate
+        let mut d2: f64 = 0.0;
+        for i in 0..dim {
+            let y_i = self.y[i].value();
+            let dy_i = self.dy[i].value();
+            let dy1_i = dy1[i].value();
+            let f0_i = f0[i].value();
+            let f1_i = f1[i].value();
+            let sci: f64 = self.atol + y_i.abs() * self.rtol;
+            let scdi: f64 = self.atol + dy_i.abs() * self.rtol;
+            d2 += ((dy1_i - dy_i) / sci) * ((dy1_i - dy_i) / sci)
+                + ((f1_i - f0_i) / scdi) * ((f1_i - f0_i) / scdi);
+        }
+        d2 = d2.sqrt() / h0;
+
+        let h1 = if d1.sqrt().max(d2.abs()) <= 1.0E-15 {
+            1.0E-6_f64.max(h0.abs() * 1.0E-3)
+        } else {
+            (0.01 / (d1.sqrt().max(d2))).powf(1.0 / 6.0)
+        };
+
+        sign(
+            (100.0 * h0.abs()).min(h1.min(self.controller.h_max())),
+            posneg,
+        )
+    }
+
+    /// Set stop function will be called at every successful integration step.
+    pub fn set_solout(&mut self, solout: fn(f64, &V, &V) -> bool) {
+        self.solout = solout;
+    }
+
+    /// Core integration method.
+    pub fn integrate(&mut self) -> Result<Stats, IntegrationError> {
+        // Initialization
+        self.x_old = self.x;
+        let mut n_step = 0;
+        let mut last = false;
+        let mut h_new = 0.0;
+        let dim = na::dimension::<V>();
+        let posneg = sign(1.0, self.x_end - self.x);
+
+        if self.h == 0.0 {
+            self.h = self.hinit();
+            self.stats.num_eval += 2;
+        }
+        self.h_old = self.h;
+
+        // Save initial values
+        let (y_tmp, dy_tmp) = (self.y, self.dy);
+        self.solution_output(y_tmp, dy_tmp);
+
+        let mut k: Vec<V> = vec![V::zero(); 6];
+        (self.f)(self.x, &self.y, &mut k[0]);
+        self.stats.num_eval += 1;
+
+        // Main loop
+        while !last {
+            // Check if step number is within allowed range
+            if n_step >= self.n_max {
+                self.h_old = self.h;
+                return Err(IntegrationError::MaxNumStepReached { x: self.x, n_step });
+            }
+
+            // Check for step size underflow
+            if 0.1 * self.h.abs() <= self.uround * self.x.abs() {
+                self.h_old = self.h;
+                return Err(IntegrationError::StepSizeUnderflow { x: self.x });
+            }
+
+            // Check if it's the last iteration
+            if (self.x + 1.01 * self.h - self.x_end) * posneg > 0.0 {
+                self.h = self.x_end - self.x;
+                last = true;
+            }
+            n_step += 1;
+
+            // 5 Stages, the last one being evaluated at the new solution
+            let h2 = self.h * self.h;
+            let mut y_next = V::zero();
+            for s in 1..6 {
+                y_next = self.y + self.dy * na::convert(self.h * self.coeffs.c(s + 1));
+                for j in 0..s {
+                    y_next += k[j] * na::convert(h2 * self.coeffs.a(s + 1, j + 1));
+                }
+                (self.f)(self.x + self.h * self.coeffs.c(s + 1), &y_next, &mut k[s]);
+            }
+            self.stats.num_eval += 5;
+
+            let mut dy_next = self.dy;
+            let mut err_y = V::zero();
+            let mut err_dy = V::zero();
+            for i in 0..6 {
+                dy_next += k[i] * na::convert(self.h * self.coeffs.b(i + 1));
+                err_y += k[i] * na::convert(h2 * self.coeffs.ebar(i + 1));
+                err_dy += k[i] * na::convert(self.h * self.coeffs.e(i + 1));
+            }
+
+            // Compute error
+            let mut err = 0.0;
+            for i in 0..dim {
+                let y_i = self.y[i].value();
+                let y_next_i = y_next[i].value();
+                let sc_i: f64 = self.atol + y_i.abs().max(y_next_i.abs()) * self.rtol;
+                let err_i = err_y[i].value();
+                err += (err_i / sc_i) * (err_i / sc_i);
+
+                let dy_i = self.dy[i].value();
+                let dy_next_i = dy_next[i].value();
+                let sc_i: f64 = self.atol + dy_i.abs().max(dy_next_i.abs()) * self.rtol;
+                let err_i = err_dy[i].value();
+                err += (err_i / sc_i) * (err_i / sc_i);
+            }
+            err = (err / (2 * dim) as f64).sqrt();
+
+            // Step size control
+            if self.controller.accept(err, self.h, &mut h_new) {
+                self.stats.accepted_steps += 1;
+
+                // Prepare dense output (quintic Hermite interpolation)
+                if self.out_type == OutputType::Dense {
+                    let e = y_next
+                        - self.y
+                        - (self.dy + k[0] * na::convert(0.5 * self.h)) * na::convert(self.h);
+                    let f = (dy_next - self.dy - k[0] * na::convert(self.h)) * na::convert(self.h);
+                    let g = (k[5] - k[0]) * na::convert(h2);
+                    self.rcont[0] = self.y;
+                    self.rcont[1] = self.dy * na::convert(self.h);
+                    self.rcont[2] = k[0] * na::convert(0.5 * h2);
+                    self.rcont[3] =
+                        e * na::convert(10.0) - f * na::convert(4.0) + g * na::convert(0.5);
+                    self.rcont[4] = -e * na::convert(15.0) + f * na::convert(7.0) - g;
+                    self.rcont[5] =
+                        e * na::convert(6.0) - f * na::convert(3.0) + g * na::convert(0.5);
+                }
+
+                k[0] = k[5];
+                self.y = y_next;
+                self.dy = dy_next;
+                self.x_old = self.x;
+                self.x += self.h;
+                self.h_old = self.h;
+
+                self.solution_output(y_next, dy_next);
+
+                if (self.solout)(self.x, &self.y, &self.dy) {
+                    last = true;
+                }
+
+                // Normal exit
+                if last {
+                    self.h_old = posneg * h_new;
+                    return Ok(self.stats);
+                }
+            } else {
+                if self.stats.accepted_steps >= 1 {
+                    self.stats.rejected_steps += 1;
+                }
+                // The step reaching x_end was rejected
+                last = false;
+            }
+            self.h = h_new;
+        }
+        Ok(self.stats)
+    }
+
+    /// If a dense output is required, computes the solution and pushes it into the output vectors. Else, pushes the solution into the output vectors.
+    fn solution_output(&mut self, y_next: V, dy_next: V) {
+        if self.out_type == OutputType::Dense {
+            let posneg = sign(1.0, self.x_end - self.x0);
+            if self.x == self.x0 {
+                self.x_out.push(self.x0);
+                self.y_out.push(y_next);
+                self.dy_out.push(dy_next);
+                self.xd += self.dx;
+            } else {
+                while (self.xd - self.x) * posneg <= 0.0 {
+                    let theta = (self.xd - self.x_old) / self.h_old;
+                    self.x_out.push(self.xd);
+                    self.y_out.push(
+                        self.rcont[0]
+                            + (self.rcont[1]
+                                + (self.rcont[2]
+                                    + (self.rcont[3]
+                                        + (self.rcont[4] + self.rcont[5] * na::convert(theta))
+                                            * na::convert(theta))
+                                        * na::convert(theta))
+                                    * na::convert(theta))
+                                * na::convert(theta),
+                    );
+                    self.dy_out.push(
+                        (self.rcont[1]
+                            + (self.rcont[2] * na::convert(2.0)
+                                + (self.rcont[3] * na::convert(3.0)
+                                    + (self.rcont[4] * na::convert(4.0)
+                                        + self.rcont[5] * na::convert(5.0 * theta))
+                                        * na::convert(theta))
+                                    * na::convert(theta))
+                                * na::convert(theta))
+                            * na::convert(1.0 / self.h_old),
+                    );
+                    self.xd += self.dx;
+                }
+            }
+        } else {
+            self.x_out.push(self.x);
+            self.y_out.push(y_next);
+            self.dy_out.push(dy_next);
+        }
+    }
+
+    /// Getter for the independent variable's output.
+    pub fn x_out(&self) -> &Vec<f64> {
+        &self.x_out
+    }
+
+    /// Getter for the dependent variables' output.
+    pub fn y_out(&self) -> &Vec<V> {
+        &self.y_out
+    }
+
+    /// Getter for the output of the first derivative of the dependent variables.
+    pub fn dy_out(&self) -> &Vec<V> {
+        &self.dy_out
+    }
+}
+
+fn sign(a: f64, b: f64) -> f64 {
+    if b > 0.0 {
+        a.abs()
+    } else {
+        -a.abs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use na::{Vector1, Vector2};
+
+    fn oscillator(_x: f64, y: &Vector1<f64>, d2y: &mut Vector1<f64>) {
+        d2y[0] = -y[0];
+    }
+
+    fn kepler(_x: f64, y: &Vector2<f64>, d2y: &mut Vector2<f64>) {
+        let r3 = y.norm().powi(3);
+        *d2y = -y / r3;
+    }
+
+    // Errors in y and y' at x = 5 with n fixed steps
+    fn fixed_step_errors(n: usize) -> (f64, f64) {
+        let h = 5.0 / n as f64;
+        let mut stepper = Rkn64::from_param(
+            oscillator,
+            0.0,
+            5.0,
+            5.0,
+            Vector1::new(1.0),
+            Vector1::new(0.0),
+            1.0e3,
+            1.0e3,
+            0.9,
+            0.04,
+            0.2,
+            10.0,
+            h,
+            h,
+            100000,
+            OutputType::Sparse,
+        );
+        let stats = stepper.integrate().unwrap();
+        assert_eq!(stats.accepted_steps as usize, n);
+        (
+            (stepper.y_out().last().unwrap()[0] - 5.0_f64.cos()).abs(),
+            (stepper.dy_out().last().unwrap()[0] + 5.0_f64.sin()).abs(),
+        )
+    }
+
+    #[test]
+    fn convergence_order() {
+        let (e_y, e_dy) = fixed_step_errors(40);
+        let (e_y2, e_dy2) = fixed_step_errors(80);
+        assert!((e_y / e_y2).log2() > 5.5);
+        assert!((e_dy / e_dy2).log2() > 5.5);
+    }
+
+    // Position and velocity at x on the orbit of eccentricity 0.5 and period 2 pi starting at
+    // periapsis, from Kepler's equation
+    fn kepler_solution(x: f64) -> (Vector2<f64>, Vector2<f64>) {
+        let e = 0.5;
+        let mut ea = x;
+        for _ in 0..50 {
+            ea -= (ea - e * ea.sin() - x) / (1.0 - e * ea.cos());
+        }
+        let b = (1.0 - e * e).sqrt();
+        let dea = 1.0 / (1.0 - e * ea.cos());
+        (
+            Vector2::new(ea.cos() - e, b * ea.sin()),
+            Vector2::new(-ea.sin() * dea, b * ea.cos() * dea),
+        )
+    }
+
+    // Errors in y and y' at x = 3 with n fixed steps on the Kepler problem
+    fn kepler_errors(n: usize) -> (f64, f64) {
+        let h = 3.0 / n as f64;
+        let (y0, dy0) = kepler_solution(0.0);
+        let mut stepper = Rkn64::from_param(
+            kepler,
+            0.0,
+            3.0,
+            3.0,
+            y0,
+            dy0,
+            1.0e3,
+            1.0e3,
+            0.9,
+            0.04,
+            0.2,
+            10.0,
+            h,
+            h,
+            100000,
+            OutputType::Sparse,
+        );
+        let stats = stepper.integrate().unwrap();
+        assert_eq!(stats.accepted_steps as usize, n);
+        let (y, dy) = kepler_solution(3.0);
+        (
+            (stepper.y_out().last().unwrap() - y).norm(),
+            (stepper.dy_out().last().unwrap() - dy).norm(),
+        )
+    }
+
+    #[test]
+    fn convergence_order_kepler() {
+        // A nonlinear problem checks the order conditions that the linear oscillator does not
+        let (e_y, e_dy) = kepler_errors(100);
+        let (e_y2, e_dy2) = kepler_errors(200);
+        assert!((e_y / e_y2).log2() > 5.5);
+        assert!((e_dy / e_dy2).log2() > 5.5);
+    }
+
+    #[test]
+    fn kepler_orbit() {
+        // Orbit of eccentricity 0.5 and period 2 pi, starting at periapsis
+        let y0 = Vector2::new(0.5, 0.0);
+        let dy0 = Vector2::new(0.0, 3.0_f64.sqrt());
+        let period = 2.0 * std::f64::consts::PI;
+        let mut stepper = Rkn64::new(kepler, 0.0, period, 0.1, y0, dy0, 1.0e-10, 1.0e-10);
+        stepper.integrate().unwrap();
+        // Conservation of the energy along the dense output
+        for (y, dy) in stepper.y_out().iter().zip(stepper.dy_out().iter()) {
+            assert!((0.5 * dy.norm_squared() - 1.0 / y.norm() + 0.5).abs() < 1.0e-8);
+        }
+
+        let mut stepper = Rkn64::from_param(
+            kepler,
+            0.0,
+            period,
+            period,
+            y0,
+            dy0,
+            1.0e-10,
+            1.0e-10,
+            0.9,
+            0.04,
+            0.2,
+            10.0,
+            period,
+            0.0,
+            100000,
+            OutputType::Sparse,
+        );
+        stepper.integrate().unwrap();
+        assert!((stepper.y_out().last().unwrap() - y0).norm() < 1.0e-7);
+        assert!((stepper.dy_out().last().unwrap() - dy0).norm() < 1.0e-7);
+    }
+
+    #[test]
+    fn rejected_last_step() {
+        // The initial step reaches x_end and is rejected
+        let mut stepper = Rkn64::from_param(
+            oscillator,
+            0.0,
+            10.0,
+            1.0,
+            Vector1::new(1.0),
+            Vector1::new(0.0),
+            1.0e-10,
+            1.0e-10,
+            0.9,
+            0.04,
+            0.2,
+            10.0,
+            10.0,
+            10.0,
+            100000,
+            OutputType::Sparse,
+        );
+        stepper.integrate().unwrap();
+        assert_eq!(*stepper.x_out().last().unwrap(), 10.0);
+        assert!((stepper.y_out().last().unwrap()[0] - 10.0_f64.cos()).abs() < 1.0e-8);
+    }
+}
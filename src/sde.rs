@@ -0,0 +1,985 @@
+#![allow(clippy::needless_range_loop)]
+
+//! Numerical methods for stochastic differential equations _dy = f(x, y) dx + G(x, y) dW_ in the sense of Itô or Stratonovich.
+//!
+//! The Wiener process is sampled from a seeded random number generator and stored in a
+//! [`BrownianPath`](struct.BrownianPath.html). Values requested between two stored points are
+//! sampled from the Brownian bridge, so that a solution can be refined or integrated again with
+//! another method on the same path.
+
+use alga::general::SubsetOf;
+use alga::linear::{FiniteDimInnerSpace, InnerSpace};
+use dop_shared::*;
+use jacobian;
+use na;
+use na::{DMatrix, DVector, Matrix2};
+use rand::distributions::StandardNormal;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::f64;
+
+/// Interpretation of the stochastic integral.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Calculus {
+    Ito,
+    Stratonovich,
+}
+
+/// Diffusion term of the equation.
+pub enum Noise<V> {
+    /// Each component of the state is driven by its own Wiener process: dy_i = ... + g_i(x, y) dW_i.
+    /// The component g_i must depend on y_i only.
+    Diagonal(fn(f64, &V, &mut V)),
+    /// The state is driven by m Wiener processes through the n x m matrix G(x, y), given with m.
+    General(fn(f64, &V, &mut DMatrix<f64>), usize),
+}
+
+impl<V> Noise<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: SubsetOf<f64>,
+{
+    /// Number of Wiener processes.
+    fn dim(&self) -> usize {
+        match *self {
+            Noise::Diagonal(_) => na::dimension::<V>(),
+            Noise::General(_, m) => m,
+        }
+    }
+
+    /// Computes G(x, y) dw.
+    fn apply(&self, x: f64, y: &V, dw: &DVector<f64>) -> V {
+        match *self {
+            Noise::Diagonal(g) => {
+                let mut g_val = V::zero();
+                g(x, y, &mut g_val);
+                let mut res = V::zero();
+                for i in 0..na::dimension::<V>() {
+                    res[i] = g_val[i] * na::convert(dw[i]);
+                }
+                res
+            }
+            Noise::General(g, m) => {
+                let mut g_val = DMatrix::zeros(na::dimension::<V>(), m);
+                g(x, y, &mut g_val);
+                jacobian::from_dvector(&(g_val * dw))
+            }
+        }
+    }
+}
+
+/// Sampled path of a Wiener process W of dimension m, together with its integral Z = ∫ W dx which
+/// gives the iterated integrals needed by the methods of order 1.5.
+#[derive(Clone)]
+pub struct BrownianPath {
+    rng: StdRng,
+    x: Vec<f64>,
+    w: Vec<DVector<f64>>,
+    z: Vec<DVector<f64>>,
+}
+
+impl BrownianPath {
+    /// Creates a path of dimension `dim` starting at W(x0) = 0, sampled from a generator seeded with `seed`.
+    pub fn new(x0: f64, dim: usize, seed: u64) -> BrownianPath {
+        BrownianPath {
+            rng: StdRng::seed_from_u64(seed),
+            x: vec![x0],
+            w: vec![DVector::zeros(dim)],
+            z: vec![DVector::zeros(dim)],
+        }
+    }
+
+    /// Dimension of the Wiener process.
+    pub fn dim(&self) -> usize {
+        self.w[0].len()
+    }
+
+    /// Points at which the path has been sampled.
+    pub fn x(&self) -> &Vec<f64> {
+        &self.x
+    }
+
+    /// Value of the Wiener process at x, which is sampled if needed.
+    pub fn w(&mut self, x: f64) -> Result<DVector<f64>, IntegrationError> {
+        let i = self.sample(x)?;
+        Ok(self.w[i].clone())
+    }
+
+    /// Returns the increment W(b) - W(a) and the iterated integral ∫ (W(s) - W(a)) ds over [a, b].
+    pub fn increments(
+        &mut self,
+        a: f64,
+        b: f64,
+    ) -> Result<(DVector<f64>, DVector<f64>), IntegrationError> {
+        let ia = self.sample(a)?;
+        let ib = self.sample(b)?;
+        let dw = &self.w[ib] - &self.w[ia];
+        let i10 = &self.z[ib] - &self.z[ia] - &self.w[ia] * (b - a);
+        Ok((dw, i10))
+    }
+
+    /// Returns the index of the point x in the path, sampling it if needed.
+    fn sample(&mut self, x: f64) -> Result<usize, IntegrationError> {
+        let n = self.x.len();
+        let tol = 8.0 * f64::EPSILON * x.abs().max(1.0);
+        if x < self.x[0] - tol {
+            return Err(IntegrationError::BeforeInitialPoint { x });
+        }
+
+        // Position of x among the sampled points
+        let mut lo = 0;
+        let mut hi = n;
+        while hi - lo > 1 {
+            let mid = (lo + hi) / 2;
+            if x >= self.x[mid] {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        if (x - self.x[lo]).abs() <= tol {
+            return Ok(lo);
+        }
+        if hi < n && (self.x[hi] - x).abs() <= tol {
+            return Ok(hi);
+        }
+        if hi == n {
+            // Extend the path: independent increments of W and of its integral
+            let h = x - self.x[n - 1];
+            let dim = self.dim();
+            let mut w = self.w[n - 1].clone();
+            let mut z = &self.z[n - 1] + &self.w[n - 1] * h;
+            for k in 0..dim {
+                let xi1: f64 = self.rng.sample(StandardNormal);
+                let xi2: f64 = self.rng.sample(StandardNormal);
+                let dw = h.sqrt() * xi1;
+                w[k] += dw;
+                z[k] += 0.5 * h * dw + (h.powi(3) / 12.0).sqrt() * xi2;
+            }
+            self.x.push(x);
+            self.w.push(w);
+            self.z.push(z);
+            return Ok(n);
+        }
+
+        // Brownian bridge between the points lo and hi. With w and y the increments of W and of
+        // its integral relative to the point lo, (w(s), y(s)) given (w(h), y(h)) is Gaussian.
+        let h = self.x[hi] - self.x[lo];
+        let s = x - self.x[lo];
+        let cov_h_inv = Matrix2::new(4.0 / h, -6.0 / (h * h), -6.0 / (h * h), 12.0 / (h * h * h));
+        let cov_s = Matrix2::new(s, 0.5 * s * s, 0.5 * s * s, s * s * s / 3.0);
+        let cov_sh = Matrix2::new(
+            s,
+            s * h - 0.5 * s * s,
+            0.5 * s * s,
+            0.5 * s * s * h - s * s * s / 6.0,
+        );
+        let k = cov_sh * cov_h_inv;
+        let cov = cov_s - k * cov_sh.transpose();
+        let l11 = cov[(0, 0)].max(0.0).sqrt();
+        let l21 = if l11 > 0.0 { cov[(1, 0)] / l11 } else { 0.0 };
+        let l22 = (cov[(1, 1)] - l21 * l21).max(0.0).sqrt();
+
+        let dim = self.dim();
+        let mut w = self.w[lo].clone();
+        let mut z = &self.z[lo] + &self.w[lo] * s;
+        for j in 0..dim {
+            let w_h = self.w[hi][j] - self.w[lo][j];
+            let y_h = self.z[hi][j] - self.z[lo][j] - h * self.w[lo][j];
+            let xi1: f64 = self.rng.sample(StandardNormal);
+            let xi2: f64 = self.rng.sample(StandardNormal);
+            w[j] += k[(0, 0)] * w_h + k[(0, 1)] * y_h + l11 * xi1;
+            z[j] += k[(1, 0)] * w_h + k[(1, 1)] * y_h + l21 * xi1 + l22 * xi2;
+        }
+        self.x.insert(hi, x);
+        self.w.insert(hi, w);
+        self.z.insert(hi, z);
+        Ok(hi)
+    }
+}
+
+/// Euler-Maruyama method with fixed step size, of strong order 0.5. Stratonovich equations are
+/// integrated with the Euler-Heun method.
+pub struct EulerMaruyama<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+{
+    f: fn(f64, &V, &mut V),
+    g: Noise<V>,
+    calculus: Calculus,
+    x: f64,
+    x_end: f64,
+    h: f64,
+    y: V,
+    path: BrownianPath,
+    x_out: Vec<f64>,
+    y_out: Vec<V>,
+    stats: Stats,
+}
+
+impl<V> EulerMaruyama<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: SubsetOf<f64>,
+{
+    /// Default initializer for the structure.
+    ///
+    /// # Arguments
+    ///
+    /// * `f`       - Pointer to the drift function
+    /// * `g`       - Diffusion term
+    /// * `x`       - Initial value of the independent variable (usually time)
+    /// * `x_end`   - Final value of the independent variable
+    /// * `h`       - Step size
+    /// * `y`       - Initial value of the dependent variable(s)
+    /// * `seed`    - Seed of the random number generator sampling the Wiener process
+    ///
+    pub fn new(
+        f: fn(f64, &V, &mut V),
+        g: Noise<V>,
+        x: f64,
+        x_end: f64,
+        h: f64,
+        y: V,
+        seed: u64,
+    ) -> EulerMaruyama<V> {
+        let path = BrownianPath::new(x, g.dim(), seed);
+        EulerMaruyama {
+            f,
+            g,
+            calculus: Calculus::Ito,
+            x,
+            x_end,
+            h,
+            y,
+            path,
+            x_out: Vec::<f64>::new(),
+            y_out: Vec::<V>::new(),
+            stats: Stats::new(),
+        }
+    }
+
+    /// Sets the interpretation of the stochastic integral. Default is Itô.
+    pub fn set_calculus(&mut self, calculus: Calculus) {
+        self.calculus = calculus;
+    }
+
+    /// Sets the path of the Wiener process, for instance to integrate again on the path of a previous run.
+    pub fn set_path(&mut self, path: BrownianPath) {
+        self.path = path;
+    }
+
+    /// Core integration method.
+    pub fn integrate(&mut self) -> Result<Stats, IntegrationError> {
+        let posneg = sign(1.0, self.x_end - self.x);
+        let h = sign(self.h, posneg);
+        let mut f_val = V::zero();
+        self.x_out.push(self.x);
+        self.y_out.push(self.y);
+
+        while (self.x_end - self.x) * posneg > 0.0 {
+            let step = if (self.x + 1.01 * h - self.x_end) * posneg > 0.0 {
+                self.x_end - self.x
+            } else {
+                h
+            };
+            let x_new = if step == h { self.x + h } else { self.x_end };
+            let (dw, _) = self.path.increments(self.x, x_new)?;
+
+            (self.f)(self.x, &self.y, &mut f_val);
+            let noise = self.g.apply(self.x, &self.y, &dw);
+            let mut y_new = self.y + f_val * na::convert(step) + noise;
+            self.stats.num_eval += 2;
+            if self.calculus == Calculus::Stratonovich {
+                let noise_pred = self.g.apply(x_new, &y_new, &dw);
+                y_new = self.y + f_val * na::convert(step) + (noise + noise_pred) * na::convert(0.5);
+                self.stats.num_eval += 1;
+            }
+
+            self.stats.accepted_steps += 1;
+            self.x = x_new;
+            self.y = y_new;
+            self.x_out.push(self.x);
+            self.y_out.push(self.y);
+        }
+        Ok(self.stats)
+    }
+
+    /// Getter for the independent variable's output.
+    pub fn x_out(&self) -> &Vec<f64> {
+        &self.x_out
+    }
+
+    /// Getter for the dependent variables' output.
+    pub fn y_out(&self) -> &Vec<V> {
+        &self.y_out
+    }
+
+    /// Getter for the path of the Wiener process.
+    pub fn path(&self) -> &BrownianPath {
+        &self.path
+    }
+}
+
+/// Derivative free Milstein method with fixed step size, of strong order 1. For general noise,
+/// the noise is assumed to be commutative.
+pub struct Milstein<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+{
+    f: fn(f64, &V, &mut V),
+    g: Noise<V>,
+    calculus: Calculus,
+    x: f64,
+    x_end: f64,
+    h: f64,
+    y: V,
+    path: BrownianPath,
+    x_out: Vec<f64>,
+    y_out: Vec<V>,
+    stats: Stats,
+}
+
+impl<V> Milstein<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: SubsetOf<f64>,
+{
+    /// Default initializer for the structure.
+    ///
+    /// # Arguments
+    ///
+    /// * `f`       - Pointer to the drift function
+    /// * `g`       - Diffusion term
+    /// * `x`       - Initial value of the independent variable (usually time)
+    /// * `x_end`   - Final value of the independent variable
+    /// * `h`       - Step size
+    /// * `y`       - Initial value of the dependent variable(s)
+    /// * `seed`    - Seed of the random number generator sampling the Wiener process
+    ///
+    pub fn new(
+        f: fn(f64, &V, &mut V),
+        g: Noise<V>,
+        x: f64,
+        x_end: f64,
+        h: f64,
+        y: V,
+        seed: u64,
+    ) -> Milstein<V> {
+        let path = BrownianPath::new(x, g.dim(), seed);
+        Milstein {
+            f,
+            g,
+            calculus: Calculus::Ito,
+            x,
+            x_end,
+            h,
+            y,
+            path,
+            x_out: Vec::<f64>::new(),
+            y_out: Vec::<V>::new(),
+            stats: Stats::new(),
+        }
+    }
+
+    /// Sets the interpretation of the stochastic integral. Default is Itô.
+    pub fn set_calculus(&mut self, calculus: Calculus) {
+        self.calculus = calculus;
+    }
+
+    /// Sets the path of the Wiener process, for instance to integrate again on the path of a previous run.
+    pub fn set_path(&mut self, path: BrownianPath) {
+        self.path = path;
+    }
+
+    /// Core integration method.
+    pub fn integrate(&mut self) -> Result<Stats, IntegrationError> {
+        let dim = na::dimension::<V>();
+        let posneg = sign(1.0, self.x_end - self.x);
+        let h = sign(self.h, posneg);
+        let ito = if self.calculus == Calculus::Ito { 1.0 } else { 0.0 };
+        let mut f_val = V::zero();
+        self.x_out.push(self.x);
+        self.y_out.push(self.y);
+
+        while (self.x_end - self.x) * posneg > 0.0 {
+            let step = if (self.x + 1.01 * h - self.x_end) * posneg > 0.0 {
+                self.x_end - self.x
+            } else {
+                h
+            };
+            let x_new = if step == h { self.x + h } else { self.x_end };
+            let sqrt_h = step.abs().sqrt();
+            let (dw, _) = self.path.increments(self.x, x_new)?;
+
+            (self.f)(self.x, &self.y, &mut f_val);
+            self.stats.num_eval += 1;
+            let y_drift = self.y + f_val * na::convert(step);
+            let mut y_new = y_drift;
+            match self.g {
+                Noise::Diagonal(g) => {
+                    // Supporting value y + f h + g sqrt(h) approximating the derivative of g. In
+                    // the sense of Stratonovich, the correction term does not have a zero mean
+                    // and central differences around y are used so that their error does not
+                    // accumulate.
+                    let mut g_val = V::zero();
+                    let mut g_sup = V::zero();
+                    g(self.x, &self.y, &mut g_val);
+                    let mut g_low = g_val;
+                    let shift = g_val * na::convert(sqrt_h);
+                    let width = if self.calculus == Calculus::Ito {
+                        g(self.x, &(y_drift + shift), &mut g_sup);
+                        self.stats.num_eval += 2;
+                        sqrt_h
+                    } else {
+                        g(self.x, &(self.y + shift), &mut g_sup);
+                        g(self.x, &(self.y - shift), &mut g_low);
+                        self.stats.num_eval += 3;
+                        2.0 * sqrt_h
+                    };
+                    for i in 0..dim {
+                        let g_i: f64 = na::convert(g_val[i]);
+                        let dg_i: f64 = na::convert(g_sup[i] - g_low[i]);
+                        let y_i: f64 = na::convert(y_new[i]);
+                        y_new[i] = na::convert(
+                            y_i + g_i * dw[i]
+                                + 0.5 * dg_i / width * (dw[i] * dw[i] - ito * step.abs()),
+                        );
+                    }
+                }
+                Noise::General(g, m) => {
+                    let mut g_val = DMatrix::zeros(dim, m);
+                    let mut g_sup = DMatrix::zeros(dim, m);
+                    g(self.x, &self.y, &mut g_val);
+                    self.stats.num_eval += 1;
+                    let mut g_low = g_val.clone();
+                    let mut incr = &g_val * &dw;
+                    for j in 0..m {
+                        // Supporting values in the direction of the j-th Wiener process
+                        let shift = jacobian::from_dvector::<V>(&g_val.column(j).into_owned())
+                            * na::convert(sqrt_h);
+                        let width = if self.calculus == Calculus::Ito {
+                            g(self.x, &(y_drift + shift), &mut g_sup);
+                            self.stats.num_eval += 1;
+                            sqrt_h
+                        } else {
+                            g(self.x, &(self.y + shift), &mut g_sup);
+                            g(self.x, &(self.y - shift), &mut g_low);
+                            self.stats.num_eval += 2;
+                            2.0 * sqrt_h
+                        };
+                        let mut iterated = &dw * (0.5 * dw[j]);
+                        iterated[j] -= 0.5 * ito * step.abs();
+                        incr += (&g_sup - &g_low) * iterated / width;
+                    }
+                    y_new += jacobian::from_dvector::<V>(&incr);
+                }
+            }
+
+            self.stats.accepted_steps += 1;
+            self.x = x_new;
+            self.y = y_new;
+            self.x_out.push(self.x);
+            self.y_out.push(self.y);
+        }
+        Ok(self.stats)
+    }
+
+    /// Getter for the independent variable's output.
+    pub fn x_out(&self) -> &Vec<f64> {
+        &self.x_out
+    }
+
+    /// Getter for the dependent variables' output.
+    pub fn y_out(&self) -> &Vec<V> {
+        &self.y_out
+    }
+
+    /// Getter for the path of the Wiener process.
+    pub fn path(&self) -> &BrownianPath {
+        &self.path
+    }
+}
+
+/// Adaptive stochastic Runge-Kutta method of strong order 1.5 of Rößler. Diagonal noise is
+/// integrated with the method SRIW1. General noise must be additive, i.e. G must not depend on y,
+/// and is integrated with the method SRA1. Rejected steps are retried on the same path of the
+/// Wiener process.
+pub struct Srk<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+{
+    f: fn(f64, &V, &mut V),
+    g: Noise<V>,
+    calculus: Calculus,
+    x: f64,
+    x0: f64,
+    x_end: f64,
+    xd: f64,
+    dx: f64,
+    y: V,
+    rtol: f64,
+    atol: f64,
+    path: BrownianPath,
+    x_out: Vec<f64>,
+    y_out: Vec<V>,
+    uround: f64,
+    h: f64,
+    h_max: f64,
+    fac_min: f64,
+    fac_max: f64,
+    safety_factor: f64,
+    n_max: u32,
+    out_type: OutputType,
+    stats: Stats,
+}
+
+impl<V> Srk<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: SubsetOf<f64>,
+{
+    /// Default initializer for the structure.
+    ///
+    /// # Arguments
+    ///
+    /// * `f`       - Pointer to the drift function
+    /// * `g`       - Diffusion term
+    /// * `x`       - Initial value of the independent variable (usually time)
+    /// * `x_end`   - Final value of the independent variable
+    /// * `dx`      - Increment in the dense output, which is hit by the steps. This argument has no effect if the output type is Sparse
+    /// * `y`       - Initial value of the dependent variable(s)
+    /// * `rtol`    - Relative tolerance used in the computation of the adaptive step size
+    /// * `atol`    - Absolute tolerance used in the computation of the adaptive step size
+    /// * `seed`    - Seed of the random number generator sampling the Wiener process
+    ///
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        f: fn(f64, &V, &mut V),
+        g: Noise<V>,
+        x: f64,
+        x_end: f64,
+        dx: f64,
+        y: V,
+        rtol: f64,
+        atol: f64,
+        seed: u64,
+    ) -> Srk<V> {
+        Srk::from_param(
+            f,
+            g,
+            x,
+            x_end,
+            dx,
+            y,
+            rtol,
+            atol,
+            seed,
+            0.9,
+            0.2,
+            5.0,
+            x_end - x,
+            0.0,
+            100000,
+            OutputType::Dense,
+        )
+    }
+
+    /// Advanced initializer for the structure.
+    ///
+    /// # Arguments
+    ///
+    /// * `f`       - Pointer to the drift function
+    /// * `g`       - Diffusion term
+    /// * `x`       - Initial value of the independent variable (usually time)
+    /// * `x_end`   - Final value of the independent variable
+    /// * `dx`      - Increment in the dense output, which is hit by the steps. This argument has no effect if the output type is Sparse
+    /// * `y`       - Initial value of the dependent variable(s)
+    /// * `rtol`    - Relative tolerance used in the computation of the adaptive step size
+    /// * `atol`    - Absolute tolerance used in the computation of the adaptive step size
+    /// * `seed`    - Seed of the random number generator sampling the Wiener process
+    /// * `safety_factor`   - Safety factor used in the computation of the adaptive step size. Default is 0.9
+    /// * `fac_min` - Minimum factor between two successive steps. Default is 0.2
+    /// * `fac_max` - Maximum factor between two successive steps. Default is 5.0
+    /// * `h_max`   - Maximum step size. Default is `x_end-x`
+    /// * `h`       - Initial value of the step size. If h = 0.0, the intial value of h is computed automatically
+    /// * `n_max`   - Maximum number of iterations. Default is 100000
+    /// * `out_type`    - Type of the output. Must be a variant of the OutputType enum. Default is Dense
+    ///
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_param(
+        f: fn(f64, &V, &mut V),
+        g: Noise<V>,
+        x: f64,
+        x_end: f64,
+        dx: f64,
+        y: V,
+        rtol: f64,
+        atol: f64,
+        seed: u64,
+        safety_factor: f64,
+        fac_min: f64,
+        fac_max: f64,
+        h_max: f64,
+        h: f64,
+        n_max: u32,
+        out_type: OutputType,
+    ) -> Srk<V> {
+        let path = BrownianPath::new(x, g.dim(), seed);
+        Srk {
+            f,
+            g,
+            calculus: Calculus::Ito,
+            x,
+            x0: x,
+            x_end,
+            xd: x,
+            dx,
+            y,
+            rtol,
+            atol,
+            path,
+            x_out: Vec::<f64>::new(),
+            y_out: Vec::<V>::new(),
+            uround: f64::EPSILON,
+            h,
+            h_max: h_max.abs(),
+            fac_min,
+            fac_max,
+            safety_factor,
+            n_max,
+            out_type,
+            stats: Stats::new(),
+        }
+    }
+
+    /// Sets the interpretation of the stochastic integral. Default is Itô.
+    pub fn set_calculus(&mut self, calculus: Calculus) {
+        self.calculus = calculus;
+    }
+
+    /// Sets the path of the Wiener process, for instance to integrate again on the path of a previous run.
+    pub fn set_path(&mut self, path: BrownianPath) {
+        self.path = path;
+    }
+
+    /// Core integration method.
+    pub fn integrate(&mut self) -> Result<Stats, IntegrationError> {
+        let dim = na::dimension::<V>();
+        let posneg = sign(1.0, self.x_end - self.x);
+        let mut n_step = 0;
+        if self.h == 0.0 {
+            self.h = 1.0e-2 * (self.x_end - self.x).abs().min(self.h_max);
+        }
+        self.h = sign(self.h.abs().min(self.h_max), posneg);
+
+        // Save initial values
+        self.x_out.push(self.x);
+        self.y_out.push(self.y);
+        self.xd += self.dx;
+
+        while (self.x_end - self.x) * posneg > 0.0 {
+            if n_step >= self.n_max {
+                return Err(IntegrationError::MaxNumStepReached { x: self.x, n_step });
+            }
+            if 0.1 * self.h.abs() <= self.uround * self.x.abs() {
+                return Err(IntegrationError::StepSizeUnderflow { x: self.x });
+            }
+            n_step += 1;
+
+            // The steps hit the output points and the final point
+            let mut x_new = self.x + self.h;
+            let mut dense = false;
+            if self.out_type == OutputType::Dense && (x_new - self.xd) * posneg >= 0.0 {
+                x_new = self.xd;
+                dense = true;
+            }
+            if (x_new - self.x_end) * posneg >= 0.0 {
+                x_new = self.x_end;
+            }
+            let h = x_new - self.x;
+
+            let (y_new, err_est) = match self.g {
+                Noise::Diagonal(_) => self.sriw1(x_new)?,
+                Noise::General(_, _) => self.sra1(x_new)?,
+            };
+
+            // Compute error
+            let mut err = 0.0;
+            for i in 0..dim {
+                let y_i: f64 = na::convert(self.y[i]);
+                let y_new_i: f64 = na::convert(y_new[i]);
+                let sc_i = self.atol + y_i.abs().max(y_new_i.abs()) * self.rtol;
+                let err_i: f64 = na::convert(err_est[i]);
+                err += (err_i / sc_i) * (err_i / sc_i);
+            }
+            err = (err / dim as f64).sqrt();
+
+            // The local error behaves as h^1.5 for the noise terms
+            let fac = self
+                .fac_max
+                .min(self.fac_min.max(self.safety_factor * err.powf(-1.0 / 1.5)));
+            if err <= 1.0 {
+                self.stats.accepted_steps += 1;
+                self.x = x_new;
+                self.y = y_new;
+                if self.out_type == OutputType::Sparse || dense || self.x == self.x_end {
+                    self.x_out.push(self.x);
+                    self.y_out.push(self.y);
+                }
+                if dense {
+                    self.xd = self.x0 + (self.x_out.len() as f64) * self.dx;
+                }
+                // Do not let a step shortened by an output point reduce the next step
+                let h_ref = if dense { self.h.abs().max(h.abs()) } else { h.abs() };
+                self.h = sign((h_ref * fac).min(self.h_max), posneg);
+            } else {
+                if self.stats.accepted_steps >= 1 {
+                    self.stats.rejected_steps += 1;
+                }
+                self.h = h * fac.min(1.0);
+            }
+        }
+        Ok(self.stats)
+    }
+
+    /// Drift of the equation in the sense of Itô.
+    fn drift(&mut self, x: f64, y: &V) -> V {
+        let mut f_val = V::zero();
+        (self.f)(x, y, &mut f_val);
+        self.stats.num_eval += 1;
+        if let (Calculus::Stratonovich, Noise::Diagonal(g)) = (self.calculus, &self.g) {
+            // Drift correction 1/2 g dg/dy, the derivative being computed by forward differences
+            let dim = na::dimension::<V>();
+            let mut g_val = V::zero();
+            let mut g_pert = V::zero();
+            let mut y_pert = *y;
+            g(x, y, &mut g_val);
+            for i in 0..dim {
+                let y_i: f64 = na::convert(y[i]);
+                y_pert[i] = na::convert(y_i + (f64::EPSILON * 1.0e-5_f64.max(y_i.abs())).sqrt());
+            }
+            g(x, &y_pert, &mut g_pert);
+            self.stats.num_eval += 2;
+            for i in 0..dim {
+                let delta: f64 = na::convert(y_pert[i] - y[i]);
+                let g_i: f64 = na::convert(g_val[i]);
+                let dg_i: f64 = na::convert(g_pert[i] - g_val[i]);
+                let f_i: f64 = na::convert(f_val[i]);
+                f_val[i] = na::convert(f_i + 0.5 * g_i * dg_i / delta);
+            }
+        }
+        f_val
+    }
+
+    /// Step of the method SRIW1 for diagonal noise. Returns the solution and the error estimate.
+    fn sriw1(&mut self, x_new: f64) -> Result<(V, V), IntegrationError> {
+        let dim = na::dimension::<V>();
+        let g = match self.g {
+            Noise::Diagonal(g) => g,
+            Noise::General(_, _) => unreachable!(),
+        };
+        let x = self.x;
+        let h = x_new - x;
+        let sqrt_h = h.abs().sqrt();
+        let (dw, i10) = self.path.increments(x, x_new)?;
+
+        // Iterated integrals scaled by the step size
+        let mut chi1 = V::zero();
+        let mut chi2 = V::zero();
+        let mut chi3 = V::zero();
+        let mut dw_v = V::zero();
+        for i in 0..dim {
+            dw_v[i] = na::convert(dw[i]);
+            chi1[i] = na::convert((dw[i] * dw[i] - h.abs()) / (2.0 * sqrt_h));
+            chi2[i] = na::convert(i10[i] / h);
+            chi3[i] = na::convert((dw[i].powi(3) - 3.0 * h.abs() * dw[i]) / (6.0 * h));
+        }
+        let mul = |a: &V, b: &V| {
+            let mut c = V::zero();
+            for i in 0..dim {
+                c[i] = a[i] * b[i];
+            }
+            c
+        };
+
+        let y = self.y;
+        let f1 = self.drift(x, &y) * na::convert(h);
+        let mut g1 = V::zero();
+        g(x, &self.y, &mut g1);
+        let h0 = self.y + f1 * na::convert(0.75) + mul(&g1, &chi2) * na::convert(1.5);
+        let h11 = self.y + f1 * na::convert(0.25) + g1 * na::convert(0.5 * sqrt_h);
+        let h12 = self.y + f1 - g1 * na::convert(sqrt_h);
+        let f2 = self.drift(x + 0.75 * h, &h0) * na::convert(h);
+        let mut g2 = V::zero();
+        let mut g3 = V::zero();
+        let mut g4 = V::zero();
+        g(x + 0.25 * h, &h11, &mut g2);
+        g(x + h, &h12, &mut g3);
+        let h13 = self.y
+            + f1 * na::convert(0.25)
+            + (g1 * na::convert(-5.0) + g2 * na::convert(3.0) + g3 * na::convert(0.5))
+                * na::convert(sqrt_h);
+        g(x + 0.25 * h, &h13, &mut g4);
+        self.stats.num_eval += 4;
+
+        let e_noise = mul(
+            &chi2,
+            &(g1 * na::convert(2.0) - g2 * na::convert(4.0 / 3.0) - g3 * na::convert(2.0 / 3.0)),
+        ) + mul(
+            &chi3,
+            &(g1 * na::convert(-2.0) + g2 * na::convert(5.0 / 3.0) - g3 * na::convert(2.0 / 3.0)
+                + g4),
+        );
+        let y_new = self.y
+            + (f1 + f2 * na::convert(2.0)) * na::convert(1.0 / 3.0)
+            + mul(&dw_v, &(-g1 + g2 * na::convert(4.0 / 3.0) + g3 * na::convert(2.0 / 3.0)))
+            + mul(&chi1, &(-g1 + g2 * na::convert(4.0 / 3.0) - g3 * na::convert(1.0 / 3.0)))
+            + e_noise;
+        let e_drift = (f2 - f1) * na::convert(2.0 / 3.0);
+        Ok((y_new, error_estimate(&e_drift, &e_noise)))
+    }
+
+    /// Step of the method SRA1 for additive noise. Returns the solution and the error estimate.
+    fn sra1(&mut self, x_new: f64) -> Result<(V, V), IntegrationError> {
+        let x = self.x;
+        let h = x_new - x;
+        let (dw, i10) = self.path.increments(x, x_new)?;
+        let chi2 = i10 / h;
+
+        let y = self.y;
+        let f1 = self.drift(x, &y) * na::convert(h);
+        let g_chi_end = self.g.apply(x_new, &self.y, &chi2);
+        let h0 = self.y + f1 * na::convert(0.75) + g_chi_end * na::convert(1.5);
+        let f2 = self.drift(x + 0.75 * h, &h0) * na::convert(h);
+        let g_dw_end = self.g.apply(x_new, &self.y, &dw);
+        let g_chi_start = self.g.apply(x, &self.y, &chi2);
+        self.stats.num_eval += 3;
+
+        let e_noise = g_chi_start - g_chi_end;
+        let y_new =
+            self.y + (f1 + f2 * na::convert(2.0)) * na::convert(1.0 / 3.0) + g_dw_end + e_noise;
+        let e_drift = (f2 - f1) * na::convert(2.0 / 3.0);
+        Ok((y_new, error_estimate(&e_drift, &e_noise)))
+    }
+
+    /// Getter for the independent variable's output.
+    pub fn x_out(&self) -> &Vec<f64> {
+        &self.x_out
+    }
+
+    /// Getter for the dependent variables' output.
+    pub fn y_out(&self) -> &Vec<V> {
+        &self.y_out
+    }
+
+    /// Getter for the path of the Wiener process.
+    pub fn path(&self) -> &BrownianPath {
+        &self.path
+    }
+}
+
+/// Combines the drift and the noise parts of the local error estimate, component by component.
+fn error_estimate<V>(e_drift: &V, e_noise: &V) -> V
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: SubsetOf<f64>,
+{
+    let mut err = V::zero();
+    for i in 0..na::dimension::<V>() {
+        let d: f64 = na::convert(e_drift[i]);
+        let n: f64 = na::convert(e_noise[i]);
+        err[i] = na::convert(d.abs() + n.abs());
+    }
+    err
+}
+
+fn sign(a: f64, b: f64) -> f64 {
+    if b > 0.0 {
+        a.abs()
+    } else {
+        -a.abs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use na::Vector1;
+
+    const MU: f64 = 0.5;
+    const SIGMA: f64 = 0.5;
+
+    fn drift(_x: f64, y: &Vector1<f64>, dy: &mut Vector1<f64>) {
+        dy[0] = MU * y[0];
+    }
+
+    fn diffusion(_x: f64, y: &Vector1<f64>, g: &mut Vector1<f64>) {
+        g[0] = SIGMA * y[0];
+    }
+
+    #[test]
+    fn weak_moments_of_geometric_brownian_motion() {
+        // E[y(1)] = exp(mu) and E[y(1)^2] = exp(2 mu + sigma^2)
+        let runs = 4000;
+        let y0 = Vector1::new(1.0);
+        let (mut m1, mut m2) = (0.0, 0.0);
+        for seed in 0..runs {
+            let g = Noise::Diagonal(diffusion);
+            let mut em = EulerMaruyama::new(drift, g, 0.0, 1.0, 0.01, y0, seed);
+            em.integrate().unwrap();
+            let y = em.y_out().last().unwrap()[0];
+            m1 += y / runs as f64;
+            m2 += y * y / runs as f64;
+        }
+        assert!((m1 - MU.exp()).abs() < 0.03);
+        assert!((m2 - (2.0 * MU + SIGMA * SIGMA).exp()).abs() < 0.15);
+    }
+
+    #[test]
+    fn strong_convergence_on_a_path() {
+        // On a given path, y(1) = exp((mu - sigma^2 / 2) + sigma W(1)) in the sense of Itô and
+        // y(1) = exp(mu + sigma W(1)) in the sense of Stratonovich
+        let y0 = Vector1::new(1.0);
+        for &calculus in &[Calculus::Ito, Calculus::Stratonovich] {
+            let mut path = BrownianPath::new(0.0, 1, 7);
+            let correction = match calculus {
+                Calculus::Ito => -0.5 * SIGMA * SIGMA,
+                Calculus::Stratonovich => 0.0,
+            };
+            let exact = (MU + correction + SIGMA * path.w(1.0).unwrap()[0]).exp();
+
+            let g = Noise::Diagonal(diffusion);
+            let mut em = EulerMaruyama::new(drift, g, 0.0, 1.0, 1e-4, y0, 0);
+            em.set_calculus(calculus);
+            em.set_path(path.clone());
+            em.integrate().unwrap();
+            assert!((em.y_out().last().unwrap()[0] - exact).abs() < 2e-2);
+
+            let g = Noise::Diagonal(diffusion);
+            let mut milstein = Milstein::new(drift, g, 0.0, 1.0, 1e-4, y0, 0);
+            milstein.set_calculus(calculus);
+            milstein.set_path(path.clone());
+            milstein.integrate().unwrap();
+            assert!((milstein.y_out().last().unwrap()[0] - exact).abs() < 1e-4);
+
+            let g = Noise::Diagonal(diffusion);
+            let mut srk = Srk::new(drift, g, 0.0, 1.0, 1.0, y0, 1e-6, 1e-6, 0);
+            srk.set_calculus(calculus);
+            srk.set_path(path.clone());
+            srk.integrate().unwrap();
+            assert_eq!(*srk.x_out().last().unwrap(), 1.0);
+            assert!((srk.y_out().last().unwrap()[0] - exact).abs() < 1e-4);
+        }
+    }
+    #[test]
+    fn path_starting_after_the_initial_point() {
+        let y0 = Vector1::new(1.0);
+        let mut em = EulerMaruyama::new(drift, Noise::Diagonal(diffusion), 0.0, 1.0, 0.1, y0, 0);
+        em.set_path(BrownianPath::new(0.5, 1, 7));
+        match em.integrate() {
+            Err(IntegrationError::BeforeInitialPoint { x }) => assert_eq!(x, 0.0),
+            _ => panic!("the path must not be sampled before its initial point"),
+        }
+    }
+}
@@ -0,0 +1,459 @@
+#![allow(clippy::needless_range_loop)]
+
+//! Forward sensitivity analysis.
+//!
+//! The sensitivities _s<sub>j</sub> = ∂y/∂p<sub>j</sub>_ of the solution with respect to parameters
+//! or initial values satisfy the linear equations _s<sub>j</sub>' = J(x, y) s<sub>j</sub> + ∂f/∂p<sub>j</sub>_,
+//! where J is the Jacobian matrix of f with respect to y. The explicit steppers Dopri5 and Dop853
+//! integrate them together with the state, using the same Runge-Kutta stages, when a
+//! [`Sensitivity`](struct.Sensitivity.html) is given with `set_sensitivity`.
+
+use alga::general::SubsetOf;
+use alga::linear::{FiniteDimInnerSpace, InnerSpace};
+use dop_shared::Value;
+use jacobian;
+use na;
+use na::{DMatrix, DVector};
+
+/// Function computing a matrix of derivatives of f at (x, y), for a system with or without
+/// parameters.
+#[derive(Clone, Copy)]
+pub(crate) enum Derivative<V> {
+    Plain(fn(f64, &V, &mut DMatrix<f64>)),
+    Parametric(fn(f64, &V, &DVector<f64>, &mut DMatrix<f64>)),
+}
+
+impl<V> Derivative<V> {
+    pub(crate) fn eval(&self, x: f64, y: &V, p: &DVector<f64>, m: &mut DMatrix<f64>) {
+        match self {
+            Derivative::Plain(d) => d(x, y, m),
+            Derivative::Parametric(d) => d(x, y, p, m),
+        }
+    }
+}
+
+/// Description of the sensitivities to integrate along with the solution.
+///
+/// The sensitivities are stored as columns, one state per column: first the sensitivities with
+/// respect to the n<sub>p</sub> parameters, then, if requested, the sensitivities with respect to
+/// each component of the initial value.
+pub struct Sensitivity<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+{
+    n_p: usize,
+    p: DVector<f64>,
+    dfdp: Option<Derivative<V>>,
+    jac: Option<Derivative<V>>,
+    initial_conditions: bool,
+    s0: Vec<V>,
+    error_control: bool,
+    jac_mat: DMatrix<f64>,
+    dfdp_mat: DMatrix<f64>,
+}
+
+impl<V> Sensitivity<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: SubsetOf<f64>,
+{
+    /// Sensitivities with respect to `n_p` parameters.
+    ///
+    /// # Arguments
+    ///
+    /// * `n_p`     - Number of parameters
+    /// * `dfdp`    - Function computing the n×n<sub>p</sub> matrix ∂f/∂p at (x, y)
+    ///
+    pub fn new(n_p: usize, dfdp: fn(f64, &V, &mut DMatrix<f64>)) -> Sensitivity<V> {
+        let dim = na::dimension::<V>();
+        Sensitivity {
+            n_p,
+            p: DVector::zeros(0),
+            dfdp: Some(Derivative::Plain(dfdp)),
+            jac: None,
+            initial_conditions: false,
+            s0: vec![V::zero(); n_p],
+            error_control: true,
+            jac_mat: DMatrix::zeros(dim, dim),
+            dfdp_mat: DMatrix::zeros(dim, n_p),
+        }
+    }
+
+    /// Sensitivities with respect to the parameters p of a system created with `with_parameters`,
+    /// which are read from the system by `set_sensitivity` and passed to `dfdp` and to the
+    /// Jacobian matrix set with `set_parametric_jacobian`.
+    ///
+    /// # Arguments
+    ///
+    /// * `dfdp`    - Function computing the n×n<sub>p</sub> matrix ∂f/∂p at (x, y) for p
+    ///
+    pub fn with_parameters(dfdp: fn(f64, &V, &DVector<f64>, &mut DMatrix<f64>)) -> Sensitivity<V> {
+        let dim = na::dimension::<V>();
+        Sensitivity {
+            n_p: 0,
+            p: DVector::zeros(0),
+            dfdp: Some(Derivative::Parametric(dfdp)),
+            jac: None,
+            initial_conditions: false,
+            s0: Vec::new(),
+            error_control: true,
+            jac_mat: DMatrix::zeros(dim, dim),
+            dfdp_mat: DMatrix::zeros(dim, 0),
+        }
+    }
+
+    /// Sensitivities with respect to the initial value only.
+    pub fn initial_conditions() -> Sensitivity<V> {
+        let dim = na::dimension::<V>();
+        Sensitivity {
+            n_p: 0,
+            p: DVector::zeros(0),
+            dfdp: None,
+            jac: None,
+            initial_conditions: true,
+            s0: Vec::new(),
+            error_control: true,
+            jac_mat: DMatrix::zeros(dim, dim),
+            dfdp_mat: DMatrix::zeros(dim, 0),
+        }
+    }
+
+    /// Adds (or removes) the sensitivities with respect to the initial value after those with
+    /// respect to the parameters.
+    pub fn set_initial_conditions(&mut self, initial_conditions: bool) {
+        self.initial_conditions = initial_conditions;
+    }
+
+    /// Sets the initial values ∂y(x0)/∂p of the sensitivities with respect to the parameters,
+    /// for initial values depending on the parameters. Default is zero.
+    pub fn set_initial_values(&mut self, s0: Vec<V>) {
+        // The number of parameters of a parametric system is only known by set_sensitivity
+        if !matches!(self.dfdp, Some(Derivative::Parametric(_))) {
+            assert_eq!(s0.len(), self.n_p, "one initial value is needed per parameter");
+        }
+        self.s0 = s0;
+    }
+
+    /// Sets the function computing the Jacobian matrix of f with respect to y. If no Jacobian is
+    /// given, its products with the sensitivities are approximated by finite differences, which
+    /// limits the accuracy of the sensitivities to about the square root of the machine precision.
+    /// Tighter tolerances then require a Jacobian or disabling the error control of the sensitivities.
+    pub fn set_jacobian(&mut self, jac: fn(f64, &V, &mut DMatrix<f64>)) {
+        self.jac = Some(Derivative::Plain(jac));
+    }
+
+    /// Sets the function computing the Jacobian matrix of f with respect to y for the parameters
+    /// of a system created with `with_parameters`.
+    pub fn set_parametric_jacobian(&mut self, jac: fn(f64, &V, &DVector<f64>, &mut DMatrix<f64>)) {
+        self.jac = Some(Derivative::Parametric(jac));
+    }
+
+    /// Includes (or not) the sensitivities in the error estimate used to select the step size.
+    /// Default is true.
+    pub fn set_error_control(&mut self, error_control: bool) {
+        self.error_control = error_control;
+    }
+
+    /// Number of parameters. With `with_parameters`, it is known once the sensitivities are given
+    /// to a stepper.
+    pub fn num_parameters(&self) -> usize {
+        self.n_p
+    }
+
+    /// Number of sensitivity columns.
+    pub fn num_columns(&self) -> usize {
+        if self.initial_conditions {
+            self.n_p + na::dimension::<V>()
+        } else {
+            self.n_p
+        }
+    }
+}
+
+// The steppers accepting dual numbers use the sensitivities, which are only created for states
+// convertible to f64
+impl<V> Sensitivity<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: Value,
+{
+    /// Reads the parameters of the system to which the sensitivities are given, if it has any.
+    pub(crate) fn set_parameters(&mut self, p: Option<&DVector<f64>>) {
+        if !self.is_parametric() {
+            return;
+        }
+        let p = p.expect("parametric sensitivities need a system created with with_parameters");
+        if let Some(Derivative::Parametric(_)) = self.dfdp {
+            self.n_p = p.len();
+            if self.s0.is_empty() {
+                self.s0 = vec![V::zero(); self.n_p];
+            }
+            assert_eq!(self.s0.len(), self.n_p, "one initial value is needed per parameter");
+            self.dfdp_mat = DMatrix::zeros(na::dimension::<V>(), self.n_p);
+        }
+        self.p = p.clone();
+    }
+
+    /// Whether the derivatives of f depend on the parameters of the system.
+    fn is_parametric(&self) -> bool {
+        matches!(self.dfdp, Some(Derivative::Parametric(_)))
+            || matches!(self.jac, Some(Derivative::Parametric(_)))
+    }
+
+    pub(crate) fn error_control(&self) -> bool {
+        self.error_control
+    }
+
+    /// Initial values of the sensitivity columns.
+    pub(crate) fn initial_values(&self) -> Vec<V> {
+        let mut s = self.s0.clone();
+        if self.initial_conditions {
+            for i in 0..na::dimension::<V>() {
+                let mut e = V::zero();
+                e[i] = na::convert(1.0);
+                s.push(e);
+            }
+        }
+        s
+    }
+
+    /// Computes the derivatives `ds` of the sensitivities `s` at (x, y), where `fy` = f(x, y).
+    /// Returns the number of evaluations of f.
+    pub(crate) fn derivatives<F>(
+        &mut self,
+        f: F,
+        x: f64,
+        y: &V,
+        fy: &V,
+        s: &[V],
+        ds: &mut Vec<V>,
+    ) -> u32
+    where
+        F: Fn(f64, &V, &mut V),
+    {
+        let dim = na::dimension::<V>();
+        let n_cols = s.len();
+        let mut n_eval = 0;
+        ds.resize(n_cols, V::zero());
+
+        if self.jac.is_some() || n_cols > dim {
+            match self.jac {
+                Some(ref jac) => jac.eval(x, y, &self.p, &mut self.jac_mat),
+                None => n_eval += jacobian::forward_differences(&f, x, y, fy, &mut self.jac_mat),
+            }
+            for c in 0..n_cols {
+                for i in 0..dim {
+                    let mut sum = 0.0;
+                    for j in 0..dim {
+                        let s_j = s[c][j].value();
+                        sum += self.jac_mat[(i, j)] * s_j;
+                    }
+                    ds[c][i] = na::convert(sum);
+                }
+            }
+        } else {
+            // Directional differences, one evaluation of f per column
+            let y_norm = y.norm().value();
+            let mut f_pert = V::zero();
+            for c in 0..n_cols {
+                let s_norm = s[c].norm().value();
+                if s_norm == 0.0 {
+                    ds[c] = V::zero();
+                    continue;
+                }
+                let delta = f64::EPSILON.sqrt() * 1.0_f64.max(y_norm) / s_norm;
+                f(x, &(*y + s[c] * na::convert(delta)), &mut f_pert);
+                ds[c] = (f_pert - *fy) * na::convert(1.0 / delta);
+                n_eval += 1;
+            }
+        }
+
+        if let Some(ref dfdp) = self.dfdp {
+            dfdp.eval(x, y, &self.p, &mut self.dfdp_mat);
+            for c in 0..self.n_p {
+                for i in 0..dim {
+                    let ds_i = ds[c][i].value();
+                    ds[c][i] = na::convert(ds_i + self.dfdp_mat[(i, c)]);
+                }
+            }
+        }
+        n_eval
+    }
+}
+
+/// Returns the columns `base + h Σ w ks[i]` for the pairs (i, w) of `weights`, where `ks[i]` holds
+/// the derivatives of the sensitivities at stage i.
+pub(crate) fn combine<V>(base: &[V], ks: &[Vec<V>], weights: &[(usize, f64)], h: f64) -> Vec<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: Value,
+{
+    let mut out = base.to_vec();
+    for (c, out_c) in out.iter_mut().enumerate() {
+        for &(i, w) in weights {
+            *out_c += ks[i][c] * na::convert(h * w);
+        }
+    }
+    out
+}
+
+/// Sum of the squared scaled errors of the sensitivities, used in the error norm of the steppers.
+pub(crate) fn error_sum<V>(s: &[V], s_next: &[V], err_est: &[V], rtol: f64, atol: f64) -> f64
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: Value,
+{
+    let mut err = 0.0;
+    for c in 0..s.len() {
+        for i in 0..na::dimension::<V>() {
+            let s_i = s[c][i].value();
+            let s_next_i = s_next[c][i].value();
+            let sc_i = atol + s_i.abs().max(s_next_i.abs()) * rtol;
+            let err_i = err_est[c][i].value();
+            err += (err_i / sc_i) * (err_i / sc_i);
+        }
+    }
+    err
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dop853::Dop853;
+    use dopri5::Dopri5;
+    use na::Vector2;
+
+    // Damped oscillator y'' = -p0 y - p1 y'
+    fn oscillator(_x: f64, y: &Vector2<f64>, p: &DVector<f64>, dy: &mut Vector2<f64>) {
+        dy[0] = y[1];
+        dy[1] = -p[0] * y[0] - p[1] * y[1];
+    }
+
+    fn parameter_derivatives(_x: f64, y: &Vector2<f64>, dfdp: &mut DMatrix<f64>) {
+        dfdp.fill(0.0);
+        dfdp[(1, 0)] = -y[0];
+        dfdp[(1, 1)] = -y[1];
+    }
+
+    fn solve(p: &DVector<f64>, y0: Vector2<f64>) -> Vector2<f64> {
+        let mut stepper =
+            Dopri5::with_parameters(oscillator, p.clone(), 0.0, 5.0, 5.0, y0, 1e-12, 1e-12);
+        stepper.integrate().unwrap();
+        *stepper.y_out().last().unwrap()
+    }
+
+    #[test]
+    fn finite_differences() {
+        let p = DVector::from_column_slice(&[2.0, 0.3]);
+        let y0 = Vector2::new(1.0, 0.5);
+        let mut stepper =
+            Dopri5::with_parameters(oscillator, p.clone(), 0.0, 5.0, 5.0, y0, 1e-10, 1e-10);
+        let mut sensitivity = Sensitivity::new(2, parameter_derivatives);
+        sensitivity.set_initial_conditions(true);
+        stepper.set_sensitivity(sensitivity);
+        stepper.integrate().unwrap();
+        let s = stepper.s_out().last().unwrap();
+        assert_eq!(s.len(), 4);
+
+        let delta = 1e-6;
+        for j in 0..4 {
+            let (mut p_plus, mut p_minus) = (p.clone(), p.clone());
+            let (mut y0_plus, mut y0_minus) = (y0, y0);
+            if j < 2 {
+                p_plus[j] += delta;
+                p_minus[j] -= delta;
+            } else {
+                y0_plus[j - 2] += delta;
+                y0_minus[j - 2] -= delta;
+            }
+            let fd = (solve(&p_plus, y0_plus) - solve(&p_minus, y0_minus)) / (2.0 * delta);
+            assert!((s[j] - fd).norm() < 1e-6);
+        }
+    }
+
+    fn parametric_derivatives(
+        _x: f64,
+        y: &Vector2<f64>,
+        _p: &DVector<f64>,
+        dfdp: &mut DMatrix<f64>,
+    ) {
+        parameter_derivatives(_x, y, dfdp);
+    }
+
+    fn parametric_jacobian(_x: f64, _y: &Vector2<f64>, p: &DVector<f64>, jac: &mut DMatrix<f64>) {
+        jac[(0, 0)] = 0.0;
+        jac[(0, 1)] = 1.0;
+        jac[(1, 0)] = -p[0];
+        jac[(1, 1)] = -p[1];
+    }
+
+    #[test]
+    fn parameters_of_the_system() {
+        // Both steppers pass the parameters of the system to the derivatives
+        let p = DVector::from_column_slice(&[2.0, 0.3]);
+        let y0 = Vector2::new(1.0, 0.5);
+        let parametric = || {
+            let mut sensitivity = Sensitivity::with_parameters(parametric_derivatives);
+            sensitivity.set_parametric_jacobian(parametric_jacobian);
+            sensitivity
+        };
+        let mut dopri5 =
+            Dopri5::with_parameters(oscillator, p.clone(), 0.0, 5.0, 5.0, y0, 1e-10, 1e-10);
+        dopri5.set_sensitivity(parametric());
+        dopri5.integrate().unwrap();
+        let mut dop853 =
+            Dop853::with_parameters(oscillator, p.clone(), 0.0, 5.0, 5.0, y0, 1e-10, 1e-10);
+        dop853.set_sensitivity(parametric());
+        dop853.integrate().unwrap();
+
+        let s5 = dopri5.s_out().last().unwrap();
+        let s8 = dop853.s_out().last().unwrap();
+        let delta = 1e-6;
+        for j in 0..2 {
+            let (mut p_plus, mut p_minus) = (p.clone(), p.clone());
+            p_plus[j] += delta;
+            p_minus[j] -= delta;
+            let fd = (solve(&p_plus, y0) - solve(&p_minus, y0)) / (2.0 * delta);
+            for s in &[s5, s8] {
+                assert_eq!(s.len(), 2);
+                assert!((s[j] - fd).norm() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn parametric_sensitivities_of_a_plain_system() {
+        let mut stepper = Dop853::new(undamped, 0.0, 1.0, 1.0, Vector2::new(1.0, 0.0), 1e-6, 1e-6);
+        stepper.set_sensitivity(Sensitivity::with_parameters(parametric_derivatives));
+    }
+
+    fn undamped(_x: f64, y: &Vector2<f64>, dy: &mut Vector2<f64>) {
+        dy[0] = y[1];
+        dy[1] = -y[0];
+    }
+
+    fn jacobian(_x: f64, _y: &Vector2<f64>, jac: &mut DMatrix<f64>) {
+        jac[(0, 0)] = 0.0;
+        jac[(0, 1)] = 1.0;
+        jac[(1, 0)] = -1.0;
+        jac[(1, 1)] = 0.0;
+    }
+
+    #[test]
+    fn initial_values_of_the_oscillator() {
+        // The sensitivities with respect to the initial value are the columns of the rotation
+        // by the angle -x
+        let y0 = Vector2::new(1.0, 0.0);
+        let mut stepper = Dop853::new(undamped, 0.0, 10.0, 0.5, y0, 1e-12, 1e-12);
+        let mut sensitivity = Sensitivity::initial_conditions();
+        sensitivity.set_jacobian(jacobian);
+        stepper.set_sensitivity(sensitivity);
+        stepper.integrate().unwrap();
+        assert_eq!(stepper.s_out().len(), stepper.x_out().len());
+        for (x, s) in stepper.x_out().iter().zip(stepper.s_out()) {
+            assert!((s[0] - Vector2::new(x.cos(), -x.sin())).norm() < 1e-10);
+            assert!((s[1] - Vector2::new(x.sin(), x.cos())).norm() < 1e-10);
+        }
+    }
+}
@@ -0,0 +1,930 @@
+//===================================================================//
+// Copyright (c) 2004, Ernst Hairer
+
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+
+// - Redistributions of source code must retain the above copyright
+// notice, this list of conditions and the following disclaimer.
+
+// - Redistributions in binary form must reproduce the above copyright
+// notice, this list of conditions and the following disclaimer in the
+// documentation and/or other materials provided with the distribution.
+
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS “AS
+// IS” AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED
+// TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A
+// PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE REGENTS OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+// This code is a Rust adaptation of the code SEULEX written originally
+// in Fortran by:
+//
+//      E. Hairer & G. Wanner
+//      Université de Genève, dept. de Mathématiques
+//      CH-1211 Genève 4, Swizerland
+//      E-mail : hairer@divsun.unige.ch, wanner@divsun.unige.ch
+//
+//===================================================================//
+
+#![allow(clippy::needless_range_loop)]
+
+//! Extrapolation method based on the linearly implicit Euler method, for stiff problems of the form M y' = f(x, y), with order and step size control and dense output.
+//!
+//! The derivative of f with respect to x is approximated by forward differences at the beginning of each step, as for
+//! the non-autonomous problems of the original code.
+
+use alga::general::SubsetOf;
+use alga::linear::{FiniteDimInnerSpace, InnerSpace};
+use dop_shared::*;
+use jacobian;
+use na;
+use na::linalg::LU;
+use na::{DMatrix, Dynamic};
+use std::f64;
+
+// Parameters of the order selection
+const FAC3: f64 = 0.7;
+const FAC4: f64 = 0.9;
+
+// Safety factors of the step size selection
+const SAFE1: f64 = 0.6;
+const SAFE2: f64 = 0.93;
+
+// Estimated work for a call to f, to the Jacobian, for a LU decomposition and for a back substitution
+const WK_FCN: f64 = 1.0;
+const WK_JAC: f64 = 5.0;
+const WK_DEC: f64 = 1.0;
+const WK_SOL: f64 = 1.0;
+
+// The stability check is activated in the lines 1 to JSTAB of the extrapolation table
+const JSTAB: usize = 2;
+
+/// Outcome of an attempted step.
+enum Step {
+    Accepted(usize),
+    Rejected(usize),
+    Unstable,
+}
+
+/// Function computing the Jacobian matrix of f with respect to y.
+type Jacobian<V> = Box<dyn Fn(f64, &V, &mut DMatrix<f64>)>;
+
+/// Structure containing the parameters for the numerical integration.
+pub struct Seulex<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+{
+    f: System<V>,
+    jac: Option<Jacobian<V>>,
+    mass: Option<DMatrix<f64>>,
+    x: f64,
+    x0: f64,
+    x_old: f64,
+    x_end: f64,
+    xd: f64,
+    dx: f64,
+    y: V,
+    rtol: f64,
+    atol: f64,
+    x_out: Vec<f64>,
+    y_out: Vec<V>,
+    uround: f64,
+    h: f64,
+    h_old: f64,
+    h_max: f64,
+    n_max: u32,
+    km: usize,
+    fac_min: f64,
+    fac_max: f64,
+    out_type: OutputType,
+    // The arrays below are indexed from 1 as in the original code
+    nj: Vec<usize>,
+    a: Vec<f64>,
+    hh: Vec<f64>,
+    w: Vec<f64>,
+    t: Vec<V>,
+    increments: Vec<Vec<V>>,
+    dens: Vec<V>,
+    kmit: usize,
+    jacobian: DMatrix<f64>,
+    dz: V,
+    fx: V,
+    scal: Vec<f64>,
+    err: f64,
+    err_old: f64,
+    reject: bool,
+    stats: Stats,
+}
+
+impl<V> Seulex<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: SubsetOf<f64>,
+{
+    /// Default initializer for the structure.
+    ///
+    /// # Arguments
+    ///
+    /// * `f`       - Pointer to the function to integrate
+    /// * `x`       - Initial value of the independent variable (usually time)
+    /// * `x_end`   - Final value of the independent variable
+    /// * `dx`      - Increment in the dense output. This argument has no effect if the output type is Sparse
+    /// * `y`       - Initial value of the dependent variable(s)
+    /// * `rtol`    - Relative tolerance used in the computation of the adaptive step size
+    /// * `atol`    - Absolute tolerance used in the computation of the adaptive step size
+    ///
+    pub fn new(
+        f: fn(f64, &V, &mut V),
+        x: f64,
+        x_end: f64,
+        dx: f64,
+        y: V,
+        rtol: f64,
+        atol: f64,
+    ) -> Seulex<V> {
+        Seulex::from_param(
+            f,
+            x,
+            x_end,
+            dx,
+            y,
+            rtol,
+            atol,
+            0.1,
+            4.0,
+            x_end - x,
+            0.0,
+            100000,
+            12,
+            OutputType::Dense,
+        )
+    }
+
+    /// Advanced initializer for the structure.
+    ///
+    /// # Arguments
+    ///
+    /// * `f`       - Pointer to the function to integrate
+    /// * `x`       - Initial value of the independent variable (usually time)
+    /// * `x_end`   - Final value of the independent variable
+    /// * `dx`      - Increment in the dense output. This argument has no effect if the output type is Sparse
+    /// * `y`       - Initial value of the dependent variable(s)
+    /// * `rtol`    - Relative tolerance used in the computation of the adaptive step size
+    /// * `atol`    - Absolute tolerance used in the computation of the adaptive step size
+    /// * `fac_min` - The step size of column j of the extrapolation table is decreased by at most fac_min^(1/j). Default is 0.1
+    /// * `fac_max` - The step size of column j of the extrapolation table is increased by at most fac_max/fac_min^(1/j). Default is 4.0
+    /// * `h_max`   - Maximum step size. Default is `x_end-x`
+    /// * `h`       - Initial value of the step size. If h = 0.0, h = 1.0e-6 is used and adapted during the first step
+    /// * `n_max`   - Maximum number of iterations. Default is 100000
+    /// * `km`      - Maximum number of columns in the extrapolation table. Default is 12
+    /// * `out_type`    - Type of the output. Must be a variant of the OutputType enum. Default is Dense
+    ///
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_param(
+        f: fn(f64, &V, &mut V),
+        x: f64,
+        x_end: f64,
+        dx: f64,
+        y: V,
+        rtol: f64,
+        atol: f64,
+        fac_min: f64,
+        fac_max: f64,
+        h_max: f64,
+        h: f64,
+        n_max: u32,
+        km: usize,
+        out_type: OutputType,
+    ) -> Seulex<V> {
+        Seulex::from_system(
+            System::Plain(f),
+            x,
+            x_end,
+            dx,
+            y,
+            rtol,
+            atol,
+            fac_min,
+            fac_max,
+            h_max,
+            h,
+            n_max,
+            km,
+            out_type,
+        )
+    }
+
+    /// Initializer for a closure f, which may capture the data of the system, such as a
+    /// [`ReactionNetwork`](../reaction/struct.ReactionNetwork.html). The other arguments are the
+    /// same as in `new`.
+    pub fn with_closure<F>(
+        f: F,
+        x: f64,
+        x_end: f64,
+        dx: f64,
+        y: V,
+        rtol: f64,
+        atol: f64,
+    ) -> Seulex<V>
+    where
+        F: Fn(f64, &V, &mut V) + 'static,
+    {
+        Seulex::from_system(
+            System::Closure(Box::new(f)),
+            x,
+            x_end,
+            dx,
+            y,
+            rtol,
+            atol,
+            0.1,
+            4.0,
+            x_end - x,
+            0.0,
+            100000,
+            12,
+            OutputType::Dense,
+        )
+    }
+
+    /// Initializer shared by the public ones, for any kind of system.
+    #[allow(clippy::too_many_arguments)]
+    fn from_system(
+        f: System<V>,
+        x: f64,
+        x_end: f64,
+        dx: f64,
+        y: V,
+        rtol: f64,
+        atol: f64,
+        fac_min: f64,
+        fac_max: f64,
+        h_max: f64,
+        h: f64,
+        n_max: u32,
+        km: usize,
+        out_type: OutputType,
+    ) -> Seulex<V> {
+        let dim = na::dimension::<V>();
+        Seulex {
+            f,
+            jac: None,
+            mass: None,
+            x,
+            x0: x,
+            xd: x,
+            dx,
+            x_old: x,
+            x_end,
+            y,
+            rtol,
+            atol,
+            x_out: Vec::<f64>::new(),
+            y_out: Vec::<V>::new(),
+            uround: f64::EPSILON,
+            h,
+            h_old: h,
+            h_max,
+            n_max,
+            km: km.max(3),
+            fac_min,
+            fac_max,
+            out_type,
+            nj: Vec::new(),
+            a: Vec::new(),
+            hh: Vec::new(),
+            w: Vec::new(),
+            t: Vec::new(),
+            increments: Vec::new(),
+            dens: Vec::new(),
+            kmit: 0,
+            jacobian: DMatrix::zeros(dim, dim),
+            dz: V::zero(),
+            fx: V::zero(),
+            scal: Vec::new(),
+            err: 0.0,
+            err_old: 0.0,
+            reject: false,
+            stats: Stats::new(),
+        }
+    }
+
+    /// Sets the function computing the Jacobian matrix of f with respect to y, a function pointer
+    /// or a closure. If no Jacobian is given, it is approximated by finite differences.
+    pub fn set_jacobian<J>(&mut self, jac: J)
+    where
+        J: Fn(f64, &V, &mut DMatrix<f64>) + 'static,
+    {
+        self.jac = Some(Box::new(jac));
+    }
+
+    /// Sets the mass matrix M of the problem M y' = f(x, y). The matrix may be singular, in which
+    /// case the problem is a differential-algebraic equation of index 1. Default is the identity.
+    pub fn set_mass_matrix(&mut self, mass: DMatrix<f64>) {
+        self.mass = Some(mass);
+    }
+
+    /// Allocates the extrapolation table.
+    fn init_tables(&mut self) {
+        let km = self.km;
+        let dim = na::dimension::<V>();
+
+        // Step number sequence: 2, 3, 4, 6, 8, 12, 16, 24, ...
+        self.nj = vec![0; km + 1];
+        for j in 1..=km {
+            self.nj[j] = match j {
+                1 => 2,
+                2 => 3,
+                _ => 2 * self.nj[j - 2],
+            };
+        }
+
+        // Work required to compute the columns of the extrapolation table
+        self.a = vec![0.0; km + 1];
+        self.a[1] = WK_JAC + self.nj[1] as f64 * (WK_FCN + WK_SOL) + WK_DEC;
+        for j in 2..=km {
+            self.a[j] = self.a[j - 1] + (self.nj[j] - 1) as f64 * WK_FCN
+                + self.nj[j] as f64 * WK_SOL
+                + WK_DEC;
+        }
+
+        self.hh = vec![0.0; km + 1];
+        self.w = vec![0.0; km + 1];
+        self.t = vec![V::zero(); km + 1];
+        self.scal = vec![0.0; dim];
+        for i in 0..dim {
+            let y_i: f64 = na::convert(self.y[i]);
+            self.scal[i] = self.atol + self.rtol * y_i.abs();
+        }
+        if self.out_type == OutputType::Dense {
+            self.increments = vec![Vec::new(); km + 1];
+            self.dens = vec![V::zero(); km + 2];
+        }
+    }
+
+    /// Core integration method.
+    pub fn integrate(&mut self) -> Result<Stats, IntegrationError> {
+        // Initialization
+        self.init_tables();
+        self.x_old = self.x;
+        let km = self.km;
+        let mut n_step = 0;
+        let mut last;
+        let posneg = sign(1.0, self.x_end - self.x);
+        let h_max = self.h_max.abs().min((self.x_end - self.x).abs());
+        let mut k = ((-(self.rtol + 1.0e-40).log10() * 0.6 + 1.5) as usize)
+            .min(km - 1)
+            .max(2);
+        if self.h == 0.0 {
+            self.h = 1.0e-6;
+        }
+        self.h = posneg * self.h.abs().min(h_max);
+        self.h_old = self.h;
+        self.reject = false;
+
+        // Save initial values
+        let y_tmp = self.y;
+        self.solution_output(y_tmp);
+
+        self.f.eval(self.x, &self.y, &mut self.dz);
+        self.stats.num_eval += 1;
+        self.compute_jacobian();
+
+        // Main loop
+        'main: loop {
+            // Check for step size underflow
+            if 0.1 * self.h.abs() <= self.uround * self.x.abs() {
+                self.h_old = self.h;
+                return Err(IntegrationError::StepSizeUnderflow { x: self.x });
+            }
+
+            // Check if it's the last iteration
+            self.h = posneg * self.h.abs().min((self.x_end - self.x).abs()).min(h_max);
+            // A step restarted after an instability may no longer reach x_end
+            last = (self.x + 1.01 * self.h - self.x_end) * posneg > 0.0;
+            if last {
+                self.h = self.x_end - self.x;
+            }
+
+            // The first and the last steps compute the columns one after the other
+            let mut first = n_step == 0 || last;
+            loop {
+                // Check if step number is within allowed range
+                if n_step >= self.n_max {
+                    self.h_old = self.h;
+                    return Err(IntegrationError::MaxNumStepReached { x: self.x, n_step });
+                }
+                n_step += 1;
+                self.err_old = 1.0e10;
+
+                let kc = match self.step(k, first) {
+                    Step::Unstable => continue 'main,
+                    Step::Rejected(kc) => {
+                        k = k.min(kc).min(km - 1);
+                        if k > 2 && self.w[k - 1] < self.w[k] * FAC3 {
+                            k -= 1;
+                        }
+                        if self.stats.accepted_steps >= 1 {
+                            self.stats.rejected_steps += 1;
+                        }
+                        self.h = posneg * self.hh[k];
+                        self.reject = true;
+                        last = false;
+                        first = false;
+                        continue;
+                    }
+                    Step::Accepted(kc) => kc,
+                };
+
+                if self.out_type == OutputType::Dense && !self.dense_output(kc) {
+                    if self.stats.accepted_steps >= 1 {
+                        self.stats.rejected_steps += 1;
+                    }
+                    self.reject = true;
+                    continue 'main;
+                }
+
+                self.stats.accepted_steps += 1;
+                self.y = self.t[1];
+                self.x_old = self.x;
+                self.x += self.h;
+                self.h_old = self.h;
+
+                let y_tmp = self.y;
+                self.solution_output(y_tmp);
+
+                // Normal exit
+                if last {
+                    return Ok(self.stats);
+                }
+
+                self.f.eval(self.x, &self.y, &mut self.dz);
+                self.stats.num_eval += 1;
+                self.compute_jacobian();
+
+                // Compute optimal order
+                let mut k_opt;
+                if kc == 2 {
+                    k_opt = 3.min(km - 1);
+                    if self.reject {
+                        k_opt = 2;
+                    }
+                } else if kc <= k {
+                    k_opt = kc;
+                    if self.w[kc - 1] < self.w[kc] * FAC3 {
+                        k_opt = kc - 1;
+                    }
+                    if self.w[kc] < self.w[kc - 1] * FAC4 {
+                        k_opt = (kc + 1).min(km - 1);
+                    }
+                } else {
+                    k_opt = kc - 1;
+                    if kc > 3 && self.w[kc - 2] < self.w[kc - 1] * FAC3 {
+                        k_opt = kc - 2;
+                    }
+                    if self.w[kc] < self.w[k_opt] * FAC4 {
+                        k_opt = kc.min(km - 1);
+                    }
+                }
+
+                // After a rejected step
+                if self.reject {
+                    k = k_opt.min(kc);
+                    self.h = posneg * self.h.abs().min(self.hh[k]);
+                    self.reject = false;
+                    continue 'main;
+                }
+
+                // Compute step size for next step
+                self.h = if k_opt <= kc {
+                    self.hh[k_opt]
+                } else if kc < k && self.w[kc] < self.w[kc - 1] * FAC4 {
+                    self.hh[kc] * self.a[k_opt + 1] / self.a[kc]
+                } else {
+                    self.hh[kc] * self.a[k_opt] / self.a[kc]
+                };
+                self.h *= posneg;
+                k = k_opt;
+                continue 'main;
+            }
+        }
+    }
+
+    /// Computes the Jacobian matrix at the current point, analytically if possible, and the derivative
+    /// of f with respect to x.
+    fn compute_jacobian(&mut self) {
+        match self.jac {
+            Some(ref jac) => jac(self.x, &self.y, &mut self.jacobian),
+            None => {
+                let f = &self.f;
+                self.stats.num_eval += jacobian::forward_differences(
+                    |x, y, dy| f.eval(x, y, dy),
+                    self.x,
+                    &self.y,
+                    &self.dz,
+                    &mut self.jacobian,
+                );
+            }
+        }
+
+        let delta = (self.uround * 1.0e-5_f64.max(self.x.abs())).sqrt();
+        let mut f_delta = V::zero();
+        self.f.eval(self.x + delta, &self.y, &mut f_delta);
+        self.stats.num_eval += 1;
+        self.fx = (f_delta - self.dz) * na::convert(1.0 / delta);
+    }
+
+    /// Attempts a step of size h with k columns in the extrapolation table.
+    fn step(&mut self, k: usize, first: bool) -> Step {
+        if first {
+            for j in 1..=k {
+                if !self.seul(j) {
+                    return Step::Unstable;
+                }
+                if j > 1 && self.err <= 1.0 {
+                    return Step::Accepted(j);
+                }
+            }
+        } else {
+            for j in 1..k {
+                if !self.seul(j) {
+                    return Step::Unstable;
+                }
+            }
+
+            // Convergence monitor
+            if k != 2 && !self.reject {
+                if self.err <= 1.0 {
+                    return Step::Accepted(k - 1);
+                }
+                if self.err > (self.nj[k + 1] * self.nj[k]) as f64 / 4.0 {
+                    return Step::Rejected(k - 1);
+                }
+            }
+            if !self.seul(k) {
+                return Step::Unstable;
+            }
+            if self.err <= 1.0 {
+                return Step::Accepted(k);
+            }
+        }
+
+        // Hope for convergence in line k + 1
+        if self.err > self.nj[k + 1] as f64 / 2.0 {
+            return Step::Rejected(k);
+        }
+        if !self.seul(k + 1) {
+            return Step::Unstable;
+        }
+        if self.err > 1.0 {
+            Step::Rejected(k + 1)
+        } else {
+            Step::Accepted(k + 1)
+        }
+    }
+
+    /// Computes the j-th line of the extrapolation table and provides an estimation of the optimal step size.
+    /// Returns false if the step has to be restarted with a halved step size.
+    fn seul(&mut self, j: usize) -> bool {
+        let dim = na::dimension::<V>();
+        let nj = self.nj[j];
+        let hj = self.h / nj as f64;
+
+        // Decomposition of the iteration matrix M/hj - J
+        let mut e = -self.jacobian.clone();
+        match self.mass {
+            Some(ref mass) => e += mass / hj,
+            None => {
+                for i in 0..dim {
+                    e[(i, i)] += 1.0 / hj;
+                }
+            }
+        }
+        let lu: LU<f64, Dynamic, Dynamic> = e.lu();
+        if !lu.is_invertible() {
+            return self.restart();
+        }
+
+        // Linearly implicit Euler method, whose right-hand side includes the term hj df/dx of the
+        // autonomous form of the problem
+        let fx = self.fx * na::convert(hj);
+        let mut yh = self.y;
+        let mut del = jacobian::to_dvector(&(self.dz + fx));
+        lu.solve_mut(&mut del);
+        let mut del_old: f64 = 0.0;
+        if self.out_type == OutputType::Dense {
+            self.increments[j].clear();
+        }
+        for mm in 0..nj {
+            if mm > 0 {
+                let mut fh = V::zero();
+                self.f.eval(self.x + hj * mm as f64, &yh, &mut fh);
+                self.stats.num_eval += 1;
+                del = jacobian::to_dvector(&(fh + fx));
+                lu.solve_mut(&mut del);
+            }
+
+            // Stability check
+            if mm <= 1 && j <= JSTAB {
+                let mut del_norm = 0.0;
+                for i in 0..dim {
+                    del_norm += (del[i] / self.scal[i]).powi(2);
+                }
+                del_norm = del_norm.sqrt();
+                if mm == 1 && del_norm > 2.0 * del_old.max(self.uround) {
+                    return self.restart();
+                }
+                del_old = del_norm;
+            }
+
+            let increment = jacobian::from_dvector::<V>(&del);
+            yh += increment;
+            if self.out_type == OutputType::Dense {
+                self.increments[j].push(increment);
+            }
+        }
+        self.t[j] = yh;
+
+        if j == 1 {
+            return true;
+        }
+
+        // Polynomial extrapolation
+        for l in (2..=j).rev() {
+            let factor = nj as f64 / self.nj[l - 1] as f64 - 1.0;
+            self.t[l - 1] = self.t[l] + (self.t[l] - self.t[l - 1]) * na::convert(1.0 / factor);
+        }
+
+        // Compute error
+        self.err = 0.0;
+        for i in 0..dim {
+            let y_i: f64 = na::convert(self.y[i]);
+            let t1_i: f64 = na::convert(self.t[1][i]);
+            let t2_i: f64 = na::convert(self.t[2][i]);
+            self.scal[i] = self.atol + self.rtol * y_i.abs().max(t1_i.abs());
+            self.err += ((t1_i - t2_i) / self.scal[i]).powi(2);
+        }
+        self.err = (self.err / dim as f64).sqrt();
+        if self.err * self.uround >= 1.0 || (j > 2 && self.err >= self.err_old) {
+            return self.restart();
+        }
+        self.err_old = (4.0 * self.err).max(1.0);
+
+        // Compute optimal step size
+        let expo = 1.0 / j as f64;
+        let fac_min = self.fac_min.powf(expo);
+        let fac = (self.fac_max / fac_min).min(fac_min.max((self.err / SAFE1).powf(expo) / SAFE2));
+        self.hh[j] = (self.h.abs() / fac).min(self.h_max.abs());
+        self.w[j] = self.a[j] / self.hh[j];
+        true
+    }
+
+    /// Halves the step size after a singular iteration matrix, an unstable or a diverging extrapolation.
+    fn restart(&mut self) -> bool {
+        self.h *= 0.5;
+        self.reject = true;
+        false
+    }
+
+    /// Computes the coefficients of the dense output. The derivatives of the solution at the end of
+    /// the step are approximated by backward differences of the last increments of each line and
+    /// extrapolated. The interpolation polynomial matches these derivatives, the initial value and
+    /// the initial derivative. Returns false if the step has to be rejected because of a large
+    /// interpolation error.
+    fn dense_output(&mut self, kc: usize) -> bool {
+        let dim = na::dimension::<V>();
+        let n_der = kc - 1;
+        let mut der = vec![V::zero(); kc + 1];
+        self.dens[0] = self.t[1];
+        for l in 1..=n_der {
+            // The l-th derivative is extrapolated from the first kc - l + 1 lines with enough
+            // points, which keeps the round-off error of the differences small
+            let jbeg = match (1..=kc).find(|&j| self.nj[j] >= l) {
+                Some(jbeg) => jbeg,
+                None => {
+                    // No line has enough points: the dense output is of lower degree
+                    for dens in &mut self.dens[l..=n_der] {
+                        *dens = V::zero();
+                    }
+                    break;
+                }
+            };
+            for j in jbeg..=kc.min(jbeg + kc - l) {
+                let increments = &self.increments[j];
+                let nj = self.nj[j];
+                let mut diff = V::zero();
+                let mut binom = 1.0;
+                for i in 0..l {
+                    diff += increments[nj - 1 - i] * na::convert(binom);
+                    binom *= -((l - 1 - i) as f64) / (i + 1) as f64;
+                }
+                der[j] = diff * na::convert((nj as f64).powi(l as i32));
+                for m in (jbeg + 1..=j).rev() {
+                    let factor = nj as f64 / self.nj[m - 1] as f64 - 1.0;
+                    der[m - 1] = der[m] + (der[m] - der[m - 1]) * na::convert(1.0 / factor);
+                }
+            }
+            self.dens[l] = der[jbeg];
+        }
+
+        // Scale the derivatives to Taylor coefficients in s = theta - 1
+        let mut fact = 1.0;
+        for l in 2..=n_der {
+            fact *= l as f64;
+            self.dens[l] *= na::convert(1.0 / fact);
+        }
+
+        // Initial derivative scaled by h. With a mass matrix, f(x, y) is not the derivative of the
+        // algebraic components and the derivative is extrapolated from the first increments.
+        let yp0 = if self.mass.is_some() {
+            for j in 1..=kc {
+                der[j] = self.increments[j][0] * na::convert(self.nj[j] as f64);
+                for m in (2..=j).rev() {
+                    let factor = self.nj[j] as f64 / self.nj[m - 1] as f64 - 1.0;
+                    der[m - 1] = der[m] + (der[m] - der[m - 1]) * na::convert(1.0 / factor);
+                }
+            }
+            der[1]
+        } else {
+            self.dz * na::convert(self.h)
+        };
+
+        // Coefficients of s^(n_der + 1) and s^(n_der + 2) such that the polynomial and its
+        // derivative match the initial values at the beginning of the step (s = -1)
+        let mut r0 = self.y;
+        let mut r1 = yp0;
+        let mut sign_l = 1.0;
+        for l in 0..=n_der {
+            r0 -= self.dens[l] * na::convert(sign_l);
+            if l > 0 {
+                r1 += self.dens[l] * na::convert(sign_l * l as f64);
+            }
+            sign_l = -sign_l;
+        }
+        // sign_l is now (-1)^(n_der + 1)
+        let n = n_der as f64;
+        self.dens[n_der + 1] = (r1 + r0 * na::convert(n + 2.0)) * na::convert(sign_l);
+        self.dens[n_der + 2] = (r1 + r0 * na::convert(n + 1.0)) * na::convert(sign_l);
+
+        // Estimation of the interpolation error from the last coefficient, weighted by the maximum
+        // of |s^n_der (s + 1)^2| on the step
+        let mut errint = 0.0;
+        for i in 0..dim {
+            let d_i: f64 = na::convert(self.dens[n_der + 2][i]);
+            errint += (d_i / self.scal[i]).powi(2);
+        }
+        let errfac = (n / (n + 2.0)).powi(n_der as i32) * (2.0 / (n + 2.0)).powi(2);
+        errint = (errint / dim as f64).sqrt() * errfac;
+        if errint > 10.0 {
+            self.h /= errint.powf(1.0 / (n_der + 2) as f64).max(0.01);
+            return false;
+        }
+        self.kmit = n_der + 2;
+        true
+    }
+
+    /// If a dense output is required, computes the solution and pushes it into the output vector. Else, pushes the solution into the output vector.
+    fn solution_output(&mut self, y_next: V) {
+        if self.out_type == OutputType::Dense {
+            let posneg = sign(1.0, self.x_end - self.x0);
+            if self.x == self.x0 {
+                self.x_out.push(self.x0);
+                self.y_out.push(y_next);
+                self.xd += self.dx;
+            } else {
+                while (self.xd - self.x) * posneg <= 0.0 {
+                    let s = (self.xd - self.x_old) / self.h_old - 1.0;
+                    let mut y = self.dens[self.kmit];
+                    for l in (0..self.kmit).rev() {
+                        y = self.dens[l] + y * na::convert(s);
+                    }
+                    self.x_out.push(self.xd);
+                    self.y_out.push(y);
+                    self.xd += self.dx;
+                }
+            }
+        } else {
+            self.x_out.push(self.x);
+            self.y_out.push(y_next);
+        }
+    }
+
+    /// Getter for the independent variable's output.
+    pub fn x_out(&self) -> &Vec<f64> {
+        &self.x_out
+    }
+
+    /// Getter for the dependent variables' output.
+    pub fn y_out(&self) -> &Vec<V> {
+        &self.y_out
+    }
+}
+
+fn sign(a: f64, b: f64) -> f64 {
+    if b > 0.0 {
+        a.abs()
+    } else {
+        -a.abs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use na::{DVector, Vector2};
+
+    fn oscillator(_x: f64, y: &Vector2<f64>, dy: &mut Vector2<f64>) {
+        dy[0] = y[1];
+        dy[1] = -y[0];
+    }
+
+    fn exact(x: f64) -> Vector2<f64> {
+        Vector2::new(x.cos(), -x.sin())
+    }
+
+    // Prothero-Robinson problem, whose solution cos(x) is smooth while the problem is stiff for
+    // large values of lambda
+    fn prothero_robinson(x: f64, y: &Vector2<f64>, dy: &mut Vector2<f64>) {
+        dy[0] = y[1] * (y[0] - x.cos()) - x.sin();
+        dy[1] = 0.0;
+    }
+
+    // Index 1 problem y1' = y2, 0 = y2 - cos(x), whose solution is y1 = sin(x)
+    fn dae(x: f64, y: &Vector2<f64>, dy: &mut Vector2<f64>) {
+        dy[0] = y[1];
+        dy[1] = y[1] - x.cos();
+    }
+
+    #[test]
+    fn stiff_problem() {
+        // The extrapolation is only accurate for the stiff problem with the derivative of f with
+        // respect to x, and the steps restarted after an instability must still reach x_end
+        for &(lambda, tol) in [(-1.0, 1.0e-4), (-1.0e6, 1.0e-4), (-1.0e6, 1.0e-8)].iter() {
+            let mut stepper = Seulex::from_param(
+                prothero_robinson,
+                0.0,
+                10.0,
+                0.0,
+                Vector2::new(1.0, lambda),
+                tol,
+                tol,
+                0.1,
+                4.0,
+                10.0,
+                0.0,
+                100000,
+                12,
+                OutputType::Sparse,
+            );
+            let stats = stepper.integrate().unwrap();
+            assert!(stats.accepted_steps < 100);
+            assert_eq!(*stepper.x_out().last().unwrap(), 10.0);
+            for (x, y) in stepper.x_out().iter().zip(stepper.y_out().iter()) {
+                assert!((y[0] - x.cos()).abs() < 100.0 * tol);
+            }
+        }
+    }
+
+    #[test]
+    fn oscillator_forward_and_backward() {
+        for &(x, x_end) in [(0.0, 10.0), (10.0, 0.0)].iter() {
+            let mut stepper = Seulex::from_param(
+                oscillator,
+                x,
+                x_end,
+                0.0,
+                exact(x),
+                1.0e-10,
+                1.0e-10,
+                0.1,
+                4.0,
+                (x_end - x).abs(),
+                0.0,
+                100000,
+                12,
+                OutputType::Sparse,
+            );
+            stepper.integrate().unwrap();
+            assert_eq!(*stepper.x_out().last().unwrap(), x_end);
+            assert!((stepper.y_out().last().unwrap() - exact(x_end)).norm() < 1.0e-7);
+        }
+    }
+
+    #[test]
+    fn differential_algebraic_equation() {
+        let mut stepper = Seulex::new(dae, 0.0, 5.0, 0.5, Vector2::new(0.0, 1.0), 1.0e-8, 1.0e-8);
+        stepper.set_mass_matrix(DMatrix::from_diagonal(&DVector::from_column_slice(&[1.0, 0.0])));
+        stepper.integrate().unwrap();
+        for (x, y) in stepper.x_out().iter().zip(stepper.y_out().iter()) {
+            assert!((y - Vector2::new(x.sin(), x.cos())).norm() < 1.0e-6);
+        }
+    }
+}
@@ -0,0 +1,465 @@
+//! Global sensitivity analysis.
+//!
+//! [`SobolIndices`](struct.SobolIndices.html) estimates the first-order and total Sobol indices of
+//! a scalar output of an integration with respect to uncertain parameters. The first-order index
+//! of a parameter is the fraction of the variance of the output explained by this parameter alone,
+//! and the total index also includes its interactions with the other parameters. With N base
+//! samples and d parameters, the output is evaluated for the N (d + 2) parameter sets of the
+//! scheme of Saltelli: two sample matrices A and B, and the d matrices obtained by replacing one
+//! column of A by the one of B. The integrations are run as an
+//! [`Ensemble`](../ensemble/struct.Ensemble.html), in parallel with the `rayon` feature, and the
+//! indices are computed with the estimators of Saltelli (2010) and Jansen (1999).
+//!
+//! The samples are drawn from [`SobolSequence`](struct.SobolSequence.html), a quasi-random
+//! sequence with the direction numbers of Joe and Kuo, optionally scrambled by a seeded random
+//! digital shift.
+
+use alga::general::SubsetOf;
+use alga::linear::{FiniteDimInnerSpace, InnerSpace};
+use dop_shared::*;
+use ensemble::Ensemble;
+use na::DVector;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::error::Error;
+use std::fmt;
+use uncertainty::Distribution;
+
+/// Degree, coefficients and initial direction numbers of the primitive polynomials of the
+/// dimensions 2 to 21, from the file new-joe-kuo-6.21201 of Joe and Kuo.
+const DIRECTIONS: [(usize, u32, &[u32]); 20] = [
+    (1, 0, &[1]),
+    (2, 1, &[1, 3]),
+    (3, 1, &[1, 3, 1]),
+    (3, 2, &[1, 1, 1]),
+    (4, 1, &[1, 1, 3, 3]),
+    (4, 4, &[1, 3, 5, 13]),
+    (5, 2, &[1, 1, 5, 5, 17]),
+    (5, 4, &[1, 1, 5, 5, 5]),
+    (5, 7, &[1, 1, 7, 11, 19]),
+    (5, 11, &[1, 1, 5, 1, 1]),
+    (5, 13, &[1, 1, 1, 3, 11]),
+    (5, 14, &[1, 3, 5, 5, 31]),
+    (6, 1, &[1, 3, 3, 9, 7, 49]),
+    (6, 13, &[1, 1, 1, 15, 21, 21]),
+    (6, 16, &[1, 3, 1, 13, 27, 49]),
+    (6, 19, &[1, 1, 1, 15, 7, 5]),
+    (6, 22, &[1, 3, 1, 15, 13, 25]),
+    (6, 25, &[1, 1, 5, 5, 19, 61]),
+    (7, 1, &[1, 3, 7, 11, 23, 15, 103]),
+    (7, 4, &[1, 3, 7, 13, 13, 15, 69]),
+];
+
+/// Maximal dimension of the Sobol sequence.
+pub const MAX_DIMENSION: usize = DIRECTIONS.len() + 1;
+
+/// Enumeration of the errors that may arise during the estimation of the indices.
+#[derive(Debug)]
+pub enum SobolError {
+    TooManyParameters { max: usize },
+    NoSample,
+    Integration(IntegrationError),
+}
+
+impl Error for SobolError {}
+
+impl fmt::Display for SobolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SobolError::TooManyParameters { max } => {
+                write!(f, "The Sobol sequence is limited to {} parameters", max)
+            }
+            SobolError::NoSample => write!(f, "No base sample"),
+            SobolError::Integration(e) => {
+                write!(f, "Every base sample has a failed integration. {}", e)
+            }
+        }
+    }
+}
+
+/// Quasi-random Sobol sequence in the unit hypercube, generated in Gray code order. The first
+/// point of the sequence, the origin, is skipped.
+#[derive(Clone, Debug)]
+pub struct SobolSequence {
+    index: u32,
+    x: Vec<u32>,
+    directions: Vec<[u32; 32]>,
+    shift: Vec<u32>,
+}
+
+impl SobolSequence {
+    /// Default initializer for the structure.
+    ///
+    /// # Arguments
+    ///
+    /// * `dim`     - Dimension of the points, at most `MAX_DIMENSION`
+    ///
+    pub fn new(dim: usize) -> SobolSequence {
+        assert!(
+            dim <= MAX_DIMENSION,
+            "the dimension of the Sobol sequence is limited to {}",
+            MAX_DIMENSION
+        );
+        let mut directions = Vec::with_capacity(dim);
+        for j in 0..dim {
+            let mut v = [0; 32];
+            if j == 0 {
+                for (k, v) in v.iter_mut().enumerate() {
+                    *v = 1 << (31 - k);
+                }
+            } else {
+                let (s, a, m) = DIRECTIONS[j - 1];
+                for k in 0..32 {
+                    v[k] = if k < s {
+                        m[k] << (31 - k)
+                    } else {
+                        let mut v_k = v[k - s] ^ (v[k - s] >> s);
+                        for l in 1..s {
+                            if (a >> (s - 1 - l)) & 1 == 1 {
+                                v_k ^= v[k - l];
+                            }
+                        }
+                        v_k
+                    };
+                }
+            }
+            directions.push(v);
+        }
+        SobolSequence {
+            index: 0,
+            x: vec![0; dim],
+            directions,
+            shift: vec![0; dim],
+        }
+    }
+
+    /// Scrambles the sequence by a random digital shift drawn from a generator with the given
+    /// seed. The shifted sequence keeps the uniformity properties of the original one.
+    pub fn set_seed(&mut self, seed: u64) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        for shift in self.shift.iter_mut() {
+            *shift = rng.gen();
+        }
+    }
+}
+
+impl Iterator for SobolSequence {
+    type Item = Vec<f64>;
+
+    fn next(&mut self) -> Option<Vec<f64>> {
+        if self.index == u32::MAX {
+            return None;
+        }
+        // Gray code: the next point differs by the direction number of the rightmost zero bit
+        let c = (!self.index).trailing_zeros() as usize;
+        self.index += 1;
+        Some(
+            self.x
+                .iter_mut()
+                .zip(self.directions.iter())
+                .zip(self.shift.iter())
+                .map(|((x, v), shift)| {
+                    *x ^= v[c];
+                    f64::from(*x ^ shift) / 4_294_967_296.0
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Structure containing the parameters of the sensitivity analysis.
+pub struct SobolIndices<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+{
+    f: fn(f64, &V, &DVector<f64>, &mut V),
+    y: V,
+    parameters: Vec<Distribution>,
+    output: fn(usize, &[f64], &[V]) -> f64,
+    n: usize,
+    x: f64,
+    x_end: f64,
+    dx: f64,
+    rtol: f64,
+    atol: f64,
+    seed: Option<u64>,
+    num_failed: usize,
+    variance: f64,
+    first_order: Vec<f64>,
+    total: Vec<f64>,
+}
+
+impl<V> SobolIndices<V>
+where
+    V: FiniteDimInnerSpace + Copy + Send + Sync + 'static,
+    <V as InnerSpace>::Real: SubsetOf<f64>,
+{
+    /// Default initializer for the structure.
+    ///
+    /// # Arguments
+    ///
+    /// * `f`           - Pointer to the function to integrate, which depends on parameters p
+    /// * `y`           - Initial value of the dependent variable(s)
+    /// * `parameters`  - Distributions of the independent uncertain parameters
+    /// * `output`      - Function giving the scalar output of the i-th integration from its output
+    ///   points and states
+    /// * `n`           - Number of base samples
+    /// * `x`           - Initial value of the independent variable (usually time)
+    /// * `x_end`       - Final value of the independent variable
+    ///
+    pub fn new(
+        f: fn(f64, &V, &DVector<f64>, &mut V),
+        y: V,
+        parameters: Vec<Distribution>,
+        output: fn(usize, &[f64], &[V]) -> f64,
+        n: usize,
+        x: f64,
+        x_end: f64,
+    ) -> SobolIndices<V> {
+        SobolIndices {
+            f,
+            y,
+            parameters,
+            output,
+            n,
+            x,
+            x_end,
+            dx: (x_end - x) / 100.0,
+            rtol: 1.0e-8,
+            atol: 1.0e-8,
+            seed: None,
+            num_failed: 0,
+            variance: 0.0,
+            first_order: Vec::new(),
+            total: Vec::new(),
+        }
+    }
+
+    /// Sets the increment of the output points passed to the output function. Default is a
+    /// hundredth of the interval.
+    pub fn set_dx(&mut self, dx: f64) {
+        self.dx = dx;
+    }
+
+    /// Sets the relative and absolute tolerances of the integrations. Default is 1e-8 for both.
+    pub fn set_tolerances(&mut self, rtol: f64, atol: f64) {
+        self.rtol = rtol;
+        self.atol = atol;
+    }
+
+    /// Sets the seed of the digital shift of the Sobol sequence. By default, the sequence is not
+    /// scrambled.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = Some(seed);
+    }
+
+    /// Integrates the N (d + 2) parameter sets and estimates the indices. A base sample is
+    /// discarded if one of its integrations failed, and the error of the first failed integration
+    /// is returned if every base sample is discarded. The returned statistics are summed over the
+    /// successful integrations. There can be at most `MAX_DIMENSION / 2` parameters.
+    pub fn integrate(&mut self) -> Result<Stats, SobolError> {
+        let d = self.parameters.len();
+        if 2 * d > MAX_DIMENSION {
+            return Err(SobolError::TooManyParameters {
+                max: MAX_DIMENSION / 2,
+            });
+        }
+        let mut sequence = SobolSequence::new(2 * d);
+        if let Some(seed) = self.seed {
+            sequence.set_seed(seed);
+        }
+        let samples: Vec<(DVector<f64>, DVector<f64>)> = sequence
+            .take(self.n)
+            .map(|u| {
+                let p = |u: &[f64]| {
+                    DVector::from_iterator(
+                        d,
+                        self.parameters
+                            .iter()
+                            .zip(u.iter())
+                            .map(|(p, u)| p.quantile(*u)),
+                    )
+                };
+                (p(&u[..d]), p(&u[d..]))
+            })
+            .collect();
+
+        // The i-th integration is the set r of the base sample j, where r = 0 is A, r = 1 is B
+        // and r = 2 + k is A with the k-th parameter of B
+        let y0 = self.y;
+        let generator = move |i: usize| {
+            let (a, b) = &samples[i / (d + 2)];
+            let p = match i % (d + 2) {
+                0 => a.clone(),
+                1 => b.clone(),
+                r => {
+                    let mut p = a.clone();
+                    p[r - 2] = b[r - 2];
+                    p
+                }
+            };
+            (y0, p)
+        };
+        let mut ensemble = Ensemble::with_generator(
+            self.f,
+            Box::new(generator),
+            self.output,
+            self.n * (d + 2),
+            self.x,
+            self.x_end,
+        );
+        ensemble.set_dx(self.dx);
+        ensemble.set_tolerances(self.rtol, self.atol);
+        let stats = ensemble.integrate();
+
+        let outputs: Vec<Vec<f64>> = ensemble
+            .results()
+            .chunks(d + 2)
+            .filter_map(|r| r.iter().map(|r| r.as_ref().ok().cloned()).collect())
+            .collect();
+        self.num_failed = self.n - outputs.len();
+        if outputs.is_empty() {
+            let error = ensemble.results().iter().find_map(|r| r.as_ref().err());
+            return Err(match error {
+                Some(e) => SobolError::Integration(e.clone()),
+                None => SobolError::NoSample,
+            });
+        }
+        let m = outputs.len() as f64;
+
+        // Variance of the outputs of A and B
+        let mean = outputs.iter().map(|o| o[0] + o[1]).sum::<f64>() / (2.0 * m);
+        self.variance = outputs
+            .iter()
+            .map(|o| (o[0] - mean).powi(2) + (o[1] - mean).powi(2))
+            .sum::<f64>()
+            / (2.0 * m - 1.0);
+
+        self.first_order = (0..d)
+            .map(|k| {
+                outputs
+                    .iter()
+                    .map(|o| o[1] * (o[k + 2] - o[0]))
+                    .sum::<f64>()
+                    / m
+            })
+            .map(|v| v / self.variance)
+            .collect();
+        self.total = (0..d)
+            .map(|k| {
+                outputs
+                    .iter()
+                    .map(|o| (o[0] - o[k + 2]).powi(2))
+                    .sum::<f64>()
+                    / (2.0 * m)
+            })
+            .map(|v| v / self.variance)
+            .collect();
+        Ok(stats)
+    }
+
+    /// Getter for the first-order indices of the parameters.
+    pub fn first_order(&self) -> &Vec<f64> {
+        &self.first_order
+    }
+
+    /// Getter for the total indices of the parameters.
+    pub fn total(&self) -> &Vec<f64> {
+        &self.total
+    }
+
+    /// Variance of the output.
+    pub fn variance(&self) -> f64 {
+        self.variance
+    }
+
+    /// Number of base samples discarded because of a failed integration.
+    pub fn num_failed(&self) -> usize {
+        self.num_failed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use na::Vector3;
+
+    #[test]
+    fn sobol_sequence_is_stratified() {
+        let points: Vec<Vec<f64>> = SobolSequence::new(MAX_DIMENSION).take(1023).collect();
+        for j in 0..MAX_DIMENSION {
+            let mut x: Vec<f64> = points.iter().map(|p| p[j] * 1024.0).collect();
+            x.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            for (i, x) in x.iter().enumerate() {
+                assert_eq!(*x, (i + 1) as f64);
+            }
+        }
+    }
+
+    // Output y0 + 2 y1 + y2 = a + 2 b + a b at x = 1, with a, b and c uniform on [-1, 1]
+    fn system(_x: f64, _y: &Vector3<f64>, p: &DVector<f64>, dy: &mut Vector3<f64>) {
+        dy[0] = p[0];
+        dy[1] = p[1];
+        dy[2] = p[0] * p[1];
+    }
+
+    fn output(_i: usize, _x: &[f64], y: &[Vector3<f64>]) -> f64 {
+        let y = y.last().unwrap();
+        y[0] + 2.0 * y[1] + y[2]
+    }
+
+    #[test]
+    fn indices_of_interacting_parameters() {
+        let mut sobol = SobolIndices::new(
+            system,
+            Vector3::zeros(),
+            vec![Distribution::Uniform(-1.0, 1.0); 3],
+            output,
+            1024,
+            0.0,
+            1.0,
+        );
+        sobol.set_seed(1);
+        sobol.integrate().unwrap();
+        let first_order = [3.0 / 16.0, 0.75, 0.0];
+        let total = [0.25, 13.0 / 16.0, 0.0];
+        assert!((sobol.variance() / (16.0 / 9.0) - 1.0).abs() < 3.0e-2);
+        for k in 0..3 {
+            assert!((sobol.first_order()[k] - first_order[k]).abs() < 1.0e-2);
+            assert!((sobol.total()[k] - total[k]).abs() < 1.0e-2);
+        }
+    }
+
+    #[test]
+    fn too_many_parameters() {
+        let parameters = vec![Distribution::Uniform(-1.0, 1.0); MAX_DIMENSION / 2 + 1];
+        let mut sobol =
+            SobolIndices::new(system, Vector3::zeros(), parameters, output, 8, 0.0, 1.0);
+        match sobol.integrate() {
+            Err(SobolError::TooManyParameters { max }) => assert_eq!(max, MAX_DIMENSION / 2),
+            _ => panic!("the parameters exceed the dimension of the sequence"),
+        }
+    }
+
+    // y0' = p0 y0², which blows up before x = 1 for y0(0) = 1 and p0 > 1
+    fn blow_up(_x: f64, y: &Vector3<f64>, p: &DVector<f64>, dy: &mut Vector3<f64>) {
+        *dy = Vector3::new(p[0] * y[0] * y[0], 0.0, 0.0);
+    }
+
+    #[test]
+    fn every_sample_failed() {
+        let mut sobol = SobolIndices::new(
+            blow_up,
+            Vector3::new(1.0, 0.0, 0.0),
+            vec![Distribution::Uniform(2.0, 3.0)],
+            output,
+            8,
+            0.0,
+            1.0,
+        );
+        match sobol.integrate() {
+            Err(SobolError::Integration(_)) => assert_eq!(sobol.num_failed(), 8),
+            _ => panic!("every integration fails"),
+        }
+    }
+}
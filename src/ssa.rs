@@ -0,0 +1,867 @@
+#![allow(clippy::needless_range_loop)]
+
+//! Stochastic simulation algorithms for reaction networks.
+//!
+//! At low copy numbers, the numbers of molecules of the species of a
+//! [`ReactionNetwork`](../reaction/struct.ReactionNetwork.html) are discrete random variables
+//! which jump when a reaction occurs. Their distribution is the solution of the chemical master
+//! equation, whose trajectories are sampled exactly by:
+//!
+//! * [`Direct`](struct.Direct.html), the direct method of Gillespie, which draws the time to the
+//!   next reaction from the total propensity and the reaction from the individual propensities;
+//! * [`NextReaction`](struct.NextReaction.html), the next reaction method of Gibson and Bruck,
+//!   which keeps the putative times of all the reactions in an indexed priority queue and only
+//!   updates the ones affected by the last reaction, which is faster for large networks.
+//!
+//! [`TauLeaping`](struct.TauLeaping.html) is an approximate method which fires many reactions at
+//! once over leaps during which the propensities change little, with the step size selection and
+//! the treatment of critical reactions of Cao, Gillespie and Petzold (2006).
+//!
+//! The states are given in the same types as for the ODE solvers, with the number of molecules of
+//! the i-th species as the i-th component, and the trajectories use the same output layout. With a
+//! dense output, the piecewise constant state is sampled at regular intervals, and with a sparse
+//! output, it is stored after each reaction or leap. Each call to `integrate` simulates a new
+//! trajectory.
+
+use alga::general::SubsetOf;
+use alga::linear::{FiniteDimInnerSpace, InnerSpace};
+use dop_shared::*;
+use jacobian::{from_dvector, to_dvector};
+use na::DVector;
+use rand::distributions::Poisson;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use reaction::ReactionNetwork;
+use std::f64;
+
+/// Gillespie's direct method.
+pub struct Direct<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+{
+    network: ReactionNetwork,
+    x: f64,
+    x_end: f64,
+    y: V,
+    rng: StdRng,
+    n_max: u32,
+    recorder: Recorder<V>,
+    stats: Stats,
+}
+
+impl<V> Direct<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: SubsetOf<f64>,
+{
+    /// Default initializer for the structure.
+    ///
+    /// # Arguments
+    ///
+    /// * `network` - Reaction network
+    /// * `x`       - Initial value of the independent variable (usually time)
+    /// * `x_end`   - Final value of the independent variable
+    /// * `dx`      - Increment in the dense output. This argument has no effect if the output type is Sparse
+    /// * `y`       - Initial numbers of molecules
+    /// * `seed`    - Seed of the random number generator
+    ///
+    pub fn new(
+        network: ReactionNetwork,
+        x: f64,
+        x_end: f64,
+        dx: f64,
+        y: V,
+        seed: u64,
+    ) -> Direct<V> {
+        Direct {
+            network,
+            x,
+            x_end,
+            y,
+            rng: StdRng::seed_from_u64(seed),
+            n_max: 100_000_000,
+            recorder: Recorder::new(x, x_end, dx),
+            stats: Stats::new(),
+        }
+    }
+
+    /// Sets the type of the output. Default is `OutputType::Dense`.
+    pub fn set_output_type(&mut self, out_type: OutputType) {
+        self.recorder.out_type = out_type;
+    }
+
+    /// Sets the maximum number of reactions. Default is 1e8.
+    pub fn set_n_max(&mut self, n_max: u32) {
+        self.n_max = n_max;
+    }
+
+    /// Simulates a trajectory. The number of function evaluations is the number of evaluations of
+    /// individual propensities, and the number of accepted steps the number of reactions.
+    pub fn integrate(&mut self) -> Result<Stats, IntegrationError> {
+        self.stats = Stats::new();
+        let reactions = self.network.reactions();
+        let dependencies = self.network.dependencies();
+        let mut n = to_dvector(&self.y).as_slice().to_vec();
+        let mut x = self.x;
+        self.recorder.start(&n);
+
+        let mut a: Vec<f64> = reactions.iter().map(|r| r.propensity(&n)).collect();
+        self.stats.num_eval += reactions.len() as u32;
+        loop {
+            let a0: f64 = a.iter().sum();
+            if a0 <= 0.0 {
+                break;
+            }
+            let tau = waiting_time(&mut self.rng, a0);
+            if x + tau >= self.x_end {
+                break;
+            }
+            if self.stats.accepted_steps >= self.n_max {
+                return Err(IntegrationError::MaxNumStepReached {
+                    x,
+                    n_step: self.stats.accepted_steps,
+                });
+            }
+            let j = match select(&a, self.rng.gen::<f64>() * a0) {
+                Some(j) => j,
+                None => break,
+            };
+
+            x += tau;
+            self.recorder.advance(x, &n);
+            for &(i, c) in reactions[j].change() {
+                n[i] += c;
+            }
+            self.recorder.event(x, &n);
+            for &k in &dependencies[j] {
+                a[k] = reactions[k].propensity(&n);
+            }
+            self.stats.num_eval += dependencies[j].len() as u32;
+            self.stats.accepted_steps += 1;
+        }
+        self.recorder.finish(&n);
+        Ok(self.stats)
+    }
+
+    /// Getter for the independent variable's output.
+    pub fn x_out(&self) -> &Vec<f64> {
+        &self.recorder.x_out
+    }
+
+    /// Getter for the dependent variables' output.
+    pub fn y_out(&self) -> &Vec<V> {
+        &self.recorder.y_out
+    }
+}
+
+/// Next reaction method of Gibson and Bruck.
+pub struct NextReaction<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+{
+    network: ReactionNetwork,
+    x: f64,
+    x_end: f64,
+    y: V,
+    rng: StdRng,
+    n_max: u32,
+    recorder: Recorder<V>,
+    stats: Stats,
+}
+
+impl<V> NextReaction<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: SubsetOf<f64>,
+{
+    /// Default initializer for the structure.
+    ///
+    /// # Arguments
+    ///
+    /// * `network` - Reaction network
+    /// * `x`       - Initial value of the independent variable (usually time)
+    /// * `x_end`   - Final value of the independent variable
+    /// * `dx`      - Increment in the dense output. This argument has no effect if the output type is Sparse
+    /// * `y`       - Initial numbers of molecules
+    /// * `seed`    - Seed of the random number generator
+    ///
+    pub fn new(
+        network: ReactionNetwork,
+        x: f64,
+        x_end: f64,
+        dx: f64,
+        y: V,
+        seed: u64,
+    ) -> NextReaction<V> {
+        NextReaction {
+            network,
+            x,
+            x_end,
+            y,
+            rng: StdRng::seed_from_u64(seed),
+            n_max: 100_000_000,
+            recorder: Recorder::new(x, x_end, dx),
+            stats: Stats::new(),
+        }
+    }
+
+    /// Sets the type of the output. Default is `OutputType::Dense`.
+    pub fn set_output_type(&mut self, out_type: OutputType) {
+        self.recorder.out_type = out_type;
+    }
+
+    /// Sets the maximum number of reactions. Default is 1e8.
+    pub fn set_n_max(&mut self, n_max: u32) {
+        self.n_max = n_max;
+    }
+
+    /// Simulates a trajectory. The number of function evaluations is the number of evaluations of
+    /// individual propensities, and the number of accepted steps the number of reactions.
+    pub fn integrate(&mut self) -> Result<Stats, IntegrationError> {
+        self.stats = Stats::new();
+        let reactions = self.network.reactions();
+        let dependencies = self.network.dependencies();
+        let mut n = to_dvector(&self.y).as_slice().to_vec();
+        let mut x = self.x;
+        self.recorder.start(&n);
+        if reactions.is_empty() {
+            self.recorder.finish(&n);
+            return Ok(self.stats);
+        }
+
+        // Putative times of the reactions
+        let mut a: Vec<f64> = reactions.iter().map(|r| r.propensity(&n)).collect();
+        self.stats.num_eval += reactions.len() as u32;
+        let rng = &mut self.rng;
+        let times = a.iter().map(|&a| x + waiting_time(rng, a)).collect();
+        let mut queue = IndexedQueue::new(times);
+        loop {
+            let (j, t) = queue.first();
+            if t >= self.x_end {
+                break;
+            }
+            if self.stats.accepted_steps >= self.n_max {
+                return Err(IntegrationError::MaxNumStepReached {
+                    x,
+                    n_step: self.stats.accepted_steps,
+                });
+            }
+
+            x = t;
+            self.recorder.advance(x, &n);
+            for &(i, c) in reactions[j].change() {
+                n[i] += c;
+            }
+            self.recorder.event(x, &n);
+
+            for &k in &dependencies[j] {
+                let a_new = reactions[k].propensity(&n);
+                let t_k = if k != j && a[k] > 0.0 && a_new > 0.0 {
+                    x + a[k] / a_new * (queue.time(k) - x)
+                } else {
+                    x + waiting_time(&mut self.rng, a_new)
+                };
+                a[k] = a_new;
+                queue.update(k, t_k);
+            }
+            if !dependencies[j].contains(&j) {
+                let t_j = x + waiting_time(&mut self.rng, a[j]);
+                queue.update(j, t_j);
+            }
+            self.stats.num_eval += dependencies[j].len() as u32;
+            self.stats.accepted_steps += 1;
+        }
+        self.recorder.finish(&n);
+        Ok(self.stats)
+    }
+
+    /// Getter for the independent variable's output.
+    pub fn x_out(&self) -> &Vec<f64> {
+        &self.recorder.x_out
+    }
+
+    /// Getter for the dependent variables' output.
+    pub fn y_out(&self) -> &Vec<V> {
+        &self.recorder.y_out
+    }
+}
+
+/// Tau-leaping method with the step size selection of Cao, Gillespie and Petzold.
+pub struct TauLeaping<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+{
+    network: ReactionNetwork,
+    x: f64,
+    x_end: f64,
+    y: V,
+    rng: StdRng,
+    epsilon: f64,
+    n_critical: f64,
+    n_max: u32,
+    recorder: Recorder<V>,
+    stats: Stats,
+}
+
+impl<V> TauLeaping<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: SubsetOf<f64>,
+{
+    /// Default initializer for the structure.
+    ///
+    /// # Arguments
+    ///
+    /// * `network` - Reaction network
+    /// * `x`       - Initial value of the independent variable (usually time)
+    /// * `x_end`   - Final value of the independent variable
+    /// * `dx`      - Increment in the dense output. This argument has no effect if the output type is Sparse
+    /// * `y`       - Initial numbers of molecules
+    /// * `seed`    - Seed of the random number generator
+    ///
+    pub fn new(
+        network: ReactionNetwork,
+        x: f64,
+        x_end: f64,
+        dx: f64,
+        y: V,
+        seed: u64,
+    ) -> TauLeaping<V> {
+        TauLeaping {
+            network,
+            x,
+            x_end,
+            y,
+            rng: StdRng::seed_from_u64(seed),
+            epsilon: 0.03,
+            n_critical: 10.0,
+            n_max: 100_000_000,
+            recorder: Recorder::new(x, x_end, dx),
+            stats: Stats::new(),
+        }
+    }
+
+    /// Sets the error control parameter, the bound on the relative change of the propensities
+    /// during a leap. Default is 0.03. The statistics of the leaps are biased by an error of the
+    /// order of epsilon.
+    pub fn set_epsilon(&mut self, epsilon: f64) {
+        self.epsilon = epsilon;
+    }
+
+    /// Sets the type of the output. Default is `OutputType::Dense`.
+    pub fn set_output_type(&mut self, out_type: OutputType) {
+        self.recorder.out_type = out_type;
+    }
+
+    /// Sets the maximum number of steps. Default is 1e8.
+    pub fn set_n_max(&mut self, n_max: u32) {
+        self.n_max = n_max;
+    }
+
+    /// Simulates a trajectory. The number of function evaluations is the number of evaluations of
+    /// individual propensities. The accepted steps are the leaps and the exact steps taken when
+    /// the leaps would be too short, and the rejected steps the leaps which would have made a
+    /// number of molecules negative.
+    pub fn integrate(&mut self) -> Result<Stats, IntegrationError> {
+        self.stats = Stats::new();
+        let reactions = self.network.reactions();
+        let m = reactions.len();
+        let mut n = to_dvector(&self.y).as_slice().to_vec();
+        let mut x = self.x;
+        let mut a = vec![0.0; m];
+        let mut n_new = n.clone();
+        self.recorder.start(&n);
+
+        while x < self.x_end {
+            if self.stats.accepted_steps >= self.n_max {
+                return Err(IntegrationError::MaxNumStepReached {
+                    x,
+                    n_step: self.stats.accepted_steps,
+                });
+            }
+            for j in 0..m {
+                a[j] = reactions[j].propensity(&n);
+            }
+            self.stats.num_eval += m as u32;
+            let a0: f64 = a.iter().sum();
+            if a0 <= 0.0 {
+                break;
+            }
+
+            // A reaction is critical if it can only occur a few more times before exhausting one
+            // of its reactants
+            let critical: Vec<bool> = (0..m)
+                .map(|j| {
+                    a[j] > 0.0
+                        && reactions[j]
+                            .change()
+                            .iter()
+                            .any(|&(i, c)| c < 0.0 && (n[i] / -c).floor() < self.n_critical)
+                })
+                .collect();
+            let mut tau1 = self.leap_size(&n, &a, &critical);
+
+            // Exact steps when the leap is too short to be worth it
+            if tau1 < 10.0 / a0 {
+                for _ in 0..100 {
+                    let a0: f64 = a.iter().sum();
+                    if a0 <= 0.0 {
+                        break;
+                    }
+                    let tau = waiting_time(&mut self.rng, a0);
+                    if x + tau >= self.x_end {
+                        x = self.x_end;
+                        break;
+                    }
+                    let j = match select(&a, self.rng.gen::<f64>() * a0) {
+                        Some(j) => j,
+                        None => break,
+                    };
+                    x += tau;
+                    self.recorder.advance(x, &n);
+                    for &(i, c) in reactions[j].change() {
+                        n[i] += c;
+                    }
+                    self.recorder.event(x, &n);
+                    for k in 0..m {
+                        a[k] = reactions[k].propensity(&n);
+                    }
+                    self.stats.num_eval += m as u32;
+                    self.stats.accepted_steps += 1;
+                }
+                continue;
+            }
+
+            // Leap, at most one critical reaction being fired
+            let a0_critical: f64 = (0..m).filter(|&j| critical[j]).map(|j| a[j]).sum();
+            loop {
+                let tau2 = waiting_time(&mut self.rng, a0_critical);
+                let (mut tau, mut fire_critical) = (tau1.min(tau2), tau2 <= tau1);
+                if x + tau >= self.x_end {
+                    tau = self.x_end - x;
+                    fire_critical = false;
+                }
+
+                n_new.copy_from_slice(&n);
+                for j in 0..m {
+                    let k = if critical[j] || a[j] * tau <= 0.0 {
+                        0.0
+                    } else {
+                        self.rng.sample(Poisson::new(a[j] * tau)) as f64
+                    };
+                    for &(i, c) in reactions[j].change() {
+                        n_new[i] += k * c;
+                    }
+                }
+                if fire_critical {
+                    let r = self.rng.gen::<f64>() * a0_critical;
+                    let a_critical: Vec<f64> = (0..m)
+                        .map(|j| if critical[j] { a[j] } else { 0.0 })
+                        .collect();
+                    if let Some(j) = select(&a_critical, r) {
+                        for &(i, c) in reactions[j].change() {
+                            n_new[i] += c;
+                        }
+                    }
+                }
+
+                if n_new.iter().any(|&n| n < 0.0) {
+                    tau1 *= 0.5;
+                    self.stats.rejected_steps += 1;
+                    continue;
+                }
+                x = if tau == self.x_end - x {
+                    self.x_end
+                } else {
+                    x + tau
+                };
+                self.recorder.advance(x, &n);
+                n.copy_from_slice(&n_new);
+                self.recorder.event(x, &n);
+                self.stats.accepted_steps += 1;
+                break;
+            }
+        }
+        self.recorder.finish(&n);
+        Ok(self.stats)
+    }
+
+    /// Largest leap for which the relative changes of the propensities of the non-critical
+    /// reactions are expected to be bounded by epsilon.
+    fn leap_size(&self, n: &[f64], a: &[f64], critical: &[bool]) -> f64 {
+        let reactions = self.network.reactions();
+        let mut mu = vec![0.0; n.len()];
+        let mut sigma2 = vec![0.0; n.len()];
+        for (j, r) in reactions.iter().enumerate() {
+            if !critical[j] {
+                for &(i, c) in r.change() {
+                    mu[i] += c * a[j];
+                    sigma2[i] += c * c * a[j];
+                }
+            }
+        }
+
+        // All the reactant species are bounded, including the ones of the reactions which
+        // cannot occur yet
+        let mut tau = f64::INFINITY;
+        for r in reactions {
+            let order: u32 = r.reactants().iter().map(|&(_, k)| k).sum();
+            for &(i, k) in r.reactants() {
+                // Bound on the relative change of the number of molecules of the reactant, which
+                // bounds the relative change of the propensities of the highest order reaction
+                let m = (n[i] - 1.0).max(1.0);
+                let g = match (order, k) {
+                    (1, _) => 1.0,
+                    (2, 2) => 2.0 + 1.0 / m,
+                    (3, 2) => 1.5 * (2.0 + 1.0 / m),
+                    (3, 3) => 3.0 + 1.0 / m + 2.0 / (m - 1.0).max(1.0),
+                    (order, _) => f64::from(order),
+                };
+                let bound = (self.epsilon * n[i] / g).max(1.0);
+                if mu[i] != 0.0 {
+                    tau = tau.min(bound / mu[i].abs());
+                }
+                if sigma2[i] != 0.0 {
+                    tau = tau.min(bound * bound / sigma2[i]);
+                }
+            }
+        }
+        tau
+    }
+
+    /// Getter for the independent variable's output.
+    pub fn x_out(&self) -> &Vec<f64> {
+        &self.recorder.x_out
+    }
+
+    /// Getter for the dependent variables' output.
+    pub fn y_out(&self) -> &Vec<V> {
+        &self.recorder.y_out
+    }
+}
+
+/// Output of a trajectory.
+struct Recorder<V> {
+    out_type: OutputType,
+    x0: f64,
+    x_end: f64,
+    dx: f64,
+    k: u32,
+    x_out: Vec<f64>,
+    y_out: Vec<V>,
+}
+
+impl<V> Recorder<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: SubsetOf<f64>,
+{
+    fn new(x0: f64, x_end: f64, dx: f64) -> Recorder<V> {
+        Recorder {
+            out_type: OutputType::Dense,
+            x0,
+            x_end,
+            dx,
+            k: 0,
+            x_out: Vec::new(),
+            y_out: Vec::new(),
+        }
+    }
+
+    /// Clears the output and stores the initial state.
+    fn start(&mut self, n: &[f64]) {
+        self.x_out.clear();
+        self.y_out.clear();
+        self.x_out.push(self.x0);
+        self.y_out
+            .push(from_dvector(&DVector::from_column_slice(n)));
+        self.k = 1;
+    }
+
+    /// k-th point of the dense output, the last one being rounded to x_end.
+    fn point(&self, k: u32) -> f64 {
+        let x = self.x0 + f64::from(k) * self.dx;
+        if (x - self.x_end).abs() <= 1.0e-9 * self.dx {
+            self.x_end
+        } else {
+            x
+        }
+    }
+
+    /// Stores the dense output before the jump at x, where the state was n.
+    fn advance(&mut self, x: f64, n: &[f64]) {
+        if self.out_type == OutputType::Dense {
+            while self.point(self.k) < x {
+                self.x_out.push(self.point(self.k));
+                self.y_out
+                    .push(from_dvector(&DVector::from_column_slice(n)));
+                self.k += 1;
+            }
+        }
+    }
+
+    /// Stores the sparse output after the jump at x to the state n.
+    fn event(&mut self, x: f64, n: &[f64]) {
+        if self.out_type == OutputType::Sparse {
+            self.x_out.push(x);
+            self.y_out
+                .push(from_dvector(&DVector::from_column_slice(n)));
+        }
+    }
+
+    /// Stores the output until x_end, where the state is n.
+    fn finish(&mut self, n: &[f64]) {
+        if self.out_type == OutputType::Dense {
+            while self.point(self.k) <= self.x_end {
+                self.x_out.push(self.point(self.k));
+                self.y_out
+                    .push(from_dvector(&DVector::from_column_slice(n)));
+                self.k += 1;
+            }
+        } else if *self.x_out.last().unwrap() < self.x_end {
+            self.x_out.push(self.x_end);
+            self.y_out
+                .push(from_dvector(&DVector::from_column_slice(n)));
+        }
+    }
+}
+
+/// Binary heap of the putative times of the reactions, which can be updated by reaction.
+struct IndexedQueue {
+    heap: Vec<usize>,
+    position: Vec<usize>,
+    times: Vec<f64>,
+}
+
+impl IndexedQueue {
+    fn new(times: Vec<f64>) -> IndexedQueue {
+        let m = times.len();
+        let mut queue = IndexedQueue {
+            heap: (0..m).collect(),
+            position: (0..m).collect(),
+            times,
+        };
+        for p in (0..m / 2).rev() {
+            queue.sift_down(p);
+        }
+        queue
+    }
+
+    /// Reaction with the earliest time, and this time.
+    fn first(&self) -> (usize, f64) {
+        (self.heap[0], self.times[self.heap[0]])
+    }
+
+    fn time(&self, j: usize) -> f64 {
+        self.times[j]
+    }
+
+    fn update(&mut self, j: usize, time: f64) {
+        self.times[j] = time;
+        let p = self.position[j];
+        self.sift_up(p);
+        let p = self.position[j];
+        self.sift_down(p);
+    }
+
+    fn sift_up(&mut self, mut p: usize) {
+        while p > 0 {
+            let parent = (p - 1) / 2;
+            if self.times[self.heap[p]] < self.times[self.heap[parent]] {
+                self.swap(p, parent);
+                p = parent;
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn sift_down(&mut self, mut p: usize) {
+        loop {
+            let mut first = p;
+            for c in &[2 * p + 1, 2 * p + 2] {
+                if *c < self.heap.len() && self.times[self.heap[*c]] < self.times[self.heap[first]]
+                {
+                    first = *c;
+                }
+            }
+            if first == p {
+                break;
+            }
+            self.swap(p, first);
+            p = first;
+        }
+    }
+
+    fn swap(&mut self, p: usize, q: usize) {
+        self.heap.swap(p, q);
+        self.position[self.heap[p]] = p;
+        self.position[self.heap[q]] = q;
+    }
+}
+
+/// Sample of the exponential distribution of mean 1.
+fn exponential(rng: &mut StdRng) -> f64 {
+    -(1.0 - rng.gen::<f64>()).ln()
+}
+
+/// Time to the next occurrence of an event of propensity a, infinite if a is zero.
+fn waiting_time(rng: &mut StdRng, a: f64) -> f64 {
+    if a > 0.0 {
+        exponential(rng) / a
+    } else {
+        f64::INFINITY
+    }
+}
+
+/// Index of the reaction selected by r, uniform between 0 and the sum of the propensities a, or
+/// None if no propensity is positive.
+fn select(a: &[f64], r: f64) -> Option<usize> {
+    let mut sum = 0.0;
+    for (j, a) in a.iter().enumerate() {
+        sum += a;
+        if r < sum {
+            return Some(j);
+        }
+    }
+    // Rounding: the last reaction with a positive propensity
+    a.iter().rposition(|&a| a > 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use na::{Vector1, Vector2, Vector3};
+
+    // Birth and death process, whose stationary distribution is Poisson of mean k
+    fn birth_death(k: f64) -> ReactionNetwork {
+        let mut network = ReactionNetwork::new(1);
+        network.add_reaction(&[], &[(0, 1)], k);
+        network.add_reaction(&[(0, 1)], &[], 1.0);
+        network
+    }
+
+    fn check(x_out: &[f64], y_out: &[Vector1<f64>], k: f64) {
+        assert_eq!(x_out.len(), 2001);
+        assert_eq!(*x_out.last().unwrap(), 2000.0);
+        let stationary = &y_out[100..];
+        let mean = stationary.iter().map(|y| y[0]).sum::<f64>() / stationary.len() as f64;
+        let var = stationary
+            .iter()
+            .map(|y| (y[0] - mean).powi(2))
+            .sum::<f64>()
+            / stationary.len() as f64;
+        assert!((mean / k - 1.0).abs() < 0.02);
+        assert!((var / k - 1.0).abs() < 0.2);
+    }
+
+    #[test]
+    fn stationary_distribution_of_birth_death() {
+        let y0 = Vector1::new(0.0);
+        let mut direct = Direct::new(birth_death(100.0), 0.0, 2000.0, 1.0, y0, 0);
+        direct.integrate().unwrap();
+        check(direct.x_out(), direct.y_out(), 100.0);
+
+        let mut next = NextReaction::new(birth_death(100.0), 0.0, 2000.0, 1.0, y0, 1);
+        next.integrate().unwrap();
+        check(next.x_out(), next.y_out(), 100.0);
+
+        // The leaps inflate the variance by a relative error of the order of their size, hence
+        // the small epsilon, but remain far fewer than the four million reactions
+        let mut tau = TauLeaping::new(birth_death(1000.0), 0.0, 2000.0, 1.0, y0, 2);
+        tau.set_epsilon(0.01);
+        let stats = tau.integrate().unwrap();
+        check(tau.x_out(), tau.y_out(), 1000.0);
+        assert!(stats.accepted_steps < 100000);
+    }
+
+    #[test]
+    fn selection_without_positive_propensity() {
+        assert_eq!(select(&[0.0, 2.0, 1.0], 2.5), Some(2));
+        // Rounding of the sum of the propensities
+        assert_eq!(select(&[1.0, 2.0, 0.0], 3.0), Some(1));
+        assert_eq!(select(&[0.0, 0.0], 0.0), None);
+        assert_eq!(select(&[f64::NAN, 0.0], 0.5), None);
+    }
+
+    #[test]
+    fn critical_reactions_of_tau_leaping() {
+        // The decay of the few molecules of A is critical and fired at most once per leap, while
+        // the birth and death of B are leaped over
+        let mut network = ReactionNetwork::new(2);
+        network.add_reaction(&[(0, 1)], &[], 1.0);
+        network.add_reaction(&[], &[(1, 1)], 1000.0);
+        network.add_reaction(&[(1, 1)], &[], 1.0);
+        let y0: Vector2<f64> = Vector2::new(5.0, 1000.0);
+        let trajectories = 1000;
+        let mut mean = 0.0;
+        for seed in 0..trajectories {
+            let mut tau = TauLeaping::new(network.clone(), 0.0, 1.0, 1.0, y0, seed);
+            let stats = tau.integrate().unwrap();
+            assert!(stats.accepted_steps < 100);
+            for y in tau.y_out() {
+                assert!(y[0] >= 0.0 && y[0] == y[0].round());
+            }
+            mean += tau.y_out()[1][0] / trajectories as f64;
+        }
+        assert!((mean - 5.0 * (-1.0_f64).exp()).abs() < 0.1);
+    }
+
+    // Chain A -> B -> C, in which each reaction changes the propensity of the other one
+    fn chain() -> ReactionNetwork {
+        let mut network = ReactionNetwork::new(3);
+        network.add_reaction(&[(0, 1)], &[(1, 1)], 1.0);
+        network.add_reaction(&[(1, 1)], &[(2, 1)], 2.0);
+        network
+    }
+
+    #[test]
+    fn dependencies_of_the_next_reaction_method() {
+        let y0 = Vector3::new(100.0, 0.0, 0.0);
+        let trajectories = 500;
+        let mut mean = Vector3::zeros();
+        for seed in 0..trajectories {
+            let mut next = NextReaction::new(chain(), 0.0, 1.0, 1.0, y0, seed);
+            next.integrate().unwrap();
+            let y = next.y_out()[1];
+            assert_eq!(y[0] + y[1] + y[2], 100.0);
+            mean += y / trajectories as f64;
+        }
+        let (e1, e2) = ((-1.0_f64).exp(), (-2.0_f64).exp());
+        let exact = Vector3::new(100.0 * e1, 100.0 * (e1 - e2), 100.0 * (1.0 - 2.0 * e1 + e2));
+        for i in 0..3 {
+            assert!((mean[i] - exact[i]).abs() < 1.0);
+        }
+    }
+
+    #[test]
+    fn trajectories_depend_on_the_seed_only() {
+        let y0 = Vector3::new(100.0, 0.0, 0.0);
+        let (x_end, dx) = (3.0, 0.1);
+        let runs = |seed| {
+            let mut direct = Direct::new(chain(), 0.0, x_end, dx, y0, seed);
+            direct.integrate().unwrap();
+            let mut next = NextReaction::new(chain(), 0.0, x_end, dx, y0, seed);
+            next.integrate().unwrap();
+            let mut tau = TauLeaping::new(chain(), 0.0, x_end, dx, y0, seed);
+            tau.integrate().unwrap();
+            vec![
+                direct.y_out().clone(),
+                next.y_out().clone(),
+                tau.y_out().clone(),
+            ]
+        };
+        assert_eq!(runs(7), runs(7));
+        let (first, second) = (runs(7), runs(8));
+        for k in 0..3 {
+            assert_ne!(first[k], second[k]);
+        }
+
+        // A new trajectory is simulated at each call
+        let mut direct = Direct::new(chain(), 0.0, x_end, dx, y0, 7);
+        direct.integrate().unwrap();
+        let y_out = direct.y_out().clone();
+        direct.integrate().unwrap();
+        assert_ne!(*direct.y_out(), y_out);
+    }
+}
@@ -0,0 +1,187 @@
+//! State transition matrix.
+//!
+//! The state transition matrix Φ(x, x0) = ∂y(x)/∂y(x0) satisfies the variational equations
+//! _Φ' = J(x, y) Φ_ with Φ(x0, x0) = I. [`StateTransition`](struct.StateTransition.html) integrates
+//! them together with the solution with Dop853 and returns Φ at every output point.
+
+use alga::general::SubsetOf;
+use alga::linear::{FiniteDimInnerSpace, InnerSpace};
+use dop853::Dop853;
+use dop_shared::*;
+use na;
+use na::DMatrix;
+use sensitivity::Sensitivity;
+
+/// Structure containing the parameters for the numerical integration of the solution and of its
+/// state transition matrix.
+pub struct StateTransition<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+{
+    stepper: Dop853<V>,
+    sensitivity: Option<Sensitivity<V>>,
+    phi_out: Vec<DMatrix<f64>>,
+}
+
+impl<V> StateTransition<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+    <V as InnerSpace>::Real: SubsetOf<f64>,
+{
+    /// Default initializer for the structure.
+    ///
+    /// # Arguments
+    ///
+    /// * `f`       - Pointer to the function to integrate
+    /// * `x`       - Initial value of the independent variable (usually time)
+    /// * `x_end`   - Final value of the independent variable
+    /// * `dx`      - Increment in the dense output. This argument has no effect if the output type is Sparse
+    /// * `y`       - Initial value of the dependent variable(s)
+    /// * `rtol`    - Relative tolerance used in the computation of the adaptive step size
+    /// * `atol`    - Absolute tolerance used in the computation of the adaptive step size
+    ///
+    pub fn new(
+        f: fn(f64, &V, &mut V),
+        x: f64,
+        x_end: f64,
+        dx: f64,
+        y: V,
+        rtol: f64,
+        atol: f64,
+    ) -> StateTransition<V> {
+        StateTransition::from_stepper(Dop853::new(f, x, x_end, dx, y, rtol, atol))
+    }
+
+    /// Initializer from a stepper created with `Dop853::from_param`, to set the parameters of the
+    /// integration.
+    pub fn from_stepper(stepper: Dop853<V>) -> StateTransition<V> {
+        StateTransition {
+            stepper,
+            sensitivity: Some(Sensitivity::initial_conditions()),
+            phi_out: Vec::new(),
+        }
+    }
+
+    /// Sets the function computing the Jacobian matrix of f with respect to y. If no Jacobian is
+    /// given, it is approximated by finite differences, and tolerances much tighter than the
+    /// square root of the machine precision should be avoided.
+    pub fn set_jacobian(&mut self, jac: fn(f64, &V, &mut DMatrix<f64>)) {
+        if let Some(ref mut sensitivity) = self.sensitivity {
+            sensitivity.set_jacobian(jac);
+        }
+    }
+
+    /// Includes (or not) the state transition matrix in the error estimate used to select the step
+    /// size. Default is true.
+    pub fn set_error_control(&mut self, error_control: bool) {
+        if let Some(ref mut sensitivity) = self.sensitivity {
+            sensitivity.set_error_control(error_control);
+        }
+    }
+
+    /// Core integration method.
+    pub fn integrate(&mut self) -> Result<Stats, IntegrationError> {
+        if let Some(sensitivity) = self.sensitivity.take() {
+            self.stepper.set_sensitivity(sensitivity);
+        }
+        let stats = self.stepper.integrate()?;
+
+        let dim = na::dimension::<V>();
+        self.phi_out = self
+            .stepper
+            .s_out()
+            .iter()
+            .map(|columns| DMatrix::from_fn(dim, dim, |i, j| na::convert(columns[j][i])))
+            .collect();
+        Ok(stats)
+    }
+
+    /// Getter for the independent variable's output.
+    pub fn x_out(&self) -> &Vec<f64> {
+        self.stepper.x_out()
+    }
+
+    /// Getter for the dependent variables' output.
+    pub fn y_out(&self) -> &Vec<V> {
+        self.stepper.y_out()
+    }
+
+    /// Getter for the state transition matrices Φ(x, x0) at the points of `x_out()`.
+    pub fn phi_out(&self) -> &Vec<DMatrix<f64>> {
+        &self.phi_out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use na::Vector2;
+
+    fn van_der_pol(_x: f64, y: &Vector2<f64>, dy: &mut Vector2<f64>) {
+        dy[0] = y[1];
+        dy[1] = (1.0 - y[0] * y[0]) * y[1] - y[0];
+    }
+
+    fn van_der_pol_jacobian(_x: f64, y: &Vector2<f64>, jac: &mut DMatrix<f64>) {
+        jac[(0, 0)] = 0.0;
+        jac[(0, 1)] = 1.0;
+        jac[(1, 0)] = -2.0 * y[0] * y[1] - 1.0;
+        jac[(1, 1)] = 1.0 - y[0] * y[0];
+    }
+
+    fn pendulum(_x: f64, y: &Vector2<f64>, dy: &mut Vector2<f64>) {
+        dy[0] = y[1];
+        dy[1] = -y[0].sin();
+    }
+
+    fn pendulum_jacobian(_x: f64, y: &Vector2<f64>, jac: &mut DMatrix<f64>) {
+        jac[(0, 0)] = 0.0;
+        jac[(0, 1)] = 1.0;
+        jac[(1, 0)] = -y[0].cos();
+        jac[(1, 1)] = 0.0;
+    }
+
+    #[test]
+    fn finite_differences() {
+        let y0 = Vector2::new(2.0, 0.0);
+        let solve = |y0: Vector2<f64>| {
+            let mut stepper = Dop853::new(van_der_pol, 0.0, 3.0, 3.0, y0, 1e-12, 1e-12);
+            stepper.integrate().unwrap();
+            *stepper.y_out().last().unwrap()
+        };
+        let delta = 1e-6;
+        let mut phi_fd = DMatrix::zeros(2, 2);
+        for j in 0..2 {
+            let mut e = Vector2::zeros();
+            e[j] = delta;
+            let column = (solve(y0 + e) - solve(y0 - e)) / (2.0 * delta);
+            phi_fd[(0, j)] = column[0];
+            phi_fd[(1, j)] = column[1];
+        }
+
+        for &analytic in &[true, false] {
+            let mut stm = StateTransition::new(van_der_pol, 0.0, 3.0, 3.0, y0, 1e-10, 1e-10);
+            if analytic {
+                stm.set_jacobian(van_der_pol_jacobian);
+            }
+            stm.integrate().unwrap();
+            assert_eq!(*stm.x_out().last().unwrap(), 3.0);
+            assert!((stm.phi_out()[0].clone() - DMatrix::identity(2, 2)).amax() < 1e-15);
+            let phi = stm.phi_out().last().unwrap();
+            assert!((phi - &phi_fd).amax() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn area_preservation() {
+        // The flow of a Hamiltonian system preserves the area: det Φ = 1
+        let y0 = Vector2::new(1.0, 0.5);
+        let mut stm = StateTransition::new(pendulum, 0.0, 20.0, 1.0, y0, 1e-10, 1e-10);
+        stm.set_jacobian(pendulum_jacobian);
+        stm.integrate().unwrap();
+        assert_eq!(stm.phi_out().len(), 21);
+        for phi in stm.phi_out() {
+            assert!((phi.determinant() - 1.0).abs() < 1e-8);
+        }
+    }
+}
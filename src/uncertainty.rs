@@ -0,0 +1,677 @@
+#![allow(clippy::needless_range_loop)]
+
+//! Propagation of uncertainties.
+//!
+//! Two alternatives to Monte Carlo ensembles which need far fewer integrations when the
+//! uncertainties are moderate:
+//!
+//! * [`UnscentedTransform`](struct.UnscentedTransform.html) propagates a Gaussian uncertainty of
+//!   the initial state. The 2n + 1 sigma points of the initial mean and covariance are integrated
+//!   with Dopri5, and the mean and covariance of the state are reconstructed from them at every
+//!   output point.
+//! * [`PolynomialChaos`](struct.PolynomialChaos.html) builds a non-intrusive polynomial chaos
+//!   expansion of the state with respect to uncertain parameters, which are independent and
+//!   either normal or uniform. The system is integrated at the nodes of a tensor Gauss quadrature
+//!   and the coefficients of the orthonormal Hermite and Legendre polynomials of total degree up
+//!   to the order of the expansion are obtained by projection. The mean and the variance follow
+//!   from the coefficients, and the expansion can be evaluated as a cheap surrogate of the model.
+//!
+//! With the `rayon` feature, the integrations are run in parallel.
+
+use alga::general::SubsetOf;
+use alga::linear::{FiniteDimInnerSpace, InnerSpace};
+use dop_shared::*;
+use dopri5::Dopri5;
+use jacobian::{from_dvector, to_dvector};
+use na;
+use na::{DMatrix, DVector};
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+use std::cmp::Reverse;
+
+/// Output points, states and statistics of one integration.
+type Trajectory<V> = Result<(Vec<f64>, Vec<V>, Stats), IntegrationError>;
+
+/// Common output points, states and summed statistics of several integrations.
+type Trajectories<V> = Result<(Vec<f64>, Vec<Vec<V>>, Stats), IntegrationError>;
+
+/// Structure containing the parameters of the unscented transform.
+pub struct UnscentedTransform<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+{
+    f: fn(f64, &V, &mut V),
+    x: f64,
+    x_end: f64,
+    dx: f64,
+    mean: V,
+    covariance: DMatrix<f64>,
+    rtol: f64,
+    atol: f64,
+    alpha: f64,
+    beta: f64,
+    kappa: f64,
+    x_out: Vec<f64>,
+    mean_out: Vec<V>,
+    covariance_out: Vec<DMatrix<f64>>,
+}
+
+impl<V> UnscentedTransform<V>
+where
+    V: FiniteDimInnerSpace + Copy + Send + Sync,
+    <V as InnerSpace>::Real: SubsetOf<f64>,
+{
+    /// Default initializer for the structure.
+    ///
+    /// # Arguments
+    ///
+    /// * `f`           - Pointer to the function to integrate
+    /// * `x`           - Initial value of the independent variable (usually time)
+    /// * `x_end`       - Final value of the independent variable
+    /// * `dx`          - Increment between the output points
+    /// * `mean`        - Mean of the initial state
+    /// * `covariance`  - Covariance of the initial state, symmetric positive semidefinite
+    /// * `rtol`        - Relative tolerance used in the computation of the adaptive step size
+    /// * `atol`        - Absolute tolerance used in the computation of the adaptive step size
+    ///
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        f: fn(f64, &V, &mut V),
+        x: f64,
+        x_end: f64,
+        dx: f64,
+        mean: V,
+        covariance: DMatrix<f64>,
+        rtol: f64,
+        atol: f64,
+    ) -> UnscentedTransform<V> {
+        UnscentedTransform {
+            f,
+            x,
+            x_end,
+            dx,
+            mean,
+            covariance,
+            rtol,
+            atol,
+            alpha: 1.0,
+            beta: 2.0,
+            kappa: 0.0,
+            x_out: Vec::new(),
+            mean_out: Vec::new(),
+            covariance_out: Vec::new(),
+        }
+    }
+
+    /// Sets the parameters of the sigma points: alpha controls their spread, beta incorporates
+    /// prior knowledge of the distribution (2 is optimal for a Gaussian) and kappa is a secondary
+    /// scaling parameter. Default is alpha = 1, beta = 2 and kappa = 0.
+    pub fn set_parameters(&mut self, alpha: f64, beta: f64, kappa: f64) {
+        self.alpha = alpha;
+        self.beta = beta;
+        self.kappa = kappa;
+    }
+
+    /// Integrates the sigma points and reconstructs the mean and the covariance of the state at
+    /// the output points. The returned statistics are summed over the sigma points.
+    pub fn integrate(&mut self) -> Result<Stats, IntegrationError> {
+        let n = na::dimension::<V>();
+        let n_f = n as f64;
+        let lambda = self.alpha * self.alpha * (n_f + self.kappa) - n_f;
+        let l = square_root(self.covariance.clone() * (n_f + lambda));
+
+        // Sigma points and weights of the mean and of the covariance
+        let mut sigma_points = vec![self.mean; 2 * n + 1];
+        for j in 0..n {
+            for i in 0..n {
+                let delta: V::Real = na::convert(l[(i, j)]);
+                sigma_points[1 + j][i] += delta;
+                sigma_points[1 + n + j][i] -= delta;
+            }
+        }
+        let mut w_mean = vec![0.5 / (n_f + lambda); 2 * n + 1];
+        let mut w_cov = w_mean.clone();
+        w_mean[0] = lambda / (n_f + lambda);
+        w_cov[0] = w_mean[0] + 1.0 - self.alpha * self.alpha + self.beta;
+
+        let (f, x, x_end, dx, rtol, atol) =
+            (self.f, self.x, self.x_end, self.dx, self.rtol, self.atol);
+        let trajectories = map(&sigma_points, |y0| {
+            let mut stepper = Dopri5::new(f, x, x_end, dx, *y0, rtol, atol);
+            let stats = stepper.integrate()?;
+            Ok((stepper.x_out().clone(), stepper.y_out().clone(), stats))
+        });
+        let (x_out, y_out, stats) = collect(trajectories)?;
+
+        self.mean_out.clear();
+        self.covariance_out.clear();
+        for k in 0..x_out.len() {
+            let mut mean = DVector::zeros(n);
+            for (w, y) in w_mean.iter().zip(y_out.iter()) {
+                mean += to_dvector(&y[k]) * *w;
+            }
+            let mut covariance = DMatrix::zeros(n, n);
+            for (w, y) in w_cov.iter().zip(y_out.iter()) {
+                let d = to_dvector(&y[k]) - &mean;
+                covariance += &d * d.transpose() * *w;
+            }
+            let mut mean_k = V::zero();
+            for i in 0..n {
+                mean_k[i] = na::convert(mean[i]);
+            }
+            self.mean_out.push(mean_k);
+            self.covariance_out.push(covariance);
+        }
+        self.x_out = x_out;
+        Ok(stats)
+    }
+
+    /// Getter for the output points.
+    pub fn x_out(&self) -> &Vec<f64> {
+        &self.x_out
+    }
+
+    /// Getter for the mean of the state at the output points.
+    pub fn mean_out(&self) -> &Vec<V> {
+        &self.mean_out
+    }
+
+    /// Getter for the covariance of the state at the output points.
+    pub fn covariance_out(&self) -> &Vec<DMatrix<f64>> {
+        &self.covariance_out
+    }
+}
+
+/// Enumeration of the distributions of the uncertain parameters.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Distribution {
+    /// Normal distribution with the given mean and standard deviation
+    Normal(f64, f64),
+    /// Uniform distribution between the given bounds
+    Uniform(f64, f64),
+}
+
+impl Distribution {
+    /// Value of the parameter corresponding to the standard variable xi, which follows the
+    /// standard normal distribution or the uniform distribution on [-1, 1].
+    pub fn from_standard(&self, xi: f64) -> f64 {
+        match *self {
+            Distribution::Normal(mean, std_dev) => mean + std_dev * xi,
+            Distribution::Uniform(a, b) => 0.5 * (a + b) + 0.5 * (b - a) * xi,
+        }
+    }
+
+    /// Standard variable corresponding to the value p of the parameter.
+    pub fn to_standard(&self, p: f64) -> f64 {
+        match *self {
+            Distribution::Normal(mean, std_dev) => (p - mean) / std_dev,
+            Distribution::Uniform(a, b) => (2.0 * p - a - b) / (b - a),
+        }
+    }
+
+    /// Quantile function, the value of the parameter whose cumulative probability is u. The
+    /// inverse of the normal distribution is computed with the rational approximation of Acklam,
+    /// whose relative error is below 1.2e-9.
+    pub fn quantile(&self, u: f64) -> f64 {
+        match *self {
+            Distribution::Normal(..) => self.from_standard(normal_quantile(u)),
+            Distribution::Uniform(a, b) => a + (b - a) * u,
+        }
+    }
+
+    /// Values of the orthonormal polynomials of degree 0 to `order` at xi: the probabilists'
+    /// Hermite polynomials for a normal distribution and the Legendre polynomials for a uniform
+    /// distribution.
+    fn polynomials(&self, order: usize, xi: f64) -> Vec<f64> {
+        let mut psi = vec![1.0; order + 1];
+        if order > 0 {
+            psi[1] = xi / self.recurrence(1);
+        }
+        for m in 1..order {
+            psi[m + 1] = (xi * psi[m] - self.recurrence(m) * psi[m - 1]) / self.recurrence(m + 1);
+        }
+        psi
+    }
+
+    /// Coefficient b_m of the three-term recurrence x p_m = b_(m+1) p_(m+1) + b_m p_(m-1) of the
+    /// orthonormal polynomials.
+    fn recurrence(&self, m: usize) -> f64 {
+        let m = m as f64;
+        match *self {
+            Distribution::Normal(..) => m.sqrt(),
+            Distribution::Uniform(..) => m / (4.0 * m * m - 1.0).sqrt(),
+        }
+    }
+
+    /// Nodes and weights of the Gauss quadrature with the given number of points, computed as the
+    /// eigenvalues and eigenvectors of the Jacobi matrix.
+    fn quadrature(&self, points: usize) -> (Vec<f64>, Vec<f64>) {
+        let mut jacobi = DMatrix::zeros(points, points);
+        for m in 1..points {
+            jacobi[(m - 1, m)] = self.recurrence(m);
+            jacobi[(m, m - 1)] = self.recurrence(m);
+        }
+        let eigen = jacobi.symmetric_eigen();
+        let nodes = eigen.eigenvalues.iter().cloned().collect();
+        let weights = (0..points)
+            .map(|j| eigen.eigenvectors[(0, j)] * eigen.eigenvectors[(0, j)])
+            .collect();
+        (nodes, weights)
+    }
+}
+
+/// Structure containing the parameters of the polynomial chaos expansion.
+pub struct PolynomialChaos<V>
+where
+    V: FiniteDimInnerSpace + Copy,
+{
+    f: fn(f64, &V, &DVector<f64>, &mut V),
+    y: V,
+    parameters: Vec<Distribution>,
+    order: usize,
+    x: f64,
+    x_end: f64,
+    dx: f64,
+    rtol: f64,
+    atol: f64,
+    multi_indices: Vec<Vec<usize>>,
+    x_out: Vec<f64>,
+    coefficients_out: Vec<Vec<V>>,
+}
+
+impl<V> PolynomialChaos<V>
+where
+    V: FiniteDimInnerSpace + Copy + Send + Sync,
+    <V as InnerSpace>::Real: SubsetOf<f64>,
+{
+    /// Default initializer for the structure.
+    ///
+    /// # Arguments
+    ///
+    /// * `f`           - Pointer to the function to integrate, which depends on parameters p
+    /// * `y`           - Initial value of the dependent variable(s)
+    /// * `parameters`  - Distributions of the independent uncertain parameters
+    /// * `order`       - Maximal total degree of the polynomials of the expansion
+    /// * `x`           - Initial value of the independent variable (usually time)
+    /// * `x_end`       - Final value of the independent variable
+    ///
+    pub fn new(
+        f: fn(f64, &V, &DVector<f64>, &mut V),
+        y: V,
+        parameters: Vec<Distribution>,
+        order: usize,
+        x: f64,
+        x_end: f64,
+    ) -> PolynomialChaos<V> {
+        // Multi-indices of total degree at most order, sorted by degree
+        let d = parameters.len();
+        let mut multi_indices = Vec::new();
+        let mut alpha = vec![0; d];
+        loop {
+            if alpha.iter().sum::<usize>() <= order {
+                multi_indices.push(alpha.clone());
+            }
+            match (0..d).find(|&i| alpha[i] < order) {
+                Some(i) => {
+                    alpha[i] += 1;
+                    for a in alpha[..i].iter_mut() {
+                        *a = 0;
+                    }
+                }
+                None => break,
+            }
+        }
+        multi_indices.sort_by_key(|alpha| (alpha.iter().sum::<usize>(), Reverse(alpha.clone())));
+
+        PolynomialChaos {
+            f,
+            y,
+            parameters,
+            order,
+            x,
+            x_end,
+            dx: (x_end - x) / 100.0,
+            rtol: 1.0e-8,
+            atol: 1.0e-8,
+            multi_indices,
+            x_out: Vec::new(),
+            coefficients_out: Vec::new(),
+        }
+    }
+
+    /// Sets the increment between the output points. Default is a hundredth of the interval.
+    pub fn set_dx(&mut self, dx: f64) {
+        self.dx = dx;
+    }
+
+    /// Sets the relative and absolute tolerances of the integrations. Default is 1e-8 for both.
+    pub fn set_tolerances(&mut self, rtol: f64, atol: f64) {
+        self.rtol = rtol;
+        self.atol = atol;
+    }
+
+    /// Integrates the system at the (order + 1)^d nodes of the quadrature, where d is the number
+    /// of parameters, and computes the coefficients of the expansion at the output points. The
+    /// returned statistics are summed over the nodes.
+    pub fn integrate(&mut self) -> Result<Stats, IntegrationError> {
+        let points = self.order + 1;
+        let d = self.parameters.len();
+        let rules: Vec<(Vec<f64>, Vec<f64>)> = self
+            .parameters
+            .iter()
+            .map(|p| p.quadrature(points))
+            .collect();
+
+        // Nodes of the tensor quadrature, in standard variables, and their weights
+        let n_nodes = points.pow(d as u32);
+        let mut nodes = Vec::with_capacity(n_nodes);
+        let mut weights = Vec::with_capacity(n_nodes);
+        for q in 0..n_nodes {
+            let mut xi = vec![0.0; d];
+            let mut w = 1.0;
+            let mut r = q;
+            for i in 0..d {
+                xi[i] = rules[i].0[r % points];
+                w *= rules[i].1[r % points];
+                r /= points;
+            }
+            nodes.push(xi);
+            weights.push(w);
+        }
+
+        let (f, y0, x, x_end, dx, rtol, atol) = (
+            self.f, self.y, self.x, self.x_end, self.dx, self.rtol, self.atol,
+        );
+        let parameters = &self.parameters;
+        let trajectories = map(&nodes, |xi| {
+            let p = DVector::from_iterator(
+                d,
+                parameters
+                    .iter()
+                    .zip(xi.iter())
+                    .map(|(p, xi)| p.from_standard(*xi)),
+            );
+            let mut stepper = Dopri5::with_parameters(f, p, x, x_end, dx, y0, rtol, atol);
+            let stats = stepper.integrate()?;
+            Ok((stepper.x_out().clone(), stepper.y_out().clone(), stats))
+        });
+        let (x_out, y_out, stats) = collect(trajectories)?;
+
+        // Projection on the polynomials
+        let dim = na::dimension::<V>();
+        let mut coefficients = vec![vec![vec![0.0; dim]; self.multi_indices.len()]; x_out.len()];
+        for (q, xi) in nodes.iter().enumerate() {
+            let psi = self.basis(xi);
+            for (k, y) in y_out[q].iter().enumerate() {
+                for (c, psi) in coefficients[k].iter_mut().zip(psi.iter()) {
+                    for i in 0..dim {
+                        let y_i: f64 = na::convert(y[i]);
+                        c[i] += weights[q] * psi * y_i;
+                    }
+                }
+            }
+        }
+        self.coefficients_out = coefficients
+            .iter()
+            .map(|c| {
+                c.iter()
+                    .map(|c| from_dvector(&DVector::from_column_slice(c)))
+                    .collect()
+            })
+            .collect();
+        self.x_out = x_out;
+        Ok(stats)
+    }
+
+    /// Values of the polynomials of the expansion at the standard variables xi.
+    fn basis(&self, xi: &[f64]) -> Vec<f64> {
+        let psi: Vec<Vec<f64>> = self
+            .parameters
+            .iter()
+            .zip(xi.iter())
+            .map(|(p, xi)| p.polynomials(self.order, *xi))
+            .collect();
+        self.multi_indices
+            .iter()
+            .map(|alpha| alpha.iter().enumerate().map(|(i, &a)| psi[i][a]).product())
+            .collect()
+    }
+
+    /// Evaluates the expansion at the k-th output point for the parameters p.
+    pub fn evaluate(&self, k: usize, p: &DVector<f64>) -> V {
+        let xi: Vec<f64> = self
+            .parameters
+            .iter()
+            .zip(p.iter())
+            .map(|(d, p)| d.to_standard(*p))
+            .collect();
+        let mut y = V::zero();
+        for (c, psi) in self.coefficients_out[k].iter().zip(self.basis(&xi)) {
+            y += *c * na::convert(psi);
+        }
+        y
+    }
+
+    /// Getter for the multi-indices of the polynomials of the expansion. The i-th entry of a
+    /// multi-index is the degree of the polynomial in the i-th parameter.
+    pub fn multi_indices(&self) -> &Vec<Vec<usize>> {
+        &self.multi_indices
+    }
+
+    /// Getter for the output points.
+    pub fn x_out(&self) -> &Vec<f64> {
+        &self.x_out
+    }
+
+    /// Getter for the coefficients of the expansion at the output points, in the order of the
+    /// multi-indices.
+    pub fn coefficients_out(&self) -> &Vec<Vec<V>> {
+        &self.coefficients_out
+    }
+
+    /// Mean of the state at the output points, the coefficient of the constant polynomial.
+    pub fn mean_out(&self) -> Vec<V> {
+        self.coefficients_out.iter().map(|c| c[0]).collect()
+    }
+
+    /// Variance of the state at the output points, per component.
+    pub fn variance_out(&self) -> Vec<V> {
+        let dim = na::dimension::<V>();
+        self.coefficients_out
+            .iter()
+            .map(|c| {
+                let mut variance = vec![0.0; dim];
+                for c in c.iter().skip(1) {
+                    for i in 0..dim {
+                        let c_i: f64 = na::convert(c[i]);
+                        variance[i] += c_i * c_i;
+                    }
+                }
+                from_dvector(&DVector::from_vec(variance))
+            })
+            .collect()
+    }
+}
+
+/// Applies the function to all the inputs, in parallel with the `rayon` feature.
+fn map<T, V, F>(inputs: &[T], f: F) -> Vec<Trajectory<V>>
+where
+    T: Sync,
+    V: Send,
+    F: Fn(&T) -> Trajectory<V> + Sync + Send,
+{
+    #[cfg(feature = "rayon")]
+    let trajectories = inputs.par_iter().map(f).collect();
+    #[cfg(not(feature = "rayon"))]
+    let trajectories = inputs.iter().map(f).collect();
+    trajectories
+}
+
+/// Gathers the trajectories, which share the same output points, and sums their statistics.
+fn collect<V>(trajectories: Vec<Trajectory<V>>) -> Trajectories<V> {
+    let mut x_out = Vec::new();
+    let mut y_out = Vec::with_capacity(trajectories.len());
+    let mut stats = Stats::new();
+    for trajectory in trajectories {
+        let (x, y, s) = trajectory?;
+        stats += s;
+        x_out = x;
+        y_out.push(y);
+    }
+    Ok((x_out, y_out, stats))
+}
+
+/// Quantile function of the standard normal distribution.
+fn normal_quantile(u: f64) -> f64 {
+    const A: [f64; 6] = [
+        -3.969683028665376e+01,
+        2.209460984245205e+02,
+        -2.759285104469687e+02,
+        1.38357751867269e+02,
+        -3.066479806614716e+01,
+        2.506628277459239e+00,
+    ];
+    const B: [f64; 5] = [
+        -5.447609879822406e+01,
+        1.615858368580409e+02,
+        -1.556989798598866e+02,
+        6.680131188771972e+01,
+        -1.328068155288572e+01,
+    ];
+    const C: [f64; 6] = [
+        -7.784894002430293e-03,
+        -3.223964580411365e-01,
+        -2.400758277161838e+00,
+        -2.549732539343734e+00,
+        4.374664141464968e+00,
+        2.938163982698783e+00,
+    ];
+    const D: [f64; 4] = [
+        7.784695709041462e-03,
+        3.224671290700398e-01,
+        2.445134137142996e+00,
+        3.754408661907416e+00,
+    ];
+    const U_LOW: f64 = 0.02425;
+
+    // Tails
+    let tail = |u: f64| {
+        let q = (-2.0 * u.ln()).sqrt();
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    };
+    if u < U_LOW {
+        tail(u)
+    } else if u > 1.0 - U_LOW {
+        -tail(1.0 - u)
+    } else {
+        let q = u - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    }
+}
+
+/// Square root L of a symmetric positive semidefinite matrix, such that L L<sup>T</sup> = m. The
+/// Cholesky factor is used if it exists. Otherwise, the square root is obtained from the
+/// eigendecomposition, whose negative eigenvalues, which only come from rounding errors, are set
+/// to zero.
+fn square_root(m: DMatrix<f64>) -> DMatrix<f64> {
+    match m.clone().cholesky() {
+        Some(cholesky) => cholesky.unpack(),
+        None => {
+            let eigen = m.symmetric_eigen();
+            let sqrt_values = eigen.eigenvalues.map(|v| v.max(0.0).sqrt());
+            eigen.eigenvectors * DMatrix::from_diagonal(&sqrt_values)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use na::{Matrix2, Vector1, Vector2};
+
+    fn rotation(_x: f64, y: &Vector2<f64>, dy: &mut Vector2<f64>) {
+        dy[0] = -0.5 * y[0] + y[1];
+        dy[1] = -y[0] - 0.5 * y[1];
+    }
+
+    fn check_linear_system(p0: Matrix2<f64>) {
+        let covariance = DMatrix::from_iterator(2, 2, p0.iter().cloned());
+        let mut ut = UnscentedTransform::new(
+            rotation,
+            0.0,
+            2.0,
+            0.5,
+            Vector2::new(1.0, 0.0),
+            covariance,
+            1.0e-10,
+            1.0e-10,
+        );
+        ut.integrate().unwrap();
+        for (k, x) in ut.x_out().iter().enumerate() {
+            let (c, s) = (x.cos(), x.sin());
+            let phi = Matrix2::new(c, s, -s, c) * (-0.5 * x).exp();
+            let p = phi * p0 * phi.transpose();
+            assert!((ut.mean_out()[k] - phi * Vector2::new(1.0, 0.0)).norm() < 1.0e-8);
+            for i in 0..2 {
+                for j in 0..2 {
+                    assert!((ut.covariance_out()[k][(i, j)] - p[(i, j)]).abs() < 1.0e-8);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn unscented_transform_of_linear_system() {
+        check_linear_system(Matrix2::new(2.0, 0.5, 0.5, 1.0));
+    }
+
+    #[test]
+    fn semidefinite_covariance() {
+        // The initial state lies on a line, and the covariance has no Cholesky factor
+        check_linear_system(Matrix2::new(1.0, 2.0, 2.0, 4.0));
+    }
+
+    fn decay(_x: f64, y: &Vector1<f64>, p: &DVector<f64>, dy: &mut Vector1<f64>) {
+        dy[0] = -p[0] * y[0] + p[1];
+    }
+
+    #[test]
+    fn polynomial_chaos_of_decay() {
+        let mut pce = PolynomialChaos::new(
+            decay,
+            Vector1::new(1.0),
+            vec![
+                Distribution::Uniform(0.5, 1.5),
+                Distribution::Normal(1.0, 0.1),
+            ],
+            8,
+            0.0,
+            1.0,
+        );
+        pce.set_tolerances(1.0e-12, 1.0e-12);
+        pce.integrate().unwrap();
+        assert_eq!(pce.multi_indices().len(), 45);
+        assert_eq!(pce.multi_indices()[1], vec![1, 0]);
+
+        // Exact mean of exp(-a t) + b (1 - exp(-a t)) / a for a ~ U(0.5, 1.5) and b ~ N(1, 0.1),
+        // where the integral of (1 - exp(-a t)) / a is given by the series of the exponential
+        // integral
+        let t = *pce.x_out().last().unwrap();
+        let mean = pce.mean_out().last().unwrap()[0];
+        let e = ((-0.5 * t).exp() - (-1.5 * t).exp()) / t;
+        let ei = |x: f64| (1..40).fold(0.0, |s, n| s + x.powi(n) / (n as f64 * factorial(n)));
+        let exact = e - (ei(-1.5 * t) - ei(-0.5 * t));
+        assert!((mean - exact).abs() < 1.0e-8);
+
+        let p = DVector::from_column_slice(&[1.0, 0.8]);
+        let y = pce.evaluate(pce.x_out().len() - 1, &p)[0];
+        assert!((y - (0.8 + 0.2 * (-t).exp())).abs() < 1.0e-6);
+    }
+
+    fn factorial(n: i32) -> f64 {
+        (1..=n).fold(1.0, |f, k| f * f64::from(k))
+    }
+}